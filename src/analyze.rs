@@ -0,0 +1,271 @@
+//! Static analysis of a ROM before running it. Follows the program's flow from its first instruction like
+//! `disassemble --follow` and reports:
+//!
+//! ```text
+//! 264 bytes, 126 instructions reached
+//! Likely variant: chip8
+//!
+//! Opcodes:
+//! 6XKK  LD Vx, kk             36
+//! ...
+//! SCHIP instructions: none
+//! XO-CHIP instructions: none
+//!
+//! Data:
+//! 2EA-2F5  12 bytes
+//!
+//! Maximum stack depth: 1
+//! ```
+//!
+//! Computed jumps (BNNN) can't be followed, so code only reached through them shows as data.
+
+// Self imports
+use crate::conformance::{self, Platform};
+use crate::decode::Instruction;
+use crate::disasm;
+use crate::variant::Variant;
+
+// Std imports
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+// Deepest the stack goes on the machines with the smallest stacks. Anything deeper is reported as too deep.
+const STACK_SIZE: usize = 16;
+
+// Largest program which fits in 4K after 0x200.
+const MAX_CHIP8_ROM: usize = 0x1000 - 0x200;
+
+/// How often one opcode family appears in the reached code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Family {
+    pub pattern: &'static str,
+    pub mnemonic: &'static str,
+    pub count: usize,
+}
+
+/// Uses of an extension's instructions, by mnemonic with how often and the first address.
+pub type Uses = BTreeMap<&'static str, (usize, usize)>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analysis {
+    pub size: usize,
+    pub instructions: usize,
+    pub families: Vec<Family>, // Most common first.
+    pub schip: Uses,
+    pub xo_chip: Uses,
+    pub data: Vec<(usize, usize)>, // First and last address of each run of bytes never reached.
+    pub stack_depth: Option<usize>, // None if calls go deeper than the stack, e.g. through recursion.
+    pub variant: Variant,
+}
+
+impl Analysis {
+    /// Analyze a ROM loaded at `start`, decoding it as `variant`.
+    pub fn new(bytes: &[u8], start: usize, variant: Variant) -> Self {
+        let code = disasm::reachable(bytes, start, variant);
+        let opcode = |addr: usize| (bytes[addr - start] as u16) << 8 | bytes[addr - start + 1] as u16;
+
+        let mut families: Vec<Family> = vec![];
+        let (mut schip, mut xo_chip, mut platforms) = (Uses::new(), Uses::new(), vec![]);
+        let mut covered = vec![false; bytes.len()];
+        for &addr in &code {
+            let info = conformance::family(opcode(addr));
+            let (pattern, mnemonic) = info.map_or(("????", ""), |info| (info.pattern, info.mnemonic));
+            match families.iter_mut().find(|family| family.pattern == pattern) {
+                Some(family) => family.count += 1,
+                None => families.push(Family {
+                    pattern,
+                    mnemonic,
+                    count: 1,
+                }),
+            }
+
+            if let Some(info) = info {
+                if !platforms.contains(&info.platform) {
+                    platforms.push(info.platform);
+                }
+                let uses = match info.platform {
+                    Platform::SuperChip => Some(&mut schip),
+                    Platform::XoChip => Some(&mut xo_chip),
+                    _ => None,
+                };
+                if let Some(uses) = uses {
+                    uses.entry(info.mnemonic).or_insert((0, addr)).0 += 1;
+                }
+            }
+
+            let len = match disasm::successors(bytes, start, variant, addr) {
+                Some((Instruction::LongI, _)) => 4,
+                _ => 2,
+            };
+            for byte in covered.iter_mut().skip(addr - start).take(len) {
+                *byte = true;
+            }
+        }
+        families.sort_by(|a, b| b.count.cmp(&a.count).then(a.pattern.cmp(b.pattern)));
+
+        let uses = |platform: Platform| platforms.contains(&platform);
+        let likely = match () {
+            #[cfg(feature = "megachip")]
+            _ if uses(Platform::MegaChip) => Variant::MegaChip,
+            _ if uses(Platform::XoChip) || bytes.len() > MAX_CHIP8_ROM => Variant::XoChip,
+            _ if uses(Platform::Chip8X) => Variant::Chip8X,
+            _ if uses(Platform::Chip8Hires) || bytes.starts_with(&[0x12, 0x60]) => Variant::Chip8Hires,
+            _ => Variant::Chip8,
+        };
+
+        Self {
+            size: bytes.len(),
+            instructions: code.len(),
+            families,
+            schip,
+            xo_chip,
+            data: data(&covered, start),
+            stack_depth: stack_depth(bytes, start, variant),
+            variant: likely,
+        }
+    }
+}
+
+// Runs of bytes no instruction covers.
+fn data(covered: &[bool], start: usize) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = vec![];
+    for (offset, _) in covered.iter().enumerate().filter(|(_, covered)| !**covered) {
+        match runs.last_mut() {
+            Some((_, last)) if *last + 1 == start + offset => *last += 1,
+            _ => runs.push((start + offset, start + offset)),
+        }
+    }
+    runs
+}
+
+// Deepest the calls go on any path through the program. Each address is visited once for every depth it is reached
+// at, which ends with None if calls keep going past the stack.
+fn stack_depth(bytes: &[u8], start: usize, variant: Variant) -> Option<usize> {
+    let mut seen = HashSet::new();
+    let mut todo = vec![(start, 0)];
+    let mut deepest = 0;
+    while let Some((addr, depth)) = todo.pop() {
+        if depth > STACK_SIZE {
+            return None;
+        }
+        if !seen.insert((addr, depth)) {
+            continue;
+        }
+        deepest = deepest.max(depth);
+
+        match disasm::successors(bytes, start, variant, addr) {
+            Some((Instruction::Call { nnn }, next)) => {
+                todo.extend(next.into_iter().map(|next| (next, if next == nnn { depth + 1 } else { depth })))
+            }
+            Some((Instruction::Ret, _)) => {}
+            Some((_, next)) => todo.extend(next.into_iter().map(|next| (next, depth))),
+            None => {}
+        }
+    }
+    Some(deepest)
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} bytes, {} instructions reached", self.size, self.instructions)?;
+        writeln!(f, "Likely variant: {}", self.variant)?;
+
+        writeln!(f, "\nOpcodes:")?;
+        for family in &self.families {
+            writeln!(f, "{:<6}{:<18}{:>6}", family.pattern, family.mnemonic, family.count)?;
+        }
+
+        for (name, uses) in [("SCHIP", &self.schip), ("XO-CHIP", &self.xo_chip)] {
+            if uses.is_empty() {
+                writeln!(f, "{} instructions: none", name)?;
+                continue;
+            }
+            writeln!(f, "{} instructions:", name)?;
+            for (mnemonic, (count, first)) in uses {
+                writeln!(f, "  {:<18}{:>6}  first at {:03X}", mnemonic, count, first)?;
+            }
+        }
+
+        writeln!(f, "\nData:")?;
+        if self.data.is_empty() {
+            writeln!(f, "none")?;
+        }
+        for (first, last) in &self.data {
+            writeln!(f, "{:03X}-{:03X}  {} bytes", first, last, last - first + 1)?;
+        }
+
+        match self.stack_depth {
+            Some(depth) => writeln!(f, "\nMaximum stack depth: {}", depth),
+            None => writeln!(
+                f,
+                "\nMaximum stack depth: more than {}, calls may be recursive",
+                STACK_SIZE
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyze(bytes: &[u8]) -> Analysis {
+        Analysis::new(bytes, 0x200, Variant::Chip8)
+    }
+
+    #[test]
+    fn test_counting_reached_opcodes() {
+        // The sprite after the loop is never executed.
+        let analysis = analyze(&[0xA2, 0x06, 0xD0, 0x15, 0x12, 0x02, 0x60, 0x90]);
+
+        assert_eq!(analysis.instructions, 3);
+        let patterns: Vec<_> = analysis
+            .families
+            .iter()
+            .map(|family| (family.pattern, family.count))
+            .collect();
+        assert_eq!(patterns, [("1NNN", 1), ("ANNN", 1), ("DXYN", 1)]);
+        assert_eq!(analysis.data, [(0x206, 0x207)]);
+        assert_eq!(analysis.variant, Variant::Chip8);
+    }
+
+    #[test]
+    fn test_extension_instructions() {
+        let analysis = analyze(&[0x00, 0xFF, 0xF0, 0x00, 0x23, 0x00, 0x00, 0xFF, 0x12, 0x08]);
+
+        assert_eq!(analysis.schip.get("HIGH"), Some(&(2, 0x200)));
+        assert_eq!(analysis.xo_chip.get("LD I, long nnnn"), Some(&(1, 0x202)));
+        assert_eq!(analysis.variant, Variant::XoChip);
+        assert!(analysis.data.is_empty());
+    }
+
+    #[test]
+    fn test_likely_variants() {
+        assert_eq!(analyze(&[0x12, 0x60]).variant, Variant::Chip8Hires);
+        assert_eq!(analyze(&[0x00, 0xFF, 0x12, 0x02]).variant, Variant::Chip8);
+        assert_eq!(analyze(&[0xB0, 0x00]).variant, Variant::Chip8);
+        assert_eq!(analyze(&vec![0x12; MAX_CHIP8_ROM + 2]).variant, Variant::XoChip);
+    }
+
+    #[test]
+    fn test_stack_depth() {
+        // Calls 206, which calls 20A, which returns. 206 returns to a loop.
+        let nested = [0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x22, 0x0A, 0x00, 0xEE, 0x00, 0xEE];
+        assert_eq!(analyze(&nested).stack_depth, Some(2));
+
+        let recursive = [0x22, 0x00];
+        assert_eq!(analyze(&recursive).stack_depth, None);
+        assert!(analyze(&recursive).to_string().contains("more than 16"));
+    }
+
+    #[test]
+    fn test_report() {
+        let report = analyze(&[0x00, 0xE0, 0x12, 0x02, 0xFF, 0xFF]).to_string();
+
+        assert!(report.starts_with("6 bytes, 2 instructions reached\nLikely variant: chip8\n\nOpcodes:\n"));
+        assert!(report.contains("00E0  CLS                    1\n"));
+        assert!(report.contains("SCHIP instructions: none\n"));
+        assert!(report.contains("\nData:\n204-205  2 bytes\n"));
+        assert!(report.ends_with("Maximum stack depth: 0\n"));
+    }
+}
@@ -0,0 +1,363 @@
+//! Assembler. Turns CHIP-8 mnemonics, the same ones the disassembler prints, into a ROM.
+//!
+//! ```text
+//! ; Bounce a dot across the screen.
+//!         LD I, dot
+//! loop:   DRW V0, V1, 1
+//!         ADD V0, 01
+//!         DRW V0, V1, 1
+//!         JP loop
+//! dot:    DB %10000000
+//! ```
+//!
+//! - Numbers are hexadecimal, as in disassembly, optionally written with a 0x prefix. Binary is written with %.
+//! - Labels end with a colon and can be used wherever an address is expected. A label which is also a hex number, such
+//!   as `face`, is read as the number.
+//! - `DB` writes bytes and `DW` big endian words, e.g. for sprites and tables.
+//! - Comments start with `;`. Mnemonics, registers and labels are case insensitive.
+//! - XO-CHIP's 16-bit index load is written `LD I, long nnnn` and takes 4 bytes. Its register ranges are written
+//!   `SAVE Vx - Vy` and `LOAD Vx - Vy`.
+//!
+//! Programs are assembled to run from the variant's program start. CHIP-8X's nibble-wise ADD Vx, Vy disassembles the
+//! same as 8XY4, and assembles as 8XY4.
+
+// Self imports
+use crate::decode::Instruction;
+
+// Std imports
+use std::collections::HashMap;
+
+/// An operand as written.
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    V(usize),
+    I,
+    IndirectI, // [I]
+    Dt,
+    St,
+    K,
+    Value(String), // A number or a label, resolved once every label is known. Also F and B, which can be numbers.
+    Long(String),  // A 16-bit number or label after `long`.
+}
+
+/// A line's contents after its label.
+enum Statement {
+    Instruction(String, Vec<Operand>),
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+}
+
+impl Statement {
+    fn len(&self) -> usize {
+        match self {
+            Statement::Instruction(_, operands) if operands.iter().any(|op| matches!(op, Operand::Long(_))) => 4,
+            Statement::Instruction(..) => 2,
+            Statement::Bytes(values) => values.len(),
+            Statement::Words(values) => values.len() * 2,
+        }
+    }
+}
+
+/// Assemble a program to be loaded at `start`. Errors give the line number.
+pub fn assemble(source: &str, start: usize) -> Result<Vec<u8>, String> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut addr = start;
+
+    // First pass: find where every label is.
+    for (number, line) in source.lines().enumerate() {
+        let at_line = |e: String| format!("line {}: {}", number + 1, e);
+        let (label, statement) = parse_line(line).map_err(at_line)?;
+
+        if let Some(label) = label {
+            if labels.insert(label.to_lowercase(), addr).is_some() {
+                return Err(at_line(format!("label {} is defined more than once", label)));
+            }
+        }
+        if let Some(statement) = statement {
+            addr += statement.len();
+            statements.push((number + 1, statement));
+        }
+    }
+
+    // Second pass: encode, now labels can be resolved.
+    let mut rom = Vec::with_capacity(addr - start);
+    for (number, statement) in statements {
+        let at_line = |e: String| format!("line {}: {}", number, e);
+        match statement {
+            Statement::Instruction(mnemonic, operands) => {
+                rom.extend(encode(&mnemonic, &operands, &labels).map_err(at_line)?);
+            }
+            Statement::Bytes(values) => {
+                for value in values {
+                    rom.push(resolve(&value, 0xFF, &labels).map_err(at_line)? as u8);
+                }
+            }
+            Statement::Words(values) => {
+                for value in values {
+                    let word = resolve(&value, 0xFFFF, &labels).map_err(at_line)? as u16;
+                    rom.extend_from_slice(&word.to_be_bytes());
+                }
+            }
+        }
+    }
+
+    Ok(rom)
+}
+
+// Split a line into its label and statement, either of which may be missing.
+fn parse_line(line: &str) -> Result<(Option<&str>, Option<Statement>), String> {
+    let mut line = line.split(';').next().unwrap_or_default().trim();
+
+    let mut label = None;
+    if let Some((name, rest)) = line.split_once(':') {
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("invalid label: {}", name));
+        }
+        label = Some(name);
+        line = rest.trim();
+    }
+
+    if line.is_empty() {
+        return Ok((label, None));
+    }
+
+    let (mnemonic, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let separator = match mnemonic.to_uppercase().as_str() {
+        "SAVE" | "LOAD" => '-', // Register ranges, e.g. SAVE V0 - V3.
+        _ => ',',
+    };
+    let operands: Vec<&str> = operands.split(separator).map(str::trim).filter(|op| !op.is_empty()).collect();
+    let values = || operands.iter().map(|op| op.to_string()).collect();
+
+    let statement = match mnemonic.to_uppercase().as_str() {
+        "DB" => Statement::Bytes(values()),
+        "DW" => Statement::Words(values()),
+        mnemonic => Statement::Instruction(
+            mnemonic.to_string(),
+            operands.iter().map(|op| parse_operand(op)).collect(),
+        ),
+    };
+    Ok((label, Some(statement)))
+}
+
+fn parse_operand(op: &str) -> Operand {
+    let upper = op.to_uppercase();
+    match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        "K" => Operand::K,
+        _ if upper.starts_with("LONG ") => Operand::Long(op[5..].trim().to_string()),
+        _ => match upper.strip_prefix('V').and_then(|x| usize::from_str_radix(x, 16).ok()) {
+            Some(x) if x < 16 && upper.len() == 2 => Operand::V(x),
+            _ => Operand::Value(op.to_string()),
+        },
+    }
+}
+
+// Resolve a number or label, checking it fits in `max`.
+fn resolve(value: &str, max: usize, labels: &HashMap<String, usize>) -> Result<usize, String> {
+    let number = match (
+        value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")),
+        value.strip_prefix('%'),
+    ) {
+        (Some(hex), _) => usize::from_str_radix(hex, 16).ok(),
+        (_, Some(binary)) => usize::from_str_radix(binary, 2).ok(),
+        _ => usize::from_str_radix(value, 16).ok(),
+    };
+
+    let resolved = number
+        .or_else(|| labels.get(&value.to_lowercase()).copied())
+        .ok_or_else(|| format!("unknown label or invalid number: {}", value))?;
+
+    if resolved > max {
+        return Err(format!("{} is too big, the most allowed is {:X}", value, max));
+    }
+    Ok(resolved)
+}
+
+// Encode an instruction to its bytes, 4 for LD I, long nnnn and 2 for everything else.
+fn encode(mnemonic: &str, operands: &[Operand], labels: &HashMap<String, usize>) -> Result<Vec<u8>, String> {
+    use Instruction::*;
+    use Operand::{Dt, IndirectI, Long, St, Value, I, K, V};
+
+    let addr = |value: &str| resolve(value, 0xFFF, labels);
+    let byte = |value: &str| resolve(value, 0xFF, labels).map(|kk| kk as u8);
+    let nibble = |value: &str| resolve(value, 0xF, labels);
+
+    let instruction = match (mnemonic.to_uppercase().as_str(), operands) {
+        ("CLS", []) => Cls,
+        ("RET", []) => Ret,
+        ("SCD", [Value(n)]) => ScrollDown { n: nibble(n)? },
+        ("SCU", [Value(n)]) => ScrollUp { n: nibble(n)? },
+        ("SCR", []) => ScrollRight,
+        ("SCL", []) => ScrollLeft,
+        ("EXIT", []) => Exit,
+        ("LOW", []) => Lores,
+        ("HIGH", []) => Hires,
+        ("SYS", [Value(nnn)]) => Sys { nnn: addr(nnn)? },
+        ("JP", [Value(nnn)]) => Jump { nnn: addr(nnn)? },
+        ("JP", [V(0), Value(nnn)]) => JumpV0 { nnn: addr(nnn)? },
+        ("CALL", [Value(nnn)]) => Call { nnn: addr(nnn)? },
+        ("SE", [V(x), Value(kk)]) => SkipEqByte { x: *x, kk: byte(kk)? },
+        ("SE", [V(x), V(y)]) => SkipEqReg { x: *x, y: *y },
+        ("SNE", [V(x), Value(kk)]) => SkipNeByte { x: *x, kk: byte(kk)? },
+        ("SNE", [V(x), V(y)]) => SkipNeReg { x: *x, y: *y },
+        ("LD", [Value(r), V(x)]) if r.eq_ignore_ascii_case("R") => SaveFlags { x: *x },
+        ("LD", [V(x), Value(r)]) if r.eq_ignore_ascii_case("R") => LoadFlags { x: *x },
+        ("LD", [V(x), Value(kk)]) => LoadByte { x: *x, kk: byte(kk)? },
+        ("LD", [V(x), V(y)]) => LoadReg { x: *x, y: *y },
+        ("LD", [I, Value(nnn)]) => LoadI { nnn: addr(nnn)? },
+        ("LD", [I, Long(nnnn)]) => {
+            let nnnn = resolve(nnnn, 0xFFFF, labels)? as u16;
+            let mut bytes = LongI.encode().to_be_bytes().to_vec();
+            bytes.extend_from_slice(&nnnn.to_be_bytes());
+            return Ok(bytes);
+        }
+        ("LD", [V(x), Dt]) => LoadDelay { x: *x },
+        ("LD", [V(x), K]) => WaitKey { x: *x },
+        ("LD", [Dt, V(x)]) => SetDelay { x: *x },
+        ("LD", [St, V(x)]) => SetSound { x: *x },
+        ("LD", [Value(f), V(x)]) if f.eq_ignore_ascii_case("F") => LoadFont { x: *x },
+        ("LD", [Value(hf), V(x)]) if hf.eq_ignore_ascii_case("HF") => LoadBigFont { x: *x },
+        ("LD", [Value(b), V(x)]) if b.eq_ignore_ascii_case("B") => Bcd { x: *x },
+        ("LD", [IndirectI, V(x)]) => StoreRegisters { x: *x },
+        ("LD", [V(x), IndirectI]) => LoadRegisters { x: *x },
+        ("PLANE", [Value(n)]) => SelectPlanes { n: nibble(n)? },
+        ("SAVE", [V(x), V(y)]) => SaveRange { x: *x, y: *y },
+        ("LOAD", [V(x), V(y)]) => LoadRange { x: *x, y: *y },
+        ("ADD", [V(x), Value(kk)]) => AddByte { x: *x, kk: byte(kk)? },
+        ("ADD", [V(x), V(y)]) => AddReg { x: *x, y: *y },
+        ("ADD", [I, V(x)]) => AddI { x: *x },
+        ("OR", [V(x), V(y)]) => Or { x: *x, y: *y },
+        ("AND", [V(x), V(y)]) => And { x: *x, y: *y },
+        ("XOR", [V(x), V(y)]) => Xor { x: *x, y: *y },
+        ("SUB", [V(x), V(y)]) => Sub { x: *x, y: *y },
+        ("SUBN", [V(x), V(y)]) => SubN { x: *x, y: *y },
+        ("SHR", [V(x)]) => ShiftRight { x: *x, y: *x },
+        ("SHR", [V(x), V(y)]) => ShiftRight { x: *x, y: *y },
+        ("SHL", [V(x)]) => ShiftLeft { x: *x, y: *x },
+        ("SHL", [V(x), V(y)]) => ShiftLeft { x: *x, y: *y },
+        ("RND", [V(x), Value(kk)]) => Random { x: *x, kk: byte(kk)? },
+        ("DRW", [V(x), V(y), Value(n)]) => Draw {
+            x: *x,
+            y: *y,
+            n: nibble(n)?,
+        },
+        ("SKP", [V(x)]) => SkipKey { x: *x },
+        ("SKNP", [V(x)]) => SkipNotKey { x: *x },
+        ("BGC", []) => CycleBackground,
+        ("COL", [V(x), V(y), Value(n)]) => Colour {
+            x: *x,
+            y: *y,
+            n: nibble(n)?,
+        },
+        ("SKP2", [V(x)]) => SkipKey2 { x: *x },
+        ("SKNP2", [V(x)]) => SkipNotKey2 { x: *x },
+        ("OUT", [V(x)]) => Out { x: *x },
+        (mnemonic, []) => return Err(format!("unknown instruction: {}", mnemonic)),
+        (mnemonic, _) => return Err(format!("unknown instruction or wrong operands: {}", mnemonic)),
+    };
+
+    Ok(instruction.encode().to_be_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm;
+    use crate::variant::Variant;
+
+    const SOURCE: &str = "
+        ; Bounce a dot across the screen.
+                LD I, dot
+        loop:   DRW V0, V1, 1
+                ADD V0, 01
+                DRW V0, V1, 1
+                JP loop
+        dot:    DB %10000000, 0xFF
+        table:  DW dot, 1234
+    ";
+
+    #[test]
+    fn test_assembling() {
+        let rom = assemble(SOURCE, 0x200).unwrap();
+        #[rustfmt::skip]
+        let expected = vec![
+            0xA2, 0x0A,
+            0xD0, 0x11,
+            0x70, 0x01,
+            0xD0, 0x11,
+            0x12, 0x02,
+            0x80, 0xFF,
+            0x02, 0x0A, 0x12, 0x34,
+        ];
+        assert_eq!(rom, expected);
+    }
+
+    #[test]
+    fn test_mnemonics_are_case_insensitive() {
+        assert_eq!(
+            assemble("ld va, [i]\nLoop: jp LOOP\n", 0x200).unwrap(),
+            vec![0xFA, 0x65, 0x12, 0x02]
+        );
+        assert_eq!(
+            assemble("ld f, v1\nLD B, V1\nDRW V0, V1, F\n", 0x200).unwrap(),
+            vec![0xF1, 0x29, 0xF1, 0x33, 0xD0, 0x1F]
+        );
+    }
+
+    #[test]
+    fn test_errors_give_line_numbers() {
+        assert_eq!(
+            assemble("CLS\nJP nowhere\n", 0x200),
+            Err("line 2: unknown label or invalid number: nowhere".to_string())
+        );
+        assert_eq!(
+            assemble("\n\nFOO\n", 0x200),
+            Err("line 3: unknown instruction: FOO".to_string())
+        );
+        assert!(assemble("LD V0, 100\n", 0x200).unwrap_err().contains("too big"));
+        assert!(assemble("SE I, V0\n", 0x200).unwrap_err().contains("wrong operands"));
+        assert!(assemble("a: CLS\na: CLS\n", 0x200)
+            .unwrap_err()
+            .contains("more than once"));
+    }
+
+    // Disassembling a ROM and assembling the result gives back the same ROM.
+    #[test]
+    fn test_round_trip() {
+        let rom = include_bytes!("../roms/pong.ch8");
+        let source: String = disasm::disassemble(rom, 0x200, Variant::Chip8)
+            .iter()
+            .map(|line| match line {
+                disasm::Line::Code { instruction, .. } => format!("{}\n", instruction),
+                disasm::Line::Long { nnnn, .. } => format!("LD I, long {:04X}\n", nnnn),
+                disasm::Line::Byte { byte, .. } => format!("DB {:02X}\n", byte),
+            })
+            .collect();
+
+        assert_eq!(assemble(&source, 0x200).unwrap(), rom.to_vec());
+    }
+
+    #[test]
+    fn test_register_ranges() {
+        assert_eq!(assemble("SAVE V1 - V4\nload v3-v0\n", 0x200).unwrap(), vec![0x51, 0x42, 0x53, 0x03]);
+    }
+
+    #[test]
+    fn test_long_index_loads() {
+        assert_eq!(
+            assemble("LD I, long data
+JP 200
+data: DB FF
+", 0x200).unwrap(),
+            vec![0xF0, 0x00, 0x02, 0x06, 0x12, 0x00, 0xFF]
+        );
+        assert!(assemble("LD I, long 10000
+", 0x200).unwrap_err().contains("too big"));
+    }
+}
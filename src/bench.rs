@@ -0,0 +1,394 @@
+//! Scripted benchmark scenarios. A scenario pins down everything that affects a run so timings are comparable between
+//! builds, and checks the final state against an expected hash so a faster build can't pass by doing something else.
+//! A scenario can also keep the hash of the state after every frame, recorded with --record-hashes, so a change to
+//! the emulator which alters the run is caught at the first frame it makes a difference.
+//!
+//! ```toml
+//! rom = "../roms/pong.ch8"    # Relative to the scenario file.
+//! variant = "chip8"           # Optional. Defaults to chip8.
+//! seed = 42                   # Seeds CXKK.
+//! cycles = 200000             # Instructions to execute per run. Runs end early if the program halts.
+//! expected_hash = "..."       # Optional. Printed after a run so it can be filled in.
+//! journal = true              # Optional. Record every change, as the GUI does for rewinding, to measure its cost.
+//! frame_hashes = "pong.hashes" # Optional. State hash after every frame, one per line. Relative to the scenario.
+//!
+//! [[input]]                   # Hold key 1 from cycle 1000. Omit key to release.
+//! cycle = 1000
+//! key = 1
+//! ```
+
+// Self imports
+use crate::cpu::CPU;
+use crate::halt::Halt;
+use crate::journal::Journal;
+use crate::variant::Variant;
+use crate::INSTRUCTIONS_PER_FRAME;
+
+// Std imports
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// External imports
+use serde::Deserialize;
+
+/// Change to the held key at a given cycle.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct InputEvent {
+    pub cycle: u64,
+    pub key: Option<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub rom: PathBuf,
+    #[serde(default)]
+    pub variant: String,
+    #[serde(default)]
+    pub seed: u64,
+    pub cycles: u64,
+    #[serde(default)]
+    pub expected_hash: Option<String>,
+    #[serde(default)]
+    pub input: Vec<InputEvent>,
+    #[serde(default)]
+    pub journal: bool,
+    #[serde(default)]
+    pub frame_hashes: Option<PathBuf>,
+}
+
+/// The first frame where a run's state differs from the recorded hashes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub frame: usize,
+    pub expected: Option<u64>, // None if the run went on past the end of the recording.
+    pub actual: Option<u64>,   // None if the run halted before the end of the recording.
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hash = |hash: Option<u64>| hash.map_or("nothing".to_string(), |hash| format!("{:016x}", hash));
+        write!(
+            f,
+            "frame {} (cycle {}) diverged: expected {}, got {}",
+            self.frame,
+            self.frame * INSTRUCTIONS_PER_FRAME,
+            hash(self.expected),
+            hash(self.actual)
+        )
+    }
+}
+
+/// Timings of every run of a scenario.
+#[derive(Debug)]
+pub struct Report {
+    pub cycles: u64,
+    pub times: Vec<Duration>,
+    pub hash: u64,
+    pub halt: Option<Halt>,
+}
+
+/// The outcome of one run of a scenario.
+#[derive(Debug)]
+pub struct Run {
+    pub time: Duration,
+    pub hash: u64,
+    pub cycles: u64,          // Cycles executed, fewer than the scenario's if the program halted.
+    pub halt: Option<Halt>,
+}
+
+impl Scenario {
+    /// Load a scenario file. The ROM path is resolved relative to the scenario.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        let mut scenario = Self::from_toml(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        if let Some(dir) = path.parent() {
+            scenario.rom = dir.join(&scenario.rom);
+            scenario.frame_hashes = scenario.frame_hashes.map(|hashes| dir.join(hashes));
+        }
+        Ok(scenario)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let mut scenario: Scenario = toml::from_str(text).map_err(|e| e.to_string())?;
+        scenario.input.sort_by_key(|event| event.cycle);
+
+        for event in &scenario.input {
+            match event.key {
+                Some(key) if key > 0xF => {
+                    return Err(format!("input at cycle {}: {:#X} is not a keypad key", event.cycle, key));
+                }
+                _ => {}
+            }
+        }
+        scenario.variant()?;
+
+        Ok(scenario)
+    }
+
+    fn variant(&self) -> Result<Variant, String> {
+        if self.variant.is_empty() {
+            Ok(Variant::default())
+        } else {
+            self.variant.parse()
+        }
+    }
+
+    /// Run the scenario `runs` times, checking every run ends in the same state.
+    pub fn bench(&self, runs: usize) -> Result<Report, String> {
+        let rom = fs::read(&self.rom).map_err(|e| format!("unable to read {}: {}", self.rom.display(), e))?;
+        let mut times = Vec::with_capacity(runs);
+        let mut last: Option<Run> = None;
+
+        for index in 0..runs {
+            let run = self.run_once(&rom)?;
+            if last.as_ref().is_some_and(|last| last.hash != run.hash) {
+                return Err(format!("run {} ended in a different state. The scenario is not deterministic", index + 1));
+            }
+
+            times.push(run.time);
+            last = Some(run);
+        }
+
+        Ok(Report {
+            cycles: last.as_ref().map_or(self.cycles, |run| run.cycles),
+            times,
+            hash: last.as_ref().map_or(0, |run| run.hash),
+            halt: last.and_then(|run| run.halt),
+        })
+    }
+
+    /// Execute the scenario once, returning how long it took and a hash of the final state. Timers tick once every
+    /// INSTRUCTIONS_PER_FRAME cycles, as they would in a normal run. The run stops as soon as the program halts.
+    pub fn run_once(&self, rom: &[u8]) -> Result<Run, String> {
+        self.run(rom, |_| {})
+    }
+
+    /// Execute the scenario once, returning the hash of the state after every frame.
+    pub fn hash_frames(&self, rom: &[u8]) -> Result<Vec<u64>, String> {
+        let mut hashes = vec![];
+        self.run(rom, |cpu| hashes.push(cpu.state_hash()))?;
+        Ok(hashes)
+    }
+
+    /// Execute the scenario once, comparing the state after every frame with the recorded hashes. Returns the first
+    /// frame which differs, if any.
+    pub fn verify(&self, rom: &[u8], expected: &[u64]) -> Result<Option<Divergence>, String> {
+        let actual = self.hash_frames(rom)?;
+        let frames = actual.len().max(expected.len());
+        let divergence = |frame| Divergence {
+            frame,
+            expected: expected.get(frame).copied(),
+            actual: actual.get(frame).copied(),
+        };
+        Ok((0..frames).map(divergence).find(|divergence| divergence.expected != divergence.actual))
+    }
+
+    // Execute the scenario once, calling `frame` with the CPU at the end of every frame.
+    fn run(&self, rom: &[u8], mut frame: impl FnMut(&CPU)) -> Result<Run, String> {
+        let mut cpu = CPU::new(self.variant()?);
+        cpu.load(rom.to_vec())?;
+        cpu.seed_rng(self.seed);
+
+        let mut journal = Journal::default();
+        if self.journal {
+            cpu.start_recording();
+        }
+
+        let mut inputs = self.input.iter().peekable();
+        let start = Instant::now();
+        let mut cycles = self.cycles;
+
+        for cycle in 0..self.cycles {
+            while let Some(event) = inputs.next_if(|event| event.cycle <= cycle) {
+                match event.key {
+                    Some(key) => cpu.set_key(key),
+                    None => cpu.clear_keys(),
+                }
+            }
+
+            cpu.cycle();
+            if (cycle + 1) % INSTRUCTIONS_PER_FRAME as u64 == 0 {
+                cpu.tick_timers();
+                if self.journal {
+                    journal.record(&mut cpu);
+                }
+                frame(&cpu);
+            }
+
+            if cpu.halt().is_some() {
+                cycles = cycle + 1;
+                break;
+            }
+        }
+
+        Ok(Run {
+            time: start.elapsed(),
+            hash: cpu.state_hash(),
+            cycles,
+            halt: cpu.halt(),
+        })
+    }
+}
+
+/// Read frame hashes, one hex hash per line.
+pub fn load_hashes(path: &Path) -> Result<Vec<u64>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            u64::from_str_radix(line.trim(), 16).map_err(|e| format!("{} line {}: {}", path.display(), n + 1, e))
+        })
+        .collect()
+}
+
+pub fn save_hashes(path: &Path, hashes: &[u64]) -> Result<(), String> {
+    let text: String = hashes.iter().map(|hash| format!("{:016x}\n", hash)).collect();
+    fs::write(path, text).map_err(|e| format!("unable to write {}: {}", path.display(), e))
+}
+
+impl Report {
+    pub fn mean(&self) -> Duration {
+        self.times.iter().sum::<Duration>() / self.times.len().max(1) as u32
+    }
+
+    pub fn worst(&self) -> Duration {
+        self.times.iter().max().copied().unwrap_or_default()
+    }
+
+    /// Instructions per second, going by the mean run time.
+    pub fn ips(&self) -> f64 {
+        self.cycles as f64 / self.mean().as_secs_f64()
+    }
+
+    pub fn hash(&self) -> String {
+        format!("{:016x}", self.hash)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "runs:   {}", self.times.len())?;
+        writeln!(f, "cycles: {}", self.cycles)?;
+        writeln!(f, "mean:   {:?}", self.mean())?;
+        writeln!(f, "worst:  {:?}", self.worst())?;
+        writeln!(f, "ips:    {:.0}", self.ips())?;
+        if let Some(halt) = self.halt {
+            writeln!(f, "{}", halt)?;
+        }
+        writeln!(f, "hash:   {}", self.hash())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Loops forever drawing a random font digit at a random position, skipping a draw while key 5 is held.
+    #[rustfmt::skip]
+    const ROM: [u8; 14] = [
+        0xC0, 0x0F, // 0x200: V0 = random & 0x0F.
+        0xC1, 0x3F, // 0x202: V1 = random & 0x3F.
+        0xF0, 0x29, // 0x204: I = font sprite of V0.
+        0x62, 0x05, // 0x206: V2 = 5.
+        0xE2, 0x9E, // 0x208: Skip the draw if key 5 is held.
+        0xD1, 0x05, // 0x20A: Draw at (V1, V0).
+        0x12, 0x00, // 0x20C: Jump to 0x200.
+    ];
+
+    fn scenario(extra: &str) -> Scenario {
+        Scenario::from_toml(&format!("rom = \"test.ch8\"\nseed = 7\ncycles = 5000\n{}", extra)).unwrap()
+    }
+
+    #[test]
+    fn test_parsing_scenario() {
+        let scenario = scenario("[[input]]\ncycle = 50\n\n[[input]]\ncycle = 10\nkey = 5\n");
+
+        assert_eq!(scenario.variant().unwrap(), Variant::Chip8);
+        assert_eq!(scenario.cycles, 5000);
+        assert_eq!(scenario.input[0], InputEvent { cycle: 10, key: Some(5) });
+        assert_eq!(scenario.input[1], InputEvent { cycle: 50, key: None });
+    }
+
+    #[test]
+    fn test_rejecting_bad_scenarios() {
+        assert!(Scenario::from_toml("rom = \"a\"\ncycles = 1\nvariant = \"nes\"\n").is_err());
+        assert!(Scenario::from_toml("rom = \"a\"\ncycles = 1\n[[input]]\ncycle = 1\nkey = 16\n").is_err());
+        assert!(Scenario::from_toml("rom = \"a\"\ncycles = 1\nframes = 2\n").is_err());
+    }
+
+    #[test]
+    fn test_runs_are_reproducible() {
+        let first = scenario("").run_once(&ROM).unwrap().hash;
+        let second = scenario("").run_once(&ROM).unwrap().hash;
+        assert_eq!(first, second);
+
+        let mut reseeded = scenario("");
+        reseeded.seed = 8;
+        let reseeded = reseeded.run_once(&ROM).unwrap().hash;
+        assert_ne!(first, reseeded);
+    }
+
+    #[test]
+    fn test_journal_does_not_change_outcome() {
+        let plain = scenario("").run_once(&ROM).unwrap().hash;
+        let journalled = scenario("journal = true").run_once(&ROM).unwrap().hash;
+        assert_eq!(plain, journalled);
+    }
+
+    #[test]
+    fn test_input_script_changes_outcome() {
+        let idle = scenario("").run_once(&ROM).unwrap().hash;
+        let pressed = scenario("[[input]]\ncycle = 100\nkey = 5\n").run_once(&ROM).unwrap().hash;
+        assert_ne!(idle, pressed);
+    }
+
+    #[test]
+    fn test_verifying_frame_hashes() {
+        let scenario = scenario("");
+        let mut hashes = scenario.hash_frames(&ROM).unwrap();
+        assert_eq!(hashes.len(), 5000 / INSTRUCTIONS_PER_FRAME);
+        assert_eq!(scenario.verify(&ROM, &hashes), Ok(None));
+
+        let (original, short) = (hashes[120], hashes[..10].to_vec());
+        hashes[120] ^= 1;
+        let divergence = scenario.verify(&ROM, &hashes).unwrap().unwrap();
+        assert_eq!(divergence, Divergence { frame: 120, expected: Some(original ^ 1), actual: Some(original) });
+        let message = format!("frame 120 (cycle {}) diverged", 120 * INSTRUCTIONS_PER_FRAME);
+        assert!(divergence.to_string().starts_with(&message));
+
+        let divergence = scenario.verify(&ROM, &short).unwrap().unwrap();
+        assert_eq!((divergence.frame, divergence.expected), (10, None));
+    }
+
+    #[test]
+    fn test_hash_files() {
+        let path = std::env::temp_dir().join(format!("chip8-hashes-{}", std::process::id()));
+        save_hashes(&path, &[0xABC, u64::MAX]).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "0000000000000abc\nffffffffffffffff\n");
+        assert_eq!(load_hashes(&path), Ok(vec![0xABC, u64::MAX]));
+
+        fs::write(&path, "12\nxyz\n").unwrap();
+        assert!(load_hashes(&path).unwrap_err().ends_with("line 2: invalid digit found in string"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_report() {
+        let report = Report {
+            cycles: 1000,
+            times: vec![Duration::from_millis(1), Duration::from_millis(3)],
+            hash: 0xABC,
+            halt: None,
+        };
+
+        assert_eq!(report.mean(), Duration::from_millis(2));
+        assert_eq!(report.worst(), Duration::from_millis(3));
+        assert_eq!(report.ips().round(), 500_000.0);
+        assert_eq!(report.hash(), "0000000000000abc");
+    }
+}
@@ -0,0 +1,164 @@
+//! CHIP-8X colour support. The VP-590 colour board gives the screen a background colour and splits it into zones 8
+//! pixels wide, each with its own foreground colour for lit pixels.
+
+// Self imports
+use crate::frame_buffer::WIDTH;
+
+// External imports
+use serde::{Deserialize, Serialize};
+
+// Width of a colour zone in pixels, and height of the zones BXY0 sets.
+const ZONE_WIDTH: usize = 8;
+const ZONE_HEIGHT: usize = 4;
+const ZONE_COLUMNS: usize = WIDTH / ZONE_WIDTH;
+
+/// Foreground colours selected by the low 3 bits of VY.
+#[rustfmt::skip]
+pub const FOREGROUND_COLOURS: [[u8; 3]; 8] = [
+    [0, 0, 0],       // Black
+    [255, 0, 0],     // Red
+    [0, 0, 255],     // Blue
+    [255, 0, 255],   // Violet
+    [0, 255, 0],     // Green
+    [255, 255, 0],   // Yellow
+    [0, 255, 255],   // Aqua
+    [255, 255, 255], // White
+];
+
+/// Background colours in the order 02A0 cycles through them.
+#[rustfmt::skip]
+pub const BACKGROUND_COLOURS: [[u8; 3]; 4] = [
+    [0, 0, 128], // Dark blue
+    [0, 0, 0],   // Black
+    [0, 128, 0], // Dark green
+    [128, 0, 0], // Dark red
+];
+
+const DEFAULT_FOREGROUND: u8 = 1; // Red
+
+/// Background colour and per-zone foreground colours. Zones are tracked per row so both BXY0 and BXYN can be honoured.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColourZones {
+    background: usize,
+    foreground: Vec<[u8; ZONE_COLUMNS]>,
+}
+
+impl ColourZones {
+    pub fn new(rows: usize) -> Self {
+        Self {
+            background: 0,
+            foreground: vec![[DEFAULT_FOREGROUND; ZONE_COLUMNS]; rows],
+        }
+    }
+
+    /// Move to the next background colour.
+    pub fn cycle_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUND_COLOURS.len();
+    }
+
+    /// Set the foreground colour of a block of zones. Columns are in zones and rows in pixels. Both wrap.
+    pub fn set_foreground(&mut self, col: usize, cols: usize, row: usize, rows: usize, colour: u8) {
+        let height = self.foreground.len();
+        for r in row..row + rows {
+            for c in col..col + cols {
+                self.foreground[r % height][c % ZONE_COLUMNS] = colour & 0x7;
+            }
+        }
+    }
+
+    /// BXY0 --> Set the colour of 8x4 zones. Low nibble of `horizontal` is the first zone column and the high nibble the
+    /// number of extra columns. `vertical` is the same for rows of zones.
+    pub fn set_coarse(&mut self, horizontal: u8, vertical: u8, colour: u8) {
+        let (col, cols) = ((horizontal & 0xF) as usize, (horizontal >> 4) as usize + 1);
+        let (row, rows) = ((vertical & 0xF) as usize, (vertical >> 4) as usize + 1);
+        self.set_foreground(col, cols, row * ZONE_HEIGHT, rows * ZONE_HEIGHT, colour);
+    }
+
+    /// BXYN --> Set the colour of 8x1 zones. `horizontal` is as for BXY0, `row` is the first pixel row and n the number
+    /// of rows.
+    pub fn set_fine(&mut self, horizontal: u8, row: u8, n: usize, colour: u8) {
+        let (col, cols) = ((horizontal & 0xF) as usize, (horizontal >> 4) as usize + 1);
+        self.set_foreground(col, cols, row as usize, n, colour);
+    }
+
+    /// Colour a frame of palette indices. Unlit pixels take the background colour and lit pixels their zone's colour.
+    pub fn colourise(&self, pixels: &[u8]) -> Vec<[u8; 3]> {
+        pixels
+            .iter()
+            .enumerate()
+            .map(|(i, index)| {
+                if *index == 0 {
+                    BACKGROUND_COLOURS[self.background]
+                } else {
+                    let (row, col) = (i / WIDTH, i % WIDTH);
+                    FOREGROUND_COLOURS[self.foreground[row][col / ZONE_WIDTH] as usize]
+                }
+            })
+            .collect()
+    }
+}
+
+/// 5XY1 --> Add each nibble of a and b separately, modulo 8. Used to move zone coordinates packed into a register.
+pub fn add_nibbles(a: u8, b: u8) -> u8 {
+    let high = ((a >> 4) + (b >> 4)) % 8;
+    let low = ((a & 0xF) + (b & 0xF)) % 8;
+    high << 4 | low
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycling_background() {
+        let mut zones = ColourZones::new(32);
+        for expected in &[1, 2, 3, 0] {
+            zones.cycle_background();
+            assert_eq!(zones.background, *expected);
+        }
+    }
+
+    #[test]
+    fn test_setting_coarse_zones() {
+        let mut zones = ColourZones::new(32);
+
+        // Columns 2 and 3, zone rows 1 to 3 (pixel rows 4 to 15).
+        zones.set_coarse(0x12, 0x21, 4);
+        assert_eq!(zones.foreground[3], [1; 8]);
+        assert_eq!(zones.foreground[4], [1, 1, 4, 4, 1, 1, 1, 1]);
+        assert_eq!(zones.foreground[15], [1, 1, 4, 4, 1, 1, 1, 1]);
+        assert_eq!(zones.foreground[16], [1; 8]);
+    }
+
+    #[test]
+    fn test_setting_fine_zones() {
+        let mut zones = ColourZones::new(32);
+
+        zones.set_fine(0x07, 31, 2, 6);
+        assert_eq!(zones.foreground[31][7], 6);
+        assert_eq!(zones.foreground[0][7], 6); // Wraps back to the top.
+        assert_eq!(zones.foreground[1][7], 1);
+    }
+
+    #[test]
+    fn test_colourising_frame() {
+        let mut zones = ColourZones::new(32);
+        zones.set_fine(0x01, 0, 1, 5);
+
+        let mut pixels = vec![0; WIDTH * 32];
+        pixels[0] = 1;
+        pixels[8] = 1;
+
+        let colours = zones.colourise(&pixels);
+        assert_eq!(colours[0], FOREGROUND_COLOURS[1]);
+        assert_eq!(colours[8], FOREGROUND_COLOURS[5]);
+        assert_eq!(colours[1], BACKGROUND_COLOURS[0]);
+    }
+
+    #[test]
+    fn test_adding_nibbles() {
+        assert_eq!(add_nibbles(0x12, 0x34), 0x46);
+        assert_eq!(add_nibbles(0x77, 0x11), 0x00);
+        assert_eq!(add_nibbles(0x05, 0x04), 0x01);
+    }
+}
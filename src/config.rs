@@ -0,0 +1,656 @@
+//! User configuration loaded from a TOML file. Maps keyboard keys onto the Chip8 keypad and emulator hotkeys.
+//!
+//! ```toml
+//! [keypad]
+//! 0 = "X"
+//! A = "Z"
+//!
+//! [keypad2] # CHIP-8X second keypad. Unbound by default.
+//! 0 = "Keypad 0"
+//!
+//! [hotkeys]
+//! step = "Space"
+//! help = "F1"
+//! suspend = "F5"
+//! overlay = "F2"
+//! reset = "F4"
+//! save-state = "F6"
+//! load-state = "F7"
+//! next-slot = "F8"
+//! rewind = "Backspace"
+//! diff = "F3"
+//! breakpoint = "F9"
+//! memory = "F10"
+//! debug-window = "Tab"
+//! hud = "F11"
+//! crt = "T"
+//! grid = "G"
+//! frame-advance = "F12"
+//! pause = "P"
+//!
+//! [display]
+//! palette = ["#000000", "#00FA00", "#FA7800", "#FAFAFA"] # Off, plane 0, plane 1, both planes. Or a palette name
+//!                                                         # or file, e.g. "amber". See palettes.rs.
+//! border = "#181818"                                      # Frame and letterbox colour.
+//! border_width = 4                                        # Window pixels around the screen.
+//! decay = 4                                               # Frames pixels take to fade out. 0 for none, the default.
+//! decay_rate = 2.0                                        # How they fade. 1 evenly, the default. See phosphor.rs.
+//! crt = true                                              # Start with the CRT look on. Off by default.
+//! vsync = true                                            # Pace frames by the monitor's refresh. Off by default.
+//!
+//! [limits]                                                # Restrictions, e.g. for a child's profile.
+//! roms = ["pong.ch8", "tetris.ch8"]                       # File names of the ROMs which may be run. All by default.
+//! debugger = false                                        # Whether the debugging options are allowed. Default true.
+//!
+//! [speed]                                                 # A preset or instructions per frame. See speed.rs.
+//! default = "vip"                                         # For every ROM without its own speed.
+//!
+//! [speed.roms]                                            # By ROM file name.
+//! "ant.ch8" = "hp48"
+//! "octojam.ch8" = "octo"
+//! ```
+//!
+//! Keys are named as SDL names them. Anything left out keeps its default binding.
+
+// Self imports
+use crate::frame_buffer::Palette;
+use crate::palettes;
+use crate::Timing;
+
+// External imports
+use serde::Deserialize;
+
+// Std imports
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+
+/// Emulator actions which can be bound to a key. Separate from the Chip8 keypad.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Hotkey {
+    Step,
+    Help,
+    Suspend,
+    Overlay,
+    Reset,
+    SaveState,
+    LoadState,
+    NextSlot,
+    Rewind,
+    Diff,
+    Breakpoint,
+    Memory,
+    DebugWindow,
+    Hud,
+    Crt,
+    Grid,
+    FrameAdvance,
+    Pause,
+}
+
+impl Hotkey {
+    pub const ALL: &'static [Hotkey] = &[
+        Hotkey::Step,
+        Hotkey::Help,
+        Hotkey::Suspend,
+        Hotkey::Overlay,
+        Hotkey::Reset,
+        Hotkey::SaveState,
+        Hotkey::LoadState,
+        Hotkey::NextSlot,
+        Hotkey::Rewind,
+        Hotkey::Diff,
+        Hotkey::Breakpoint,
+        Hotkey::Memory,
+        Hotkey::DebugWindow,
+        Hotkey::Hud,
+        Hotkey::Crt,
+        Hotkey::Grid,
+        Hotkey::FrameAdvance,
+        Hotkey::Pause,
+    ];
+
+    /// Name used for the hotkey in config files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hotkey::Step => "step",
+            Hotkey::Help => "help",
+            Hotkey::Suspend => "suspend",
+            Hotkey::Overlay => "overlay",
+            Hotkey::Reset => "reset",
+            Hotkey::SaveState => "save-state",
+            Hotkey::LoadState => "load-state",
+            Hotkey::NextSlot => "next-slot",
+            Hotkey::Rewind => "rewind",
+            Hotkey::Diff => "diff",
+            Hotkey::Breakpoint => "breakpoint",
+            Hotkey::Memory => "memory",
+            Hotkey::DebugWindow => "debug-window",
+            Hotkey::Hud => "hud",
+            Hotkey::Crt => "crt",
+            Hotkey::Grid => "grid",
+            Hotkey::FrameAdvance => "frame-advance",
+            Hotkey::Pause => "pause",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Hotkey::Step => "Print CPU state. Executes one instruction in debug mode",
+            Hotkey::Help => "Show key bindings",
+            Hotkey::Suspend => "Save the whole session to disk and quit. Resume with --resume",
+            Hotkey::Overlay => "Toggle the debug overlay in the window title. Shows a hash of the machine state",
+            Hotkey::Reset => "Restart the ROM without restarting the emulator",
+            Hotkey::SaveState => "Save the session to the current save state slot",
+            Hotkey::LoadState => "Load the current save state slot",
+            Hotkey::NextSlot => "Select the next save state slot",
+            Hotkey::Rewind => "Hold to run time backwards",
+            Hotkey::Diff => "Toggle showing what changed each frame: new pixels green, erased red, the rest dimmed",
+            Hotkey::Breakpoint => "Toggle a breakpoint at the next instruction",
+            Hotkey::Memory => "Open or close a window with a live hexdump of memory. Scroll it with the mouse wheel",
+            Hotkey::DebugWindow => "Open or close a window with the registers, disassembly, call stack and memory at I",
+            Hotkey::Hud => "Toggle the on-screen HUD: frame rate, instructions per second, registers and next opcode",
+            Hotkey::Crt => "Toggle the CRT look: scanlines, curved glass and a vignette",
+            Hotkey::Grid => "Toggle thin lines between the screen's pixels, for counting them",
+            Hotkey::FrameAdvance => "Run one frame and its timer tick, then pause again. Pauses first if running",
+            Hotkey::Pause => "Pause or resume the ROM without the debugger",
+        }
+    }
+
+    fn default_key(&self) -> &'static str {
+        match self {
+            Hotkey::Step => "Space",
+            Hotkey::Help => "F1",
+            Hotkey::Suspend => "F5",
+            Hotkey::Overlay => "F2",
+            Hotkey::Reset => "F4",
+            Hotkey::SaveState => "F6",
+            Hotkey::LoadState => "F7",
+            Hotkey::NextSlot => "F8",
+            Hotkey::Rewind => "Backspace",
+            Hotkey::Diff => "F3",
+            Hotkey::Breakpoint => "F9",
+            Hotkey::Memory => "F10",
+            Hotkey::DebugWindow => "Tab",
+            Hotkey::Hud => "F11",
+            Hotkey::Crt => "T",
+            Hotkey::Grid => "G",
+            Hotkey::FrameAdvance => "F12",
+            Hotkey::Pause => "P",
+        }
+    }
+}
+
+// Default keyboard key for each Chip8 key, indexed by the Chip8 key's value. See keypad.rs for the layout.
+const DEFAULT_KEYPAD: [&str; 16] = [
+    "X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V",
+];
+
+// Background, plane 0, plane 1 and both planes.
+pub const DEFAULT_PALETTE: Palette = [[0, 0, 0], [0, 250, 0], [250, 120, 0], [250, 250, 250]];
+
+// Slightly lighter than the default background so the edge of the screen can be seen.
+const DEFAULT_BORDER: [u8; 3] = [24, 24, 24];
+
+/// Config as written in the file. Every section is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ConfigFile {
+    keypad: HashMap<String, String>,
+    keypad2: HashMap<String, String>,
+    hotkeys: HashMap<String, String>,
+    display: DisplayFile,
+    limits: LimitsFile,
+    speed: SpeedFile,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct DisplayFile {
+    palette: Option<PaletteFile>,
+    border: Option<String>,
+    border_width: Option<u32>,
+    decay: Option<u32>,
+    decay_rate: Option<f32>,
+    crt: Option<bool>,
+    vsync: Option<bool>,
+}
+
+/// A palette's colours, or the name of a preset or palette file.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PaletteFile {
+    Colours(Vec<String>),
+    Named(String),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct LimitsFile {
+    roms: Option<Vec<String>>,
+    debugger: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SpeedFile {
+    default: Option<String>,
+    roms: HashMap<String, String>,
+}
+
+/// Colours used to draw the screen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplayConfig {
+    /// Colour for each combination of lit planes. Index 0 is the colour of pixels which are off.
+    pub palette: Palette,
+
+    /// Colour of the frame around the screen and of any letterboxing when the window is resized.
+    pub border: [u8; 3],
+
+    /// Width of the frame in window pixels.
+    pub border_width: u32,
+
+    /// Frames a pixel takes to fade out after turning off, like a CRT's phosphor. 0 turns them off at once.
+    pub decay: u32,
+
+    /// How pixels fade over the decay. 1 dims them evenly, higher rates quickly at first and lower ones slowly.
+    pub decay_rate: f32,
+
+    /// Whether the screen starts with the CRT look: scanlines, curved glass and a vignette.
+    pub crt: bool,
+
+    /// Whether frames are presented in step with the monitor's refresh, with the instructions run each refresh worked
+    /// out from the time since the last, instead of sleeping between frames.
+    pub vsync: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            palette: DEFAULT_PALETTE,
+            border: DEFAULT_BORDER,
+            border_width: 0,
+            decay: 0,
+            decay_rate: 1.0,
+            crt: false,
+            vsync: false,
+        }
+    }
+}
+
+/// What may be run, and how.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Limits {
+    /// File names of the ROMs which may be run, or None for any ROM.
+    pub roms: Option<Vec<String>>,
+
+    /// Whether the debugger (single stepping and watchpoints) may be used.
+    pub debugger: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            roms: None,
+            debugger: true,
+        }
+    }
+}
+
+impl Limits {
+    pub fn check_rom(&self, rom: &Path) -> Result<(), String> {
+        let name = rom.file_name().and_then(OsStr::to_str).unwrap_or_default();
+        match &self.roms {
+            Some(roms) if !roms.iter().any(|allowed| allowed == name) => {
+                Err(format!("{} is not in the list of ROMs allowed by the config", rom.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_debugger(&self) -> Result<(), String> {
+        if self.debugger {
+            Ok(())
+        } else {
+            Err("the debugger is disabled by the config".to_string())
+        }
+    }
+}
+
+/// How fast ROMs run, overall and for particular ROMs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Speeds {
+    /// Speed for ROMs without their own, or None to leave it to the command line.
+    pub default: Option<Timing>,
+
+    /// Speed for each ROM, by file name.
+    pub roms: HashMap<String, Timing>,
+}
+
+impl Speeds {
+    pub fn for_rom(&self, rom: &Path) -> Option<Timing> {
+        let name = rom.file_name().and_then(OsStr::to_str).unwrap_or_default();
+        self.roms.get(name).copied().or(self.default)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Keyboard key bound to each Chip8 key, indexed by the Chip8 key's value.
+    pub keypad: [String; 16],
+
+    /// Keyboard key bound to each key of the CHIP-8X second keypad. Keys may be left unbound.
+    pub keypad2: HashMap<u8, String>,
+
+    /// Keyboard key bound to each hotkey.
+    pub hotkeys: HashMap<Hotkey, String>,
+
+    pub display: DisplayConfig,
+
+    pub limits: Limits,
+
+    pub speed: Speeds,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut keypad: [String; 16] = Default::default();
+        for (k, key) in DEFAULT_KEYPAD.iter().enumerate() {
+            keypad[k] = key.to_string();
+        }
+
+        let hotkeys = Hotkey::ALL.iter().map(|h| (*h, h.default_key().to_string())).collect();
+        Self {
+            keypad,
+            keypad2: HashMap::new(),
+            hotkeys,
+            display: DisplayConfig::default(),
+            limits: Limits::default(),
+            speed: Speeds::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Load a config file, checking no key is bound to more than one thing.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        Self::from_toml(&text)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let file: ConfigFile = toml::from_str(text).map_err(|e| format!("invalid config: {}", e))?;
+        let mut config = Self::default();
+
+        for (k, key) in file.keypad {
+            config.keypad[parse_keypad_key(&k)? as usize] = key;
+        }
+
+        for (k, key) in file.keypad2 {
+            config.keypad2.insert(parse_keypad_key(&k)?, key);
+        }
+
+        for (name, key) in file.hotkeys {
+            let hotkey = Hotkey::ALL
+                .iter()
+                .find(|h| h.name() == name)
+                .ok_or_else(|| format!("unknown hotkey: {}", name))?;
+            config.hotkeys.insert(*hotkey, key);
+        }
+
+        match file.display.palette {
+            Some(PaletteFile::Colours(palette)) => {
+                if palette.len() != config.display.palette.len() {
+                    return Err(format!("palette needs {} colours", config.display.palette.len()));
+                }
+                for (entry, colour) in config.display.palette.iter_mut().zip(&palette) {
+                    *entry = parse_colour(colour)?;
+                }
+            }
+            Some(PaletteFile::Named(name)) => config.display.palette = palettes::load(&name)?,
+            None => {}
+        }
+
+        if let Some(border) = file.display.border {
+            config.display.border = parse_colour(&border)?;
+        }
+
+        if let Some(border_width) = file.display.border_width {
+            config.display.border_width = border_width;
+        }
+
+        if let Some(decay) = file.display.decay {
+            config.display.decay = decay;
+        }
+
+        if let Some(decay_rate) = file.display.decay_rate {
+            if !(decay_rate > 0.0 && decay_rate.is_finite()) {
+                return Err(format!("decay_rate must be above 0, not {}", decay_rate));
+            }
+            config.display.decay_rate = decay_rate;
+        }
+
+        if let Some(crt) = file.display.crt {
+            config.display.crt = crt;
+        }
+
+        if let Some(vsync) = file.display.vsync {
+            config.display.vsync = vsync;
+        }
+
+        config.limits.roms = file.limits.roms;
+        if let Some(debugger) = file.limits.debugger {
+            config.limits.debugger = debugger;
+        }
+
+        config.speed.default = file.speed.default.map(|speed| speed.parse()).transpose()?;
+        for (rom, speed) in file.speed.roms {
+            config.speed.roms.insert(rom, speed.parse()?);
+        }
+
+        let conflicts = config.conflicts();
+        if conflicts.is_empty() {
+            Ok(config)
+        } else {
+            Err(conflicts.join("\n"))
+        }
+    }
+
+    /// Describe every keyboard key bound to more than one keypad key or hotkey.
+    fn conflicts(&self) -> Vec<String> {
+        let mut uses: HashMap<String, Vec<String>> = HashMap::new();
+
+        for (k, key) in self.keypad.iter().enumerate() {
+            uses.entry(key.to_lowercase()).or_default().push(format!("keypad {:X}", k));
+        }
+
+        for (k, key) in &self.keypad2 {
+            uses.entry(key.to_lowercase()).or_default().push(format!("keypad2 {:X}", k));
+        }
+
+        for hotkey in Hotkey::ALL {
+            let key = &self.hotkeys[hotkey];
+            uses.entry(key.to_lowercase()).or_default().push(format!("hotkey {}", hotkey.name()));
+        }
+
+        let mut conflicts: Vec<String> = uses
+            .iter()
+            .filter(|(_, actions)| actions.len() > 1)
+            .map(|(key, actions)| format!("key \"{}\" is bound to {}", key, actions.join(" and ")))
+            .collect();
+
+        conflicts.sort();
+        conflicts
+    }
+
+    /// Human readable list of hotkey bindings.
+    pub fn help(&self) -> String {
+        let mut help = String::from("Hotkeys:\n");
+        for hotkey in Hotkey::ALL {
+            help += &format!("  {:<8} {}\n", self.hotkeys[hotkey], hotkey.description());
+        }
+        help
+    }
+
+    /// The Chip8 key bound to a key, by its SDL name. For frontends without SDL, whose own names for letters, digits
+    /// and Space are the same.
+    pub fn keypad_key(&self, name: &str) -> Option<u8> {
+        self.keypad.iter().position(|key| key.eq_ignore_ascii_case(name)).map(|k| k as u8)
+    }
+}
+
+/// Parse a hexadecimal Chip8 key name such as "A".
+fn parse_keypad_key(k: &str) -> Result<u8, String> {
+    u8::from_str_radix(k, 16)
+        .ok()
+        .filter(|k| *k < 16)
+        .ok_or_else(|| format!("invalid keypad key: {}", k))
+}
+
+/// Parse a colour written as "#RRGGBB".
+pub fn parse_colour(s: &str) -> Result<[u8; 3], String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    let channel = |i: usize| hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok([r, g, b]),
+        _ => Err(format!("invalid colour: {}", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config_has_no_conflicts() {
+        assert!(Config::default().conflicts().is_empty());
+    }
+
+    #[test]
+    fn test_empty_config_is_default() {
+        assert_eq!(Config::from_toml(""), Ok(Config::default()));
+    }
+
+    #[test]
+    fn test_rebinding_keys() {
+        let config = Config::from_toml("[keypad]\nA = \"M\"\n\n[hotkeys]\nstep = \"N\"\n").unwrap();
+        assert_eq!(config.keypad[0xA], "M");
+        assert_eq!(config.hotkeys[&Hotkey::Step], "N");
+        assert_eq!(config.hotkeys[&Hotkey::Help], "F1");
+    }
+
+    #[test]
+    fn test_hotkey_conflicting_with_keypad() {
+        let err = Config::from_toml("[hotkeys]\nhelp = \"q\"\n").unwrap_err();
+        assert_eq!(err, "key \"q\" is bound to keypad 4 and hotkey help");
+    }
+
+    #[test]
+    fn test_hotkeys_conflicting_with_each_other() {
+        let err = Config::from_toml("[hotkeys]\nhelp = \"Space\"\n").unwrap_err();
+        assert_eq!(err, "key \"space\" is bound to hotkey step and hotkey help");
+    }
+
+    #[test]
+    fn test_binding_second_keypad() {
+        let config = Config::from_toml("[keypad2]\nF = \"Keypad 9\"\n").unwrap();
+        assert_eq!(config.keypad2[&0xF], "Keypad 9");
+
+        let err = Config::from_toml("[keypad2]\n0 = \"X\"\n").unwrap_err();
+        assert_eq!(err, "key \"x\" is bound to keypad 0 and keypad2 0");
+    }
+
+    #[test]
+    fn test_display_colours() {
+        let toml = "[display]\nborder = \"#102030\"\nborder_width = 6\ndecay = 3\ndecay_rate = 2.5\ncrt = true\n\
+                    vsync = true\n";
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.display.border, [0x10, 0x20, 0x30]);
+        assert_eq!(config.display.border_width, 6);
+        assert_eq!(config.display.decay, 3);
+        assert_eq!(config.display.decay_rate, 2.5);
+        assert!(config.display.crt);
+        assert!(config.display.vsync);
+        assert_eq!(config.display.palette, DEFAULT_PALETTE);
+
+        let toml = "[display]\npalette = [\"#111111\", \"#222222\", \"#333333\", \"abcdef\"]\n";
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.display.palette[0], [0x11; 3]);
+        assert_eq!(config.display.palette[3], [0xAB, 0xCD, 0xEF]);
+        assert_eq!(config.display.border, DEFAULT_BORDER);
+        assert_eq!(config.display.decay_rate, 1.0);
+
+        let err = Config::from_toml("[display]\ndecay_rate = 0.0\n").unwrap_err();
+        assert_eq!(err, "decay_rate must be above 0, not 0");
+    }
+
+    #[test]
+    fn test_invalid_display_colours() {
+        let err = Config::from_toml("[display]\nborder = \"#12345\"\n").unwrap_err();
+        assert_eq!(err, "invalid colour: #12345");
+
+        let err = Config::from_toml("[display]\nborder = \"#12345G\"\n").unwrap_err();
+        assert_eq!(err, "invalid colour: #12345G");
+
+        let err = Config::from_toml("[display]\npalette = [\"#000000\"]\n").unwrap_err();
+        assert_eq!(err, "palette needs 4 colours");
+    }
+
+    #[test]
+    fn test_keypad_key_by_name() {
+        let config = Config::from_toml("[keypad]\n0 = \"Return\"\n").unwrap();
+        assert_eq!(config.keypad_key("return"), Some(0x0));
+        assert_eq!(config.keypad_key("V"), Some(0xF));
+        assert_eq!(config.keypad_key("X"), None);
+    }
+
+    #[test]
+    fn test_named_palette() {
+        let config = Config::from_toml("[display]\npalette = \"amber\"\n").unwrap();
+        assert_eq!(config.display.palette, palettes::load("amber").unwrap());
+        assert!(Config::from_toml("[display]\npalette = \"no-such-palette\"\n").is_err());
+    }
+
+    #[test]
+    fn test_unknown_hotkey() {
+        assert!(Config::from_toml("[hotkeys]\nexplode = \"M\"\n").is_err());
+    }
+
+    #[test]
+    fn test_invalid_keypad_key() {
+        assert!(Config::from_toml("[keypad]\nG = \"M\"\n").is_err());
+    }
+
+    #[test]
+    fn test_help_lists_every_hotkey() {
+        let help = Config::default().help();
+        assert!(help.contains("Space"));
+        assert!(help.contains("F1"));
+    }
+
+    #[test]
+    fn test_limits() {
+        let config = Config::from_toml("[limits]\nroms = [\"pong.ch8\"]\ndebugger = false\n").unwrap();
+        assert_eq!(config.limits.check_rom(Path::new("roms/pong.ch8")), Ok(()));
+        assert!(config.limits.check_rom(Path::new("roms/tetris.ch8")).is_err());
+        assert!(config.limits.check_debugger().is_err());
+
+        let config = Config::default();
+        assert_eq!(config.limits.check_rom(Path::new("roms/tetris.ch8")), Ok(()));
+        assert_eq!(config.limits.check_debugger(), Ok(()));
+    }
+
+    #[test]
+    fn test_speeds() {
+        let toml = "[speed]\ndefault = \"hp48\"\n\n[speed.roms]\n\"ant.ch8\" = \"octo\"\n\"pong.ch8\" = \"12\"\n";
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config.speed.for_rom(Path::new("roms/ant.ch8")), Some(Timing::Fixed(1000)));
+        assert_eq!(config.speed.for_rom(Path::new("roms/pong.ch8")), Some(Timing::Fixed(12)));
+        assert_eq!(config.speed.for_rom(Path::new("roms/tetris.ch8")), Some(Timing::Fixed(30)));
+
+        assert_eq!(Config::default().speed.for_rom(Path::new("roms/tetris.ch8")), None);
+        assert!(Config::from_toml("[speed]\ndefault = \"warp\"\n").is_err());
+    }
+}
@@ -0,0 +1,337 @@
+//! Instruction set conformance matrix. Lists every opcode across CHIP-8, CHIP-8X, hi-res CHIP-8, SCHIP, XO-CHIP and
+//! MegaChip along with whether this interpreter implements it, which quirk options change its behaviour and which tests
+//! cover it. MegaChip opcodes only count as implemented when built with the `megachip` feature.
+//!
+//! Quirks are named as the options which switch them, e.g. shift-vx for `--quirks shift-vx` and key-wait for
+//! `--key-wait`. Behaviours other interpreters differ on but which are fixed here, such as sprites wrapping at the
+//! screen's edges, are listed apart from them.
+
+// Std imports
+use std::fmt;
+use std::str::FromStr;
+
+// Test source used to work out which opcodes are covered. Opcode tests are named `test_opcode_<pattern>`.
+const TEST_SOURCE: &str = include_str!("cpu_tests.rs");
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Platform {
+    Chip8,
+    Chip8X,
+    Chip8Hires,
+    SuperChip,
+    XoChip,
+    MegaChip,
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "CHIP-8"),
+            Platform::Chip8X => write!(f, "CHIP-8X"),
+            Platform::Chip8Hires => write!(f, "CHIP-8 hi-res"),
+            Platform::SuperChip => write!(f, "SCHIP"),
+            Platform::XoChip => write!(f, "XO-CHIP"),
+            Platform::MegaChip => write!(f, "MegaChip"),
+        }
+    }
+}
+
+/// A single row of the opcode table.
+#[derive(Debug)]
+pub struct OpcodeInfo {
+    pub pattern: &'static str,
+    pub mnemonic: &'static str,
+    pub platform: Platform,
+    pub implemented: bool,
+    pub quirks: &'static [&'static str], // Options which change its behaviour.
+    pub fixed: &'static [&'static str],  // Behaviours which differ between interpreters but can't be changed here.
+    pub sample: u16, // An example instruction matching the pattern. Used to check the table is honest.
+}
+
+macro_rules! op {
+    ($pattern:expr, $mnemonic:expr, $platform:ident, $implemented:expr, $quirks:expr, $sample:expr) => {
+        op!($pattern, $mnemonic, $platform, $implemented, $quirks, $sample, fixed: &[])
+    };
+    (
+        $pattern:expr, $mnemonic:expr, $platform:ident, $implemented:expr, $quirks:expr, $sample:expr, fixed: $fixed:expr
+    ) => {
+        OpcodeInfo {
+            pattern: $pattern,
+            mnemonic: $mnemonic,
+            platform: Platform::$platform,
+            implemented: $implemented,
+            quirks: $quirks,
+            fixed: $fixed,
+            sample: $sample,
+        }
+    };
+}
+
+// MegaChip opcodes are only built with the megachip feature.
+const MEGACHIP: bool = cfg!(feature = "megachip");
+
+#[rustfmt::skip]
+pub const OPCODES: &[OpcodeInfo] = &[
+    op!("0NNN", "SYS nnn",          Chip8,     true,  &["sys"], 0x0123),
+    op!("00E0", "CLS",              Chip8,     true,  &[], 0x00E0),
+    op!("00EE", "RET",              Chip8,     true,  &[], 0x00EE),
+    op!("1NNN", "JP nnn",           Chip8,     true,  &[], 0x1200),
+    op!("2NNN", "CALL nnn",         Chip8,     true,  &[], 0x2200),
+    op!("3XKK", "SE Vx, kk",        Chip8,     true,  &[], 0x3000),
+    op!("4XKK", "SNE Vx, kk",       Chip8,     true,  &[], 0x4000),
+    op!("5XY0", "SE Vx, Vy",        Chip8,     true,  &[], 0x5010),
+    op!("6XKK", "LD Vx, kk",        Chip8,     true,  &[], 0x6000),
+    op!("7XKK", "ADD Vx, kk",       Chip8,     true,  &[], 0x7000),
+    op!("8XY0", "LD Vx, Vy",        Chip8,     true,  &[], 0x8010),
+    op!("8XY1", "OR Vx, Vy",        Chip8,     true,  &[], 0x8011),
+    op!("8XY2", "AND Vx, Vy",       Chip8,     true,  &[], 0x8012),
+    op!("8XY3", "XOR Vx, Vy",       Chip8,     true,  &[], 0x8013),
+    op!("8XY4", "ADD Vx, Vy",       Chip8,     true,  &[], 0x8014),
+    op!("8XY5", "SUB Vx, Vy",       Chip8,     true,  &[], 0x8015),
+    op!("8XY6", "SHR Vx, Vy",       Chip8,     true,  &["shift-vx"], 0x8016),
+    op!("8XY7", "SUBN Vx, Vy",      Chip8,     true,  &[], 0x8017),
+    op!("8XYE", "SHL Vx, Vy",       Chip8,     true,  &["shift-vx"], 0x801E),
+    op!("9XY0", "SNE Vx, Vy",       Chip8,     true,  &[], 0x9010),
+    op!("ANNN", "LD I, nnn",        Chip8,     true,  &[], 0xA200),
+    op!("BNNN", "JP V0, nnn",       Chip8,     true,  &[], 0xB200),
+    op!("CXKK", "RND Vx, kk",       Chip8,     true,  &[], 0xC0FF),
+    op!("DXYN", "DRW Vx, Vy, n",    Chip8,     true,  &[], 0xD015, fixed: &["wrap-x", "wrap-y"]),
+    op!("EX9E", "SKP Vx",           Chip8,     true,  &[], 0xE09E),
+    op!("EXA1", "SKNP Vx",          Chip8,     true,  &[], 0xE0A1),
+    op!("FX07", "LD Vx, DT",        Chip8,     true,  &[], 0xF007),
+    op!("FX0A", "LD Vx, K",         Chip8,     true,  &["key-wait"], 0xF00A),
+    op!("FX15", "LD DT, Vx",        Chip8,     true,  &[], 0xF015),
+    op!("FX18", "LD ST, Vx",        Chip8,     true,  &[], 0xF018),
+    op!("FX1E", "ADD I, Vx",        Chip8,     true,  &[], 0xF01E),
+    op!("FX29", "LD F, Vx",         Chip8,     true,  &[], 0xF029),
+    op!("FX33", "LD B, Vx",         Chip8,     true,  &[], 0xF033),
+    op!("FX55", "LD [I], Vx",       Chip8,     true,  &["increment-i"], 0xF055),
+    op!("FX65", "LD Vx, [I]",       Chip8,     true,  &["increment-i"], 0xF065),
+    op!("02A0", "BGC",              Chip8X,    true,  &[], 0x02A0),
+    op!("5XY1", "ADD Vx, Vy",       Chip8X,    true,  &[], 0x5011),
+    op!("BXYN", "COL Vx, Vy, n",    Chip8X,    true,  &[], 0xB012),
+    op!("EXF2", "SKP2 Vx",          Chip8X,    true,  &[], 0xE0F2),
+    op!("EXF5", "SKNP2 Vx",         Chip8X,    true,  &[], 0xE0F5),
+    op!("FXF8", "OUT Vx",           Chip8X,    true,  &[], 0xF0F8),
+    op!("FXFB", "IN Vx",            Chip8X,    false, &[], 0xF0FB),
+    op!("0230", "CLS",              Chip8Hires, true, &[], 0x0230),
+    op!("00CN", "SCD n",            SuperChip, true,  &[], 0x00C1),
+    op!("00FB", "SCR",              SuperChip, true,  &[], 0x00FB),
+    op!("00FC", "SCL",              SuperChip, true,  &[], 0x00FC),
+    op!("00FD", "EXIT",             SuperChip, true,  &[], 0x00FD),
+    op!("00FE", "LOW",              SuperChip, true,  &[], 0x00FE),
+    op!("00FF", "HIGH",             SuperChip, true,  &[], 0x00FF),
+    op!("DXY0", "DRW Vx, Vy, 0",    SuperChip, true,  &[], 0xD010),
+    op!("FX30", "LD HF, Vx",        SuperChip, true,  &[], 0xF030),
+    op!("FX75", "LD R, Vx",         SuperChip, true,  &[], 0xF075),
+    op!("FX85", "LD Vx, R",         SuperChip, true,  &[], 0xF085),
+    op!("00DN", "SCU n",            XoChip,    true,  &[], 0x00D1),
+    op!("5XY2", "SAVE Vx - Vy",     XoChip,    true,  &[], 0x5012),
+    op!("5XY3", "LOAD Vx - Vy",     XoChip,    true,  &[], 0x5013),
+    op!("F000", "LD I, long nnnn",  XoChip,    true,  &[], 0xF000),
+    op!("FN01", "PLANE n",          XoChip,    true,  &[], 0xF101),
+    op!("F002", "AUDIO",            XoChip,    false, &[], 0xF002),
+    op!("FX3A", "PITCH Vx",         XoChip,    false, &[], 0xF03A),
+    op!("0010", "MEGAOFF",          MegaChip,  MEGACHIP, &[], 0x0010),
+    op!("0011", "MEGAON",           MegaChip,  MEGACHIP, &[], 0x0011),
+    op!("00BN", "SCRU n",           MegaChip,  MEGACHIP, &[], 0x00B1),
+    op!("01NN", "LDHI I, nnnnnn",   MegaChip,  MEGACHIP, &[], 0x0100),
+    op!("02NN", "LDPAL nn",         MegaChip,  MEGACHIP, &[], 0x0201),
+    op!("03NN", "SPRW nn",          MegaChip,  MEGACHIP, &[], 0x0302),
+    op!("04NN", "SPRH nn",          MegaChip,  MEGACHIP, &[], 0x0402),
+    op!("05NN", "ALPHA nn",         MegaChip,  MEGACHIP, &[], 0x05FF),
+    op!("060N", "DIGISND n",        MegaChip,  MEGACHIP, &[], 0x0600),
+    op!("0700", "STOPSND",          MegaChip,  MEGACHIP, &[], 0x0700),
+    op!("080N", "BMODE n",          MegaChip,  MEGACHIP, &[], 0x0801),
+    op!("09NN", "CCOL nn",          MegaChip,  MEGACHIP, &[], 0x0902),
+];
+
+/// The implemented table entry an opcode belongs to. Where more than one pattern matches, e.g. 00E0 and 0NNN, the
+/// most specific wins, and between equally specific ones, e.g. BNNN and CHIP-8X's BXYN, the first in the table.
+pub fn family(opcode: u16) -> Option<&'static OpcodeInfo> {
+    let hex = format!("{:04X}", opcode);
+    let literal = |op: &&OpcodeInfo| op.pattern.chars().filter(char::is_ascii_hexdigit).count();
+
+    OPCODES
+        .iter()
+        .filter(|op| op.implemented)
+        .filter(|op| {
+            let mut pairs = op.pattern.chars().zip(hex.chars());
+            pairs.all(|(p, h)| !p.is_ascii_hexdigit() || p == h)
+        })
+        .rev()
+        .max_by_key(literal)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReportFormat {
+    Markdown,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(format!("unknown report format: {}", s)),
+        }
+    }
+}
+
+/// Names of every `#[test]` function in the CPU tests.
+fn test_names() -> Vec<&'static str> {
+    let mut names = Vec::new();
+    let mut is_test = false;
+
+    for line in TEST_SOURCE.lines().map(str::trim) {
+        if line == "#[test]" {
+            is_test = true;
+        } else if is_test && line.starts_with("fn ") {
+            if let Some(end) = line.find('(') {
+                names.push(&line[3..end]);
+            }
+            is_test = false;
+        }
+    }
+
+    names
+}
+
+/// Tests which cover the given opcode.
+fn covering_tests(op: &OpcodeInfo, tests: &[&'static str]) -> Vec<&'static str> {
+    let needle = format!("opcode_{}", op.pattern.to_lowercase());
+    tests.iter().copied().filter(|name| name.contains(&needle)).collect()
+}
+
+/// Generate the conformance report in the requested format.
+pub fn report(format: ReportFormat) -> String {
+    let tests = test_names();
+
+    match format {
+        ReportFormat::Markdown => markdown_report(&tests),
+        ReportFormat::Json => json_report(&tests),
+    }
+}
+
+fn markdown_report(tests: &[&'static str]) -> String {
+    let mut out = String::from("# Instruction Set Conformance\n\n");
+
+    let platforms = [
+        Platform::Chip8,
+        Platform::Chip8X,
+        Platform::Chip8Hires,
+        Platform::SuperChip,
+        Platform::XoChip,
+        Platform::MegaChip,
+    ];
+
+    for platform in &platforms {
+        let ops: Vec<&OpcodeInfo> = OPCODES.iter().filter(|op| op.platform == *platform).collect();
+        let implemented = ops.iter().filter(|op| op.implemented).count();
+        out += &format!("- {}: {}/{} implemented\n", platform, implemented, ops.len());
+    }
+
+    out += "\n| Opcode | Mnemonic | Platform | Implemented | Quirks | Fixed | Tests |\n";
+    out += "|--------|----------|----------|-------------|--------|-------|-------|\n";
+
+    let list = |items: &[&str]| if items.is_empty() { "-".to_string() } else { items.join(", ") };
+    for op in OPCODES {
+        out += &format!(
+            "| {} | {} | {} | {} | {} | {} | {} |\n",
+            op.pattern,
+            op.mnemonic,
+            op.platform,
+            if op.implemented { "yes" } else { "no" },
+            list(op.quirks),
+            list(op.fixed),
+            list(&covering_tests(op, tests)),
+        );
+    }
+
+    out
+}
+
+fn json_report(tests: &[&'static str]) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    let list = |items: &[&str]| format!("[{}]", items.iter().map(|s| quote(s)).collect::<Vec<_>>().join(", "));
+
+    let rows: Vec<String> = OPCODES
+        .iter()
+        .map(|op| {
+            format!(
+                "  {{\"opcode\": {}, \"mnemonic\": {}, \"platform\": {}, \"implemented\": {}, \"quirks\": {}, \
+                 \"fixed\": {}, \"tests\": {}}}",
+                quote(op.pattern),
+                quote(op.mnemonic),
+                quote(&op.platform.to_string()),
+                op.implemented,
+                list(op.quirks),
+                list(op.fixed),
+                list(&covering_tests(op, tests)),
+            )
+        })
+        .collect();
+
+    format!("[\n{}\n]\n", rows.join(",\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finding_opcode_tests() {
+        let tests = test_names();
+        let op = OPCODES.iter().find(|op| op.pattern == "8XY4").unwrap();
+        assert_eq!(covering_tests(op, &tests), vec!["test_opcode_8xy4", "test_opcode_8xy4_writes_vf_last"]);
+    }
+
+    #[test]
+    fn test_sample_matches_pattern() {
+        for op in OPCODES {
+            let sample = format!("{:04X}", op.sample);
+            for (p, s) in op.pattern.chars().zip(sample.chars()) {
+                if p.is_ascii_hexdigit() {
+                    assert_eq!(p, s, "{} sample {} does not match", op.pattern, sample);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_finding_families() {
+        assert_eq!(family(0x00E0).unwrap().pattern, "00E0");
+        assert_eq!(family(0x0A12).unwrap().pattern, "0NNN");
+        assert_eq!(family(0xD125).unwrap().pattern, "DXYN");
+        assert_eq!(family(0xB123).unwrap().pattern, "BNNN");
+        assert_eq!(family(0xF165).unwrap().pattern, "FX65");
+        assert!(family(0x5124).is_none());
+    }
+
+    #[test]
+    fn test_quirks_are_named_as_options() {
+        for op in OPCODES {
+            for name in op.quirks.iter().chain(op.fixed) {
+                assert!(name.chars().all(|c| c.is_ascii_lowercase() || c == '-'), "{} lists {}", op.pattern, name);
+            }
+        }
+        let drw = OPCODES.iter().find(|op| op.pattern == "DXYN").unwrap();
+        assert!(drw.quirks.is_empty());
+        assert_eq!(drw.fixed, ["wrap-x", "wrap-y"]);
+        assert!(report(ReportFormat::Json).contains("\"quirks\": [], \"fixed\": [\"wrap-x\", \"wrap-y\"]"));
+    }
+
+    #[test]
+    fn test_reports_list_every_opcode() {
+        let markdown = report(ReportFormat::Markdown);
+        let json = report(ReportFormat::Json);
+
+        for op in OPCODES {
+            assert!(markdown.contains(&format!("| {} |", op.pattern)));
+            assert!(json.contains(&format!("\"opcode\": \"{}\"", op.pattern)));
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Code and data coverage. While tracking, the CPU marks every address it fetches an instruction from and every
+//! address it reads as data, e.g. sprites drawn by DXYN or values loaded by FX65. The map is saved as text, one line
+//! per 32 addresses:
+//!
+//! ```text
+//! # C executed, D read as data, B both, . neither
+//! 200  CCCCCCCCCCCCCCCCCCCC............
+//! 220  DDDDDDDD........................
+//! ```
+//!
+//! The disassembler reads it back to list bytes which were only ever read as data instead of as instructions.
+
+// Std imports
+use std::cell::Cell;
+use std::fs;
+use std::path::Path;
+
+const EXECUTED: u8 = 1;
+const READ: u8 = 2;
+
+// Addresses on each line of a saved map.
+const PER_LINE: usize = 32;
+
+const LEGEND: &str = "# C executed, D read as data, B both, . neither";
+
+/// How every address in memory has been used. The marks are Cells so reads can record them without needing mutable
+/// access, like watchpoint hits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Coverage {
+    marks: Vec<Cell<u8>>,
+}
+
+impl Coverage {
+    pub fn new(memory_size: usize) -> Self {
+        Self {
+            marks: vec![Cell::new(0); memory_size],
+        }
+    }
+
+    /// Mark an instruction fetched from an address.
+    pub fn execute(&self, addr: usize) {
+        self.mark(addr, EXECUTED);
+        self.mark(addr + 1, EXECUTED);
+    }
+
+    /// Mark a byte read as data.
+    pub fn read(&self, addr: usize) {
+        self.mark(addr, READ);
+    }
+
+    fn mark(&self, addr: usize, mark: u8) {
+        if let Some(cell) = self.marks.get(addr % self.marks.len().max(1)) {
+            cell.set(cell.get() | mark);
+        }
+    }
+
+    fn marks(&self, addr: usize) -> u8 {
+        self.marks.get(addr).map_or(0, Cell::get)
+    }
+
+    pub fn executed(&self, addr: usize) -> bool {
+        self.marks(addr) & EXECUTED != 0
+    }
+
+    /// Whether an address was read as data and never executed.
+    pub fn data_only(&self, addr: usize) -> bool {
+        self.marks(addr) == READ
+    }
+
+    /// The map from `start` to the last address used, in the format above.
+    pub fn map(&self, start: usize) -> String {
+        let end = self.marks.iter().rposition(|mark| mark.get() != 0).map_or(start, |last| last + 1).max(start);
+        let mut out = format!("{}\n", LEGEND);
+
+        for line in (start..end).step_by(PER_LINE) {
+            let marks: String = (line..(line + PER_LINE).min(end))
+                .map(|addr| match self.marks(addr) {
+                    EXECUTED => 'C',
+                    READ => 'D',
+                    0 => '.',
+                    _ => 'B',
+                })
+                .collect();
+            out += &format!("{:03X}  {}\n", line, marks);
+        }
+        out
+    }
+
+    pub fn save(&self, path: &Path, start: usize) -> Result<(), String> {
+        fs::write(path, self.map(start)).map_err(|e| format!("unable to write {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = Vec::new();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let invalid = || format!("invalid coverage line: {}", line);
+            let (addr, marks) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+            let addr = usize::from_str_radix(addr, 16).map_err(|_| invalid())?;
+
+            let marks = marks
+                .trim()
+                .chars()
+                .map(|c| match c {
+                    'C' => Ok(EXECUTED),
+                    'D' => Ok(READ),
+                    'B' => Ok(EXECUTED | READ),
+                    '.' => Ok(0),
+                    _ => Err(invalid()),
+                })
+                .collect::<Result<Vec<u8>, String>>()?;
+            lines.push((addr, marks));
+        }
+
+        let size = lines.iter().map(|(addr, marks)| addr + marks.len()).max().unwrap_or(0);
+        let coverage = Self::new(size);
+        for (addr, marks) in lines {
+            for (offset, mark) in marks.into_iter().enumerate() {
+                coverage.marks[addr + offset].set(mark);
+            }
+        }
+        Ok(coverage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maps_round_trip() {
+        let coverage = Coverage::new(4096);
+        for addr in (0x200..0x214).step_by(2) {
+            coverage.execute(addr);
+        }
+        for addr in 0x220..0x228 {
+            coverage.read(addr);
+        }
+        coverage.read(0x200);
+
+        let map = coverage.map(0x200);
+        assert_eq!(
+            map,
+            format!("{}\n200  B{}{}\n220  DDDDDDDD\n", LEGEND, "C".repeat(19), ".".repeat(12))
+        );
+
+        let loaded = Coverage::parse(&map).unwrap();
+        assert!(loaded.executed(0x200) && !loaded.data_only(0x200));
+        assert!(loaded.data_only(0x227));
+        assert!(!loaded.executed(0x214) && !loaded.data_only(0x214));
+        assert_eq!(loaded.map(0x200), map);
+    }
+
+    #[test]
+    fn test_invalid_maps() {
+        assert!(Coverage::parse("200  CCX").is_err());
+        assert!(Coverage::parse("zz  CC").is_err());
+    }
+}
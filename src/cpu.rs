@@ -1,13 +1,73 @@
 // Self imports
-use crate::frame_buffer::FrameBuffer;
-use crate::keypad::Keypad;
+use crate::chip8x::{self, ColourZones};
+use crate::coverage::Coverage;
+use crate::frame_buffer::{FrameBuffer, PLANES, WIDTH};
+use crate::decode::{decode, Instruction};
+use crate::disasm::{self, Line};
+use crate::draw_hit::DrawHit;
+use crate::halt::Halt;
+use crate::hooks::{Hooks, Opcode, Registers, Step};
+use crate::journal::{Change, Recorder};
+use crate::keypad::{KeyWait, Keypad};
+use crate::random::{Random, RngSource};
+use crate::rpl::RplFlags;
+use crate::protect::{ProtectedWrite, WriteProtect};
+use crate::quirks::Quirks;
+use crate::sys::SysHandler;
+use crate::uninit::{Initialized, UninitRead, UninitReads};
+use crate::watch::{Access, WatchHit, Watchpoints};
+use crate::write_log::{LoggedWrite, WriteLog};
+#[cfg(feature = "megachip")]
+use crate::megachip::{self, Blend, MegaChip};
+use crate::variant::Variant;
+use crate::vip_timing;
 
-use crate::OFFSET;
 use crate::WRAP_X;
 use crate::WRAP_Y;
 
+// Std imports
+use std::cell::Cell;
+use std::collections::BTreeSet;
+
 // External imports
-use rand::Rng;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+// Hi-res CHIP-8 ROMs open with a jump into the VIP interpreter's hi-res patch at 0x260, which sets up the 64x64 screen
+// and then runs the program from 0x2C0. There is no patch to run here so the jump goes straight to the program.
+const HIRES_ENTRY: [u8; 2] = [0x12, 0x60];
+const HIRES_PROGRAM: [u8; 2] = [0x12, 0xC0];
+
+// Size of SCHIP's hires screen.
+const HIRES_WIDTH: usize = 128;
+const HIRES_HEIGHT: usize = 64;
+
+/// 0 to F. 5 Bytes per character. Index in memory is the character's hex value multiplied by 5.
+#[rustfmt::skip]
+pub const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0,
+    0x20, 0x60, 0x20, 0x20, 0x70,
+    0xF0, 0x10, 0xF0, 0x80, 0xF0,
+    0xF0, 0x10, 0xF0, 0x10, 0xF0,
+    0x90, 0x90, 0xF0, 0x10, 0x10,
+    0xF0, 0x80, 0xF0, 0x10, 0xF0,
+    0xF0, 0x80, 0xF0, 0x90, 0xF0,
+    0xF0, 0x10, 0x20, 0x40, 0x40,
+    0xF0, 0x90, 0xF0, 0x90, 0xF0,
+    0xF0, 0x90, 0xF0, 0x10, 0xF0,
+    0xF0, 0x90, 0xF0, 0x90, 0x90,
+    0xE0, 0x90, 0xE0, 0x90, 0xE0,
+    0xF0, 0x80, 0x80, 0x80, 0xF0,
+    0xE0, 0x90, 0x90, 0x90, 0xE0,
+    0xF0, 0x80, 0xF0, 0x80, 0xF0,
+    0xF0, 0x80, 0xF0, 0x80, 0x80
+];
+
+// SCHIP's 8x10 digits are kept straight after the small font.
+const BIG_FONT: usize = 0x50;
+
+// First byte after the fonts. Write protection covers from here up to the program.
+const FONT_END: usize = BIG_FONT + 100;
 
 /// The three things a Program Counter can do...
 enum ProgramCounter {
@@ -26,11 +86,28 @@ impl ProgramCounter {
     }
 }
 
-/// Represents the CPU of a computer that could run Chip8 programs.
-#[derive(Debug, PartialEq)]
+/// Whether the CPU is executing instructions or stopped by FX0A.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum State {
+    Running,
+
+    /// Waiting for a key to be pressed and released before storing it in Vx. Holds the keys being ignored because they
+    /// were held when FX0A started, and the key once it has been pressed.
+    WaitingForKey { x: usize, ignored: u16, pressed: Option<u8> },
+}
+
+/// Represents the CPU of a computer that could run Chip8 programs. Serializing it captures the whole machine, memory,
+/// screen and keypads included, along with whether it is recording and the changes recorded but not yet taken.
+/// Settings which belong to the host (the SYS handler, key wait, watchpoints, hooks and RPL flags) are left out and come
+/// back as defaults.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct CPU {
     // Memory consists of 4096 bytes. 0x000 to 0x1FF for interpreter (0x050 to 0x0A0 for font set). 0x200 onwards for program.
-    memory: [u8; 4096],
+    // Some variants address more. Out of range addresses wrap back around to the start.
+    memory: Vec<u8>,
+
+    // Program as it was loaded, before it had a chance to modify itself. Kept so a reset can load it again.
+    rom: Vec<u8>,
 
     // Group of 16 8-bit registers (0x0 to 0xF). Register V[F] is a flag not for use by programs.
     v: [u8; 16],
@@ -39,140 +116,1137 @@ pub struct CPU {
     stack: [usize; 16],
     sp: usize,
 
-    // 16-bit register used to store memory addresses. Only 4k of memory so only 12 bits are used.
+    // 16-bit register used to store memory addresses. Most variants only have 4k of memory, so only 12 bits are used
+    // and addresses beyond it wrap. XO-CHIP uses all 16 bits.
     i: usize,
 
     // 16-bit register used to store address of currently executing instruction. Using usize to reduce number of casts.
     pc: usize,
 
     // Two 8-bit registers used as timers. One for Delay, one for Sound. Decrement at 60Hz when set.
-    delay_counter: u8,
     delay_timer: u8,
     sound_timer: u8,
 
-    // FrameBuffer is monochrome and 64x32.
+    // Running, or stopped until a key is pressed and released.
+    state: State,
+
+    // Machine cycles overspent by the last instruction of the previous VIP-timed frame.
+    cycle_debt: u32,
+
+    // Machine the program was written for. Decides where programs load and the screen size.
+    variant: Variant,
+
+    // FrameBuffer is 64 pixels wide. Height depends on the variant.
     frame: FrameBuffer,
 
     // 16 possible keys. Mapping found in Keycode file.
     keypad: Keypad,
+
+    // CHIP-8X only. A second keypad, the colour board's zones and the last value written to the output port.
+    keypad2: Keypad,
+    colour_zones: Option<ColourZones>,
+    port_out: u8,
+
+    // Source of CXKK's random bytes. Kept in the CPU so a suspended session resumes with the same sequence.
+    rng: Random,
+
+    // What 0NNN does. Chosen by the host rather than the program, so it isn't part of a suspended session.
+    #[serde(skip)]
+    sys: SysHandler,
+
+    // When FX0A accepts a key. Chosen by the host, like the SYS handler.
+    #[serde(skip)]
+    key_wait: KeyWait,
+
+    // Behaviours interpreters disagree on. Chosen by the host, or switched on by quirk detection while running.
+    #[serde(skip)]
+    quirks: Quirks,
+
+    // Whether writes to the interpreter area below the program are allowed. Chosen by the host, like the key wait.
+    #[serde(skip)]
+    write_protect: WriteProtect,
+
+    // Whether reads of bytes the program never initialized are reported, which bytes have been, and the first read to
+    // break on since the host last checked. Chosen by the host like write protection.
+    #[serde(skip)]
+    uninit_reads: UninitReads,
+    #[serde(skip)]
+    initialized: Option<Initialized>,
+    #[serde(skip)]
+    uninit_hit: Cell<Option<UninitRead>>,
+
+    // Debugger watchpoints, and the first access to hit one since the host last checked. The hit is a Cell so reads
+    // can record it without needing mutable access.
+    #[serde(skip)]
+    watchpoints: Watchpoints,
+    #[serde(skip)]
+    watch_hit: Cell<Option<WatchHit>>,
+
+    // Memory ranges whose writes the debugger is keeping a history of, and the writes.
+    #[serde(skip)]
+    write_log: WriteLog,
+
+    // Debugger breakpoints, checked before each instruction of a frame. A frame stops before the instruction at one,
+    // remembering it so the next frame passes over it, and the hit waits for the host like a watchpoint hit.
+    #[serde(skip)]
+    breakpoints: BTreeSet<usize>,
+    #[serde(skip)]
+    stopped_at: Option<usize>,
+    #[serde(skip)]
+    break_hit: Option<usize>,
+
+    // Whether the debugger is breaking on draws, and the first draw since the host last checked. Frames stop after it.
+    #[serde(skip)]
+    break_on_draw: bool,
+    #[serde(skip)]
+    draw_hit: Option<DrawHit>,
+
+    // Which addresses have been executed or read as data, while the host is tracking coverage.
+    #[serde(skip)]
+    coverage: Option<Coverage>,
+
+    // Host callbacks run around every executed instruction.
+    #[serde(skip)]
+    hooks: Hooks,
+
+    // SCHIP's RPL user flags, used by FX75 and FX85. Persisted by the host rather than saved with the session.
+    #[serde(skip)]
+    rpl: RplFlags,
+
+    // Changes made since the host last took them, while the host is recording. See the journal module.
+    recorder: Option<Box<Recorder>>,
+
+    // MegaChip only. The MegaChip screen, palette, sprite settings and sound.
+    #[cfg(feature = "megachip")]
+    megachip: Option<MegaChip>,
 }
 
 impl Default for CPU {
     fn default() -> Self {
+        Self::new(Variant::default())
+    }
+}
+
+impl CPU {
+    pub fn new(variant: Variant) -> Self {
         let mut cpu = Self {
-            memory: [0; 4096],
+            memory: vec![0; variant.memory_size()],
+            rom: Vec::new(),
             v: [0; 16],
             sp: 0,
             stack: [usize::MAX; 16],
             i: 0,
-            pc: OFFSET,
-            delay_counter: 0,
+            pc: variant.program_start(),
             delay_timer: 0,
             sound_timer: 0,
-            frame: FrameBuffer::new(WRAP_X, WRAP_Y),
+            state: State::Running,
+            cycle_debt: 0,
+            variant,
+            frame: FrameBuffer::with_height(variant.screen_height(), WRAP_X, WRAP_Y),
             keypad: Keypad::new(),
+            keypad2: Keypad::new(),
+            colour_zones: None,
+            port_out: 0,
+            rng: Random::default(),
+            sys: SysHandler::default(),
+            key_wait: KeyWait::default(),
+            quirks: Quirks::default(),
+            write_protect: WriteProtect::default(),
+            uninit_reads: UninitReads::default(),
+            initialized: None,
+            uninit_hit: Cell::new(None),
+            watchpoints: Watchpoints::default(),
+            watch_hit: Cell::new(None),
+            write_log: WriteLog::default(),
+            breakpoints: BTreeSet::new(),
+            stopped_at: None,
+            break_hit: None,
+            break_on_draw: false,
+            draw_hit: None,
+            coverage: None,
+            hooks: Hooks::default(),
+            rpl: RplFlags::default(),
+            recorder: None,
+            #[cfg(feature = "megachip")]
+            megachip: None,
         };
 
+        if variant == Variant::Chip8X {
+            cpu.colour_zones = Some(ColourZones::new(variant.screen_height()));
+        }
+
+        #[cfg(feature = "megachip")]
+        if variant == Variant::MegaChip {
+            cpu.megachip = Some(MegaChip::default());
+        }
+
         cpu.load_font();
         cpu
     }
-}
 
-impl CPU {
+    /// Execute one instruction, or check the keypad if waiting for a key. Does nothing once the program has halted.
     pub fn cycle(&mut self) {
-        if self.delay_timer > 0 {
-            self.delay_counter += 1;
-            if self.delay_counter == 9 {
-                self.delay_timer -= 1;
-                self.delay_counter = 0;
+        if self.waiting_for_key() {
+            self.wait_for_key();
+        } else if self.halt().is_none() {
+            let instruction = self.fetch();
+            self.execute_hooked(instruction);
+        }
+        self.commit();
+    }
+
+    /// Execute a frame's worth of instructions and then tick the timers once. The frame ends early if a breakpoint or
+    /// watchpoint is hit, after a draw or uninitialized read while breaking on them, if the program is waiting for a
+    /// key, as nothing can happen until the keypad changes, or if it has halted.
+    pub fn step_frame(&mut self, ipf: usize) {
+        for _ in 0..ipf {
+            if self.at_breakpoint() {
+                break;
+            }
+            self.cycle();
+            let hit = self.watch_hit.get().is_some() || self.uninit_hit.get().is_some() || self.draw_hit.is_some();
+            if hit || self.waiting_for_key() || self.halt().is_some() {
+                break;
             }
         }
 
-        self.execute_instruction(self.get_instruction())
+        self.tick_timers();
+    }
+
+    /// Execute up to n instructions for the debugger, without ticking the timers. Stops early, as a frame does, before
+    /// an instruction at a breakpoint other than the first, after a watchpoint hit, a draw or uninitialized read while
+    /// breaking on them, or if the program is waiting for a key or has halted. Returns how many were executed.
+    pub fn step(&mut self, n: usize) -> usize {
+        for executed in 0..n {
+            if self.halt().is_some() || executed > 0 && self.at_breakpoint() {
+                return executed;
+            }
+            self.cycle();
+            let hit = self.watch_hit.get().is_some() || self.uninit_hit.get().is_some() || self.draw_hit.is_some();
+            if hit || self.waiting_for_key() {
+                return executed + 1;
+            }
+        }
+        n
+    }
+
+    /// Execute instructions until a COSMAC VIP frame's worth of machine cycles have been used, then tick the timers
+    /// once. Any cycles overspent by the final instruction are taken from the next frame, as is the part of a draw
+    /// after the display interrupt it waits for, which ends the frame. The frame also ends early if a breakpoint or
+    /// watchpoint is hit, after a draw or uninitialized read while breaking on them, or if the program has halted.
+    pub fn step_vip_frame(&mut self) {
+        let mut cycles = self.cycle_debt;
+
+        while cycles < vip_timing::CYCLES_PER_FRAME {
+            if self.halt().is_some() {
+                cycles = cycles.max(vip_timing::CYCLES_PER_FRAME);
+                break;
+            }
+
+            if self.waiting_for_key() {
+                self.wait_for_key();
+                self.commit();
+                if self.waiting_for_key() {
+                    cycles = cycles.max(vip_timing::CYCLES_PER_FRAME);
+                    break;
+                }
+            }
+
+            if self.at_breakpoint() {
+                break;
+            }
+
+            let (pc, opcode) = (self.pc, self.fetch());
+            let cost = self.vip_cost(opcode);
+            self.execute_hooked(opcode);
+            self.commit();
+
+            cycles += cost.cycles;
+            if self.pc == (pc + 4) % self.memory.len() {
+                cycles += cost.skip;
+            }
+            // The rest of the instruction runs in the next frame, once the display interrupt has been handled.
+            if let Some(after) = cost.after_interrupt {
+                cycles = cycles.max(vip_timing::CYCLES_PER_FRAME) + after;
+                break;
+            }
+
+            if self.watch_hit.get().is_some() || self.uninit_hit.get().is_some() || self.draw_hit.is_some() {
+                break;
+            }
+        }
+
+        let debt = cycles.saturating_sub(vip_timing::CYCLES_PER_FRAME);
+        self.change(Change::CycleDebt { before: self.cycle_debt, after: debt });
+        self.tick_timers();
+    }
+
+    // What an instruction will cost on the VIP, from the state it starts in.
+    fn vip_cost(&self, opcode: usize) -> vip_timing::Cost {
+        let screen = match self.frame.width() {
+            64 => self.frame.plane(0),
+            _ => &[],
+        };
+        let machine = vip_timing::Machine { v: &self.v, i: self.i, memory: &self.memory, screen };
+        vip_timing::cost(&decode(opcode as u16, self.variant), &machine)
+    }
+
+    /// Why the program has finished, if it has: it has exited, is jumping to itself or has run into empty memory. Either
+    /// way it can never do anything again, so no more instructions are executed. Doesn't trigger watchpoints.
+    ///
+    /// Empty memory is SYS 000, so it isn't a halt while a SYS callback is set to handle it.
+    pub fn halt(&self) -> Option<Halt> {
+        if self.waiting_for_key() {
+            return None;
+        }
+
+        let instruction = decode(self.get_instruction() as u16, self.variant);
+        match Halt::at(self.pc % self.memory.len(), instruction) {
+            Some(Halt::EmptyMemory { .. }) if matches!(self.sys, SysHandler::Callback(_)) => None,
+            halt => halt,
+        }
+    }
+
+    /// True while FX0A is waiting for a key. Nothing is executed until one is pressed and released, so the host can
+    /// sleep until the keypad changes.
+    pub fn waiting_for_key(&self) -> bool {
+        matches!(self.state, State::WaitingForKey { .. })
+    }
+
+    // Remember the first key pressed while waiting, and finish waiting once it is released, or straight away if keys
+    // count on press. Ignored keys stop being ignored once they are released.
+    fn wait_for_key(&mut self) {
+        if let State::WaitingForKey { x, ignored, pressed } = self.state {
+            let held = self.keypad.pressed();
+            let ignored = ignored & held;
+
+            match pressed {
+                None => match (0..16).find(|k| (held & !ignored) >> k & 1 == 1) {
+                    Some(k) if self.key_wait == KeyWait::Press => self.finish_waiting(x, k),
+                    pressed => self.set_state(State::WaitingForKey { x, ignored, pressed }),
+                },
+                Some(k) if held >> k & 1 == 0 => self.finish_waiting(x, k),
+                Some(_) => {}
+            }
+        }
     }
 
-    /// Read a Vec<u8> ROM into memory.
-    pub fn load(&mut self, rom: Vec<u8>) {
-        self.memory[OFFSET..OFFSET + rom.len()].copy_from_slice(&rom); // Load ROM into program memory.
+    fn finish_waiting(&mut self, x: usize, k: u8) {
+        self.set_v(x, k);
+        self.set_state(State::Running);
     }
 
-    /// Get frame buffer
-    pub fn get_framebuffer(&mut self) -> Vec<u64> {
-        self.frame.get_buffer()
+    /// Reseed the random number generator so CXKK produces a repeatable sequence.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Random::seeded(seed);
+    }
+
+    /// Choose where CXKK's random bytes come from.
+    pub fn set_rng(&mut self, rng: Random) {
+        self.rng = rng;
+    }
+
+    pub fn watchpoints_mut(&mut self) -> &mut Watchpoints {
+        &mut self.watchpoints
+    }
+
+    pub fn write_log(&self) -> &WriteLog {
+        &self.write_log
+    }
+
+    pub fn write_log_mut(&mut self) -> &mut WriteLog {
+        &mut self.write_log
+    }
+
+    /// The first access to hit a watchpoint since the last call. Hits are recorded once the instruction making the
+    /// access has finished.
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    pub fn breakpoints_mut(&mut self) -> &mut BTreeSet<usize> {
+        &mut self.breakpoints
+    }
+
+    /// The address of the breakpoint a frame stopped at since the last call, if any. The program counter is left on it.
+    pub fn take_break_hit(&mut self) -> Option<usize> {
+        self.break_hit.take()
+    }
+
+    /// Stop frames after every sprite drawn, for the debugger.
+    pub fn set_break_on_draw(&mut self, on: bool) {
+        self.break_on_draw = on;
+        self.draw_hit = None;
+    }
+
+    pub fn break_on_draw(&self) -> bool {
+        self.break_on_draw
+    }
+
+    /// The first sprite drawn since the last call while breaking on draws.
+    pub fn take_draw_hit(&mut self) -> Option<DrawHit> {
+        self.draw_hit.take()
+    }
+
+    /// Whether a breakpoint, watchpoint, draw or uninitialized read has stopped the last frame and not been taken yet.
+    pub fn has_hit(&self) -> bool {
+        let hit = self.watch_hit.get().is_some() || self.uninit_hit.get().is_some() || self.draw_hit.is_some();
+        hit || self.break_hit.is_some()
+    }
+
+    // Whether the next instruction is at a breakpoint, other than the one last stopped at. Records the hit.
+    fn at_breakpoint(&mut self) -> bool {
+        if self.stopped_at == Some(self.pc) || !self.breakpoints.contains(&self.pc) {
+            return false;
+        }
+
+        self.stopped_at = Some(self.pc);
+        self.break_hit = Some(self.pc);
+        true
+    }
+
+    /// Start recording which addresses are executed and which are read as data.
+    pub fn track_coverage(&mut self) {
+        self.coverage = Some(Coverage::new(self.memory.len()));
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Choose where FX75 and FX85 keep the RPL user flags.
+    pub fn set_rpl_flags(&mut self, rpl: RplFlags) {
+        self.rpl = rpl;
+    }
+
+    pub fn rpl_flags(&self) -> &RplFlags {
+        &self.rpl
+    }
+
+    /// Choose when FX0A accepts a key.
+    pub fn set_key_wait(&mut self, key_wait: KeyWait) {
+        self.key_wait = key_wait;
+    }
+
+    /// Choose how the instructions interpreters disagree on behave.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Choose what happens to writes between the fonts and the program.
+    pub fn set_write_protect(&mut self, write_protect: WriteProtect) {
+        self.write_protect = write_protect;
+    }
+
+    /// Choose what happens when bytes the program never initialized are read. Tracking starts from the memory as it
+    /// is now, and starts again whenever a ROM is loaded.
+    pub fn set_uninit_reads(&mut self, uninit_reads: UninitReads) {
+        self.uninit_reads = uninit_reads;
+        self.initialized = match uninit_reads {
+            UninitReads::Off => None,
+            UninitReads::Log | UninitReads::Break => {
+                Some(Initialized::new(&self.memory, self.variant.program_start(), self.rom.len()))
+            }
+        };
+    }
+
+    /// The first uninitialized read to break on since the last call.
+    pub fn take_uninit_read(&mut self) -> Option<UninitRead> {
+        self.uninit_hit.take()
+    }
+
+    /// Choose what 0NNN does.
+    pub fn set_sys_handler(&mut self, handler: SysHandler) {
+        self.sys = handler;
+    }
+
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    /// Hooks run before and after every executed instruction.
+    pub fn hooks_mut(&mut self) -> &mut Hooks {
+        &mut self.hooks
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            v: self.v,
+            i: self.i,
+            sp: self.sp,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+        }
+    }
+
+    /// Replace the registers and PC for the debugger. Like poke, the change isn't recorded for rewinding. The stack
+    /// pointer must be within the stack.
+    pub fn set_registers(&mut self, registers: Registers, pc: usize) {
+        let Registers { v, i, sp, delay_timer, sound_timer } = registers;
+        assert!(sp <= self.stack.len(), "stack pointer {} is beyond the stack", sp);
+
+        self.v = v;
+        self.i = i;
+        self.sp = sp;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.pc = pc % self.memory.len();
+    }
+
+    /// Address of the next instruction.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Return addresses of the subroutines being run, outermost first.
+    pub fn stack(&self) -> &[usize] {
+        &self.stack[..self.sp]
+    }
+
+    /// Read a byte of memory without triggering watchpoints, for debugger views. Addresses wrap like reads do.
+    pub fn peek(&self, addr: usize) -> u8 {
+        self.memory[addr % self.memory.len()]
+    }
+
+    /// Write a byte of memory for the debugger. Unlike writes by instructions it isn't watched, write protected or
+    /// recorded for rewinding, so it can patch the program itself.
+    pub fn poke(&mut self, addr: usize, byte: u8) {
+        let len = self.memory.len();
+        self.memory[addr % len] = byte;
+        if let Some(initialized) = &self.initialized {
+            initialized.write(addr % len);
+        }
+    }
+
+    /// Bytes of memory, which depends on the variant.
+    pub fn memory_size(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// Read register Vx. For hosts handling 0NNN.
+    pub fn register(&self, x: usize) -> u8 {
+        self.v[x]
+    }
+
+    /// Write register Vx. For hosts handling 0NNN.
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.set_v(x, value);
+    }
+
+    /// Decrement the delay and sound timers. Should be called at 60Hz, independent of how fast the CPU is cycled.
+    pub fn tick_timers(&mut self) {
+        self.set_delay_timer(self.delay_timer.saturating_sub(1));
+        self.set_sound_timer(self.sound_timer.saturating_sub(1));
+    }
+
+    /// Read a Vec<u8> ROM into memory. Fails if it doesn't fit between the variant's program start and the end of
+    /// memory, e.g. a 3K ROM for ETI-660, whose programs start at 0x600.
+    pub fn load(&mut self, rom: Vec<u8>) -> Result<(), String> {
+        let start = self.variant.program_start();
+        let room = self.memory.len() - start;
+        if rom.len() > room {
+            return Err(format!("ROM is {} bytes, {} allows {}", rom.len(), self.variant, room));
+        }
+        self.memory[start..start + rom.len()].copy_from_slice(&rom); // Load ROM into program memory.
+
+        if self.variant == Variant::Chip8Hires && rom.starts_with(&HIRES_ENTRY) {
+            self.memory[start..start + 2].copy_from_slice(&HIRES_PROGRAM);
+        }
+
+        info!("loaded {} bytes at {:03X} as {}", rom.len(), start, self.variant);
+        self.rom = rom;
+        self.set_uninit_reads(self.uninit_reads);
+        Ok(())
+    }
+
+    /// Soft reset. Puts memory, registers, stack, timers and the screen back as they were when the ROM was loaded.
+    /// The RNG carries on from where it was, and the SYS handler, key wait, watchpoints and hooks are kept as they
+    /// belong to the host.
+    pub fn reset(&mut self) {
+        let mut cpu = Self {
+            rng: std::mem::take(&mut self.rng),
+            ..Self::new(self.variant)
+        };
+        cpu.load(std::mem::take(&mut self.rom)).expect("the ROM fitted when it was loaded");
+        self.restore(cpu);
+    }
+
+    /// Number of rows on the screen.
+    pub fn screen_height(&self) -> usize {
+        self.frame.height()
+    }
+
+    /// Screen the program starts with, in pixels, or MegaChip's if it can switch to it. Used to size the window. SCHIP's
+    /// hires screen is scaled into the same space.
+    pub fn screen_size(&self) -> (usize, usize) {
+        #[cfg(feature = "megachip")]
+        if self.megachip.is_some() {
+            return (megachip::WIDTH, megachip::HEIGHT);
+        }
+
+        (WIDTH, self.screen_height())
+    }
+
+    /// True in SCHIP's 128x64 hires mode.
+    pub fn hires(&self) -> bool {
+        self.frame.width() == HIRES_WIDTH
+    }
+
+    /// Switch between SCHIP's lores and 128x64 hires modes, clearing the screen. Lores is the variant's usual screen.
+    fn set_hires(&mut self, hires: bool) {
+        let (width, height) = match hires {
+            true => (HIRES_WIDTH, HIRES_HEIGHT),
+            false => (WIDTH, self.variant.screen_height()),
+        };
+
+        self.commit(); // Words written before the switch are recorded before it.
+        let before = self.recorder.is_some().then(|| self.frame.clone());
+        self.frame.resize(width, height);
+
+        if let (Some(recorder), Some(before)) = (self.recorder.as_deref_mut(), before) {
+            recorder.changes.push(Change::Screen(Box::new((before, self.frame.clone()))));
+        }
+    }
+
+    /// Get frame buffer composited into one palette index per pixel.
+    pub fn get_framebuffer(&self) -> Vec<u8> {
+        self.frame.composite()
+    }
+
+    pub fn frame(&self) -> &FrameBuffer {
+        &self.frame
+    }
+
+    /// Get the frame buffer. Used to toggle plane visibility when debugging.
+    pub fn frame_mut(&mut self) -> &mut FrameBuffer {
+        &mut self.frame
     }
 
     /// Press a key
     pub fn set_key(&mut self, k: u8) {
-        self.keypad.set_pressed(k)
+        self.set_keys(0, 1 << k);
     }
 
     /// Clear all keypad inputs. No keys are being pressed.
     pub fn clear_keys(&mut self) {
-        self.keypad.clear()
+        self.set_keys(0, 0);
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Keys held on each keypad, with bit k set while key k is held.
+    pub fn held_keys(&self) -> (u16, u16) {
+        (self.keypad.pressed(), self.keypad2.pressed())
+    }
+
+    /// Press a key on the CHIP-8X second keypad.
+    pub fn set_key2(&mut self, k: u8) {
+        self.set_keys(1, 1 << k);
+    }
+
+    /// Clear all second keypad inputs.
+    pub fn clear_keys2(&mut self) {
+        self.set_keys(1, 0);
+    }
+
+    /// Colour zones when running CHIP-8X. Used to colour the frame buffer for display.
+    pub fn colour_zones(&self) -> Option<&ColourZones> {
+        self.colour_zones.as_ref()
+    }
+
+    #[cfg(feature = "megachip")]
+    pub fn megachip(&self) -> Option<&MegaChip> {
+        self.megachip.as_ref()
+    }
+
+    /// FNV-1a hash of the state a program can observe: memory, registers, stack, timers and the screen. Stable across
+    /// changes to how the emulator stores anything else, so it can be recorded in files.
+    pub fn state_hash(&self) -> u64 {
+        let mut bytes = self.memory.to_vec();
+        bytes.extend_from_slice(&self.v);
+        for value in self.stack.iter().chain(&[self.sp, self.i, self.pc]) {
+            bytes.extend_from_slice(&(*value as u64).to_le_bytes());
+        }
+        bytes.extend_from_slice(&[self.delay_timer, self.sound_timer]);
+        for plane in 0..PLANES {
+            for row in self.frame.plane(plane) {
+                bytes.extend_from_slice(&row.to_le_bytes());
+            }
+        }
+
+        fnv1a(&bytes)
+    }
+
+    /// The ROM as it was loaded, before the program had a chance to modify itself.
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// FNV-1a hash of the ROM as it was loaded. Used to check save states belong to the running program.
+    pub fn rom_hash(&self) -> u64 {
+        fnv1a(&self.rom)
+    }
+
+    /// Swap in another machine, e.g. one loaded from a save state, keeping the host's settings.
+    /// Recording carries on, but changes made before the swap can't be undone from the new machine.
+    pub fn restore(&mut self, mut cpu: CPU) {
+        cpu.sys = std::mem::take(&mut self.sys);
+        cpu.key_wait = self.key_wait;
+        cpu.quirks = self.quirks;
+        cpu.write_protect = self.write_protect;
+        cpu.set_uninit_reads(self.uninit_reads);
+        cpu.watchpoints = std::mem::take(&mut self.watchpoints);
+        cpu.write_log = std::mem::take(&mut self.write_log);
+        cpu.breakpoints = std::mem::take(&mut self.breakpoints);
+        cpu.break_on_draw = self.break_on_draw;
+        cpu.coverage = self.coverage.take();
+        cpu.hooks = std::mem::take(&mut self.hooks);
+        cpu.rpl = std::mem::take(&mut self.rpl);
+        let recording = self.recording();
+        *self = cpu;
+
+        if recording {
+            self.start_recording();
+        }
+    }
+
+    /// Start recording every change to the machine's state, discarding anything recorded so far.
+    pub fn start_recording(&mut self) {
+        self.recorder = Some(Box::default());
+        self.frame.record_writes(true);
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.recorder = None;
+        self.frame.record_writes(false);
+    }
+
+    pub fn recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Changes recorded since the last call, oldest first. Empty when not recording.
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        self.commit();
+        match &mut self.recorder {
+            Some(recorder) => std::mem::take(&mut recorder.changes),
+            None => Vec::new(),
+        }
+    }
+
+    /// Changes made by the instruction executing, or last executed, so far. Empty when not recording.
+    pub fn instruction_changes(&mut self) -> &[Change] {
+        self.commit();
+        match &self.recorder {
+            Some(recorder) => {
+                let changes = &recorder.changes;
+                let start = changes.iter().rposition(|change| matches!(change, Change::Instruction { .. }));
+                &changes[start.map_or(0, |start| start + 1)..]
+            }
+            None => &[],
+        }
+    }
+
+    /// Reverse changes, newest first. Anything recorded but not yet taken is discarded.
+    pub fn undo(&mut self, changes: &[Change]) {
+        for change in changes.iter().rev() {
+            self.apply(change, false);
+        }
+        if self.recording() {
+            self.start_recording();
+        }
+    }
+
+    /// Make changes again, oldest first. Anything recorded but not yet taken is discarded.
+    pub fn redo(&mut self, changes: &[Change]) {
+        for change in changes {
+            self.apply(change, true);
+        }
+        if self.recording() {
+            self.start_recording();
+        }
+    }
+
+    // Make a change, recording it if recording. Instructions and the host change the machine's state through this and
+    // the setters below, apart from the debugger's pokes and register edits and the parts kept whole. Inlined so each
+    // setter's change comes down to a plain write, with recording out of line, as they are on every instruction's
+    // path.
+    #[inline(always)]
+    fn change(&mut self, change: Change) {
+        self.apply(&change, true);
+        if self.recorder.is_some() {
+            self.record(change);
+        }
+    }
+
+    #[inline(never)]
+    fn record(&mut self, change: Change) {
+        if let Some(recorder) = self.recorder.as_deref_mut().filter(|_| !change.unchanged()) {
+            recorder.changes.push(change);
+        }
+    }
+
+    fn set_v(&mut self, x: usize, value: u8) {
+        self.change(Change::Register { x: x as u8, before: self.v[x], after: value });
+    }
+
+    fn set_i(&mut self, i: usize) {
+        self.change(Change::I { before: self.i, after: i });
+    }
+
+    fn set_pc(&mut self, pc: usize) {
+        self.change(Change::Pc { before: self.pc, after: pc });
+    }
+
+    // Push or pop a return address, moving the stack pointer to sp.
+    fn set_stack(&mut self, sp: usize, addr: Option<usize>) {
+        if let Some(addr) = addr {
+            self.change(Change::Stack { index: self.sp as u8, before: self.stack[self.sp], after: addr });
+        }
+        self.change(Change::Sp { before: self.sp, after: sp });
+    }
+
+    fn set_delay_timer(&mut self, value: u8) {
+        self.change(Change::DelayTimer { before: self.delay_timer, after: value });
+    }
+
+    fn set_sound_timer(&mut self, value: u8) {
+        self.change(Change::SoundTimer { before: self.sound_timer, after: value });
+    }
+
+    fn set_state(&mut self, state: State) {
+        // Only boxed when it will be kept, as key waits change the state every cycle.
+        if self.recording() && state != self.state {
+            self.change(Change::State(Box::new((self.state, state))));
+        } else {
+            self.state = state;
+        }
+    }
+
+    fn set_keys(&mut self, pad: u8, keys: u16) {
+        let before = if pad == 0 { self.keypad.pressed() } else { self.keypad2.pressed() };
+        self.change(Change::Keys { pad, before, after: keys });
+    }
+
+    #[inline(always)]
+    fn apply(&mut self, change: &Change, forward: bool) {
+        fn pick<T: Copy>(before: T, after: T, forward: bool) -> T {
+            if forward {
+                after
+            } else {
+                before
+            }
+        }
+
+        match change {
+            Change::Instruction { .. } => {}
+            Change::Register { x, before, after } => self.v[*x as usize] = pick(*before, *after, forward),
+            Change::I { before, after } => self.i = pick(*before, *after, forward),
+            Change::Pc { before, after } => self.pc = pick(*before, *after, forward),
+            Change::Sp { before, after } => self.sp = pick(*before, *after, forward),
+            Change::Stack { index, before, after } => self.stack[*index as usize] = pick(*before, *after, forward),
+            Change::DelayTimer { before, after } => self.delay_timer = pick(*before, *after, forward),
+            Change::SoundTimer { before, after } => self.sound_timer = pick(*before, *after, forward),
+            Change::State(states) => self.state = pick(states.0, states.1, forward),
+            Change::CycleDebt { before, after } => self.cycle_debt = pick(*before, *after, forward),
+            Change::PortOut { before, after } => self.port_out = pick(*before, *after, forward),
+            Change::Keys { pad: 0, before, after } => self.keypad.set_keys(pick(*before, *after, forward)),
+            Change::Keys { before, after, .. } => self.keypad2.set_keys(pick(*before, *after, forward)),
+            Change::Memory { addr, before, after } => self.memory[*addr as usize] = pick(*before, *after, forward),
+            Change::Row { plane, row, before, after } => {
+                self.frame.set_row(*plane as usize, *row as usize, pick(*before, *after, forward))
+            }
+            Change::Planes { before, after } => self.frame.select_planes(pick(*before, *after, forward)),
+            Change::Rng(rngs) => {
+                let rng = if forward { &rngs.1 } else { &rngs.0 };
+                if let Some(rng) = rng.try_clone() {
+                    self.rng = rng;
+                }
+            }
+            Change::Screen(screens) => {
+                let recording = self.frame.recording_writes();
+                self.frame = if forward { screens.1.clone() } else { screens.0.clone() };
+                self.frame.record_writes(recording);
+            }
+            Change::Zones(zones) => {
+                self.colour_zones = Some(if forward { zones.1.clone() } else { zones.0.clone() });
+            }
+            #[cfg(feature = "megachip")]
+            Change::MegaChip(mega) => {
+                self.megachip = Some(if forward { mega.1.clone() } else { mega.0.clone() });
+            }
+        }
+    }
+
+    // When recording, add the words the frame buffer has written, and anything kept whole which has changed, to the
+    // recorded changes. Called after every instruction and timer tick.
+    fn commit(&mut self) {
+        if let Some(recorder) = self.recorder.as_deref_mut() {
+            recorder.changes.extend(self.frame.take_writes());
+
+            if let (Some(before), Some(after)) = (recorder.zones.take(), &self.colour_zones) {
+                recorder.changes.push(Change::Zones(Box::new((*before, after.clone()))));
+            }
+
+            #[cfg(feature = "megachip")]
+            if let (Some(before), Some(after)) = (recorder.megachip.take(), &self.megachip) {
+                recorder.changes.push(Change::MegaChip(Box::new((*before, after.clone()))));
+            }
+        }
+    }
+
+    /// state_hash folded to 32 bits, short enough to compare at a glance.
+    pub fn short_hash(&self) -> u32 {
+        let hash = self.state_hash();
+        (hash ^ (hash >> 32)) as u32
     }
 
     /// Get the current opcode. Two bytes. Big endian. First always at positive index.
     fn get_instruction(&self) -> usize {
-        (self.memory[self.pc] as usize) << 8 | (self.memory[self.pc + 1] as usize)
+        self.word_at(self.pc) as usize
+    }
+
+    /// Read a big endian word without checking watchpoints, for fetching instructions and their operands.
+    fn word_at(&self, addr: usize) -> u16 {
+        let len = self.memory.len();
+        (self.memory[addr % len] as u16) << 8 | self.memory[(addr + 1) % len] as u16
+    }
+
+    /// Decode the instruction at an address, e.g. to find how far a skip goes.
+    fn decode_at(&self, addr: usize) -> Instruction {
+        decode(self.word_at(addr), self.variant)
+    }
+
+    /// Get the current opcode in order to execute it, checking execute watchpoints.
+    fn fetch(&self) -> usize {
+        if !self.watchpoints.is_empty() {
+            self.watch(self.pc % self.memory.len(), Access::EXECUTE);
+        }
+        if let Some(coverage) = &self.coverage {
+            coverage.execute(self.pc % self.memory.len());
+        }
+        self.get_instruction()
+    }
+
+    /// Read a byte from memory. Out of range addresses wrap within the address space instead of panicking.
+    fn read_byte(&self, addr: usize) -> u8 {
+        let addr = addr % self.memory.len();
+        if !self.watchpoints.is_empty() {
+            self.watch(addr, Access::READ);
+        }
+        if let Some(coverage) = &self.coverage {
+            coverage.read(addr);
+        }
+        // Reads after the first to break on are left to report later.
+        let waiting = self.uninit_reads == UninitReads::Break && self.uninit_hit.get().is_some();
+        if !waiting && self.initialized.as_ref().is_some_and(|initialized| initialized.read(addr)) {
+            let read = UninitRead { addr, pc: self.pc, opcode: self.word_at(self.pc) };
+            match self.uninit_reads {
+                UninitReads::Break => self.uninit_hit.set(Some(read)),
+                UninitReads::Log | UninitReads::Off => warn!("{}", read),
+            }
+        }
+        self.memory[addr]
+    }
+
+    /// Write a byte to memory. Out of range addresses wrap within the address space instead of panicking.
+    fn write_byte(&mut self, addr: usize, byte: u8) {
+        let addr = addr % self.memory.len();
+        if !self.watchpoints.is_empty() {
+            self.watch(addr, Access::WRITE);
+        }
+        if self.write_protect != WriteProtect::Off && (FONT_END..self.variant.program_start()).contains(&addr) {
+            let blocked = self.write_protect == WriteProtect::Block;
+            warn!("{}", ProtectedWrite { addr, byte, pc: self.pc, blocked });
+            if blocked {
+                return;
+            }
+        }
+        if let Some(initialized) = &self.initialized {
+            initialized.write(addr);
+        }
+        if !self.write_log.is_empty() {
+            let (pc, opcode, before) = (self.pc, self.word_at(self.pc), self.memory[addr]);
+            self.write_log.record(LoggedWrite { addr, pc, opcode, before, after: byte });
+        }
+        self.change(Change::Memory { addr: addr as u32, before: self.memory[addr], after: byte });
+    }
+
+    // Record an access if it hits a watchpoint and nothing else has been hit yet.
+    fn watch(&self, addr: usize, access: Access) {
+        if self.watch_hit.get().is_none() && self.watchpoints.watched(addr).contains(access) {
+            let opcode = self.word_at(self.pc);
+            self.watch_hit.set(Some(WatchHit { addr, access, pc: self.pc, opcode }));
+        }
+    }
+
+    /// Execute an instruction, running the host's hooks around it. The hooks are taken out while they run so they can
+    /// borrow the CPU.
+    fn execute_hooked(&mut self, opcode: usize) {
+        self.stopped_at = None;
+        self.hooks.count_instruction();
+        if let Some(recorder) = self.recorder.as_deref_mut() {
+            recorder.changes.push(Change::Instruction { pc: self.pc as u32 });
+        }
+        if self.hooks.is_empty() {
+            self.execute_instruction(opcode);
+            return;
+        }
+
+        let mut hooks = std::mem::take(&mut self.hooks);
+        let instruction = decode(opcode as u16, self.variant);
+        let (pc, opcode) = (self.pc, Opcode(opcode as u16));
+
+        hooks.run_before(self, &Step { pc, opcode, instruction, registers: self.registers() });
+        self.execute(opcode.0, instruction);
+        hooks.run_after(self, &Step { pc, opcode, instruction, registers: self.registers() });
+
+        self.hooks = hooks;
     }
 
     /// Execute the instruction/opcode pointed to by the program counter
-    fn execute_instruction(&mut self, instruction: usize) {
-        let nibbles = (
-            (instruction & 0xF000) >> 12,
-            ((instruction & 0x0F00) >> 8) as usize,
-            ((instruction & 0x00F0) >> 4) as usize,
-            (instruction & 0x000F),
-        );
-
-        let kk = (instruction & 0x00FF) as u8;
-        let nnn = instruction & 0x0FFF;
-
-        let pc_change = match nibbles {
-            (0x0, 0x0, 0xE, 0x0) => self.opcode_00e0(),
-            (0x0, 0x0, 0xE, 0xE) => self.opcode_00ee(),
-            (0x1, _, _, _) => self.opcode_1nnn(nnn),
-            (0x2, _, _, _) => self.opcode_2nnn(nnn),
-            (0x3, x, _, _) => self.opcode_3xkk(x, kk),
-            (0x4, x, _, _) => self.opcode_4xkk(x, kk),
-            (0x5, x, y, 0x0) => self.opcode_5xy0(x, y),
-            (0x6, x, _, _) => self.opcode_6xkk(x, kk),
-            (0x7, x, _, _) => self.opcode_7xkk(x, kk),
-            (0x8, x, y, 0x0) => self.opcode_8xy0(x, y),
-            (0x8, x, y, 0x1) => self.opcode_8xy1(x, y),
-            (0x8, x, y, 0x2) => self.opcode_8xy2(x, y),
-            (0x8, x, y, 0x3) => self.opcode_8xy3(x, y),
-            (0x8, x, y, 0x4) => self.opcode_8xy4(x, y),
-            (0x8, x, y, 0x5) => self.opcode_8xy5(x, y),
-            (0x8, x, y, 0x6) => self.opcode_8xy6(x, y),
-            (0x8, x, y, 0x7) => self.opcode_8xy7(x, y),
-            (0x8, x, y, 0xE) => self.opcode_8xye(x, y),
-            (0x9, x, y, 0x0) => self.opcode_9xy0(x, y),
-            (0xA, _, _, _) => self.opcode_annn(nnn),
-            (0xB, _, _, _) => self.opcode_bnnn(nnn),
-            (0xC, x, _, _) => self.opcode_cxkk(x, kk),
-            (0xD, x, y, n) => self.opcode_dxyn(x, y, n),
-            (0xE, x, 0x9, 0xE) => self.opcode_ex9e(x),
-            (0xE, x, 0xA, 0x1) => self.opcode_exa1(x),
-            (0xF, x, 0x0, 0x7) => self.opcode_fx07(x),
-            (0xF, x, 0x0, 0xA) => self.opcode_fx0a(x),
-            (0xF, x, 0x1, 0x5) => self.opcode_fx15(x),
-            (0xF, x, 0x1, 0x8) => self.opcode_fx18(x),
-            (0xF, x, 1, 0xE) => self.opcode_fx1e(x),
-            (0xF, x, 0x2, 0x9) => self.opcode_fx29(x),
-            (0xF, x, 0x3, 0x3) => self.opcode_fx33(x),
-            (0xF, x, 0x5, 0x5) => self.opcode_fx55(x),
-            (0xF, x, 0x6, 0x5) => self.opcode_fx65(x),
-            _ => panic!("{:#04x} is not a valid opcode", instruction),
+    fn execute_instruction(&mut self, opcode: usize) {
+        self.execute(opcode as u16, decode(opcode as u16, self.variant));
+    }
+
+    fn execute(&mut self, opcode: u16, instruction: Instruction) {
+        // Outside MegaChip mode MegaChip's instructions are ordinary SYS calls.
+        #[cfg(feature = "megachip")]
+        let instruction = if instruction.needs_megachip_mode() && !self.megachip_enabled() {
+            Instruction::Sys { nnn: opcode as usize & 0xFFF }
+        } else {
+            instruction
+        };
+
+        let pc_change = match instruction {
+            #[cfg(feature = "megachip")]
+            Instruction::Cls if self.megachip_enabled() => self.opcode_00e0_megachip(),
+            #[cfg(feature = "megachip")]
+            Instruction::Draw { x, y, .. } if self.megachip_enabled() => self.opcode_dxyn_megachip(x, y),
+            Instruction::Sys { nnn } => self.opcode_0nnn(nnn),
+            Instruction::Cls => self.opcode_00e0(),
+            Instruction::Ret => self.opcode_00ee(),
+            Instruction::Jump { nnn } => self.opcode_1nnn(nnn),
+            Instruction::Call { nnn } => self.opcode_2nnn(nnn),
+            Instruction::SkipEqByte { x, kk } => self.opcode_3xkk(x, kk),
+            Instruction::SkipNeByte { x, kk } => self.opcode_4xkk(x, kk),
+            Instruction::SkipEqReg { x, y } => self.opcode_5xy0(x, y),
+            Instruction::LoadByte { x, kk } => self.opcode_6xkk(x, kk),
+            Instruction::AddByte { x, kk } => self.opcode_7xkk(x, kk),
+            Instruction::LoadReg { x, y } => self.opcode_8xy0(x, y),
+            Instruction::Or { x, y } => self.opcode_8xy1(x, y),
+            Instruction::And { x, y } => self.opcode_8xy2(x, y),
+            Instruction::Xor { x, y } => self.opcode_8xy3(x, y),
+            Instruction::AddReg { x, y } => self.opcode_8xy4(x, y),
+            Instruction::Sub { x, y } => self.opcode_8xy5(x, y),
+            Instruction::ShiftRight { x, y } => self.opcode_8xy6(x, y),
+            Instruction::SubN { x, y } => self.opcode_8xy7(x, y),
+            Instruction::ShiftLeft { x, y } => self.opcode_8xye(x, y),
+            Instruction::SkipNeReg { x, y } => self.opcode_9xy0(x, y),
+            Instruction::LoadI { nnn } => self.opcode_annn(nnn),
+            Instruction::JumpV0 { nnn } => self.opcode_bnnn(nnn),
+            Instruction::Random { x, kk } => self.opcode_cxkk(x, kk),
+            Instruction::Draw { x, y, n: 0 } if self.hires() || self.variant == Variant::XoChip => {
+                self.opcode_dxy0(x, y)
+            }
+            Instruction::Draw { x, y, n } => self.opcode_dxyn(x, y, n),
+            Instruction::SkipKey { x } => self.opcode_ex9e(x),
+            Instruction::SkipNotKey { x } => self.opcode_exa1(x),
+            Instruction::LoadDelay { x } => self.opcode_fx07(x),
+            Instruction::WaitKey { x } => self.opcode_fx0a(x),
+            Instruction::SetDelay { x } => self.opcode_fx15(x),
+            Instruction::SetSound { x } => self.opcode_fx18(x),
+            Instruction::AddI { x } => self.opcode_fx1e(x),
+            Instruction::LoadFont { x } => self.opcode_fx29(x),
+            Instruction::LoadBigFont { x } => self.opcode_fx30(x),
+            Instruction::Bcd { x } => self.opcode_fx33(x),
+            Instruction::StoreRegisters { x } => self.opcode_fx55(x),
+            Instruction::LoadRegisters { x } => self.opcode_fx65(x),
+            Instruction::ScrollDown { n } => self.opcode_00cn(n),
+            Instruction::ScrollRight => self.opcode_00fb(),
+            Instruction::ScrollLeft => self.opcode_00fc(),
+            Instruction::Exit => self.opcode_00fd(),
+            Instruction::Lores => self.opcode_00fe(),
+            Instruction::Hires => self.opcode_00ff(),
+            Instruction::SaveFlags { x } => self.opcode_fx75(x),
+            Instruction::LongI => self.opcode_f000(),
+            Instruction::SaveRange { x, y } => self.opcode_5xy2(x, y),
+            Instruction::LoadRange { x, y } => self.opcode_5xy3(x, y),
+            Instruction::SelectPlanes { n } => self.opcode_fn01(n),
+            Instruction::ScrollUp { n } => self.opcode_00dn(n),
+            Instruction::LoadFlags { x } => self.opcode_fx85(x),
+            Instruction::CycleBackground => self.opcode_02a0(),
+            Instruction::AddNibbles { x, y } => self.opcode_5xy1(x, y),
+            Instruction::Colour { x, y, n } => self.opcode_bxyn(x, y, n),
+            Instruction::SkipKey2 { x } => self.opcode_exf2(x),
+            Instruction::SkipNotKey2 { x } => self.opcode_exf5(x),
+            Instruction::Out { x } => self.opcode_fxf8(x),
+            #[cfg(feature = "megachip")]
+            Instruction::MegaOff => self.opcode_0010(),
+            #[cfg(feature = "megachip")]
+            Instruction::MegaOn => self.opcode_0011(),
+            #[cfg(feature = "megachip")]
+            Instruction::MegaScrollUp { n } => self.opcode_00bn(n),
+            #[cfg(feature = "megachip")]
+            Instruction::LoadLongI { nn } => self.opcode_01nn(nn),
+            #[cfg(feature = "megachip")]
+            Instruction::LoadPalette { nn } => self.opcode_02nn(nn),
+            #[cfg(feature = "megachip")]
+            Instruction::SpriteWidth { nn } => self.opcode_03nn(nn),
+            #[cfg(feature = "megachip")]
+            Instruction::SpriteHeight { nn } => self.opcode_04nn(nn),
+            #[cfg(feature = "megachip")]
+            Instruction::Alpha { nn } => self.opcode_05nn(nn),
+            #[cfg(feature = "megachip")]
+            Instruction::PlaySound { n } => self.opcode_060n(n),
+            #[cfg(feature = "megachip")]
+            Instruction::StopSound => self.opcode_0700(),
+            #[cfg(feature = "megachip")]
+            Instruction::BlendMode { n } => self.opcode_080n(n),
+            #[cfg(feature = "megachip")]
+            Instruction::CollisionColour { nn } => self.opcode_09nn(nn),
+            Instruction::Invalid(_) => panic!("{:#04x} is not a valid opcode", opcode),
         };
 
+        self.advance(pc_change);
+    }
+
+    fn advance(&mut self, pc_change: ProgramCounter) {
         match pc_change {
-            ProgramCounter::Next => self.pc += 2,
-            ProgramCounter::Skip => self.pc += 4,
-            ProgramCounter::Jump(addr) => self.pc = addr,
+            ProgramCounter::Next => self.set_pc(self.pc + 2),
+            ProgramCounter::Skip => self.set_pc(self.pc + 2 + self.decode_at(self.pc + 2).size()),
+            ProgramCounter::Jump(addr) => self.set_pc(addr),
         };
     }
 
+    /// SYS nnn --> Call the machine language routine at nnn. What happens is up to the SYS handler.
+    fn opcode_0nnn(&mut self, nnn: usize) -> ProgramCounter {
+        match self.sys {
+            SysHandler::Panic => panic!("SYS {:03X} at {:03X}: machine language routines are not supported", nnn, self.pc),
+            SysHandler::Ignore => debug!("ignored SYS {:03X} at {:03X}", nnn, self.pc),
+            SysHandler::Log => warn!("SYS {:03X} at {:03X}", nnn, self.pc),
+            SysHandler::Callback(_) => {
+                // Take the handler out while it runs so it can borrow the CPU.
+                let mut handler = std::mem::take(&mut self.sys);
+                if let SysHandler::Callback(callback) = &mut handler {
+                    callback(self, nnn);
+                }
+                self.sys = handler;
+            }
+        }
+        ProgramCounter::Next
+    }
+
     /// CLS --> Clear the screen.
     fn opcode_00e0(&mut self) -> ProgramCounter {
         self.frame.clear();
@@ -181,7 +1255,7 @@ impl CPU {
 
     /// RET -> Exit subroutine. Set program counter to top address in the stack and subtract 1 from the stack pointer.
     fn opcode_00ee(&mut self) -> ProgramCounter {
-        self.sp -= 1;
+        self.set_stack(self.sp - 1, None);
         ProgramCounter::Jump(self.stack[self.sp])
     }
 
@@ -192,8 +1266,7 @@ impl CPU {
 
     /// CALL nnn -> Add current program counter ( plus two) to stack and set program counter to given address.
     fn opcode_2nnn(&mut self, nnn: usize) -> ProgramCounter {
-        self.stack[self.sp] = self.pc + 2;
-        self.sp += 1;
+        self.set_stack(self.sp + 1, Some(self.pc + 2));
         ProgramCounter::Jump(nnn)
     }
 
@@ -214,76 +1287,90 @@ impl CPU {
 
     /// LD Vx kk --> Load nn into Vx.
     fn opcode_6xkk(&mut self, x: usize, kk: u8) -> ProgramCounter {
-        self.v[x] = kk;
+        self.set_v(x, kk);
         ProgramCounter::Next
     }
     /// ADD Vx kk --> Add kk to the contents of Vx and store in Vx.
     fn opcode_7xkk(&mut self, x: usize, kk: u8) -> ProgramCounter {
-        self.v[x] = self.v[x].wrapping_add(kk);
+        self.set_v(x, self.v[x].wrapping_add(kk));
         ProgramCounter::Next
     }
 
     /// LD Vx Vy --> Store value of Vy in Vx
     fn opcode_8xy0(&mut self, x: usize, y: usize) -> ProgramCounter {
-        self.v[x] = self.v[y];
+        self.set_v(x, self.v[y]);
         ProgramCounter::Next
     }
 
     /// OR Vx Vy --> Store value of bitwise OR between Vx and Vy.
     fn opcode_8xy1(&mut self, x: usize, y: usize) -> ProgramCounter {
-        self.v[x] |= self.v[y];
+        self.set_v(x, self.v[x] | self.v[y]);
         ProgramCounter::Next
     }
 
     /// AND Vx Vy --> Store value of bitwise AND between Vx and Vy.
     fn opcode_8xy2(&mut self, x: usize, y: usize) -> ProgramCounter {
-        self.v[x] &= self.v[y];
+        self.set_v(x, self.v[x] & self.v[y]);
         ProgramCounter::Next
     }
 
     /// XOR Vx Vy --> Store value of bitwise XOR between Vx and Vy.
     fn opcode_8xy3(&mut self, x: usize, y: usize) -> ProgramCounter {
-        self.v[x] ^= self.v[y];
+        self.set_v(x, self.v[x] ^ self.v[y]);
         ProgramCounter::Next
     }
 
+    // Arithmetic and shift instructions compute their result from the original operands, write Vx and only then write
+    // VF. If either operand is VF the flag therefore always wins, as on the original hardware.
+
     /// ADD Vx Vy --> Add Vx and Vy. Store result in Vx. Set VF to 1 upon overflow.
     fn opcode_8xy4(&mut self, x: usize, y: usize) -> ProgramCounter {
-        let vx = self.v[x] as u16;
-        let vy = self.v[y] as u16;
-        let res = vx + vy;
-
-        self.v[0xF] = if res > 255 { 1 } else { 0 };
-        self.v[x] = res as u8;
+        let (res, overflow) = self.v[x].overflowing_add(self.v[y]);
 
+        self.set_v(x, res);
+        self.set_v(0xF, overflow as u8);
         ProgramCounter::Next
     }
 
     /// SUB Vx Vy --> Store value of Vx - Vy and set VF to 1 if Vx is greater than Vy (i.e. no borrow occurred).
     fn opcode_8xy5(&mut self, x: usize, y: usize) -> ProgramCounter {
-        self.v[0xF] = if self.v[x] > self.v[y] { 1 } else { 0 };
-        self.v[x] = self.v[x].wrapping_sub(self.v[y]);
+        let flag = if self.v[x] > self.v[y] { 1 } else { 0 };
+        let res = self.v[x].wrapping_sub(self.v[y]);
+
+        self.set_v(x, res);
+        self.set_v(0xF, flag);
         ProgramCounter::Next
     }
 
     /// SHR Vx Vy --> Shift Vy one bit to the right and store result. Set VF if underflow occurs.
+    /// Shifts Vx in place instead with the shift-vx quirk.
     fn opcode_8xy6(&mut self, x: usize, y: usize) -> ProgramCounter {
-        self.v[0xF] = self.v[y] & 1;
-        self.v[x] = self.v[y] >> 1;
+        let y = if self.quirks.shift_vx { x } else { y };
+        let flag = self.v[y] & 1;
+
+        self.set_v(x, self.v[y] >> 1);
+        self.set_v(0xF, flag);
         ProgramCounter::Next
     }
 
     /// SUBN Vx Vy --> Store value of Vy - Vx and set VF to 1 if Vy is greater than Vx (i.e. no borrow occurred).
     fn opcode_8xy7(&mut self, x: usize, y: usize) -> ProgramCounter {
-        self.v[0xF] = if self.v[y] > self.v[x] { 1 } else { 0 };
-        self.v[x] = self.v[y].wrapping_sub(self.v[x]);
+        let flag = if self.v[y] > self.v[x] { 1 } else { 0 };
+        let res = self.v[y].wrapping_sub(self.v[x]);
+
+        self.set_v(x, res);
+        self.set_v(0xF, flag);
         ProgramCounter::Next
     }
 
     /// SHL Vx Vy --> Shift Vy one bit and store. Set VF if overflow occurs.
+    /// Shifts Vx in place instead with the shift-vx quirk.
     fn opcode_8xye(&mut self, x: usize, y: usize) -> ProgramCounter {
-        self.v[0xf] = (self.v[y] >> 7) & 1;
-        self.v[x] = self.v[y] << 1;
+        let y = if self.quirks.shift_vx { x } else { y };
+        let flag = (self.v[y] >> 7) & 1;
+
+        self.set_v(x, self.v[y] << 1);
+        self.set_v(0xF, flag);
         ProgramCounter::Next
     }
 
@@ -294,7 +1381,7 @@ impl CPU {
 
     /// LD I nnn --> Load nnn into the I register.
     fn opcode_annn(&mut self, nnn: usize) -> ProgramCounter {
-        self.i = nnn;
+        self.set_i(nnn);
         ProgramCounter::Next
     }
 
@@ -305,20 +1392,77 @@ impl CPU {
 
     /// RND Vx kk --> Generate a random byte and AND with nnn Store result in Vx.
     fn opcode_cxkk(&mut self, x: usize, kk: u8) -> ProgramCounter {
-        let mut rng = rand::thread_rng();
-        self.v[x] = rng.gen::<u8>() & kk;
+        let before = self.recorder.as_ref().and_then(|_| self.rng.try_clone());
+
+        let program = &self.memory[self.variant.program_start()..];
+        let byte = self.rng.next_byte(program) & kk;
+        self.set_v(x, byte);
+
+        if let (Some(recorder), Some(before)) = (&mut self.recorder, before) {
+            if let Some(after) = self.rng.try_clone() {
+                recorder.changes.push(Change::Rng(Box::new((before, after))));
+            }
+        }
         ProgramCounter::Next
     }
 
     /// DRW Vx Vy n --> Draw the sprite beginning at memory address I and ending at I + k at position (Vx, Vy).
+    /// With more than one XO-CHIP plane selected, each plane has its own sprite, one after another from I.
     fn opcode_dxyn(&mut self, x: usize, y: usize, n: usize) -> ProgramCounter {
-        let sprite = &self.memory[self.i..self.i + n];
-        let change = self.frame
-            .draw_sprite(sprite, self.v[y] as usize, self.v[x] as usize);
-        self.v[0xF] = if change { 1} else {0 };
+        self.note_draw(x, y, n, 1);
+        let change = self.draw_planes(n, FrameBuffer::draw_sprite_on, x, y);
+        self.set_v(0xF, if change { 1} else {0 });
         ProgramCounter::Next
     }
 
+    /// SCHIP: DRW Vx, Vy, 0 --> Draw a 16x16 sprite, two bytes per row, from memory starting at I. VF is set on
+    /// collision like DXYN. Only in hires mode, or in either mode on XO-CHIP. Otherwise DXY0 draws an empty sprite, as
+    /// on the VIP.
+    fn opcode_dxy0(&mut self, x: usize, y: usize) -> ProgramCounter {
+        self.note_draw(x, y, 32, 2);
+        let change = self.draw_planes(32, FrameBuffer::draw_wide_sprite_on, x, y);
+        self.set_v(0xF, change as u8);
+        ProgramCounter::Next
+    }
+
+    // Record a draw of `len` bytes on each selected plane if breaking on draws and nothing has been drawn since the
+    // host last checked. Done before drawing, while VF still holds the coordinate if it is one.
+    fn note_draw(&mut self, x: usize, y: usize, len: usize, bytes_per_row: usize) {
+        if !self.break_on_draw || self.draw_hit.is_some() {
+            return;
+        }
+
+        let planes = self.frame.selected().count();
+        self.draw_hit = Some(DrawHit {
+            pc: self.pc,
+            opcode: self.word_at(self.pc),
+            addr: self.i,
+            x: self.v[x] as usize,
+            y: self.v[y] as usize,
+            sprite: (self.i..self.i + len * planes).map(|addr| self.peek(addr)).collect(),
+            bytes_per_row,
+        });
+    }
+
+    // Draw a sprite of `len` bytes on each selected plane at (Vx, Vy), reading the next sprite from memory for each
+    // plane. Returns true if any lit pixel was turned off.
+    fn draw_planes(
+        &mut self,
+        len: usize,
+        draw: impl Fn(&mut FrameBuffer, usize, &[u8], usize, usize) -> bool,
+        x: usize,
+        y: usize,
+    ) -> bool {
+        let (row, col) = (self.v[y] as usize, self.v[x] as usize);
+        let mut change = false;
+        for (index, plane) in self.frame.selected().enumerate() {
+            let start = self.i + index * len;
+            let sprite: Vec<u8> = (start..start + len).map(|addr| self.read_byte(addr)).collect();
+            change |= draw(&mut self.frame, plane, &sprite, row, col);
+        }
+        change
+    }
+
     /// SKP Vx --> Skip next instruction if the key with value Vx is pressed.
     fn opcode_ex9e(&mut self, x: usize) -> ProgramCounter {
         ProgramCounter::skip_if(self.keypad.is_pressed(self.v[x]))
@@ -331,36 +1475,35 @@ impl CPU {
 
     /// LD Vx DT --> The value of the delay timer is places into Vx.
     fn opcode_fx07(&mut self, x: usize) -> ProgramCounter {
-        self.v[x] = self.delay_timer;
+        self.set_v(x, self.delay_timer);
         ProgramCounter::Next
     }
 
-    /// LD Vx K --> Wait for a keypress and store value of the key in Vx.
+    /// LD Vx K --> Wait for a key to be pressed and released and store value of the key in Vx.
     fn opcode_fx0a(&mut self, x: usize) -> ProgramCounter {
-        for k in 0..15 {
-            if self.keypad.is_pressed(k) {
-                self.v[x] = k;
-                return ProgramCounter::Next;
-            }
-        }
-        ProgramCounter::Jump(self.pc) // Eww. Maybe add ProgramCounter::Back.
+        let ignored = match self.key_wait {
+            KeyWait::FreshPress => self.keypad.pressed(),
+            KeyWait::Release | KeyWait::Press => 0,
+        };
+        self.set_state(State::WaitingForKey { x, ignored, pressed: None });
+        ProgramCounter::Next
     }
 
     /// LD DT Vx --> Set delay timer to value stored in Vx.
     fn opcode_fx15(&mut self, x: usize) -> ProgramCounter {
-        self.delay_timer = self.v[x];
+        self.set_delay_timer(self.v[x]);
         ProgramCounter::Next
     }
 
     /// LD ST Vx --> Load value of Vx into sound timer.
     fn opcode_fx18(&mut self, x: usize) -> ProgramCounter {
-        self.sound_timer = self.v[x];
+        self.set_sound_timer(self.v[x]);
         ProgramCounter::Next
     }
 
     /// ADD I Vx --> Store I + Vx in the I register.
     fn opcode_fx1e(&mut self, x: usize) -> ProgramCounter {
-        self.i = self.i.wrapping_add(self.v[x] as usize);
+        self.set_i(self.i.wrapping_add(self.v[x] as usize));
         ProgramCounter::Next
     }
 
@@ -370,69 +1513,372 @@ impl CPU {
             panic!("OP F{}29: {} is not a valid character.", x, x);
         }
 
-        self.i = (self.v[x] * 5) as usize;
+        self.set_i((self.v[x] * 5) as usize);
+        ProgramCounter::Next
+    }
+
+    /// SCHIP: LD HF, Vx --> Set I to the location of the big sprite for the digit in Vx. Only the low nibble of Vx is
+    /// used, and there are only sprites for 0 to 9.
+    fn opcode_fx30(&mut self, x: usize) -> ProgramCounter {
+        self.set_i(BIG_FONT + (self.v[x] & 0xF) as usize * 10);
         ProgramCounter::Next
     }
 
     /// LD B Vx --> Store the binary coded decimal representation of Vx in memory locations I, I + 1 and I + 2.
     fn opcode_fx33(&mut self, x: usize) -> ProgramCounter {
-        self.memory[self.i] = self.v[x >> 8] / 100;
-        self.memory[self.i] = (self.v[x >> 8] / 10) % 10;
-        self.memory[self.i] = (self.v[x >> 8] % 100) % 10;
+        let vx = self.v[x];
+        self.write_byte(self.i, vx / 100);
+        self.write_byte(self.i + 1, (vx / 10) % 10);
+        self.write_byte(self.i + 2, vx % 10);
         ProgramCounter::Next
     }
 
-    /// LD <I> Vx --> Store registers 0 up to Vx in memory starting at I.
+    /// LD <I> Vx --> Store registers 0 up to Vx in memory starting at I. Leaves I after them with the increment-i quirk.
     fn opcode_fx55(&mut self, x: usize) -> ProgramCounter {
         for i in 0..=x {
-            let idx = self.i + i;
-            self.memory[idx] = self.v[i];
+            self.write_byte(self.i + i, self.v[i]);
+        }
+        if self.quirks.increment_i {
+            self.set_i(self.i.wrapping_add(x + 1));
         }
         ProgramCounter::Next
     }
 
-    /// LD Vx <I> --> Read values of I to I + x into registers V0 to Vx.
+    /// LD Vx <I> --> Read values of I to I + x into registers V0 to Vx. Leaves I after them with the increment-i quirk.
     fn opcode_fx65(&mut self, x: usize) -> ProgramCounter {
         for i in 0..=x {
-            let idx = self.i + i;
-            self.v[i] = self.memory[idx];
+            self.set_v(i, self.read_byte(self.i + i));
         }
+        if self.quirks.increment_i {
+            self.set_i(self.i.wrapping_add(x + 1));
+        }
+        ProgramCounter::Next
+    }
+
+    /// SCHIP: SCD n --> Scroll the screen down n rows.
+    fn opcode_00cn(&mut self, n: usize) -> ProgramCounter {
+        self.frame.scroll_down(n);
+        ProgramCounter::Next
+    }
+
+    /// SCHIP: SCR --> Scroll the screen right 4 pixels.
+    fn opcode_00fb(&mut self) -> ProgramCounter {
+        self.frame.scroll_right(4);
+        ProgramCounter::Next
+    }
+
+    /// SCHIP: SCL --> Scroll the screen left 4 pixels.
+    fn opcode_00fc(&mut self) -> ProgramCounter {
+        self.frame.scroll_left(4);
+        ProgramCounter::Next
+    }
+
+    /// SCHIP: EXIT --> Stop the program. The program counter stays on the EXIT, which halts the CPU.
+    fn opcode_00fd(&mut self) -> ProgramCounter {
+        ProgramCounter::Jump(self.pc)
+    }
+
+    /// SCHIP: LOW --> Switch to the lores screen, clearing it.
+    fn opcode_00fe(&mut self) -> ProgramCounter {
+        self.set_hires(false);
+        ProgramCounter::Next
+    }
+
+    /// SCHIP: HIGH --> Switch to the 128x64 hires screen, clearing it.
+    fn opcode_00ff(&mut self) -> ProgramCounter {
+        self.set_hires(true);
+        ProgramCounter::Next
+    }
+
+    /// XO-CHIP: LD I, long nnnn --> Set I to the 16-bit address in the word after the opcode, then skip over it.
+    fn opcode_f000(&mut self) -> ProgramCounter {
+        self.set_i(self.word_at(self.pc + 2) as usize);
+        ProgramCounter::Jump(self.pc + 4)
+    }
+
+    /// XO-CHIP: SAVE Vx - Vy --> Store registers Vx to Vy in memory starting at I, in order from Vx. I is unchanged.
+    fn opcode_5xy2(&mut self, x: usize, y: usize) -> ProgramCounter {
+        for (offset, reg) in register_range(x, y).enumerate() {
+            self.write_byte(self.i + offset, self.v[reg]);
+        }
+        ProgramCounter::Next
+    }
+
+    /// XO-CHIP: LOAD Vx - Vy --> Read registers Vx to Vy from memory starting at I, in order from Vx. I is unchanged.
+    fn opcode_5xy3(&mut self, x: usize, y: usize) -> ProgramCounter {
+        for (offset, reg) in register_range(x, y).enumerate() {
+            self.set_v(reg, self.read_byte(self.i + offset));
+        }
+        ProgramCounter::Next
+    }
+
+    /// XO-CHIP: PLANE n --> Select the planes drawing, clearing and scrolling affect, as a bitmask. 0 selects none.
+    fn opcode_fn01(&mut self, n: usize) -> ProgramCounter {
+        let after = n as u8 & ((1 << PLANES) - 1);
+        self.change(Change::Planes { before: self.frame.selected_planes(), after });
+        ProgramCounter::Next
+    }
+
+    /// XO-CHIP: SCU n --> Scroll the selected planes up n rows.
+    fn opcode_00dn(&mut self, n: usize) -> ProgramCounter {
+        self.frame.scroll_up(n);
+        ProgramCounter::Next
+    }
+
+    /// SCHIP: LD R, Vx --> Save V0 to Vx in the RPL user flags. Only V0 to V7 fit.
+    fn opcode_fx75(&mut self, x: usize) -> ProgramCounter {
+        let values = self.v;
+        if let Err(e) = self.rpl.save(&values[..=x]) {
+            warn!("{}", e);
+        }
+        ProgramCounter::Next
+    }
+
+    /// SCHIP: LD Vx, R --> Load V0 to Vx from the RPL user flags. Only V0 to V7 have flags.
+    fn opcode_fx85(&mut self, x: usize) -> ProgramCounter {
+        let flags = *self.rpl.flags();
+        let len = (x + 1).min(flags.len());
+        for (x, flag) in flags[..len].iter().enumerate() {
+            self.set_v(x, *flag);
+        }
+        ProgramCounter::Next
+    }
+
+    /// CHIP-8X: BGC --> Cycle the background colour between blue, black, green and red.
+    fn opcode_02a0(&mut self) -> ProgramCounter {
+        if let Some(zones) = self.zones() {
+            zones.cycle_background();
+        }
+        ProgramCounter::Next
+    }
+
+    // Colour zones about to be changed by an instruction.
+    fn zones(&mut self) -> Option<&mut ColourZones> {
+        let zones = self.colour_zones.as_mut()?;
+        if let Some(recorder) = &mut self.recorder {
+            recorder.touch_zones(zones);
+        }
+        Some(zones)
+    }
+
+    /// CHIP-8X: ADD Vx Vy --> Add each nibble of Vx and Vy separately, modulo 8. Store result in Vx.
+    fn opcode_5xy1(&mut self, x: usize, y: usize) -> ProgramCounter {
+        self.set_v(x, chip8x::add_nibbles(self.v[x], self.v[y]));
+        ProgramCounter::Next
+    }
+
+    /// CHIP-8X: COL Vx Vy n --> Set the foreground colour Vy of the zones described by Vx and Vx+1. With n of 0 zones are
+    /// 8x4 pixels, otherwise 8x1 pixels covering n rows.
+    fn opcode_bxyn(&mut self, x: usize, y: usize, n: usize) -> ProgramCounter {
+        let (horizontal, vertical, colour) = (self.v[x], self.v[(x + 1) % 16], self.v[y]);
+        if let Some(zones) = self.zones() {
+            if n == 0 {
+                zones.set_coarse(horizontal, vertical, colour);
+            } else {
+                zones.set_fine(horizontal, vertical, n, colour);
+            }
+        }
+        ProgramCounter::Next
+    }
+
+    /// CHIP-8X: SKP2 Vx --> Skip next instruction if the key with value Vx is pressed on the second keypad.
+    fn opcode_exf2(&mut self, x: usize) -> ProgramCounter {
+        ProgramCounter::skip_if(self.keypad2.is_pressed(self.v[x]))
+    }
+
+    /// CHIP-8X: SKNP2 Vx --> Skip next instruction if the key with value Vx is not pressed on the second keypad.
+    fn opcode_exf5(&mut self, x: usize) -> ProgramCounter {
+        ProgramCounter::skip_if(!self.keypad2.is_pressed(self.v[x]))
+    }
+
+    /// CHIP-8X: OUT Vx --> Write Vx to the output port. On the VIP this sets the tone of the sound.
+    fn opcode_fxf8(&mut self, x: usize) -> ProgramCounter {
+        self.change(Change::PortOut { before: self.port_out, after: self.v[x] });
+        ProgramCounter::Next
+    }
+
+    #[cfg(feature = "megachip")]
+    fn megachip_enabled(&self) -> bool {
+        self.megachip.as_ref().is_some_and(MegaChip::enabled)
+    }
+
+    #[cfg(feature = "megachip")]
+    fn mega(&mut self) -> &mut MegaChip {
+        let mega = self.megachip.as_mut().expect("MegaChip instruction on a CPU without MegaChip");
+        if let Some(recorder) = &mut self.recorder {
+            recorder.touch_megachip(mega);
+        }
+        mega
+    }
+
+    /// MegaChip: MEGAOFF --> Leave MegaChip mode.
+    #[cfg(feature = "megachip")]
+    fn opcode_0010(&mut self) -> ProgramCounter {
+        self.mega().set_enabled(false);
+        ProgramCounter::Next
+    }
+
+    /// MegaChip: MEGAON --> Enter MegaChip mode.
+    #[cfg(feature = "megachip")]
+    fn opcode_0011(&mut self) -> ProgramCounter {
+        self.mega().set_enabled(true);
+        ProgramCounter::Next
+    }
+
+    /// MegaChip: SCRU n --> Scroll the screen up n rows.
+    #[cfg(feature = "megachip")]
+    fn opcode_00bn(&mut self, n: usize) -> ProgramCounter {
+        self.mega().scroll_up(n);
+        ProgramCounter::Next
+    }
+
+    /// MegaChip: CLS --> Show everything drawn since the last 00E0 and start drawing on a blank screen.
+    #[cfg(feature = "megachip")]
+    fn opcode_00e0_megachip(&mut self) -> ProgramCounter {
+        self.mega().present();
+        ProgramCounter::Next
+    }
+
+    /// MegaChip: LDHI I, nnnnnn --> Set I to a 24-bit address. The low 16 bits are the following word.
+    #[cfg(feature = "megachip")]
+    fn opcode_01nn(&mut self, nn: u8) -> ProgramCounter {
+        let low = (self.read_byte(self.pc + 2) as usize) << 8 | self.read_byte(self.pc + 3) as usize;
+        self.set_i((nn as usize) << 16 | low);
+        ProgramCounter::Jump(self.pc + 4)
+    }
+
+    /// MegaChip: LDPAL nn --> Load nn ARGB colours from I into the palette, starting at index 1.
+    #[cfg(feature = "megachip")]
+    fn opcode_02nn(&mut self, nn: u8) -> ProgramCounter {
+        let colours: Vec<u8> = (0..nn as usize * 4).map(|offset| self.read_byte(self.i + offset)).collect();
+        self.mega().load_palette(&colours);
+        ProgramCounter::Next
+    }
+
+    /// MegaChip: SPRW nn --> Set the width of sprites. 0 means 256.
+    #[cfg(feature = "megachip")]
+    fn opcode_03nn(&mut self, nn: u8) -> ProgramCounter {
+        self.mega().set_sprite_width(nn);
+        ProgramCounter::Next
+    }
+
+    /// MegaChip: SPRH nn --> Set the height of sprites. 0 means 256.
+    #[cfg(feature = "megachip")]
+    fn opcode_04nn(&mut self, nn: u8) -> ProgramCounter {
+        self.mega().set_sprite_height(nn);
+        ProgramCounter::Next
+    }
+
+    /// MegaChip: ALPHA nn --> Set the screen's alpha. 0 fades the screen out entirely.
+    #[cfg(feature = "megachip")]
+    fn opcode_05nn(&mut self, nn: u8) -> ProgramCounter {
+        self.mega().set_alpha(nn);
+        ProgramCounter::Next
+    }
+
+    /// MegaChip: DIGISND n --> Play the digitised sound at I. Loops if n is 0, plays once otherwise.
+    #[cfg(feature = "megachip")]
+    fn opcode_060n(&mut self, n: usize) -> ProgramCounter {
+        let start = self.i % self.memory.len();
+        if let Some(mega) = &mut self.megachip {
+            if let Some(recorder) = &mut self.recorder {
+                recorder.touch_megachip(mega);
+            }
+            mega.play(&self.memory[start..], n == 0);
+        }
+        ProgramCounter::Next
+    }
+
+    /// MegaChip: STOPSND --> Stop the digitised sound.
+    #[cfg(feature = "megachip")]
+    fn opcode_0700(&mut self) -> ProgramCounter {
+        self.mega().stop_sound();
+        ProgramCounter::Next
+    }
+
+    /// MegaChip: BMODE n --> Set how sprites blend with the screen. Unknown modes are ignored.
+    #[cfg(feature = "megachip")]
+    fn opcode_080n(&mut self, n: usize) -> ProgramCounter {
+        match Blend::from_nibble(n) {
+            Some(blend) => self.mega().set_blend(blend),
+            None => warn!("ignored unknown blend mode {:X} at {:03X}", n, self.pc),
+        }
+        ProgramCounter::Next
+    }
+
+    /// MegaChip: CCOL nn --> Set the palette index whose pixels count as collisions.
+    #[cfg(feature = "megachip")]
+    fn opcode_09nn(&mut self, nn: u8) -> ProgramCounter {
+        self.mega().set_collision_colour(nn);
+        ProgramCounter::Next
+    }
+
+    /// MegaChip: DRW Vx, Vy --> Blit the sprite at I to (Vx, Vy). Sets VF if it hit the collision colour.
+    #[cfg(feature = "megachip")]
+    fn opcode_dxyn_megachip(&mut self, x: usize, y: usize) -> ProgramCounter {
+        let len = self.mega().sprite_len();
+        let sprite: Vec<u8> = (0..len).map(|offset| self.read_byte(self.i + offset)).collect();
+        let (vx, vy) = (self.v[x] as usize, self.v[y] as usize);
+
+        let collision = self.mega().draw_sprite(&sprite, vx, vy);
+        self.set_v(0xF, collision as u8);
         ProgramCounter::Next
     }
 
     #[rustfmt::skip]
     fn load_font(&mut self) {
-        // 0 to F. 5 Bytes per character. Index in memory is the character's hex value multiplied by 5.
-        let font: [u8; 80] = [
-            0xF0, 0x90, 0x90, 0x90, 0xF0,
-            0x20, 0x60, 0x20, 0x20, 0x70,
-            0xF0, 0x10, 0xF0, 0x80, 0xF0,
-            0xF0, 0x10, 0xF0, 0x10, 0xF0,
-            0x90, 0x90, 0xF0, 0x10, 0x10,
-            0xF0, 0x80, 0xF0, 0x10, 0xF0,
-            0xF0, 0x80, 0xF0, 0x90, 0xF0,
-            0xF0, 0x10, 0x20, 0x40, 0x40,
-            0xF0, 0x90, 0xF0, 0x90, 0xF0,
-            0xF0, 0x90, 0xF0, 0x10, 0xF0,
-            0xF0, 0x90, 0xF0, 0x90, 0x90,
-            0xE0, 0x90, 0xE0, 0x90, 0xE0,
-            0xF0, 0x80, 0x80, 0x80, 0xF0,
-            0xE0, 0x90, 0x90, 0x90, 0xE0,
-            0xF0, 0x80, 0xF0, 0x80, 0xF0,
-            0xF0, 0x80, 0xF0, 0x80, 0x80
+        // SCHIP's 0 to 9. 10 bytes per digit. There are no big letters.
+        let big_font: [u8; 100] = [
+            0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C,
+            0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C,
+            0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF,
+            0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C,
+            0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06,
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C,
+            0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C,
+            0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60,
+            0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C,
+            0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C
         ];
 
-        self.memory[0..80].copy_from_slice(&font);
+        self.memory[0..80].copy_from_slice(&FONT);
+        self.memory[BIG_FONT..BIG_FONT + 100].copy_from_slice(&big_font);
+    }
+
+    /// Disassemble `count` instructions of memory from `addr`, for debugger views. Doesn't trigger watchpoints.
+    pub fn disassemble(&self, addr: usize, count: usize) -> Vec<Line> {
+        let len = self.memory.len();
+        let bytes: Vec<u8> = (addr..addr + count * 2).map(|a| self.memory[a % len]).collect();
+        disasm::disassemble(&bytes, addr, self.variant)
     }
 
     pub fn dbg(&self) {
         println!("--- DEBUG ---");
         println!("PC: {:x}", self.pc);
         println!("OP: {:x}", self.get_instruction());
+        for line in self.disassemble(self.pc, 4) {
+            println!("  {}", line);
+        }
+        println!("HASH: {:08x}", self.short_hash());
         println!("-------------\n");
     }
 }
 
+// Registers x to y inclusive, counting down when x > y as XO-CHIP's SAVE and LOAD do.
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
 #[cfg(test)]
 #[path = "./cpu_tests.rs"]
 mod cpu_tests;
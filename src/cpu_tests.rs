@@ -1,15 +1,31 @@
 #![allow(non_snake_case)]
 
 // Self imports
-use crate::cpu::CPU;
+use crate::conformance::{Platform, OPCODES};
+use crate::cpu::{State, CPU};
 use crate::frame_buffer::FrameBuffer;
-use crate::keypad::Keypad;
+use crate::halt::Halt;
+use crate::hooks::Hooks;
+use crate::keypad::{KeyWait, Keypad};
+use crate::protect::WriteProtect;
+use crate::quirks::Quirks;
+use crate::random::{Random, VipRandom};
+use crate::rpl::RplFlags;
+use crate::sys::SysHandler;
+use crate::uninit::UninitReads;
+use crate::watch::{Access, WatchHit, Watchpoints};
+use crate::variant::Variant;
+use crate::vip_timing;
+use crate::write_log::WriteLog;
 
 use crate::OFFSET;
 
 // Std imports
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
 use std::fs::File;
 use std::io::Read;
+use std::rc::Rc;
 
 fn create_test_cpu() -> CPU {
     let path = "./roms/test/BC_test.ch8";
@@ -21,7 +37,7 @@ fn create_test_cpu() -> CPU {
 
     // Create CPU and load ROM.
     let mut cpu = CPU::default();
-    cpu.load(rom);
+    cpu.load(rom).unwrap();
     cpu
 }
 
@@ -32,26 +48,74 @@ fn load_and_execute_instruction(cpu: &mut CPU, instr: u16) {
 
 #[test]
 fn test_creating_default_cpu() {
-    let cpu = CPU::default();
+    let mut cpu = CPU::default();
+    cpu.seed_rng(1);
 
     let mut expected = CPU {
-        memory: [0; 4096],
+        memory: vec![0; 4096],
+        rom: Vec::new(),
         v: [0; 16],
         sp: 0,
         stack: [usize::MAX; 16],
         i: 0,
         pc: OFFSET,
-        delay_counter: 0,
         delay_timer: 0,
         sound_timer: 0,
+        state: State::Running,
+        cycle_debt: 0,
+        variant: Variant::Chip8,
         frame: FrameBuffer::new(true, true),
         keypad: Keypad::new(),
+        keypad2: Keypad::new(),
+        colour_zones: None,
+        port_out: 0,
+        rng: Random::seeded(1),
+        sys: SysHandler::Panic,
+        key_wait: KeyWait::Release,
+        quirks: Quirks::default(),
+        write_protect: WriteProtect::Off,
+        uninit_reads: UninitReads::Off,
+        initialized: None,
+        uninit_hit: Cell::new(None),
+        watchpoints: Watchpoints::default(),
+        watch_hit: Cell::new(None),
+        write_log: WriteLog::default(),
+        breakpoints: BTreeSet::new(),
+        stopped_at: None,
+        break_hit: None,
+        break_on_draw: false,
+        draw_hit: None,
+        coverage: None,
+        hooks: Hooks::default(),
+        rpl: RplFlags::default(),
+        recorder: None,
+        #[cfg(feature = "megachip")]
+        megachip: None,
     };
     expected.load_font();
 
     assert_eq!(cpu, expected);
 }
 
+#[test]
+fn test_creating_eti660_cpu() {
+    let mut cpu = CPU::new(Variant::Eti660);
+    cpu.load(vec![0x00, 0xE0]).unwrap();
+
+    assert_eq!(cpu.pc, 0x600);
+    assert_eq!(cpu.memory[0x600], 0x00);
+    assert_eq!(cpu.memory[0x601], 0xE0);
+    assert_eq!(cpu.memory[0x200], 0x00);
+    assert_eq!(cpu.screen_height(), 48);
+}
+
+#[test]
+fn test_loading_roms_too_big_for_eti660() {
+    let rom = vec![0x12; 3000]; // Fits from 0x200, but not from 0x600.
+    assert!(CPU::new(Variant::Chip8).load(rom.clone()).is_ok());
+    assert_eq!(CPU::new(Variant::Eti660).load(rom), Err("ROM is 3000 bytes, eti660 allows 2560".to_string()));
+}
+
 #[test]
 fn test_loading_rom() {
     let cpu = create_test_cpu();
@@ -74,6 +138,200 @@ fn test_fetching_instructions() {
     assert_eq!(cpu.get_instruction(), 0x6401);
 }
 
+#[test]
+fn test_fetching_instruction_wraps_memory() {
+    let mut cpu = CPU::default();
+
+    cpu.memory[0xFFF] = 0x12;
+    cpu.memory[0x000] = 0x34;
+    cpu.pc = 0xFFF;
+    assert_eq!(cpu.get_instruction(), 0x1234);
+}
+
+#[test]
+fn test_write_protection() {
+    let mut cpu = CPU::default();
+    cpu.v[0] = 0xAB;
+
+    // Unprotected by default.
+    cpu.i = 0x1F0;
+    load_and_execute_instruction(&mut cpu, 0xF055);
+    assert_eq!(cpu.memory[0x1F0], 0xAB);
+
+    cpu.set_write_protect(WriteProtect::Block);
+    cpu.i = 0x1F1;
+    load_and_execute_instruction(&mut cpu, 0xF055);
+    assert_eq!(cpu.memory[0x1F1], 0);
+
+    // The fonts and the program aren't protected.
+    cpu.i = 0x000;
+    load_and_execute_instruction(&mut cpu, 0xF055);
+    assert_eq!(cpu.memory[0x000], 0xAB);
+    cpu.i = 0x200;
+    load_and_execute_instruction(&mut cpu, 0xF055);
+    assert_eq!(cpu.memory[0x200], 0xAB);
+
+    cpu.set_write_protect(WriteProtect::Log);
+    cpu.i = 0x1F1;
+    load_and_execute_instruction(&mut cpu, 0xF055);
+    assert_eq!(cpu.memory[0x1F1], 0xAB);
+}
+
+#[test]
+fn test_xo_chip_addresses_64k() {
+    let mut cpu = CPU::new(Variant::XoChip);
+    assert_eq!(cpu.memory.len(), 0x10000);
+
+    cpu.memory[0x202] = 0x12;
+    cpu.memory[0x203] = 0x34;
+    load_and_execute_instruction(&mut cpu, 0xF000);
+    cpu.v[0] = 7;
+    load_and_execute_instruction(&mut cpu, 0xF055);
+    assert_eq!(cpu.memory[0x1234], 7);
+    assert_eq!(cpu.memory[0x234], 0);
+
+    // Memory still wraps, at 64K instead.
+    cpu.memory[0xFFFF] = 0x12;
+    cpu.memory[0x0000] = 0x34;
+    cpu.pc = 0xFFFF;
+    assert_eq!(cpu.get_instruction(), 0x1234);
+}
+
+#[test]
+fn test_memory_access_wraps_out_of_range_i() {
+    let mut cpu = CPU::default();
+
+    // FX55 beyond the end of memory should wrap back to the start.
+    cpu.i = 0xFFE;
+    cpu.v[0] = 1;
+    cpu.v[1] = 2;
+    cpu.v[2] = 3;
+    load_and_execute_instruction(&mut cpu, 0xF255);
+    assert_eq!(cpu.memory[0xFFE], 1);
+    assert_eq!(cpu.memory[0xFFF], 2);
+    assert_eq!(cpu.memory[0x000], 3);
+
+    // FX65 should read back the same wrapped values.
+    cpu.v = [0; 16];
+    load_and_execute_instruction(&mut cpu, 0xF265);
+    assert_eq!(cpu.v[0], 1);
+    assert_eq!(cpu.v[1], 2);
+    assert_eq!(cpu.v[2], 3);
+
+    // DXYN should fetch sprite bytes from the wrapped addresses rather than panicking.
+    cpu.i = 0xFFF;
+    cpu.memory[0xFFF] = 0x80;
+    cpu.memory[0x000] = 0x80;
+    load_and_execute_instruction(&mut cpu, 0xD332);
+    assert_eq!(cpu.frame.get_pixel(0, 0), true);
+    assert_eq!(cpu.frame.get_pixel(1, 0), true);
+}
+
+#[test]
+fn test_ticking_timers() {
+    let mut cpu = CPU::default();
+
+    cpu.delay_timer = 2;
+    cpu.sound_timer = 1;
+
+    cpu.tick_timers();
+    assert_eq!(cpu.delay_timer, 1);
+    assert_eq!(cpu.sound_timer, 0);
+
+    // Timers stop at zero rather than wrapping.
+    cpu.tick_timers();
+    cpu.tick_timers();
+    assert_eq!(cpu.delay_timer, 0);
+    assert_eq!(cpu.sound_timer, 0);
+}
+
+#[test]
+fn test_stepping_frame() {
+    let mut cpu = create_test_cpu();
+
+    cpu.delay_timer = 10;
+    cpu.step_frame(3);
+
+    // Three instructions executed (none of which jump) and one timer tick.
+    assert_eq!(cpu.pc, 0x206);
+    assert_eq!(cpu.delay_timer, 9);
+}
+
+#[test]
+fn test_stepping_vip_frame() {
+    let mut cpu = CPU::default();
+
+    // 6XKK followed by a jump back to itself. Costs 154 machine cycles per loop.
+    cpu.memory[0x200..0x204].copy_from_slice(&[0x60, 0x01, 0x12, 0x00]);
+    cpu.delay_timer = 10;
+    cpu.step_vip_frame();
+
+    assert_eq!(cpu.delay_timer, 9);
+    assert!(cpu.cycle_debt < 80);
+}
+
+#[test]
+fn test_skipping_costs_vip_cycles() {
+    // Leave a single cycle of the frame, so one instruction runs and the rest of its cost is taken from the next.
+    let overspent = |opcode: [u8; 2]| {
+        let mut cpu = CPU::default();
+        cpu.memory[0x200..0x202].copy_from_slice(&opcode);
+        cpu.cycle_debt = vip_timing::CYCLES_PER_FRAME - 1;
+        cpu.step_vip_frame();
+        cpu.cycle_debt + 1
+    };
+
+    // SE V0, 0 skips and SE V0, 1 doesn't.
+    assert_eq!(overspent([0x30, 0x00]), 82);
+    assert_eq!(overspent([0x30, 0x01]), 78);
+}
+
+#[test]
+fn test_drawing_ends_vip_frame() {
+    let mut cpu = CPU::default();
+
+    // Draw then jump back to the start. The draw should end the frame after a single instruction, leaving the part
+    // after the display interrupt to the next frame.
+    cpu.memory[0x200..0x204].copy_from_slice(&[0xD0, 0x05, 0x12, 0x00]);
+    cpu.step_vip_frame();
+
+    assert_eq!(cpu.pc, 0x202);
+    assert_eq!(cpu.cycle_debt, 2 * (13 + 5 * 25));
+}
+
+#[test]
+fn test_cycling_does_not_tick_timers() {
+    let mut cpu = create_test_cpu();
+
+    cpu.delay_timer = 10;
+    for _ in 0..20 {
+        cpu.cycle();
+    }
+    assert_eq!(cpu.delay_timer, 10);
+}
+
+#[test]
+/// Every opcode the conformance table claims is implemented must execute without panicking.
+fn test_conformance_table_is_honest() {
+    for op in OPCODES.iter().filter(|op| op.implemented) {
+        let mut cpu = match op.platform {
+            Platform::Chip8X => CPU::new(Variant::Chip8X),
+            Platform::Chip8Hires => CPU::new(Variant::Chip8Hires),
+            Platform::XoChip => CPU::new(Variant::XoChip),
+            #[cfg(feature = "megachip")]
+            Platform::MegaChip => {
+                let mut cpu = CPU::new(Variant::MegaChip);
+                load_and_execute_instruction(&mut cpu, 0x0011);
+                cpu
+            }
+            _ => CPU::default(),
+        };
+        cpu.sp = 1; // Allow RET to pop without underflowing.
+        cpu.set_sys_handler(SysHandler::Ignore); // 0NNN panics by default.
+        load_and_execute_instruction(&mut cpu, op.sample);
+    }
+}
+
 #[test]
 fn test_setting_key() {
     let mut cpu = create_test_cpu();
@@ -112,6 +370,53 @@ fn test_opcode_00e0() {
     assert_eq!(cpu.pc, 0x202);
 }
 
+#[test]
+/// SYS should panic by default, or be skipped or trapped depending on the handler.
+fn test_opcode_0nnn() {
+    let result = std::panic::catch_unwind(|| {
+        let mut cpu = CPU::default();
+        load_and_execute_instruction(&mut cpu, 0x0123);
+    });
+    assert!(result.is_err());
+
+    let mut cpu = CPU::default();
+    cpu.set_sys_handler(SysHandler::Ignore);
+    load_and_execute_instruction(&mut cpu, 0x0123);
+    assert_eq!(cpu.pc, 0x202);
+
+    let mut cpu = CPU::default();
+    cpu.set_sys_handler(SysHandler::Callback(Box::new(|cpu, nnn| {
+        cpu.set_register(0x3, (nnn & 0xFF) as u8);
+    })));
+    load_and_execute_instruction(&mut cpu, 0x0145);
+    assert_eq!(cpu.register(0x3), 0x45);
+    assert_eq!(cpu.pc, 0x202);
+
+    // The callback is kept for the next SYS.
+    load_and_execute_instruction(&mut cpu, 0x0167);
+    assert_eq!(cpu.register(0x3), 0x67);
+}
+
+#[test]
+/// 0000 and, outside SCHIP, 00FD are SYS calls, so a SYS callback should see them rather than the CPU halting.
+fn test_sys_callbacks_take_over_halts() {
+    let mut cpu = CPU::new(Variant::Eti660);
+    cpu.load(vec![0x00, 0xFD, 0x00, 0x00]).unwrap();
+    assert_eq!(cpu.halt(), None);
+
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let seen = calls.clone();
+    cpu.set_sys_handler(SysHandler::Callback(Box::new(move |_, nnn| seen.borrow_mut().push(nnn))));
+    cpu.cycle();
+    assert_eq!(cpu.halt(), None);
+    cpu.cycle();
+    assert_eq!(*calls.borrow(), [0x0FD, 0x000]);
+
+    let mut cpu = CPU::default();
+    cpu.load(vec![0x00, 0x00]).unwrap();
+    assert_eq!(cpu.halt(), Some(Halt::EmptyMemory { addr: 0x200 }));
+}
+
 #[test]
 /// PC should jump to address pointed to on stack by SP.
 fn test_opcode_00ee() {
@@ -381,6 +686,86 @@ fn test_opcode_8xye() {
     assert_eq!(cpu.pc, 0x202);
 }
 
+#[test]
+/// With the shift-vx quirk, shifts should shift Vx in place and ignore Vy.
+fn test_opcode_8xy6_8xye_shift_vx() {
+    let mut cpu = CPU::default();
+    cpu.set_quirks(Quirks { shift_vx: true, ..Quirks::default() });
+
+    cpu.v[1] = 5;
+    cpu.v[2] = 64;
+    load_and_execute_instruction(&mut cpu, 0x8126);
+    assert_eq!((cpu.v[1], cpu.v[0xF]), (2, 1));
+
+    load_and_execute_instruction(&mut cpu, 0x812E);
+    assert_eq!((cpu.v[1], cpu.v[0xF]), (4, 0));
+}
+
+#[test]
+/// VF should hold the carry even when it is the destination, and be read as an operand before being overwritten.
+fn test_opcode_8xy4_writes_vf_last() {
+    let mut cpu = CPU::default();
+
+    cpu.v[0xF] = 200;
+    cpu.v[1] = 100;
+    load_and_execute_instruction(&mut cpu, 0x8F14);
+    assert_eq!(cpu.v[0xF], 1);
+
+    cpu.v[0xF] = 200;
+    cpu.v[1] = 100;
+    load_and_execute_instruction(&mut cpu, 0x81F4);
+    assert_eq!(cpu.v[1], 44);
+    assert_eq!(cpu.v[0xF], 1);
+}
+
+#[test]
+/// VF should hold the borrow flag even when it is an operand.
+fn test_opcode_8xy5_writes_vf_last() {
+    let mut cpu = CPU::default();
+
+    cpu.v[0xF] = 10;
+    cpu.v[1] = 30;
+    load_and_execute_instruction(&mut cpu, 0x8F15);
+    assert_eq!(cpu.v[0xF], 0);
+
+    cpu.v[0xF] = 10;
+    cpu.v[1] = 30;
+    load_and_execute_instruction(&mut cpu, 0x81F5);
+    assert_eq!(cpu.v[1], 20);
+    assert_eq!(cpu.v[0xF], 1);
+}
+
+#[test]
+/// VF should hold the borrow flag even when it is an operand.
+fn test_opcode_8xy7_writes_vf_last() {
+    let mut cpu = CPU::default();
+
+    cpu.v[0xF] = 10;
+    cpu.v[1] = 30;
+    load_and_execute_instruction(&mut cpu, 0x8F17);
+    assert_eq!(cpu.v[0xF], 1);
+
+    cpu.v[0xF] = 30;
+    cpu.v[1] = 10;
+    load_and_execute_instruction(&mut cpu, 0x81F7);
+    assert_eq!(cpu.v[1], 20);
+    assert_eq!(cpu.v[0xF], 1);
+}
+
+#[test]
+/// Shifting VF should leave the shifted out bit in VF.
+fn test_shifts_write_vf_last() {
+    let mut cpu = CPU::default();
+
+    cpu.v[0xF] = 0b0000_0011;
+    load_and_execute_instruction(&mut cpu, 0x8FF6);
+    assert_eq!(cpu.v[0xF], 1);
+
+    cpu.v[0xF] = 0b0100_0000;
+    load_and_execute_instruction(&mut cpu, 0x8FFE);
+    assert_eq!(cpu.v[0xF], 0);
+}
+
 #[test]
 /// Should skip next instruction if Vx != Vy.
 fn test_opcode_9xy0() {
@@ -441,6 +826,21 @@ fn test_opcode_cxkk() {
     }
 }
 
+#[test]
+/// The VIP source should walk the program, adding each byte to the last result.
+fn test_opcode_cxkk_vip() {
+    let mut cpu = CPU::default();
+    cpu.set_rng(Random::Vip(VipRandom::default()));
+    cpu.memory[0x201] = 0xF0;
+    cpu.memory[0x202] = 0x10;
+
+    load_and_execute_instruction(&mut cpu, 0xC0FF);
+    assert_eq!(cpu.v[0], 0xF0);
+
+    load_and_execute_instruction(&mut cpu, 0xC0FF);
+    assert_eq!(cpu.v[0], 0x00); // 0xF0 + 0x10 wraps around.
+}
+
 #[test]
 /// Should draw the sprite at given position. Sprite is a 0 for this case.
 fn test_opcode_dxyn() {
@@ -475,6 +875,59 @@ fn test_opcode_dxyn() {
     assert_eq!(cpu.frame.get_pixel(4, 2), true);
     assert_eq!(cpu.frame.get_pixel(4, 3), true);
     assert_eq!(cpu.frame.get_pixel(4, 4), false);
+    assert_eq!(cpu.v[0xF], 0);
+
+    // Drawing the same sprite again erases it and reports the collision in VF.
+    load_and_execute_instruction(&mut cpu, 0xD005);
+    assert_eq!(cpu.frame.get_pixel(0, 0), false);
+    assert_eq!(cpu.v[0xF], 1);
+
+    // Drawing without overlap clears VF again.
+    cpu.v[1] = 10;
+    load_and_execute_instruction(&mut cpu, 0xD015);
+    assert_eq!(cpu.v[0xF], 0);
+}
+
+#[test]
+/// Should draw a 16x16 sprite from the 32 bytes at I in hires mode, setting VF on collision.
+fn test_opcode_dxy0() {
+    let mut cpu = CPU::default();
+    load_and_execute_instruction(&mut cpu, 0x00FF);
+    cpu.i = 0x300;
+    cpu.memory[0x300] = 0x80;
+    cpu.memory[0x301] = 0x01;
+    cpu.memory[0x31F] = 0xFF;
+    cpu.v[0] = 8;
+
+    load_and_execute_instruction(&mut cpu, 0xD000);
+    assert_eq!(cpu.frame.get_pixel(8, 8), true);
+    assert_eq!(cpu.frame.get_pixel(8, 23), true);
+    assert_eq!(cpu.frame.get_pixel(8, 9), false);
+    assert_eq!(cpu.frame.get_pixel(23, 16), true);
+    assert_eq!(cpu.frame.get_pixel(23, 23), true);
+    assert_eq!(cpu.frame.get_pixel(23, 15), false);
+    assert_eq!(cpu.v[0xF], 0);
+
+    load_and_execute_instruction(&mut cpu, 0xD000);
+    assert_eq!(cpu.frame.get_pixel(8, 8), false);
+    assert_eq!(cpu.v[0xF], 1);
+}
+
+#[test]
+/// CHIP-8 in lores should draw nothing for DXY0, as on the VIP, while XO-CHIP draws 16x16 in either mode.
+fn test_opcode_dxy0_lores() {
+    for variant in [Variant::Chip8, Variant::Chip8X, Variant::Eti660, Variant::XoChip] {
+        let mut cpu = CPU::new(variant);
+        cpu.i = 0x300;
+        cpu.memory[0x300..0x320].fill(0xFF);
+        cpu.v[0xF] = 1;
+
+        load_and_execute_instruction(&mut cpu, 0xD000);
+        let drawn = cpu.frame.get_pixel(0, 0);
+        assert_eq!(drawn, variant == Variant::XoChip, "{}", variant);
+        assert_eq!(cpu.frame.get_pixel(15, 15), drawn, "{}", variant);
+        assert_eq!(cpu.v[0xF], 0, "{}", variant);
+    }
 }
 
 #[test]
@@ -522,15 +975,105 @@ fn test_opcode_fx0a() {
     let mut cpu = CPU::default();
 
     load_and_execute_instruction(&mut cpu, 0xf00a);
-    assert_eq!(cpu.pc, 0x200);
+    assert_eq!(cpu.state, State::WaitingForKey { x: 0, ignored: 0, pressed: None });
+    assert_eq!(cpu.pc, 0x202);
 
-    cpu.execute_instruction(0xf00a);
-    assert_eq!(cpu.pc, 0x200);
+    // Nothing executes while waiting.
+    cpu.cycle();
+    assert_eq!(cpu.pc, 0x202);
 
-    cpu.keypad.set_pressed(0xD);
-    cpu.execute_instruction(0xf00a);
-    assert_eq!(cpu.v[0], 0xD);
+    // The key only counts once released.
+    cpu.keypad.set_pressed(0xF);
+    cpu.cycle();
+    assert_eq!(cpu.state, State::WaitingForKey { x: 0, ignored: 0, pressed: Some(0xF) });
+    assert_eq!(cpu.v[0], 0);
+
+    cpu.keypad.clear();
+    cpu.cycle();
+    assert_eq!(cpu.state, State::Running);
+    assert_eq!(cpu.v[0], 0xF);
+    assert_eq!(cpu.pc, 0x202);
+}
+
+// Run FX0A into V0 with key 5 already held, then release it, press 7 and release that. Returns V0 after each step,
+// or None while still waiting.
+fn wait_with_held_key(key_wait: KeyWait) -> Vec<Option<u8>> {
+    let mut cpu = CPU::default();
+    cpu.set_key_wait(key_wait);
+    cpu.v[0] = 0xFF;
+    cpu.set_key(5);
+    load_and_execute_instruction(&mut cpu, 0xF00A);
+
+    let mut results = Vec::new();
+    for key in [Some(5), None, Some(7), None].iter() {
+        match key {
+            Some(k) => cpu.set_key(*k),
+            None => cpu.clear_keys(),
+        }
+
+        if cpu.waiting_for_key() {
+            cpu.cycle();
+        }
+        results.push(if cpu.waiting_for_key() { None } else { Some(cpu.v[0]) });
+    }
+    results
+}
+
+#[test]
+/// On the COSMAC VIP a key held before FX0A counts once it is released.
+fn test_opcode_fx0a_held_key_release() {
+    assert_eq!(wait_with_held_key(KeyWait::Release), vec![None, Some(5), Some(5), Some(5)]);
+}
+
+#[test]
+/// Counting keys on press accepts a key held before FX0A straight away.
+fn test_opcode_fx0a_held_key_press() {
+    assert_eq!(wait_with_held_key(KeyWait::Press), vec![Some(5), Some(5), Some(5), Some(5)]);
+}
+
+#[test]
+/// Fresh presses ignore a key held before FX0A and take the next key pressed and released.
+fn test_opcode_fx0a_held_key_fresh_press() {
+    assert_eq!(wait_with_held_key(KeyWait::FreshPress), vec![None, None, None, Some(7)]);
+}
+
+#[test]
+/// Frames should stop early while waiting for a key but the timers still tick.
+fn test_frames_while_waiting_for_key() {
+    let mut cpu = CPU::default();
+    cpu.delay_timer = 10;
+
+    load_and_execute_instruction(&mut cpu, 0xf00a);
+    cpu.step_frame(9);
+    assert_eq!(cpu.pc, 0x202);
+    assert_eq!(cpu.delay_timer, 9);
+
+    cpu.step_vip_frame();
     assert_eq!(cpu.pc, 0x202);
+    assert_eq!(cpu.delay_timer, 8);
+    assert_eq!(cpu.cycle_debt, 0);
+}
+
+#[test]
+/// A jump to itself or empty memory should halt the program, ending frames early while the timers still tick.
+fn test_halting() {
+    let mut cpu = CPU::default();
+    cpu.load(vec![0x60, 0x01, 0x12, 0x02]).unwrap();
+    cpu.delay_timer = 10;
+
+    cpu.step_frame(9);
+    assert_eq!(cpu.halt(), Some(Halt::JumpToSelf { addr: 0x202 }));
+    assert_eq!(cpu.v[0], 1);
+    assert_eq!(cpu.delay_timer, 9);
+
+    cpu.step_vip_frame();
+    assert_eq!(cpu.pc, 0x202);
+    assert_eq!(cpu.delay_timer, 8);
+
+    cpu.pc = 0x204;
+    assert_eq!(cpu.halt(), Some(Halt::EmptyMemory { addr: 0x204 }));
+    cpu.cycle();
+    assert_eq!(cpu.pc, 0x204);
 }
 
 #[test]
@@ -544,7 +1087,8 @@ fn test_opcode_fx15() {
     assert_eq!(cpu.pc, 0x202);
 }
 
-/// Should store the value of Vx in the delay timer.
+#[test]
+/// Should store the value of Vx in the sound timer.
 fn test_opcode_fx18() {
     let mut cpu = CPU::default();
 
@@ -587,8 +1131,121 @@ fn test_opcode_fx29() {
 }
 
 #[test]
-/// TODO -- Should store binary-coded decimal representation of Vx in memory at locations I, I + 1 and I + 2.
-fn test_opcode_fx33() {}
+/// Should point I at the big sprite for the digit in Vx.
+fn test_opcode_fx30() {
+    let mut cpu = CPU::default();
+
+    cpu.v[0] = 0;
+    load_and_execute_instruction(&mut cpu, 0xF030);
+    assert_eq!(cpu.i, 0x50);
+    assert_eq!(cpu.memory[0x50], 0x3C);
+
+    cpu.v[0] = 9;
+    load_and_execute_instruction(&mut cpu, 0xF030);
+    assert_eq!(cpu.i, 0xAA);
+    assert_eq!(cpu.memory[0xAA..0xB4], [0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C]);
+}
+
+#[test]
+/// Should store registers Vx to Vy at I, backwards when x > y, leaving I alone.
+fn test_opcode_5xy2() {
+    let mut cpu = CPU::default();
+
+    cpu.i = 0x300;
+    cpu.v[..4].copy_from_slice(&[1, 2, 3, 4]);
+    load_and_execute_instruction(&mut cpu, 0x5132);
+    assert_eq!(cpu.memory[0x300..0x303], [2, 3, 4]);
+    assert_eq!(cpu.i, 0x300);
+
+    load_and_execute_instruction(&mut cpu, 0x5202);
+    assert_eq!(cpu.memory[0x300..0x303], [3, 2, 1]);
+    assert_eq!(cpu.pc, 0x202);
+}
+
+#[test]
+/// Should load registers Vx to Vy from I, backwards when x > y, leaving I alone.
+fn test_opcode_5xy3() {
+    let mut cpu = CPU::default();
+
+    cpu.i = 0x300;
+    cpu.memory[0x300..0x303].copy_from_slice(&[7, 8, 9]);
+    load_and_execute_instruction(&mut cpu, 0x5463);
+    assert_eq!(cpu.v[4..7], [7, 8, 9]);
+
+    load_and_execute_instruction(&mut cpu, 0x5A83);
+    assert_eq!(cpu.v[8..11], [9, 8, 7]);
+    assert_eq!(cpu.i, 0x300);
+}
+
+#[test]
+/// Should select planes for drawing, each plane drawing its own sprite from I onwards.
+fn test_opcode_fn01() {
+    let mut cpu = CPU::default();
+
+    load_and_execute_instruction(&mut cpu, 0xF301);
+    assert_eq!(cpu.frame.selected_planes(), 0b11);
+
+    cpu.i = 0x300;
+    cpu.memory[0x300] = 0x80;
+    cpu.memory[0x301] = 0x40;
+    load_and_execute_instruction(&mut cpu, 0xD001);
+    assert_eq!(cpu.frame.plane(0)[0], 1 << 63);
+    assert_eq!(cpu.frame.plane(1)[0], 1 << 62);
+
+    // Only plane 1 is selected, so it draws the first sprite.
+    load_and_execute_instruction(&mut cpu, 0xF201);
+    load_and_execute_instruction(&mut cpu, 0xD001);
+    assert_eq!(cpu.frame.plane(0)[0], 1 << 63);
+    assert_eq!(cpu.frame.plane(1)[0], 1 << 63 | 1 << 62);
+    assert_eq!(cpu.v[0xF], 0);
+
+    // No planes are selected, so nothing is drawn.
+    load_and_execute_instruction(&mut cpu, 0xF001);
+    load_and_execute_instruction(&mut cpu, 0xD001);
+    assert_eq!(cpu.frame.plane(1)[0], 1 << 63 | 1 << 62);
+}
+
+#[test]
+/// Should set I to the 16-bit address after the opcode and move on past it.
+fn test_opcode_f000() {
+    let mut cpu = CPU::default();
+
+    cpu.memory[0x202] = 0x12;
+    cpu.memory[0x203] = 0x34;
+    load_and_execute_instruction(&mut cpu, 0xF000);
+    assert_eq!(cpu.i, 0x1234);
+    assert_eq!(cpu.pc, 0x204);
+}
+
+#[test]
+/// Skips should jump over the whole 4 bytes of LD I, long nnnn.
+fn test_skipping_long_index_loads() {
+    let mut cpu = CPU::default();
+
+    cpu.memory[0x202] = 0xF0;
+    cpu.memory[0x203] = 0x00;
+    load_and_execute_instruction(&mut cpu, 0x3000);
+    assert_eq!(cpu.pc, 0x206);
+
+    // Not skipping moves on to the long load as usual.
+    load_and_execute_instruction(&mut cpu, 0x4000);
+    assert_eq!(cpu.pc, 0x202);
+}
+
+#[test]
+/// Should store binary-coded decimal representation of Vx in memory at locations I, I + 1 and I + 2.
+fn test_opcode_fx33() {
+    let mut cpu = CPU::default();
+
+    cpu.i = 0x300;
+    cpu.v[4] = 254;
+    load_and_execute_instruction(&mut cpu, 0xF433);
+
+    assert_eq!(cpu.memory[0x300], 2);
+    assert_eq!(cpu.memory[0x301], 5);
+    assert_eq!(cpu.memory[0x302], 4);
+    assert_eq!(cpu.pc, 0x202);
+}
 
 #[test]
 /// Should store registers V0 to Vx in memory starting at location I.
@@ -644,3 +1301,564 @@ fn test_opcode_fx65() {
 
     assert_eq!(cpu.pc, 0x202);
 }
+
+#[test]
+/// With the increment-i quirk, loads and stores should leave I just after the last register.
+fn test_opcode_fx55_fx65_increment_i() {
+    let mut cpu = CPU::default();
+    cpu.set_quirks(Quirks { increment_i: true, ..Quirks::default() });
+    cpu.i = 0x300;
+
+    load_and_execute_instruction(&mut cpu, 0xF255);
+    assert_eq!(cpu.i, 0x303);
+
+    load_and_execute_instruction(&mut cpu, 0xF065);
+    assert_eq!(cpu.i, 0x304);
+}
+
+#[test]
+/// Should scroll the screen down n rows.
+fn test_opcode_00cn() {
+    let mut cpu = CPU::default();
+    cpu.frame.set_pixel(0, 0, true);
+
+    load_and_execute_instruction(&mut cpu, 0x00C3);
+    assert_eq!(cpu.frame.get_pixel(3, 0), true);
+    assert_eq!(cpu.frame.get_pixel(0, 0), false);
+}
+
+#[test]
+/// Should scroll the selected planes up n rows.
+fn test_opcode_00dn() {
+    let mut cpu = CPU::default();
+    cpu.frame.set_pixel(5, 0, true);
+    cpu.frame.select_planes(0b10);
+    cpu.frame.set_pixel(5, 1, true);
+
+    load_and_execute_instruction(&mut cpu, 0x00D3);
+    assert_eq!(cpu.frame.get_pixel(2, 1), true);
+    cpu.frame.select_planes(0b01);
+    assert_eq!(cpu.frame.get_pixel(5, 0), true); // Planes which aren't selected stay put.
+}
+
+#[test]
+/// Should scroll the screen right 4 pixels.
+fn test_opcode_00fb() {
+    let mut cpu = CPU::default();
+    cpu.frame.set_pixel(0, 0, true);
+
+    load_and_execute_instruction(&mut cpu, 0x00FB);
+    assert_eq!(cpu.frame.get_pixel(0, 4), true);
+    assert_eq!(cpu.frame.get_pixel(0, 0), false);
+}
+
+#[test]
+/// Should scroll the screen left 4 pixels.
+fn test_opcode_00fc() {
+    let mut cpu = CPU::default();
+    cpu.frame.set_pixel(0, 4, true);
+
+    load_and_execute_instruction(&mut cpu, 0x00FC);
+    assert_eq!(cpu.frame.get_pixel(0, 0), true);
+    assert_eq!(cpu.frame.get_pixel(0, 4), false);
+}
+
+#[test]
+/// Should switch back to the variant's own screen, clearing it.
+fn test_opcode_00fe() {
+    let mut cpu = CPU::new(Variant::Eti660);
+    load_and_execute_instruction(&mut cpu, 0x00FF);
+    cpu.frame.set_pixel(0, 100, true);
+
+    load_and_execute_instruction(&mut cpu, 0x00FE);
+    assert_eq!(cpu.hires(), false);
+    assert_eq!((cpu.frame.width(), cpu.screen_height()), (64, 48));
+    assert_eq!(cpu.get_framebuffer(), vec![0; 64 * 48]);
+}
+
+#[test]
+/// Should switch to the 128x64 hires screen, clearing it.
+fn test_opcode_00ff() {
+    let mut cpu = CPU::default();
+    cpu.frame.set_pixel(0, 0, true);
+
+    load_and_execute_instruction(&mut cpu, 0x00FF);
+    assert_eq!(cpu.hires(), true);
+    assert_eq!((cpu.frame.width(), cpu.screen_height()), (128, 64));
+    assert_eq!(cpu.frame.get_pixel(0, 0), false);
+
+    // Sprites can be drawn anywhere on the wider screen.
+    cpu.v[0] = 120;
+    cpu.v[1] = 60;
+    load_and_execute_instruction(&mut cpu, 0xD015);
+    assert_eq!(cpu.frame.get_pixel(60, 120), true);
+    assert_eq!(cpu.frame.get_pixel(63, 123), true);
+}
+
+#[test]
+/// EXIT should leave the program counter where it is, halting the CPU.
+fn test_opcode_00fd() {
+    let mut cpu = CPU::default();
+    load_and_execute_instruction(&mut cpu, 0x00FD);
+    assert_eq!(cpu.pc, 0x200);
+
+    cpu.load(vec![0x00, 0xFD]).unwrap();
+    assert_eq!(cpu.halt(), Some(Halt::Exit { addr: 0x200 }));
+}
+
+#[test]
+/// Should save registers V0 to Vx in the RPL user flags, which outlive the CPU.
+fn test_opcode_fx75() {
+    let path = std::env::temp_dir().join(format!("chip8-fx75-{}.flags", std::process::id()));
+    let mut cpu = CPU::default();
+    cpu.set_rpl_flags(RplFlags::load(&path).unwrap());
+
+    cpu.v[0] = 12;
+    cpu.v[2] = 34;
+    cpu.v[3] = 56;
+
+    load_and_execute_instruction(&mut cpu, 0xF275);
+
+    assert_eq!(cpu.rpl_flags().flags(), &[12, 0, 34, 0, 0, 0, 0, 0]);
+    assert_eq!(RplFlags::load(&path).unwrap().flags(), cpu.rpl_flags().flags());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+/// Should load registers V0 to Vx from the RPL user flags. Only V0 to V7 have flags.
+fn test_opcode_fx85() {
+    let mut cpu = CPU::default();
+    let mut rpl = RplFlags::default();
+    rpl.save(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+    cpu.set_rpl_flags(rpl);
+    cpu.v[8] = 99;
+
+    load_and_execute_instruction(&mut cpu, 0xFF85);
+
+    assert_eq!(cpu.v[..9], [1, 2, 3, 4, 5, 6, 7, 8, 99]);
+}
+
+#[test]
+/// Should cycle the background colour.
+fn test_opcode_02a0() {
+    let mut cpu = CPU::new(Variant::Chip8X);
+
+    load_and_execute_instruction(&mut cpu, 0x02A0);
+    assert_eq!(cpu.colour_zones().unwrap().colourise(&[0])[0], [0, 0, 0]);
+    assert_eq!(cpu.pc, 0x202);
+}
+
+#[test]
+/// Should add each nibble of Vx and Vy modulo 8.
+fn test_opcode_5xy1() {
+    let mut cpu = CPU::new(Variant::Chip8X);
+
+    cpu.v[1] = 0x16;
+    cpu.v[2] = 0x13;
+    load_and_execute_instruction(&mut cpu, 0x5121);
+    assert_eq!(cpu.v[1], 0x21);
+    assert_eq!(cpu.pc, 0x202);
+
+    // Without CHIP-8X 5XY1 is not an instruction.
+    let result = std::panic::catch_unwind(|| {
+        let mut cpu = CPU::default();
+        load_and_execute_instruction(&mut cpu, 0x5121);
+    });
+    assert!(result.is_err());
+}
+
+#[test]
+/// Should set the colour of the zones given by Vx and Vx+1 to Vy.
+fn test_opcode_bxyn() {
+    let mut cpu = CPU::new(Variant::Chip8X);
+    let mut pixels = vec![0; 64 * 32];
+    pixels[64 * 5 + 9] = 1;
+
+    // Zone column 1, pixel row 5, colour green.
+    cpu.v[0] = 0x01;
+    cpu.v[1] = 5;
+    cpu.v[2] = 4;
+    load_and_execute_instruction(&mut cpu, 0xB021);
+    assert_eq!(cpu.colour_zones().unwrap().colourise(&pixels)[64 * 5 + 9], [0, 255, 0]);
+    assert_eq!(cpu.pc, 0x202);
+}
+
+#[test]
+/// Should skip the next instruction if key Vx is pressed on the second keypad.
+fn test_opcode_exf2() {
+    let mut cpu = CPU::new(Variant::Chip8X);
+
+    cpu.v[0] = 0x3;
+    cpu.set_key(0x3);
+    load_and_execute_instruction(&mut cpu, 0xE0F2);
+    assert_eq!(cpu.pc, 0x202);
+
+    cpu.set_key2(0x3);
+    load_and_execute_instruction(&mut cpu, 0xE0F2);
+    assert_eq!(cpu.pc, 0x204);
+}
+
+#[test]
+/// Should skip the next instruction if key Vx is not pressed on the second keypad.
+fn test_opcode_exf5() {
+    let mut cpu = CPU::new(Variant::Chip8X);
+
+    cpu.v[0] = 0x3;
+    load_and_execute_instruction(&mut cpu, 0xE0F5);
+    assert_eq!(cpu.pc, 0x204);
+
+    cpu.set_key2(0x3);
+    load_and_execute_instruction(&mut cpu, 0xE0F5);
+    assert_eq!(cpu.pc, 0x202);
+}
+
+#[test]
+/// Should write Vx to the output port.
+fn test_opcode_fxf8() {
+    let mut cpu = CPU::new(Variant::Chip8X);
+
+    cpu.v[4] = 0x80;
+    load_and_execute_instruction(&mut cpu, 0xF4F8);
+    assert_eq!(cpu.port_out, 0x80);
+    assert_eq!(cpu.pc, 0x202);
+}
+
+#[test]
+/// Hi-res CHIP-8 should clear the whole 64x64 screen.
+fn test_opcode_0230() {
+    let mut cpu = CPU::new(Variant::Chip8Hires);
+    cpu.frame.set_pixel(0, 0, true);
+    cpu.frame.set_pixel(63, 63, true);
+
+    load_and_execute_instruction(&mut cpu, 0x0230);
+    assert_eq!(cpu.frame.get_pixel(0, 0), false);
+    assert_eq!(cpu.frame.get_pixel(63, 63), false);
+    assert_eq!(cpu.pc, 0x202);
+}
+
+#[test]
+/// 0230 is only an instruction on hi-res CHIP-8.
+fn test_opcode_0230_needs_chip8_hires() {
+    let result = std::panic::catch_unwind(|| {
+        let mut cpu = CPU::default();
+        load_and_execute_instruction(&mut cpu, 0x0230);
+    });
+    assert!(result.is_err());
+}
+
+#[test]
+/// A hi-res ROM's jump into the interpreter's hi-res patch should go straight to the program at 0x2C0 instead.
+fn test_loading_hires_rom() {
+    let mut cpu = CPU::new(Variant::Chip8Hires);
+    cpu.load(vec![0x12, 0x60, 0x00, 0xE0]).unwrap();
+    assert_eq!(cpu.screen_height(), 64);
+
+    cpu.cycle();
+    assert_eq!(cpu.pc, 0x2C0);
+
+    // Other ROMs are left as they are.
+    let mut cpu = CPU::new(Variant::Chip8Hires);
+    cpu.load(vec![0x12, 0x04]).unwrap();
+    cpu.cycle();
+    assert_eq!(cpu.pc, 0x204);
+}
+
+#[test]
+/// A serialized CPU should restore to the same machine, minus the host's settings.
+fn test_serializing_cpu() {
+    let mut cpu = CPU::new(Variant::Chip8X);
+    cpu.load(vec![0x6A, 0x05, 0xA3, 0x00, 0xFA, 0x55, 0xF0, 0x29, 0xD0, 0x15, 0x12, 0x00]).unwrap();
+    cpu.set_key(0x3);
+    cpu.set_sys_handler(SysHandler::Log);
+    cpu.step_vip_frame();
+
+    let bytes = bincode::serialize(&cpu).unwrap();
+    let mut restored: CPU = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(restored.sys, SysHandler::Panic);
+
+    restored.set_sys_handler(SysHandler::Log);
+    assert_eq!(restored, cpu);
+    assert_eq!(restored.get_framebuffer(), cpu.get_framebuffer());
+}
+
+#[test]
+/// Resetting should put the machine back as it was after loading, including memory the program has written to.
+fn test_reset() {
+    let rom = vec![0x6A, 0x05, 0xA3, 0x00, 0xFA, 0x55, 0xF0, 0x29, 0xD0, 0x15, 0x12, 0x00];
+    let mut cpu = CPU::default();
+    cpu.load(rom.clone()).unwrap();
+    cpu.set_sys_handler(SysHandler::Ignore);
+    let loaded = cpu.state_hash();
+
+    cpu.step_frame(5);
+    assert_ne!(cpu.state_hash(), loaded);
+
+    cpu.reset();
+    assert_eq!(cpu.state_hash(), loaded);
+    assert_eq!(cpu.pc, 0x200);
+    assert_eq!(cpu.rom, rom);
+    assert_eq!(cpu.sys, SysHandler::Ignore);
+}
+
+#[cfg(feature = "megachip")]
+fn create_megachip_cpu() -> CPU {
+    let mut cpu = CPU::new(Variant::MegaChip);
+    load_and_execute_instruction(&mut cpu, 0x0011);
+    cpu
+}
+
+#[test]
+#[cfg(feature = "megachip")]
+/// MegaChip instructions should only work after 0011 switches MegaChip mode on.
+fn test_opcode_0011() {
+    let mut cpu = CPU::new(Variant::MegaChip);
+    assert_eq!(cpu.megachip().unwrap().enabled(), false);
+
+    load_and_execute_instruction(&mut cpu, 0x0011);
+    assert_eq!(cpu.megachip().unwrap().enabled(), true);
+    assert_eq!(cpu.screen_size(), (256, 192));
+}
+
+#[test]
+#[cfg(feature = "megachip")]
+fn test_opcode_0010() {
+    let mut cpu = create_megachip_cpu();
+    load_and_execute_instruction(&mut cpu, 0x0010);
+    assert_eq!(cpu.megachip().unwrap().enabled(), false);
+}
+
+#[test]
+#[cfg(feature = "megachip")]
+/// Should set I to a 24-bit address made from NN and the following word, then skip over that word.
+fn test_opcode_01nn() {
+    let mut cpu = create_megachip_cpu();
+    cpu.memory[0x202] = 0x34;
+    cpu.memory[0x203] = 0x56;
+
+    load_and_execute_instruction(&mut cpu, 0x0112);
+    assert_eq!(cpu.i, 0x123456);
+    assert_eq!(cpu.pc, 0x204);
+}
+
+#[test]
+#[cfg(feature = "megachip")]
+/// Sprites should be drawn in the colours loaded into the palette, and only shown after 00E0.
+fn test_opcode_02nn() {
+    let mut cpu = create_megachip_cpu();
+    cpu.i = 0x10000;
+    cpu.memory[0x10000..0x10004].copy_from_slice(&[0xFF, 10, 20, 30]);
+    cpu.memory[0x10004] = 1; // One pixel sprite of colour 1.
+
+    load_and_execute_instruction(&mut cpu, 0x0201);
+    cpu.i = 0x10004;
+    cpu.v[0] = 3;
+    load_and_execute_instruction(&mut cpu, 0xD000);
+    assert_eq!(cpu.megachip().unwrap().render()[3 * 256 + 3], [0, 0, 0]);
+
+    load_and_execute_instruction(&mut cpu, 0x00E0);
+    assert_eq!(cpu.megachip().unwrap().render()[3 * 256 + 3], [10, 20, 30]);
+}
+
+#[test]
+#[cfg(feature = "megachip")]
+fn test_opcode_03nn() {
+    let mut cpu = create_megachip_cpu();
+    load_and_execute_instruction(&mut cpu, 0x0300);
+    load_and_execute_instruction(&mut cpu, 0x0402);
+    assert_eq!(cpu.megachip().unwrap().sprite_len(), 512);
+}
+
+#[test]
+#[cfg(feature = "megachip")]
+fn test_opcode_04nn() {
+    let mut cpu = create_megachip_cpu();
+    load_and_execute_instruction(&mut cpu, 0x0403);
+    assert_eq!(cpu.megachip().unwrap().sprite_len(), 3);
+}
+
+#[test]
+#[cfg(feature = "megachip")]
+fn test_opcode_05nn() {
+    let mut cpu = create_megachip_cpu();
+    load_and_execute_instruction(&mut cpu, 0x0500);
+    assert_eq!(cpu.pc, 0x202);
+}
+
+#[test]
+#[cfg(feature = "megachip")]
+/// Should play the sound at I, and 0700 should stop it.
+fn test_opcode_060n() {
+    let mut cpu = create_megachip_cpu();
+    cpu.i = 0x1000;
+    cpu.memory[0x1000..0x1008].copy_from_slice(&[0x1F, 0x40, 0x00, 0x00, 0x02, 0x00, 0x80, 0x81]);
+
+    load_and_execute_instruction(&mut cpu, 0x0600);
+    let sound = cpu.megachip().unwrap().sound().unwrap();
+    assert_eq!(sound.samples, vec![0x80, 0x81]);
+    assert_eq!(sound.looping, true);
+
+    load_and_execute_instruction(&mut cpu, 0x0700);
+    assert_eq!(cpu.megachip().unwrap().sound(), None);
+}
+
+#[test]
+#[cfg(feature = "megachip")]
+fn test_opcode_0700() {
+    let mut cpu = create_megachip_cpu();
+    load_and_execute_instruction(&mut cpu, 0x0700);
+    assert_eq!(cpu.megachip().unwrap().sound(), None);
+}
+
+#[test]
+#[cfg(feature = "megachip")]
+fn test_opcode_080n() {
+    let mut cpu = create_megachip_cpu();
+    load_and_execute_instruction(&mut cpu, 0x0802);
+    load_and_execute_instruction(&mut cpu, 0x080F); // Unknown modes are ignored.
+    assert_eq!(cpu.pc, 0x202);
+}
+
+#[test]
+#[cfg(feature = "megachip")]
+/// DXYN should set VF when the sprite draws over the collision colour.
+fn test_opcode_09nn() {
+    let mut cpu = create_megachip_cpu();
+    cpu.memory[0x1000] = 2;
+    cpu.i = 0x1000;
+
+    load_and_execute_instruction(&mut cpu, 0x0902);
+    load_and_execute_instruction(&mut cpu, 0xD000);
+    assert_eq!(cpu.v[0xF], 0);
+
+    load_and_execute_instruction(&mut cpu, 0xD000);
+    assert_eq!(cpu.v[0xF], 1);
+}
+
+#[test]
+#[cfg(feature = "megachip")]
+fn test_opcode_00bn() {
+    let mut cpu = create_megachip_cpu();
+    cpu.i = 0x10000;
+    cpu.memory[0x10000..0x10004].copy_from_slice(&[0xFF, 10, 20, 30]);
+    cpu.memory[0x10004] = 1;
+    load_and_execute_instruction(&mut cpu, 0x0201);
+
+    cpu.i = 0x10004;
+    cpu.v[1] = 5;
+    load_and_execute_instruction(&mut cpu, 0xD010);
+    load_and_execute_instruction(&mut cpu, 0x00B2);
+    load_and_execute_instruction(&mut cpu, 0x00E0);
+    assert_eq!(cpu.megachip().unwrap().render()[3 * 256], [10, 20, 30]);
+}
+
+#[test]
+/// Execute watchpoints should report fetching an instruction in range, and stop the frame there.
+fn test_execute_watchpoint() {
+    let mut cpu = create_test_cpu();
+    cpu.watchpoints_mut().add("x:204-205".parse().unwrap());
+
+    cpu.step_frame(9);
+    let hit = cpu.take_watch_hit().unwrap();
+    assert_eq!(hit, WatchHit { addr: 0x204, access: Access::EXECUTE, pc: 0x204, opcode: 0x6401 });
+    assert_eq!(cpu.pc, 0x206);
+
+    // Hits are only reported once.
+    assert_eq!(cpu.take_watch_hit(), None);
+}
+
+#[test]
+/// Read and write watchpoints should only report the kinds of access they filter for.
+fn test_read_and_write_watchpoints() {
+    let mut cpu = CPU::default();
+    cpu.watchpoints_mut().add("r:300-30F".parse().unwrap());
+    cpu.i = 0x300;
+
+    // FX55 writes 0x300 to 0x302 which is not watched for writes.
+    load_and_execute_instruction(&mut cpu, 0xF255);
+    assert_eq!(cpu.take_watch_hit(), None);
+
+    // FX65 reads them back.
+    load_and_execute_instruction(&mut cpu, 0xF265);
+    let hit = cpu.take_watch_hit().unwrap();
+    assert_eq!((hit.addr, hit.access), (0x300, Access::READ));
+
+    cpu.watchpoints_mut().add("w:302".parse().unwrap());
+    load_and_execute_instruction(&mut cpu, 0xF255);
+    let hit = cpu.take_watch_hit().unwrap();
+    assert_eq!((hit.addr, hit.access, hit.pc), (0x302, Access::WRITE, 0x200));
+}
+
+#[test]
+/// Sprite reads and BCD writes should be watched too, reporting the instruction which made them.
+fn test_watchpoints_report_the_instruction() {
+    let mut cpu = CPU::default();
+    cpu.load(vec![0xA3, 0x00, 0xD0, 0x11, 0xF0, 0x33]).unwrap();
+    cpu.watchpoints_mut().add("rw:300-302".parse().unwrap());
+
+    cpu.cycle();
+    cpu.cycle();
+    let hit = cpu.take_watch_hit().unwrap();
+    assert_eq!((hit.addr, hit.access, hit.pc, hit.opcode), (0x300, Access::READ, 0x202, 0xD011));
+
+    cpu.cycle();
+    let hit = cpu.take_watch_hit().unwrap();
+    assert_eq!((hit.addr, hit.access, hit.pc, hit.opcode), (0x300, Access::WRITE, 0x204, 0xF033));
+}
+
+#[test]
+/// Breaking on draws should end the frame after the first draw, with the sprite and where it went.
+fn test_breaking_on_draws() {
+    let mut cpu = CPU::default();
+    cpu.load(vec![0x60, 0x0C, 0x6F, 0x08, 0xA2, 0x0A, 0xD0, 0xF2, 0x12, 0x06, 0xF0, 0x90]).unwrap();
+    cpu.set_break_on_draw(true);
+
+    cpu.step_frame(100);
+    assert_eq!(cpu.pc, 0x208);
+    let hit = cpu.take_draw_hit().unwrap();
+    assert_eq!((hit.pc, hit.opcode, hit.addr), (0x206, 0xD0F2, 0x20A));
+    assert_eq!((hit.x, hit.y, hit.sprite), (12, 8, vec![0xF0, 0x90]));
+    assert_eq!(cpu.take_draw_hit(), None);
+
+    cpu.set_break_on_draw(false);
+    cpu.step_frame(100);
+    assert_eq!(cpu.take_draw_hit(), None);
+}
+
+#[test]
+/// Reading bytes the program never wrote should break once for each, naming the instruction.
+fn test_breaking_on_uninitialized_reads() {
+    let mut cpu = CPU::default();
+    cpu.set_uninit_reads(UninitReads::Break);
+    cpu.load(vec![0xA3, 0x00, 0xF1, 0x65, 0xF1, 0x65, 0xF1, 0x55, 0xF1, 0x65, 0x12, 0x08]).unwrap();
+
+    cpu.step_frame(100);
+    assert_eq!(cpu.pc, 0x204);
+    let read = cpu.take_uninit_read().unwrap();
+    assert_eq!((read.addr, read.pc, read.opcode), (0x300, 0x202, 0xF165));
+
+    // 301 was read by the same instruction, so is reported by the next. Then FX55 initializes both.
+    cpu.step_frame(100);
+    assert_eq!(cpu.take_uninit_read().map(|read| (read.addr, read.pc)), Some((0x301, 0x204)));
+    cpu.step_frame(100);
+    assert_eq!(cpu.take_uninit_read(), None);
+}
+
+#[test]
+/// Fetching instructions is not a read.
+fn test_fetch_is_not_a_read() {
+    let mut cpu = CPU::default();
+    cpu.watchpoints_mut().add("r:200-201".parse().unwrap());
+    load_and_execute_instruction(&mut cpu, 0x6001);
+    assert_eq!(cpu.take_watch_hit(), None);
+}
+
+#[test]
+/// The short hash should match for identical machines and change with the state.
+fn test_short_hash() {
+    let mut a = CPU::default();
+    let b = CPU::default();
+    assert_eq!(a.short_hash(), b.short_hash());
+
+    a.v[3] = 1;
+    assert_ne!(a.short_hash(), b.short_hash());
+}
@@ -0,0 +1,72 @@
+//! Crash reports. When a ROM stops the emulator, e.g. with an invalid opcode or by returning with an empty stack, the
+//! host writes everything needed to look into it to a file next to the suspend file: what went wrong, the registers,
+//! the call stack, the instructions around the PC and the crash trace if one was kept.
+
+// Self imports
+use crate::cpu::CPU;
+use crate::crash_trace::CrashTrace;
+use crate::debugger;
+use crate::symbols::Symbols;
+
+// Std imports
+use std::any::Any;
+use std::fs;
+use std::path::Path;
+
+/// The message a panic was raised with.
+pub fn message(panic: &(dyn Any + Send)) -> &str {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(message), _) => message,
+        (_, Some(message)) => message,
+        _ => "unknown error",
+    }
+}
+
+/// The report for a CPU which stopped with an error. The PC is still on the instruction which failed.
+pub fn report(cpu: &CPU, symbols: &Symbols, message: &str, trace: Option<&CrashTrace>) -> String {
+    let mut out = format!("Crashed: {}\n\nRegisters:\n{}", message, debugger::regs(cpu));
+    out += &format!("\nCall stack, innermost first:\n{}", debugger::stack(cpu, symbols));
+    out += &format!("\nAround the PC:\n{}", debugger::frame(cpu, symbols, 0));
+    if let Some(trace) = trace {
+        out += &format!("\n{}", trace);
+    }
+    out
+}
+
+pub fn write(path: &Path, report: &str) -> Result<(), String> {
+    fs::write(path, report).map_err(|e| format!("unable to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn test_reporting_a_crash() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x22, 0x04, 0x00, 0x00, 0x60, 0x20, 0xF0, 0x29]).unwrap();
+        cpu.start_recording();
+        let trace = CrashTrace::attach(&mut cpu, 8);
+
+        let panic = panic::catch_unwind(AssertUnwindSafe(|| cpu.step_frame(4))).unwrap_err();
+        let symbols = Symbols::parse("204 font").unwrap();
+        let report = report(&cpu, &symbols, message(panic.as_ref()), Some(&trace.borrow()));
+
+        assert!(report.starts_with("Crashed: "));
+        assert!(report.contains("PC 206  SP 1"));
+        assert!(report.contains("Call stack, innermost first:\n 0  206  F029  LD F, V0           font+2\n"));
+        assert!(report.contains("=> 206  F029  LD F, V0\n"));
+        assert!(report.ends_with("206  F029  LD F, V0          did not finish\n"));
+    }
+
+    #[test]
+    fn test_panic_messages() {
+        let panic = panic::catch_unwind(|| panic!("stack underflow")).unwrap_err();
+        assert_eq!(message(panic.as_ref()), "stack underflow");
+
+        let panic = panic::catch_unwind(|| panic!("invalid opcode {:04X}", 0xFFFF)).unwrap_err();
+        assert_eq!(message(panic.as_ref()), "invalid opcode FFFF");
+    }
+}
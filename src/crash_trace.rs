@@ -0,0 +1,183 @@
+//! The last instructions executed, kept in a ring buffer so a ROM which stops the emulator, e.g. with an invalid
+//! opcode, can be diagnosed from how it got there. The host prints it when the CPU panics.
+//!
+//! What each instruction changed comes from the changes the CPU records for the journal, so it must be recording.
+
+// Self imports
+use crate::cpu::CPU;
+use crate::decode::Instruction;
+use crate::hooks::{Opcode, Step};
+use crate::journal::Change;
+
+// Std imports
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+
+/// One executed instruction and the registers and memory it changed.
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub pc: usize,
+    pub opcode: Opcode,
+    pub instruction: Instruction,
+    pub changes: Vec<Change>,
+    pub finished: bool,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let changes = match self.finished {
+            true => self.changes.iter().filter_map(shown).collect::<Vec<_>>().join(" "),
+            false => "did not finish".to_string(),
+        };
+        let line = format!("{:03X}  {}  {:<18}{}", self.pc, self.opcode, self.instruction.to_string(), changes);
+        write!(f, "{}", line.trim_end())
+    }
+}
+
+// A change as the trace shows it, with the new value. Changes to anything but registers and memory aren't shown.
+fn shown(change: &Change) -> Option<String> {
+    match *change {
+        Change::Register { x, after, .. } => Some(format!("V{:X}={:02X}", x, after)),
+        Change::I { after, .. } => Some(format!("I={:03X}", after)),
+        Change::Sp { after, .. } => Some(format!("SP={}", after)),
+        Change::DelayTimer { after, .. } => Some(format!("DT={:02X}", after)),
+        Change::SoundTimer { after, .. } => Some(format!("ST={:02X}", after)),
+        Change::Memory { addr, after, .. } => Some(format!("[{:03X}]={:02X}", addr, after)),
+        _ => None,
+    }
+}
+
+// A copy of a change the trace shows, replacing any earlier change to the same register or byte.
+fn keep(changes: &mut Vec<Change>, change: &Change) {
+    let change = match *change {
+        Change::Register { x, before, after } => Change::Register { x, before, after },
+        Change::I { before, after } => Change::I { before, after },
+        Change::Sp { before, after } => Change::Sp { before, after },
+        Change::DelayTimer { before, after } => Change::DelayTimer { before, after },
+        Change::SoundTimer { before, after } => Change::SoundTimer { before, after },
+        Change::Memory { addr, before, after } => Change::Memory { addr, before, after },
+        _ => return,
+    };
+
+    let same = |kept: &Change| match (kept, &change) {
+        (Change::Register { x, .. }, Change::Register { x: other, .. }) => x == other,
+        (Change::Memory { addr, .. }, Change::Memory { addr: other, .. }) => addr == other,
+        (kept, change) => std::mem::discriminant(kept) == std::mem::discriminant(change),
+    };
+    changes.retain(|kept| !same(kept));
+    changes.push(change);
+}
+
+pub struct CrashTrace {
+    entries: VecDeque<Entry>,
+    length: usize,
+}
+
+impl CrashTrace {
+    pub fn new(length: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(length),
+            length: length.max(1),
+        }
+    }
+
+    /// Start keeping the last instructions the CPU executes.
+    pub fn attach(cpu: &mut CPU, length: usize) -> Rc<RefCell<Self>> {
+        let trace = Rc::new(RefCell::new(Self::new(length)));
+
+        let before = trace.clone();
+        cpu.hooks_mut().before(move |_, step| before.borrow_mut().push(step));
+
+        let after = trace.clone();
+        cpu.hooks_mut().after(move |cpu, _| after.borrow_mut().finish(cpu.instruction_changes()));
+
+        trace
+    }
+
+    fn push(&mut self, step: &Step) {
+        // The oldest entry's changes are reused, so a full trace doesn't allocate for every instruction.
+        let mut changes = match self.entries.len() == self.length {
+            true => self.entries.pop_front().map(|entry| entry.changes).unwrap_or_default(),
+            false => Vec::new(),
+        };
+        changes.clear();
+
+        self.entries.push_back(Entry {
+            pc: step.pc,
+            opcode: step.opcode,
+            instruction: step.instruction,
+            changes,
+            finished: false,
+        });
+    }
+
+    fn finish(&mut self, changes: &[Change]) {
+        if let Some(entry) = self.entries.back_mut() {
+            for change in changes {
+                keep(&mut entry.changes, change);
+            }
+            entry.finished = true;
+        }
+    }
+
+    /// The instructions kept, oldest first.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+}
+
+impl fmt::Display for CrashTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Last {} instructions, oldest first:", self.entries.len())?;
+        for entry in &self.entries {
+            writeln!(f, "  {}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn test_only_the_last_instructions_are_kept() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x60, 0x01, 0x61, 0x02, 0xA3, 0x00, 0x12, 0x06]).unwrap();
+        cpu.start_recording();
+        let trace = CrashTrace::attach(&mut cpu, 2);
+
+        cpu.step_frame(3);
+        let lines: Vec<String> = trace.borrow().entries().map(|entry| entry.to_string()).collect();
+        assert_eq!(lines, vec!["202  6102  LD V1, 02         V1=02", "204  A300  LD I, 300         I=300"]);
+    }
+
+    #[test]
+    fn test_memory_writes_are_shown() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x60, 0x7B, 0xA3, 0x00, 0xF0, 0x33]).unwrap();
+        cpu.start_recording();
+        let trace = CrashTrace::attach(&mut cpu, 1);
+
+        cpu.step_frame(3);
+        let line = trace.borrow().entries().next().unwrap().to_string();
+        assert_eq!(line, "204  F033  LD B, V0          [300]=01 [301]=02 [302]=03");
+    }
+
+    #[test]
+    fn test_crashing_instruction_is_kept() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x60, 0x20, 0xF0, 0x29]).unwrap();
+        cpu.start_recording();
+        let trace = CrashTrace::attach(&mut cpu, 32);
+
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| cpu.step_frame(2))).is_err());
+        let trace = trace.borrow().to_string();
+        assert!(trace.starts_with("Last 2 instructions, oldest first:\n  200  6020"));
+        assert!(trace.ends_with("202  F029  LD F, V0          did not finish\n"));
+    }
+}
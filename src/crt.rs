@@ -0,0 +1,90 @@
+//! Retro CRT look, toggled with the crt hotkey. Frames are scaled up so each pixel covers a few rows of the screen,
+//! then given dark gaps between its rows like a CRT's scanlines, bulged outwards like the curved glass of a tube and
+//! darkened towards the edges like its vignette.
+//!
+//! The display driver draws the result in place of the plain frame.
+
+// Screen pixels each frame pixel is scaled to in both directions, leaving room for the scanline gaps.
+pub const SCALE: usize = 4;
+
+// How far the picture bulges. The corners are pulled in by about this fraction of the screen.
+const CURVATURE: f32 = 0.04;
+
+// How much darker the edges are than the middle.
+const VIGNETTE: f32 = 0.3;
+
+// Brightness of the gap at the bottom of each row of frame pixels, and how much of the row it takes.
+const SCANLINE: f32 = 0.55;
+const SCANLINE_GAP: f32 = 0.25;
+
+/// Draw a frame, row by row, as RGBA pixels with the CRT look. Returns the pixels, width and height.
+pub fn render(colours: &[[u8; 3]], width: usize) -> (Vec<u8>, usize, usize) {
+    let height = colours.len() / width;
+    let (out_width, out_height) = (width * SCALE, height * SCALE);
+    let mut rgba = vec![0; out_width * out_height * 4];
+
+    for y in 0..out_height {
+        for x in 0..out_width {
+            // Position from -1 to 1 across the screen, bulged outwards from the middle.
+            let u = (x as f32 + 0.5) / out_width as f32 * 2.0 - 1.0;
+            let v = (y as f32 + 0.5) / out_height as f32 * 2.0 - 1.0;
+            let (u, v) = (u * (1.0 + CURVATURE * v * v), v * (1.0 + CURVATURE * u * u));
+            if u.abs() >= 1.0 || v.abs() >= 1.0 {
+                continue; // Off the edge of the glass.
+            }
+
+            let column = (u + 1.0) / 2.0 * width as f32;
+            let row = (v + 1.0) / 2.0 * height as f32;
+            let colour = colours[row as usize * width + column as usize];
+
+            let scanline = if row.fract() >= 1.0 - SCANLINE_GAP { SCANLINE } else { 1.0 };
+            let brightness = scanline * (1.0 - VIGNETTE * (u * u + v * v) / 2.0);
+            let pixel = &mut rgba[(y * out_width + x) * 4..][..4];
+            for (out, channel) in pixel.iter_mut().zip(colour.iter()) {
+                *out = (*channel as f32 * brightness) as u8;
+            }
+            pixel[3] = 255;
+        }
+    }
+
+    (rgba, out_width, out_height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 3] = [255; 3];
+
+    fn pixel(rgba: &[u8], width: usize, x: usize, y: usize) -> &[u8] {
+        &rgba[(y * width + x) * 4..][..4]
+    }
+
+    #[test]
+    fn test_size() {
+        let (rgba, width, height) = render(&[WHITE; 64 * 32], 64);
+        assert_eq!((width, height), (64 * SCALE, 32 * SCALE));
+        assert_eq!(rgba.len(), width * height * 4);
+    }
+
+    #[test]
+    fn test_corners_curve_away() {
+        let (rgba, width, height) = render(&[WHITE; 64 * 32], 64);
+        assert_eq!(pixel(&rgba, width, 0, 0), [0, 0, 0, 0]);
+        assert_eq!(pixel(&rgba, width, width - 1, height - 1), [0, 0, 0, 0]);
+        assert_eq!(pixel(&rgba, width, width / 2, 0)[3], 255);
+    }
+
+    #[test]
+    fn test_scanlines_and_vignette() {
+        let (rgba, width, height) = render(&[WHITE; 64 * 32], 64);
+        let (x, y) = (width / 2, height / 2);
+
+        // The middle of a row is brightest, its last screen row is a gap and the edges are darker.
+        let brightness = |x: usize, y: usize| pixel(&rgba, width, x, y)[0] as usize;
+        let middle = brightness(x, y + 1);
+        assert!(middle > 250);
+        assert!(brightness(x, y + SCALE - 1) < middle * 2 / 3);
+        assert!(brightness(2 * SCALE, y + 1) < middle * 9 / 10);
+    }
+}
@@ -0,0 +1,72 @@
+//! Debugger panels, shown in their own window by the debug window hotkey so the game's window stays clear. Has the
+//! registers, the instructions around the PC, the call stack and the memory I points at, as the regs, frame 0, stack
+//! and mem commands show them:
+//!
+//! ```text
+//! REGISTERS
+//! V0 00  V1 00  V2 00  V3 00  V4 00  V5 00  V6 00  V7 00
+//! ...
+//!
+//! CODE
+//! => 200  00E0  CLS
+//!    202  A22A  LD I, 22A
+//! ...
+//! ```
+//!
+//! The display driver's HUD font draws the text, as there is no text rendering.
+
+// Self imports
+use crate::cpu::CPU;
+use crate::debugger;
+use crate::symbols::Symbols;
+
+/// Characters which fit on a line, enough for a row of eight registers or a line of memory.
+pub const COLUMNS: usize = 56;
+
+/// Lines which fit in the window. Anything past them is cut off.
+pub const ROWS: usize = 36;
+
+// Bytes of memory shown from I.
+const MEMORY_SHOWN: usize = 32;
+
+/// The panels' lines, top first.
+pub fn lines(cpu: &CPU, symbols: &Symbols) -> Vec<String> {
+    let panels = vec![
+        ("REGISTERS", debugger::regs(cpu)),
+        ("CODE", debugger::frame(cpu, symbols, 0)),
+        ("STACK", debugger::stack(cpu, symbols)),
+        ("MEMORY AT I", debugger::mem(cpu, cpu.registers().i, MEMORY_SHOWN)),
+    ];
+
+    let mut lines = vec![];
+    for (title, text) in panels {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.push(title.to_string());
+        lines.extend(text.lines().map(str::to_string));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panels() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0xA2, 0x06, 0x22, 0x06, 0x12, 0x04, 0x00, 0xEE]).unwrap();
+        cpu.cycle();
+        cpu.cycle();
+
+        let lines = lines(&cpu, &Symbols::default());
+        let code = lines.iter().position(|line| line == "CODE").unwrap();
+        assert_eq!(lines[code + 4], "=> 206  00EE  RET");
+
+        let stack = lines.iter().position(|line| line == "STACK").unwrap();
+        assert_eq!(lines[stack + 1..stack + 3], [" 0  206  00EE  RET", " 1  202  2206  CALL 206"]);
+        assert_eq!(lines.last().unwrap(), "216  00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00");
+        assert!(lines.iter().all(|line| line.len() <= COLUMNS));
+    }
+}
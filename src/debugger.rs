@@ -0,0 +1,815 @@
+//! Debugger commands, typed on stdin while the ROM is paused in debug mode. Stdin is read on its own thread so the
+//! window keeps responding while waiting for a command.
+//!
+//! ```text
+//! step [n]          Execute n instructions, 1 by default, stopping early at breakpoints, watchpoints or a halt.
+//! back [n]          Undo the last n instructions, 1 by default.
+//! forward [n]       Redo n instructions undone by back, 1 by default, exactly as they first ran.
+//! regs              Show the registers.
+//! mem <addr> [n]    Show n bytes of memory from addr, 16 by default.
+//! poke <addr> <b>   Write the hex byte b to addr, even in the program.
+//! set <expr> <n>    Set V0-VF, I, PC, SP, DT, ST or a byte like [300] to the hex value n.
+//! break [addr]      Toggle a breakpoint before the instruction at addr, or list them.
+//! watch [spec]      Add a watchpoint, e.g. rw:300-30F, or list them.
+//! unwatch <n>       Remove the nth watchpoint in the list.
+//! log [range]       Log every write into a range, e.g. 300-30F, or show the writes logged.
+//! unlog <n>         Stop logging the nth range in the list.
+//! draw              Toggle pausing after every sprite drawn, showing the sprite and where it went.
+//! display [expr]    Show V0-VF, I, PC, SP, DT, ST or a byte like [300] at every prompt, or list them.
+//! undisplay <n>     Stop showing the nth expression.
+//! hot [n]           Show the n hottest addresses and loops, 10 by default. Starts profiling the first time.
+//! stack             List the subroutine calls being made, innermost first.
+//! frame <n>         Disassemble around the call made by frame n in the stack, or the PC for frame 0.
+//! continue          Run until a breakpoint or watchpoint is hit.
+//! goto <addr>       Set the PC to addr and continue from there, e.g. to skip broken code.
+//! help              List the commands.
+//! ```
+//!
+//! Addresses and bytes are hex, with or without a 0x prefix. Addresses can also be labels from a symbol file. Counts
+//! are decimal unless they have a 0x prefix. An empty line repeats the last command, so stepping is a matter of
+//! pressing enter. Most commands can be shortened to their first letter, and stack to bt. Displayed expressions are
+//! shown before each prompt, with their old value when it has changed.
+
+// Self imports
+use crate::cpu::CPU;
+use crate::symbols::Symbols;
+use crate::watch::Watchpoint;
+
+// Std imports
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+const HELP: &str = "\
+Debugger commands:
+  step [n]          Execute n instructions, 1 by default, stopping early at breakpoints, watchpoints or a halt.
+  back [n]          Undo the last n instructions, 1 by default.
+  forward [n]       Redo n instructions undone by back, 1 by default, exactly as they first ran.
+  regs              Show the registers.
+  mem <addr> [n]    Show n bytes of memory from addr, 16 by default.
+  poke <addr> <b>   Write the hex byte b to addr, even in the program.
+  set <expr> <n>    Set V0-VF, I, PC, SP, DT, ST or a byte like [300] to the hex value n.
+  break [addr]      Toggle a breakpoint before the instruction at addr, or list them.
+  watch [spec]      Add a watchpoint, e.g. rw:300-30F, or list them.
+  unwatch <n>       Remove the nth watchpoint in the list.
+  log [range]       Log every write into a range, e.g. 300-30F, or show the writes logged.
+  unlog <n>         Stop logging the nth range in the list.
+  draw              Toggle pausing after every sprite drawn, showing the sprite and where it went.
+  display [expr]    Show V0-VF, I, PC, SP, DT, ST or a byte like [300] at every prompt, or list them.
+  undisplay <n>     Stop showing the nth expression.
+  hot [n]           Show the n hottest addresses and loops, 10 by default. Starts profiling the first time.
+  stack             List the subroutine calls being made, innermost first.
+  frame <n>         Disassemble around the call made by frame n in the stack, or the PC for frame 0.
+  continue          Run until a breakpoint or watchpoint is hit.
+  goto <addr>       Set the PC to addr and continue from there, e.g. to skip broken code.
+  help              List the commands.
+";
+
+// Bytes shown on each line of a memory dump.
+const BYTES_PER_LINE: usize = 16;
+
+// Subroutine calls the stack holds, and so the largest SP.
+const STACK_SIZE: usize = 16;
+
+// Instructions shown either side of a frame's call by frame.
+const FRAME_CONTEXT: usize = 3;
+
+/// Addresses and loops shown by hot, unless asked for more or fewer.
+pub const HOTTEST: usize = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Command {
+    Step(usize),
+    Back(usize),
+    Forward(usize),
+    Regs,
+    Mem { addr: usize, len: usize },
+    Poke { addr: usize, byte: u8 },
+    Set(Expr, usize),
+    Break(Option<usize>),
+    Watch(Option<Watchpoint>),
+    Unwatch(usize),
+    Log(Option<(usize, usize)>), // First and last address.
+    Unlog(usize),
+    Draw,
+    Display(Option<Expr>),
+    Undisplay(usize),
+    Hot(usize),
+    Stack,
+    Frame(usize),
+    Continue,
+    Goto(usize),
+    Help,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, &Symbols::default())
+    }
+}
+
+impl Command {
+    /// Parse a command whose addresses may be labels.
+    pub fn parse(s: &str, symbols: &Symbols) -> Result<Self, String> {
+        let parse_addr = |s| symbols.resolve(s);
+        let mut words = s.split_whitespace();
+        let name = words.next().unwrap_or_default().to_lowercase();
+        let args: Vec<&str> = words.collect();
+
+        let command = match (name.as_str(), args.as_slice()) {
+            ("step" | "s", []) => Command::Step(1),
+            ("step" | "s", [n]) => Command::Step(parse_count(n)?),
+            ("back", []) => Command::Back(1),
+            ("back", [n]) => Command::Back(parse_count(n)?),
+            ("forward", []) => Command::Forward(1),
+            ("forward", [n]) => Command::Forward(parse_count(n)?),
+            ("regs" | "r", []) => Command::Regs,
+            ("mem" | "m", [addr]) => Command::Mem { addr: parse_addr(addr)?, len: BYTES_PER_LINE },
+            ("mem" | "m", [addr, len]) => Command::Mem { addr: parse_addr(addr)?, len: parse_count(len)? },
+            ("poke", [addr, byte]) => Command::Poke { addr: parse_addr(addr)?, byte: parse_byte(byte)? },
+            ("set", [expr, value]) => {
+                let expr: Expr = expr.parse()?;
+                Command::Set(expr, expr.check(parse_addr(value).map_err(|_| format!("invalid value: {}", value))?)?)
+            }
+            ("break" | "b", []) => Command::Break(None),
+            ("break" | "b", [addr]) => Command::Break(Some(parse_addr(addr)?)),
+            ("watch" | "w", []) => Command::Watch(None),
+            ("watch" | "w", [spec]) => Command::Watch(Some(spec.parse()?)),
+            ("unwatch" | "u", [n]) => Command::Unwatch(parse_count(n)?),
+            ("log", []) => Command::Log(None),
+            ("log", [range]) => {
+                let (start, end) = match range.split_once('-') {
+                    Some((start, end)) => (parse_addr(start)?, parse_addr(end)?),
+                    None => (parse_addr(range)?, parse_addr(range)?),
+                };
+                if end < start {
+                    return Err(format!("invalid range: {} (expected e.g. 300-30F)", range));
+                }
+                Command::Log(Some((start, end)))
+            }
+            ("unlog", [n]) => Command::Unlog(parse_count(n)?),
+            ("draw", []) => Command::Draw,
+            ("display" | "d", []) => Command::Display(None),
+            ("display" | "d", [expr]) => Command::Display(Some(expr.parse()?)),
+            ("undisplay", [n]) => Command::Undisplay(parse_count(n)?),
+            ("hot", []) => Command::Hot(HOTTEST),
+            ("hot", [n]) => Command::Hot(parse_count(n)?),
+            ("stack" | "bt", []) => Command::Stack,
+            ("frame" | "f", [n]) => Command::Frame(parse_count(n)?),
+            ("continue" | "c", []) => Command::Continue,
+            ("goto" | "g", [addr]) => Command::Goto(Expr::Pc.check(parse_addr(addr)?)?),
+            ("help" | "h", []) => Command::Help,
+            (
+                "step" | "s" | "back" | "forward" | "regs" | "r" | "mem" | "m" | "poke" | "set" | "break" | "b"
+                | "watch" | "w" | "unwatch" | "u" | "log" | "unlog" | "draw" | "display" | "d" | "undisplay" | "hot"
+                | "stack" | "bt" | "frame" | "f" | "continue" | "c" | "goto" | "g" | "help" | "h",
+                _,
+            ) => return Err(format!("wrong arguments for {} (type help for a list of commands)", name)),
+            _ => return Err(format!("unknown command: {} (type help for a list of commands)", name)),
+        };
+        Ok(command)
+    }
+}
+
+/// Something shown at every prompt.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Expr {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+    Byte(usize), // The byte of memory at an address.
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid expression: {} (expected e.g. V3, I, DT or [300])", s);
+        let expr = match s.to_uppercase().as_str() {
+            "I" => Expr::I,
+            "PC" => Expr::Pc,
+            "SP" => Expr::Sp,
+            "DT" => Expr::Dt,
+            "ST" => Expr::St,
+            upper => match (upper.strip_prefix('V'), upper.strip_prefix('[').and_then(|s| s.strip_suffix(']'))) {
+                (Some(x), _) if x.len() == 1 => Expr::V(usize::from_str_radix(x, 16).map_err(|_| invalid())?),
+                (_, Some(addr)) => Expr::Byte(parse_addr(addr).map_err(|_| invalid())?),
+                _ => return Err(invalid()),
+            },
+        };
+        Ok(expr)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::V(x) => write!(f, "V{:X}", x),
+            Expr::I => write!(f, "I"),
+            Expr::Pc => write!(f, "PC"),
+            Expr::Sp => write!(f, "SP"),
+            Expr::Dt => write!(f, "DT"),
+            Expr::St => write!(f, "ST"),
+            Expr::Byte(addr) => write!(f, "[{:03X}]", addr),
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, cpu: &CPU) -> usize {
+        let registers = cpu.registers();
+        match *self {
+            Expr::V(x) => registers.v[x] as usize,
+            Expr::I => registers.i,
+            Expr::Pc => cpu.pc(),
+            Expr::Sp => registers.sp,
+            Expr::Dt => registers.delay_timer as usize,
+            Expr::St => registers.sound_timer as usize,
+            Expr::Byte(addr) => cpu.peek(addr) as usize,
+        }
+    }
+
+    // The value if it fits in what the expression names.
+    fn check(&self, value: usize) -> Result<usize, String> {
+        let max = match self {
+            Expr::V(_) | Expr::Dt | Expr::St | Expr::Byte(_) => 0xFF,
+            Expr::I | Expr::Pc => 0xFFFF,
+            Expr::Sp => STACK_SIZE,
+        };
+        match value <= max {
+            true => Ok(value),
+            false => Err(format!("{:X} is too big for {}, which can be up to {:X}", value, self, max)),
+        }
+    }
+
+    // Set what the expression names.
+    fn set(&self, cpu: &mut CPU, value: usize) {
+        let (mut registers, mut pc) = (cpu.registers(), cpu.pc());
+        match *self {
+            Expr::V(x) => registers.v[x] = value as u8,
+            Expr::I => registers.i = value,
+            Expr::Pc => pc = value,
+            Expr::Sp => registers.sp = value,
+            Expr::Dt => registers.delay_timer = value as u8,
+            Expr::St => registers.sound_timer = value as u8,
+            Expr::Byte(addr) => return cpu.poke(addr, value as u8),
+        }
+        cpu.set_registers(registers, pc);
+    }
+
+    // Addresses in three hex digits like the rest of the debugger, bytes in two and the stack depth in decimal.
+    fn format(&self, value: usize) -> String {
+        match self {
+            Expr::I | Expr::Pc => format!("{:03X}", value),
+            Expr::Sp => value.to_string(),
+            _ => format!("{:02X}", value),
+        }
+    }
+}
+
+/// Parse a hex address, with or without a 0x prefix.
+pub fn parse_addr(s: &str) -> Result<usize, String> {
+    let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    usize::from_str_radix(hex, 16).map_err(|_| format!("invalid address: {}", s))
+}
+
+fn parse_byte(s: &str) -> Result<u8, String> {
+    let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    u8::from_str_radix(hex, 16).map_err(|_| format!("invalid byte: {}", s))
+}
+
+fn parse_count(s: &str) -> Result<usize, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("invalid count: {}", s))
+}
+
+/// Reads commands from stdin without blocking the caller.
+pub struct Debugger {
+    lines: Receiver<String>,
+    last: Option<Command>,
+    prompted: bool, // Whether a prompt is waiting for the next command.
+    displays: Vec<(Expr, usize)>, // Expressions shown at every prompt, with the value last shown.
+    symbols: Symbols,             // Labels which can be used for addresses.
+}
+
+impl Debugger {
+    /// Start reading commands from stdin.
+    pub fn spawn(symbols: Symbols) -> Self {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        println!("Paused. Type help for debugger commands");
+        Self::with_lines(lines, symbols)
+    }
+
+    fn with_lines(lines: Receiver<String>, symbols: Symbols) -> Self {
+        Self {
+            lines,
+            last: None,
+            prompted: false,
+            displays: Vec::new(),
+            symbols,
+        }
+    }
+
+    /// The next command typed, if there is one yet. Prompts for it first if needed, after showing the displayed
+    /// expressions. Commands which can't be parsed are reported and skipped, and display commands are handled here,
+    /// as are stack and frame, which need the labels.
+    pub fn poll(&mut self, cpu: &CPU) -> Option<Command> {
+        if !self.prompted {
+            print!("{}(chip8) ", self.show_displays(cpu));
+            let _ = io::stdout().flush();
+            self.prompted = true;
+        }
+
+        let line = self.lines.try_recv().ok()?;
+        let parsed = match line.trim() {
+            "" => self.last.ok_or_else(|| "no command to repeat".to_string()),
+            line => Command::parse(line, &self.symbols),
+        };
+
+        self.prompted = false;
+        match parsed {
+            Ok(command @ (Command::Display(_) | Command::Undisplay(_))) => {
+                self.last = Some(command);
+                print!("{}", self.display(cpu, &command));
+                None
+            }
+            Ok(command @ Command::Stack) => {
+                self.last = Some(command);
+                print!("{}", stack(cpu, &self.symbols));
+                None
+            }
+            Ok(command @ Command::Frame(n)) => {
+                self.last = Some(command);
+                print!("{}", frame(cpu, &self.symbols, n));
+                None
+            }
+            Ok(command) => {
+                self.last = Some(command);
+                Some(command)
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                None
+            }
+        }
+    }
+
+    // Add, list or remove displayed expressions.
+    fn display(&mut self, cpu: &CPU, command: &Command) -> String {
+        match *command {
+            Command::Display(Some(expr)) => {
+                self.displays.push((expr, expr.eval(cpu)));
+                format!("Displaying {}\n", expr)
+            }
+            Command::Display(None) if self.displays.is_empty() => "No displays\n".to_string(),
+            Command::Display(None) => {
+                self.displays.iter().enumerate().map(|(n, (expr, _))| format!("{:>2}  {}\n", n, expr)).collect()
+            }
+            Command::Undisplay(n) if n < self.displays.len() => {
+                format!("Stopped displaying {}\n", self.displays.remove(n).0)
+            }
+            Command::Undisplay(n) => format!("No display {}\n", n),
+            _ => String::new(),
+        }
+    }
+
+    // Each displayed expression and its value, followed by the value last shown if it has changed.
+    fn show_displays(&mut self, cpu: &CPU) -> String {
+        let mut out = String::new();
+        for (expr, last) in &mut self.displays {
+            let value = expr.eval(cpu);
+            out += &match value == *last {
+                true => format!("{} = {}\n", expr, expr.format(value)),
+                false => format!("{} = {}  (was {})\n", expr, expr.format(value), expr.format(*last)),
+            };
+            *last = value;
+        }
+        out
+    }
+}
+
+/// Run a command other than continue, back, forward and hot, which are up to the host, and display, stack and frame
+/// commands, which are up to the debugger, and return what it has to say.
+pub fn execute(cpu: &mut CPU, command: &Command) -> String {
+    match *command {
+        Command::Step(n) => match cpu.step(n) {
+            executed if executed < n => {
+                format!("Stopped after {} of {} instructions\n{}", executed, n, next_instruction(cpu))
+            }
+            _ => next_instruction(cpu),
+        },
+        Command::Regs => regs(cpu),
+        Command::Mem { addr, len } => mem(cpu, addr, len),
+        Command::Goto(addr) => execute(cpu, &Command::Set(Expr::Pc, addr)),
+        Command::Set(expr, value) => {
+            let before = expr.eval(cpu);
+            expr.set(cpu, value);
+            format!("{}: {} -> {}\n", expr, expr.format(before), expr.format(expr.eval(cpu)))
+        }
+        Command::Poke { addr, byte } => {
+            let before = cpu.peek(addr);
+            cpu.poke(addr, byte);
+            format!("{:03X}: {:02X} -> {:02X}\n", addr, before, byte)
+        }
+        Command::Break(Some(addr)) => {
+            let breakpoints = cpu.breakpoints_mut();
+            if breakpoints.remove(&addr) {
+                format!("Removed breakpoint at {:03X}\n", addr)
+            } else {
+                breakpoints.insert(addr);
+                format!("Breakpoint at {:03X}\n", addr)
+            }
+        }
+        Command::Break(None) => match cpu.breakpoints_mut() {
+            breakpoints if breakpoints.is_empty() => "No breakpoints\n".to_string(),
+            breakpoints => breakpoints.iter().map(|addr| format!("Breakpoint at {:03X}\n", addr)).collect(),
+        },
+        Command::Watch(Some(watchpoint)) => {
+            cpu.watchpoints_mut().add(watchpoint);
+            format!("Watching {}\n", watchpoint)
+        }
+        Command::Watch(None) => match cpu.watchpoints_mut().list() {
+            [] => "No watchpoints\n".to_string(),
+            list => list.iter().enumerate().map(|(n, watchpoint)| format!("{:>2}  {}\n", n, watchpoint)).collect(),
+        },
+        Command::Unwatch(n) => match cpu.watchpoints_mut().remove(n) {
+            Some(watchpoint) => format!("Stopped watching {}\n", watchpoint),
+            None => format!("No watchpoint {}\n", n),
+        },
+        Command::Log(Some((start, end))) => {
+            cpu.write_log_mut().add(start, end);
+            match start == end {
+                true => format!("Logging writes to {:03X}\n", start),
+                false => format!("Logging writes to {:03X}-{:03X}\n", start, end),
+            }
+        }
+        Command::Log(None) => match cpu.write_log() {
+            log if log.is_empty() => "No logged ranges\n".to_string(),
+            log => log.to_string(),
+        },
+        Command::Unlog(n) => match cpu.write_log_mut().remove(n) {
+            Some((start, end)) if start == end => format!("Stopped logging {:03X}\n", start),
+            Some((start, end)) => format!("Stopped logging {:03X}-{:03X}\n", start, end),
+            None => format!("No logged range {}\n", n),
+        },
+        Command::Draw => {
+            cpu.set_break_on_draw(!cpu.break_on_draw());
+            match cpu.break_on_draw() {
+                true => "Breaking on draws\n".to_string(),
+                false => "Stopped breaking on draws\n".to_string(),
+            }
+        }
+        Command::Back(_)
+        | Command::Forward(_)
+        | Command::Display(_)
+        | Command::Undisplay(_)
+        | Command::Hot(_)
+        | Command::Stack
+        | Command::Frame(_)
+        | Command::Continue => String::new(),
+        Command::Help => HELP.to_string(),
+    }
+}
+
+/// The instruction about to be executed, as shown after stepping.
+pub fn next_instruction(cpu: &CPU) -> String {
+    cpu.disassemble(cpu.pc(), 1).iter().map(|line| format!("{}\n", line)).collect()
+}
+
+// Where each frame of the call stack is, innermost first: the PC, then the call made by each subroutine entered.
+// Calls push the address after them, so each call is two bytes before what was pushed.
+fn frames(cpu: &CPU) -> Vec<usize> {
+    let calls = cpu.stack().iter().rev().map(|ret| ret.wrapping_sub(2));
+    std::iter::once(cpu.pc()).chain(calls).collect()
+}
+
+/// The call stack, as shown by stack. Each frame has the instruction it is at and the label it is in, if any.
+pub fn stack(cpu: &CPU, symbols: &Symbols) -> String {
+    let mut out = String::new();
+    for (n, addr) in frames(cpu).into_iter().enumerate() {
+        let line = cpu.disassemble(addr, 1).first().map(|line| line.with_symbols(symbols)).unwrap_or_default();
+        let location = symbols.locate(addr).unwrap_or_default();
+        let frame = format!("{:>2}  {:<30}{}", n, line, location);
+        out += &format!("{}\n", frame.trim_end());
+    }
+    out
+}
+
+/// The instructions around a frame's call, or the PC for frame 0, as shown by frame. Labels are shown as in a
+/// listing, and the frame's instruction is marked.
+pub fn frame(cpu: &CPU, symbols: &Symbols, n: usize) -> String {
+    let addr = match frames(cpu).get(n) {
+        Some(&addr) => addr,
+        None => return format!("No frame {}\n", n),
+    };
+
+    let start = addr.saturating_sub(FRAME_CONTEXT * 2);
+    let mut out = String::new();
+    for line in cpu.disassemble(start, (addr - start) / 2 + FRAME_CONTEXT + 1) {
+        if let Some(name) = symbols.name(line.addr()) {
+            out += &format!("{}:\n", name);
+        }
+        let marker = if line.addr() == addr { "=>" } else { "  " };
+        out += &format!("{} {}\n", marker, line.with_symbols(symbols));
+    }
+    out
+}
+
+/// The registers, as shown by regs.
+pub fn regs(cpu: &CPU) -> String {
+    let registers = cpu.registers();
+    let mut out = String::new();
+    for (row, values) in registers.v.chunks(8).enumerate() {
+        let line: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(x, v)| format!("V{:X} {:02X}", row * 8 + x, v))
+            .collect();
+        out += &format!("{}\n", line.join("  "));
+    }
+    out += &format!(
+        "I {:03X}  PC {:03X}  SP {}  DT {:02X}  ST {:02X}\n",
+        registers.i,
+        cpu.pc(),
+        registers.sp,
+        registers.delay_timer,
+        registers.sound_timer
+    );
+    out
+}
+
+/// A dump of memory, as shown by mem.
+pub fn mem(cpu: &CPU, addr: usize, len: usize) -> String {
+    let mut out = String::new();
+    for start in (addr..addr + len).step_by(BYTES_PER_LINE) {
+        let end = (start + BYTES_PER_LINE).min(addr + len);
+        let bytes: Vec<String> = (start..end).map(|a| format!("{:02X}", cpu.peek(a))).collect();
+        out += &format!("{:03X}  {}\n", start, bytes.join(" "));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_commands() {
+        assert_eq!("step".parse(), Ok(Command::Step(1)));
+        assert_eq!("s 10".parse(), Ok(Command::Step(10)));
+        assert_eq!("back 3".parse(), Ok(Command::Back(3)));
+        assert_eq!("forward".parse(), Ok(Command::Forward(1)));
+        assert_eq!("mem 0x300 32".parse(), Ok(Command::Mem { addr: 0x300, len: 32 }));
+        assert_eq!("MEM 300".parse(), Ok(Command::Mem { addr: 0x300, len: 16 }));
+        assert_eq!("break 245".parse(), Ok(Command::Break(Some(0x245))));
+        assert_eq!("b".parse(), Ok(Command::Break(None)));
+        assert_eq!("continue".parse(), Ok(Command::Continue));
+        assert_eq!("goto 0x2F0".parse(), Ok(Command::Goto(0x2F0)));
+        assert_eq!("hot".parse(), Ok(Command::Hot(10)));
+
+        assert_eq!("poke 0x30A 0xFF".parse(), Ok(Command::Poke { addr: 0x30A, byte: 0xFF }));
+        assert!("poke 30A 100".parse::<Command>().unwrap_err().contains("invalid byte"));
+
+        let symbols = Symbols::parse("20C main_loop").unwrap();
+        assert_eq!(Command::parse("break main_loop", &symbols), Ok(Command::Break(Some(0x20C))));
+        assert_eq!(Command::parse("m 300", &symbols), Ok(Command::Mem { addr: 0x300, len: 16 }));
+        assert_eq!("bt".parse(), Ok(Command::Stack));
+        assert_eq!("frame 2".parse(), Ok(Command::Frame(2)));
+        assert!("mem".parse::<Command>().unwrap_err().contains("wrong arguments"));
+        assert!("jump 200".parse::<Command>().unwrap_err().contains("unknown command"));
+    }
+
+    #[test]
+    fn test_empty_lines_repeat_the_last_command() {
+        let (sender, lines) = mpsc::channel();
+        let mut debugger = Debugger::with_lines(lines, Symbols::default());
+        let cpu = CPU::default();
+
+        sender.send(String::new()).unwrap();
+        assert_eq!(debugger.poll(&cpu), None);
+
+        sender.send("s 2".to_string()).unwrap();
+        sender.send(String::new()).unwrap();
+        assert_eq!(debugger.poll(&cpu), Some(Command::Step(2)));
+        assert_eq!(debugger.poll(&cpu), Some(Command::Step(2)));
+        assert_eq!(debugger.poll(&cpu), None);
+    }
+
+    #[test]
+    fn test_parsing_expressions() {
+        assert_eq!("v3".parse(), Ok(Expr::V(3)));
+        assert_eq!("VF".parse(), Ok(Expr::V(0xF)));
+        assert_eq!("dt".parse(), Ok(Expr::Dt));
+        assert_eq!("[0x300]".parse(), Ok(Expr::Byte(0x300)));
+        assert!("V10".parse::<Expr>().is_err());
+        assert!("[zz]".parse::<Expr>().is_err());
+        assert_eq!(Expr::Byte(0x300).to_string(), "[300]");
+    }
+
+    #[test]
+    fn test_displays_flag_changed_values() {
+        let (_, lines) = mpsc::channel();
+        let mut debugger = Debugger::with_lines(lines, Symbols::default());
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x60, 0x2A, 0xA3, 0x00, 0x12, 0x04]).unwrap();
+
+        assert_eq!(debugger.display(&cpu, &"display v0".parse().unwrap()), "Displaying V0\n");
+        debugger.display(&cpu, &"d i".parse().unwrap());
+        assert_eq!(debugger.display(&cpu, &Command::Display(None)), " 0  V0\n 1  I\n");
+
+        cpu.cycle();
+        assert_eq!(debugger.show_displays(&cpu), "V0 = 2A  (was 00)\nI = 000\n");
+        assert_eq!(debugger.show_displays(&cpu), "V0 = 2A\nI = 000\n");
+
+        assert_eq!(debugger.display(&cpu, &Command::Undisplay(0)), "Stopped displaying V0\n");
+        cpu.cycle();
+        assert_eq!(debugger.show_displays(&cpu), "I = 300  (was 000)\n");
+    }
+
+    #[test]
+    fn test_stepping_and_showing_state() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x60, 0x2A, 0xA3, 0x00, 0x12, 0x04]).unwrap();
+
+        assert_eq!(execute(&mut cpu, &Command::Step(2)), "204  1204  JP 204\n");
+        assert!(execute(&mut cpu, &Command::Regs).starts_with("V0 2A  V1 00"));
+        assert!(execute(&mut cpu, &Command::Regs).ends_with("I 300  PC 204  SP 0  DT 00  ST 00\n"));
+        assert_eq!(
+            execute(&mut cpu, &Command::Mem { addr: 0x200, len: 20 }),
+            "200  60 2A A3 00 12 04 00 00 00 00 00 00 00 00 00 00\n210  00 00 00 00\n"
+        );
+    }
+
+    #[test]
+    fn test_poking_patches_the_program() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x60, 0x2A, 0x12, 0x02]).unwrap();
+        cpu.watchpoints_mut().add("w:200-203".parse().unwrap());
+
+        assert_eq!(execute(&mut cpu, &"poke 201 7F".parse().unwrap()), "201: 2A -> 7F\n");
+        assert_eq!(cpu.take_watch_hit(), None);
+        cpu.cycle();
+        assert_eq!(cpu.register(0), 0x7F);
+    }
+
+    #[test]
+    fn test_setting_registers() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x70, 0x01, 0x12, 0x00, 0x00, 0xEE]).unwrap();
+
+        assert_eq!(execute(&mut cpu, &"set v3 0x10".parse().unwrap()), "V3: 00 -> 10\n");
+        assert_eq!(execute(&mut cpu, &"set PC 202".parse().unwrap()), "PC: 200 -> 202\n");
+        assert_eq!(execute(&mut cpu, &"set [300] ff".parse().unwrap()), "[300]: 00 -> FF\n");
+        assert_eq!((cpu.register(3), cpu.pc(), cpu.peek(0x300)), (0x10, 0x202, 0xFF));
+
+        execute(&mut cpu, &"set dt 3c".parse().unwrap());
+        assert_eq!(cpu.registers().delay_timer, 0x3C);
+        assert!("set V0 100".parse::<Command>().unwrap_err().contains("too big for V0"));
+        assert!("set SP 11".parse::<Command>().is_err());
+        assert!("set X 1".parse::<Command>().unwrap_err().contains("invalid expression"));
+    }
+
+    #[test]
+    fn test_breakpoints_stop_frames() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x70, 0x01, 0x70, 0x01, 0x12, 0x00]).unwrap();
+
+        assert_eq!(execute(&mut cpu, &Command::Break(Some(0x202))), "Breakpoint at 202\n");
+        cpu.step_frame(100);
+        assert_eq!(cpu.take_break_hit(), Some(0x202));
+        assert_eq!(cpu.register(0), 1);
+
+        assert_eq!(execute(&mut cpu, &Command::Break(None)), "Breakpoint at 202\n");
+        assert_eq!(execute(&mut cpu, &Command::Break(Some(0x202))), "Removed breakpoint at 202\n");
+        assert_eq!(execute(&mut cpu, &Command::Break(None)), "No breakpoints\n");
+    }
+
+    #[test]
+    fn test_stepping_stops_early() {
+        // Adds to V0 in a loop.
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x70, 0x01, 0x70, 0x01, 0x12, 0x00]).unwrap();
+        cpu.breakpoints_mut().insert(0x200);
+
+        // The breakpoint the PC starts on doesn't stop it.
+        assert!(execute(&mut cpu, &"step 500".parse().unwrap()).starts_with("Stopped after 3 of 500 instructions\n"));
+        assert_eq!((cpu.pc(), cpu.register(0)), (0x200, 2));
+        assert_eq!(cpu.take_break_hit(), Some(0x200));
+        execute(&mut cpu, &Command::Break(Some(0x200)));
+
+        assert!(!execute(&mut cpu, &Command::Step(30)).starts_with("Stopped"));
+        assert_eq!(cpu.register(0), 22);
+    }
+
+    #[test]
+    fn test_goto_skips_code() {
+        // Jump over the instruction setting V0.
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x60, 0x01, 0x61, 0x02]).unwrap();
+
+        assert_eq!(execute(&mut cpu, &"g 202".parse().unwrap()), "PC: 200 -> 202\n");
+        cpu.cycle();
+        assert_eq!((cpu.register(0), cpu.register(1)), (0, 2));
+        assert!("goto 10000".parse::<Command>().unwrap_err().contains("too big for PC"));
+    }
+
+    #[test]
+    fn test_logging_writes() {
+        // Stores V0 and V1 at 300, then changes V0 and stores it again.
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x60, 0x07, 0x61, 0x09, 0xA3, 0x00, 0xF1, 0x55, 0x60, 0x08, 0xF0, 0x55]).unwrap();
+
+        assert_eq!(execute(&mut cpu, &"log 300".parse().unwrap()), "Logging writes to 300\n");
+        cpu.step_frame(6);
+        let expected = " 0  300\n      206  F155  300: 00 -> 07\n      20A  F055  300: 07 -> 08\n";
+        assert_eq!(execute(&mut cpu, &Command::Log(None)), expected);
+
+        assert_eq!(execute(&mut cpu, &Command::Unlog(1)), "No logged range 1\n");
+        assert_eq!(execute(&mut cpu, &Command::Unlog(0)), "Stopped logging 300\n");
+        assert_eq!(execute(&mut cpu, &Command::Log(None)), "No logged ranges\n");
+        assert_eq!("log 300-30f".parse(), Ok(Command::Log(Some((0x300, 0x30F)))));
+        assert!("log 30F-300".parse::<Command>().unwrap_err().contains("invalid range"));
+    }
+
+    #[test]
+    fn test_adding_and_removing_watchpoints() {
+        let mut cpu = CPU::default();
+        let command = "watch w:300-30f".parse().unwrap();
+        assert_eq!(execute(&mut cpu, &command), "Watching w:300-30F\n");
+        execute(&mut cpu, &"w rx:200".parse().unwrap());
+        assert_eq!(execute(&mut cpu, &Command::Watch(None)), " 0  w:300-30F\n 1  rx:200\n");
+
+        assert_eq!(execute(&mut cpu, &Command::Unwatch(0)), "Stopped watching w:300-30F\n");
+        assert_eq!(execute(&mut cpu, &Command::Unwatch(1)), "No watchpoint 1\n");
+        assert!("watch 300".parse::<Command>().unwrap_err().contains("invalid watchpoint"));
+    }
+
+    #[test]
+    fn test_toggling_breaking_on_draws() {
+        let mut cpu = CPU::default();
+        assert_eq!(execute(&mut cpu, &"draw".parse().unwrap()), "Breaking on draws\n");
+        assert!(cpu.break_on_draw());
+        assert_eq!(execute(&mut cpu, &Command::Draw), "Stopped breaking on draws\n");
+    }
+
+    #[test]
+    fn test_showing_the_call_stack() {
+        let mut cpu = CPU::default();
+        #[rustfmt::skip]
+        cpu.load(vec![
+            0x22, 0x04, // 0x200: CALL 204.
+            0x12, 0x02, // 0x202: Loop forever.
+            0x22, 0x08, // 0x204: CALL 208.
+            0x00, 0xEE, // 0x206: RET.
+            0x60, 0x01, // 0x208: V0 = 1.
+            0x00, 0xEE, // 0x20A: RET.
+        ]).unwrap();
+        cpu.step_frame(2);
+        let symbols = Symbols::parse("200 main\n204 outer\n208 inner").unwrap();
+
+        assert_eq!(
+            stack(&cpu, &symbols),
+            concat!(
+                " 0  208  6001  LD V0, 01          inner\n",
+                " 1  204  2208  CALL inner         outer\n",
+                " 2  200  2204  CALL outer         main\n",
+            )
+        );
+        assert_eq!(stack(&CPU::default(), &Symbols::default()).lines().count(), 1);
+
+        assert_eq!(
+            frame(&cpu, &symbols, 1),
+            concat!(
+                "   1FE  0000  SYS 000\n",
+                "main:\n",
+                "   200  2204  CALL outer\n",
+                "   202  1202  JP 202\n",
+                "outer:\n",
+                "=> 204  2208  CALL inner\n",
+                "   206  00EE  RET\n",
+                "inner:\n",
+                "   208  6001  LD V0, 01\n",
+                "   20A  00EE  RET\n",
+            )
+        );
+        assert_eq!(frame(&cpu, &symbols, 3), "No frame 3\n");
+    }
+}
@@ -0,0 +1,579 @@
+//! Instruction decoding. Turns an opcode into a typed instruction which execution, disassembly, tracing and tests all
+//! share, so there is one place deciding what an opcode means. Some opcodes mean different things on different
+//! variants, so decoding needs the variant as well as the opcode.
+
+// Self imports
+use crate::variant::Variant;
+
+// Std imports
+use std::fmt;
+
+/// A decoded instruction. `x` and `y` are register numbers, not the values in them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0NNN: SYS nnn.
+    Sys { nnn: usize },
+    /// 00E0: CLS. Also 0230 on hi-res CHIP-8.
+    Cls,
+    /// 00EE: RET.
+    Ret,
+    /// 1NNN: JP nnn.
+    Jump { nnn: usize },
+    /// 2NNN: CALL nnn.
+    Call { nnn: usize },
+    /// 3XKK: SE Vx, kk.
+    SkipEqByte { x: usize, kk: u8 },
+    /// 4XKK: SNE Vx, kk.
+    SkipNeByte { x: usize, kk: u8 },
+    /// 5XY0: SE Vx, Vy.
+    SkipEqReg { x: usize, y: usize },
+    /// 6XKK: LD Vx, kk.
+    LoadByte { x: usize, kk: u8 },
+    /// 7XKK: ADD Vx, kk.
+    AddByte { x: usize, kk: u8 },
+    /// 8XY0: LD Vx, Vy.
+    LoadReg { x: usize, y: usize },
+    /// 8XY1: OR Vx, Vy.
+    Or { x: usize, y: usize },
+    /// 8XY2: AND Vx, Vy.
+    And { x: usize, y: usize },
+    /// 8XY3: XOR Vx, Vy.
+    Xor { x: usize, y: usize },
+    /// 8XY4: ADD Vx, Vy.
+    AddReg { x: usize, y: usize },
+    /// 8XY5: SUB Vx, Vy.
+    Sub { x: usize, y: usize },
+    /// 8XY6: SHR Vx, Vy.
+    ShiftRight { x: usize, y: usize },
+    /// 8XY7: SUBN Vx, Vy.
+    SubN { x: usize, y: usize },
+    /// 8XYE: SHL Vx, Vy.
+    ShiftLeft { x: usize, y: usize },
+    /// 9XY0: SNE Vx, Vy.
+    SkipNeReg { x: usize, y: usize },
+    /// ANNN: LD I, nnn.
+    LoadI { nnn: usize },
+    /// BNNN: JP V0, nnn.
+    JumpV0 { nnn: usize },
+    /// CXKK: RND Vx, kk.
+    Random { x: usize, kk: u8 },
+    /// DXYN: DRW Vx, Vy, n.
+    Draw { x: usize, y: usize, n: usize },
+    /// EX9E: SKP Vx.
+    SkipKey { x: usize },
+    /// EXA1: SKNP Vx.
+    SkipNotKey { x: usize },
+    /// FX07: LD Vx, DT.
+    LoadDelay { x: usize },
+    /// FX0A: LD Vx, K.
+    WaitKey { x: usize },
+    /// FX15: LD DT, Vx.
+    SetDelay { x: usize },
+    /// FX18: LD ST, Vx.
+    SetSound { x: usize },
+    /// FX1E: ADD I, Vx.
+    AddI { x: usize },
+    /// FX29: LD F, Vx.
+    LoadFont { x: usize },
+    /// FX33: LD B, Vx.
+    Bcd { x: usize },
+    /// FX55: LD [I], Vx.
+    StoreRegisters { x: usize },
+    /// FX65: LD Vx, [I].
+    LoadRegisters { x: usize },
+
+    /// SCHIP 00CN: SCD n.
+    ScrollDown { n: usize },
+    /// SCHIP 00FB: SCR.
+    ScrollRight,
+    /// SCHIP 00FC: SCL.
+    ScrollLeft,
+    /// SCHIP 00FD: EXIT.
+    Exit,
+    /// SCHIP 00FE: LOW.
+    Lores,
+    /// SCHIP 00FF: HIGH.
+    Hires,
+    /// SCHIP FX30: LD HF, Vx.
+    LoadBigFont { x: usize },
+    /// SCHIP FX75: LD R, Vx.
+    SaveFlags { x: usize },
+    /// SCHIP FX85: LD Vx, R.
+    LoadFlags { x: usize },
+
+    /// XO-CHIP F000 NNNN: LD I, long nnnn. The address is the word after the opcode, so the instruction is 4 bytes.
+    LongI,
+    /// XO-CHIP 5XY2: SAVE Vx - Vy. The range runs backwards when x > y.
+    SaveRange { x: usize, y: usize },
+    /// XO-CHIP 5XY3: LOAD Vx - Vy.
+    LoadRange { x: usize, y: usize },
+    /// XO-CHIP FN01: PLANE n. n is a bitmask of the planes to draw to.
+    SelectPlanes { n: usize },
+    /// XO-CHIP 00DN: SCU n.
+    ScrollUp { n: usize },
+
+    /// CHIP-8X 02A0: BGC.
+    CycleBackground,
+    /// CHIP-8X 5XY1: ADD Vx, Vy, nibble by nibble.
+    AddNibbles { x: usize, y: usize },
+    /// CHIP-8X BXYN: COL Vx, Vy, n.
+    Colour { x: usize, y: usize, n: usize },
+    /// CHIP-8X EXF2: SKP2 Vx.
+    SkipKey2 { x: usize },
+    /// CHIP-8X EXF5: SKNP2 Vx.
+    SkipNotKey2 { x: usize },
+    /// CHIP-8X FXF8: OUT Vx.
+    Out { x: usize },
+
+    /// MegaChip 0010: MEGAOFF.
+    #[cfg(feature = "megachip")]
+    MegaOff,
+    /// MegaChip 0011: MEGAON.
+    #[cfg(feature = "megachip")]
+    MegaOn,
+    /// MegaChip 00BN: SCRU n.
+    #[cfg(feature = "megachip")]
+    MegaScrollUp { n: usize },
+    /// MegaChip 01NN: LDHI I, nnnnnn. The low 16 bits of the address are the following word.
+    #[cfg(feature = "megachip")]
+    LoadLongI { nn: u8 },
+    /// MegaChip 02NN: LDPAL nn.
+    #[cfg(feature = "megachip")]
+    LoadPalette { nn: u8 },
+    /// MegaChip 03NN: SPRW nn.
+    #[cfg(feature = "megachip")]
+    SpriteWidth { nn: u8 },
+    /// MegaChip 04NN: SPRH nn.
+    #[cfg(feature = "megachip")]
+    SpriteHeight { nn: u8 },
+    /// MegaChip 05NN: ALPHA nn.
+    #[cfg(feature = "megachip")]
+    Alpha { nn: u8 },
+    /// MegaChip 060N: DIGISND n.
+    #[cfg(feature = "megachip")]
+    PlaySound { n: usize },
+    /// MegaChip 0700: STOPSND.
+    #[cfg(feature = "megachip")]
+    StopSound,
+    /// MegaChip 080N: BMODE n.
+    #[cfg(feature = "megachip")]
+    BlendMode { n: usize },
+    /// MegaChip 09NN: CCOL nn.
+    #[cfg(feature = "megachip")]
+    CollisionColour { nn: u8 },
+
+    /// Not an instruction on this variant.
+    Invalid(u16),
+}
+
+impl Instruction {
+    /// Whether this is a MegaChip instruction which only works in MegaChip mode. Outside it, it is a SYS call like any
+    /// other 0NNN.
+    #[cfg(feature = "megachip")]
+    pub fn needs_megachip_mode(&self) -> bool {
+        use Instruction::*;
+        matches!(
+            self,
+            MegaOff
+                | MegaScrollUp { .. }
+                | LoadLongI { .. }
+                | LoadPalette { .. }
+                | SpriteWidth { .. }
+                | SpriteHeight { .. }
+                | Alpha { .. }
+                | PlaySound { .. }
+                | StopSound
+                | BlendMode { .. }
+                | CollisionColour { .. }
+        )
+    }
+
+    /// Bytes the instruction takes up, including any operand words after the opcode.
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LongI => 4,
+            #[cfg(feature = "megachip")]
+            Instruction::LoadLongI { .. } => 4,
+            _ => 2,
+        }
+    }
+
+    /// The opcode which decodes to this instruction.
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+
+        let xy = |op: u16, x: usize, y: usize, n: usize| op | (x as u16) << 8 | (y as u16) << 4 | n as u16;
+        let xkk = |op: u16, x: usize, kk: u8| op | (x as u16) << 8 | kk as u16;
+        let addr = |op: u16, nnn: usize| op | nnn as u16 & 0xFFF;
+
+        match *self {
+            Sys { nnn } => addr(0x0000, nnn),
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            Jump { nnn } => addr(0x1000, nnn),
+            Call { nnn } => addr(0x2000, nnn),
+            SkipEqByte { x, kk } => xkk(0x3000, x, kk),
+            SkipNeByte { x, kk } => xkk(0x4000, x, kk),
+            SkipEqReg { x, y } => xy(0x5000, x, y, 0x0),
+            LoadByte { x, kk } => xkk(0x6000, x, kk),
+            AddByte { x, kk } => xkk(0x7000, x, kk),
+            LoadReg { x, y } => xy(0x8000, x, y, 0x0),
+            Or { x, y } => xy(0x8000, x, y, 0x1),
+            And { x, y } => xy(0x8000, x, y, 0x2),
+            Xor { x, y } => xy(0x8000, x, y, 0x3),
+            AddReg { x, y } => xy(0x8000, x, y, 0x4),
+            Sub { x, y } => xy(0x8000, x, y, 0x5),
+            ShiftRight { x, y } => xy(0x8000, x, y, 0x6),
+            SubN { x, y } => xy(0x8000, x, y, 0x7),
+            ShiftLeft { x, y } => xy(0x8000, x, y, 0xE),
+            SkipNeReg { x, y } => xy(0x9000, x, y, 0x0),
+            LoadI { nnn } => addr(0xA000, nnn),
+            JumpV0 { nnn } => addr(0xB000, nnn),
+            Random { x, kk } => xkk(0xC000, x, kk),
+            Draw { x, y, n } => xy(0xD000, x, y, n),
+            SkipKey { x } => xkk(0xE000, x, 0x9E),
+            SkipNotKey { x } => xkk(0xE000, x, 0xA1),
+            LoadDelay { x } => xkk(0xF000, x, 0x07),
+            WaitKey { x } => xkk(0xF000, x, 0x0A),
+            SetDelay { x } => xkk(0xF000, x, 0x15),
+            SetSound { x } => xkk(0xF000, x, 0x18),
+            AddI { x } => xkk(0xF000, x, 0x1E),
+            LoadFont { x } => xkk(0xF000, x, 0x29),
+            Bcd { x } => xkk(0xF000, x, 0x33),
+            StoreRegisters { x } => xkk(0xF000, x, 0x55),
+            LoadRegisters { x } => xkk(0xF000, x, 0x65),
+            ScrollDown { n } => 0x00C0 | n as u16,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            Lores => 0x00FE,
+            Hires => 0x00FF,
+            LoadBigFont { x } => xkk(0xF000, x, 0x30),
+            SaveFlags { x } => xkk(0xF000, x, 0x75),
+            LongI => 0xF000,
+            SaveRange { x, y } => xy(0x5000, x, y, 0x2),
+            LoadRange { x, y } => xy(0x5000, x, y, 0x3),
+            SelectPlanes { n } => xkk(0xF000, n, 0x01),
+            ScrollUp { n } => 0x00D0 | n as u16,
+            LoadFlags { x } => xkk(0xF000, x, 0x85),
+            CycleBackground => 0x02A0,
+            AddNibbles { x, y } => xy(0x5000, x, y, 0x1),
+            Colour { x, y, n } => xy(0xB000, x, y, n),
+            SkipKey2 { x } => xkk(0xE000, x, 0xF2),
+            SkipNotKey2 { x } => xkk(0xE000, x, 0xF5),
+            Out { x } => xkk(0xF000, x, 0xF8),
+            #[cfg(feature = "megachip")]
+            MegaOff => 0x0010,
+            #[cfg(feature = "megachip")]
+            MegaOn => 0x0011,
+            #[cfg(feature = "megachip")]
+            MegaScrollUp { n } => 0x00B0 | n as u16,
+            #[cfg(feature = "megachip")]
+            LoadLongI { nn } => 0x0100 | nn as u16,
+            #[cfg(feature = "megachip")]
+            LoadPalette { nn } => 0x0200 | nn as u16,
+            #[cfg(feature = "megachip")]
+            SpriteWidth { nn } => 0x0300 | nn as u16,
+            #[cfg(feature = "megachip")]
+            SpriteHeight { nn } => 0x0400 | nn as u16,
+            #[cfg(feature = "megachip")]
+            Alpha { nn } => 0x0500 | nn as u16,
+            #[cfg(feature = "megachip")]
+            PlaySound { n } => 0x0600 | n as u16,
+            #[cfg(feature = "megachip")]
+            StopSound => 0x0700,
+            #[cfg(feature = "megachip")]
+            BlendMode { n } => 0x0800 | n as u16,
+            #[cfg(feature = "megachip")]
+            CollisionColour { nn } => 0x0900 | nn as u16,
+            Invalid(opcode) => opcode,
+        }
+    }
+}
+
+/// Decode an opcode as the given variant would.
+pub fn decode(opcode: u16, variant: Variant) -> Instruction {
+    let nibbles = (
+        (opcode >> 12) as usize,
+        (opcode >> 8 & 0xF) as usize,
+        (opcode >> 4 & 0xF) as usize,
+        (opcode & 0xF) as usize,
+    );
+
+    let kk = (opcode & 0x00FF) as u8;
+    let nnn = (opcode & 0x0FFF) as usize;
+
+    #[cfg(feature = "megachip")]
+    if variant == Variant::MegaChip {
+        if let Some(instruction) = decode_megachip(nibbles, kk) {
+            return instruction;
+        }
+    }
+
+    let chip8x = variant == Variant::Chip8X;
+    let chip8_hires = variant == Variant::Chip8Hires;
+    // SCHIP's EXIT stops the interpreter. CHIP-8X, hi-res CHIP-8 and the ETI-660 have no such thing, so there 00FD
+    // calls the machine code at 0FD. CHIP-8 runs SCHIP programs too, so keeps it.
+    let exit = !matches!(variant, Variant::Chip8X | Variant::Chip8Hires | Variant::Eti660 | Variant::Eti660Hires);
+
+    match nibbles {
+        (0x0, 0x2, 0xA, 0x0) if chip8x => Instruction::CycleBackground,
+        (0x5, x, y, 0x1) if chip8x => Instruction::AddNibbles { x, y },
+        (0xB, x, y, n) if chip8x => Instruction::Colour { x, y, n },
+        (0xE, x, 0xF, 0x2) if chip8x => Instruction::SkipKey2 { x },
+        (0xE, x, 0xF, 0x5) if chip8x => Instruction::SkipNotKey2 { x },
+        (0xF, x, 0xF, 0x8) if chip8x => Instruction::Out { x },
+        (0x0, 0x2, 0x3, 0x0) if chip8_hires => Instruction::Cls, // Hi-res CLS. Same as 00E0 on the taller screen.
+        (0x0, 0x0, 0xE, 0x0) => Instruction::Cls,
+        (0x0, 0x0, 0xE, 0xE) => Instruction::Ret,
+        (0x0, 0x0, 0xC, n) => Instruction::ScrollDown { n },
+        (0x0, 0x0, 0xD, n) => Instruction::ScrollUp { n },
+        (0x0, 0x0, 0xF, 0xB) => Instruction::ScrollRight,
+        (0x0, 0x0, 0xF, 0xC) => Instruction::ScrollLeft,
+        (0x0, 0x0, 0xF, 0xD) if exit => Instruction::Exit,
+        (0x0, 0x0, 0xF, 0xE) => Instruction::Lores,
+        (0x0, 0x0, 0xF, 0xF) => Instruction::Hires,
+        (0x0, _, _, _) => Instruction::Sys { nnn },
+        (0x1, _, _, _) => Instruction::Jump { nnn },
+        (0x2, _, _, _) => Instruction::Call { nnn },
+        (0x3, x, _, _) => Instruction::SkipEqByte { x, kk },
+        (0x4, x, _, _) => Instruction::SkipNeByte { x, kk },
+        (0x5, x, y, 0x0) => Instruction::SkipEqReg { x, y },
+        (0x5, x, y, 0x2) => Instruction::SaveRange { x, y },
+        (0x5, x, y, 0x3) => Instruction::LoadRange { x, y },
+        (0x6, x, _, _) => Instruction::LoadByte { x, kk },
+        (0x7, x, _, _) => Instruction::AddByte { x, kk },
+        (0x8, x, y, 0x0) => Instruction::LoadReg { x, y },
+        (0x8, x, y, 0x1) => Instruction::Or { x, y },
+        (0x8, x, y, 0x2) => Instruction::And { x, y },
+        (0x8, x, y, 0x3) => Instruction::Xor { x, y },
+        (0x8, x, y, 0x4) => Instruction::AddReg { x, y },
+        (0x8, x, y, 0x5) => Instruction::Sub { x, y },
+        (0x8, x, y, 0x6) => Instruction::ShiftRight { x, y },
+        (0x8, x, y, 0x7) => Instruction::SubN { x, y },
+        (0x8, x, y, 0xE) => Instruction::ShiftLeft { x, y },
+        (0x9, x, y, 0x0) => Instruction::SkipNeReg { x, y },
+        (0xA, _, _, _) => Instruction::LoadI { nnn },
+        (0xB, _, _, _) => Instruction::JumpV0 { nnn },
+        (0xC, x, _, _) => Instruction::Random { x, kk },
+        (0xD, x, y, n) => Instruction::Draw { x, y, n },
+        (0xE, x, 0x9, 0xE) => Instruction::SkipKey { x },
+        (0xE, x, 0xA, 0x1) => Instruction::SkipNotKey { x },
+        (0xF, x, 0x0, 0x7) => Instruction::LoadDelay { x },
+        (0xF, x, 0x0, 0xA) => Instruction::WaitKey { x },
+        (0xF, x, 0x1, 0x5) => Instruction::SetDelay { x },
+        (0xF, x, 0x1, 0x8) => Instruction::SetSound { x },
+        (0xF, x, 0x1, 0xE) => Instruction::AddI { x },
+        (0xF, x, 0x2, 0x9) => Instruction::LoadFont { x },
+        (0xF, x, 0x3, 0x3) => Instruction::Bcd { x },
+        (0xF, x, 0x5, 0x5) => Instruction::StoreRegisters { x },
+        (0xF, x, 0x6, 0x5) => Instruction::LoadRegisters { x },
+        (0xF, x, 0x3, 0x0) => Instruction::LoadBigFont { x },
+        (0xF, 0x0, 0x0, 0x0) => Instruction::LongI,
+        (0xF, n, 0x0, 0x1) => Instruction::SelectPlanes { n },
+        (0xF, x, 0x7, 0x5) => Instruction::SaveFlags { x },
+        (0xF, x, 0x8, 0x5) => Instruction::LoadFlags { x },
+        _ => Instruction::Invalid(opcode),
+    }
+}
+
+/// MegaChip's own instructions. 00E0 and DXYN are decoded as usual and behave differently in MegaChip mode.
+#[cfg(feature = "megachip")]
+fn decode_megachip(nibbles: (usize, usize, usize, usize), nn: u8) -> Option<Instruction> {
+    let instruction = match nibbles {
+        (0x0, 0x0, 0x1, 0x0) => Instruction::MegaOff,
+        (0x0, 0x0, 0x1, 0x1) => Instruction::MegaOn,
+        (0x0, 0x0, 0xB, n) => Instruction::MegaScrollUp { n },
+        (0x0, 0x1, _, _) => Instruction::LoadLongI { nn },
+        (0x0, 0x2, _, _) => Instruction::LoadPalette { nn },
+        (0x0, 0x3, _, _) => Instruction::SpriteWidth { nn },
+        (0x0, 0x4, _, _) => Instruction::SpriteHeight { nn },
+        (0x0, 0x5, _, _) => Instruction::Alpha { nn },
+        (0x0, 0x6, 0x0, n) => Instruction::PlaySound { n },
+        (0x0, 0x7, 0x0, 0x0) => Instruction::StopSound,
+        (0x0, 0x8, 0x0, n) => Instruction::BlendMode { n },
+        (0x0, 0x9, _, _) => Instruction::CollisionColour { nn },
+        _ => return None,
+    };
+
+    Some(instruction)
+}
+
+/// Disassembly, with the mnemonics of the conformance table.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Instruction::*;
+
+        match *self {
+            Sys { nnn } => write!(f, "SYS {:03X}", nnn),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            Jump { nnn } => write!(f, "JP {:03X}", nnn),
+            Call { nnn } => write!(f, "CALL {:03X}", nnn),
+            SkipEqByte { x, kk } => write!(f, "SE V{:X}, {:02X}", x, kk),
+            SkipNeByte { x, kk } => write!(f, "SNE V{:X}, {:02X}", x, kk),
+            SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            LoadByte { x, kk } => write!(f, "LD V{:X}, {:02X}", x, kk),
+            AddByte { x, kk } => write!(f, "ADD V{:X}, {:02X}", x, kk),
+            LoadReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            SubN { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LoadI { nnn } => write!(f, "LD I, {:03X}", nnn),
+            JumpV0 { nnn } => write!(f, "JP V0, {:03X}", nnn),
+            Random { x, kk } => write!(f, "RND V{:X}, {:02X}", x, kk),
+            Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {:X}", x, y, n),
+            SkipKey { x } => write!(f, "SKP V{:X}", x),
+            SkipNotKey { x } => write!(f, "SKNP V{:X}", x),
+            LoadDelay { x } => write!(f, "LD V{:X}, DT", x),
+            WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            AddI { x } => write!(f, "ADD I, V{:X}", x),
+            LoadFont { x } => write!(f, "LD F, V{:X}", x),
+            Bcd { x } => write!(f, "LD B, V{:X}", x),
+            StoreRegisters { x } => write!(f, "LD [I], V{:X}", x),
+            LoadRegisters { x } => write!(f, "LD V{:X}, [I]", x),
+            ScrollDown { n } => write!(f, "SCD {:X}", n),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Lores => write!(f, "LOW"),
+            Hires => write!(f, "HIGH"),
+            LoadBigFont { x } => write!(f, "LD HF, V{:X}", x),
+            SaveFlags { x } => write!(f, "LD R, V{:X}", x),
+            LongI => write!(f, "LD I, long"),
+            SaveRange { x, y } => write!(f, "SAVE V{:X} - V{:X}", x, y),
+            LoadRange { x, y } => write!(f, "LOAD V{:X} - V{:X}", x, y),
+            SelectPlanes { n } => write!(f, "PLANE {:X}", n),
+            ScrollUp { n } => write!(f, "SCU {:X}", n),
+            LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+            CycleBackground => write!(f, "BGC"),
+            AddNibbles { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Colour { x, y, n } => write!(f, "COL V{:X}, V{:X}, {:X}", x, y, n),
+            SkipKey2 { x } => write!(f, "SKP2 V{:X}", x),
+            SkipNotKey2 { x } => write!(f, "SKNP2 V{:X}", x),
+            Out { x } => write!(f, "OUT V{:X}", x),
+            #[cfg(feature = "megachip")]
+            MegaOff => write!(f, "MEGAOFF"),
+            #[cfg(feature = "megachip")]
+            MegaOn => write!(f, "MEGAON"),
+            #[cfg(feature = "megachip")]
+            MegaScrollUp { n } => write!(f, "SCRU {:X}", n),
+            #[cfg(feature = "megachip")]
+            LoadLongI { nn } => write!(f, "LDHI I, {:02X}....", nn),
+            #[cfg(feature = "megachip")]
+            LoadPalette { nn } => write!(f, "LDPAL {:02X}", nn),
+            #[cfg(feature = "megachip")]
+            SpriteWidth { nn } => write!(f, "SPRW {:02X}", nn),
+            #[cfg(feature = "megachip")]
+            SpriteHeight { nn } => write!(f, "SPRH {:02X}", nn),
+            #[cfg(feature = "megachip")]
+            Alpha { nn } => write!(f, "ALPHA {:02X}", nn),
+            #[cfg(feature = "megachip")]
+            PlaySound { n } => write!(f, "DIGISND {:X}", n),
+            #[cfg(feature = "megachip")]
+            StopSound => write!(f, "STOPSND"),
+            #[cfg(feature = "megachip")]
+            BlendMode { n } => write!(f, "BMODE {:X}", n),
+            #[cfg(feature = "megachip")]
+            CollisionColour { nn } => write!(f, "CCOL {:02X}", nn),
+            Invalid(opcode) => write!(f, "DW {:04X}", opcode),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoding_fields() {
+        let chip8 = Variant::Chip8;
+        assert_eq!(decode(0x00E0, chip8), Instruction::Cls);
+        assert_eq!(decode(0x0123, chip8), Instruction::Sys { nnn: 0x123 });
+        assert_eq!(decode(0x00FD, chip8), Instruction::Exit);
+        assert_eq!(decode(0x00FD, Variant::Eti660), Instruction::Sys { nnn: 0x0FD });
+        assert_eq!(decode(0x00FD, Variant::Chip8X), Instruction::Sys { nnn: 0x0FD });
+        assert_eq!(decode(0x3A2F, chip8), Instruction::SkipEqByte { x: 0xA, kk: 0x2F });
+        assert_eq!(decode(0x8AB6, chip8), Instruction::ShiftRight { x: 0xA, y: 0xB });
+        assert_eq!(decode(0xD125, chip8), Instruction::Draw { x: 1, y: 2, n: 5 });
+        assert_eq!(decode(0xF165, chip8), Instruction::LoadRegisters { x: 1 });
+        assert_eq!(decode(0x00D2, chip8), Instruction::ScrollUp { n: 2 });
+    }
+
+    #[test]
+    fn test_invalid_opcodes() {
+        for opcode in [0x5121, 0x812F, 0xE0F2, 0xF0FF].iter() {
+            assert_eq!(decode(*opcode, Variant::Chip8), Instruction::Invalid(*opcode));
+        }
+    }
+
+    #[test]
+    fn test_decoding_depends_on_variant() {
+        assert_eq!(decode(0x02A0, Variant::Chip8), Instruction::Sys { nnn: 0x2A0 });
+        assert_eq!(decode(0x02A0, Variant::Chip8X), Instruction::CycleBackground);
+        assert_eq!(decode(0xB123, Variant::Chip8), Instruction::JumpV0 { nnn: 0x123 });
+        assert_eq!(decode(0xB123, Variant::Chip8X), Instruction::Colour { x: 1, y: 2, n: 3 });
+        assert_eq!(decode(0x0230, Variant::Chip8), Instruction::Sys { nnn: 0x230 });
+        assert_eq!(decode(0x0230, Variant::Chip8Hires), Instruction::Cls);
+    }
+
+    #[test]
+    fn test_encoding_reverses_decoding() {
+        for variant in [Variant::Chip8, Variant::Chip8X].iter() {
+            for opcode in (0..=0xFFFF).step_by(7) {
+                let instruction = decode(opcode, *variant);
+                // 0230 decodes as CLS on hi-res CHIP-8, so only check variants where decoding is one to one.
+                assert_eq!(instruction.encode(), opcode, "{} on {}", instruction, variant);
+            }
+        }
+    }
+
+    #[test]
+    fn test_disassembly() {
+        let disassemble = |opcode| decode(opcode, Variant::Chip8).to_string();
+        assert_eq!(disassemble(0x1228), "JP 228");
+        assert_eq!(disassemble(0x6A02), "LD VA, 02");
+        assert_eq!(disassemble(0xD015), "DRW V0, V1, 5");
+        assert_eq!(disassemble(0xF355), "LD [I], V3");
+        assert_eq!(disassemble(0x5A33), "LOAD VA - V3");
+        assert_eq!(disassemble(0xFFFF), "DW FFFF");
+    }
+
+    // Every implemented opcode in the conformance table decodes to an instruction on its platform.
+    #[test]
+    fn test_conformance_samples_decode() {
+        use crate::conformance::{Platform, OPCODES};
+
+        for op in OPCODES.iter().filter(|op| op.implemented) {
+            let variant = match op.platform {
+                Platform::Chip8X => Variant::Chip8X,
+                Platform::Chip8Hires => Variant::Chip8Hires,
+                Platform::XoChip => Variant::XoChip,
+                #[cfg(feature = "megachip")]
+                Platform::MegaChip => Variant::MegaChip,
+                _ => Variant::Chip8,
+            };
+            let instruction = decode(op.sample, variant);
+            assert!(instruction != Instruction::Invalid(op.sample), "{} does not decode", op.pattern);
+        }
+    }
+
+    #[cfg(feature = "megachip")]
+    #[test]
+    fn test_decoding_megachip() {
+        assert_eq!(decode(0x0011, Variant::Chip8), Instruction::Sys { nnn: 0x011 });
+        assert_eq!(decode(0x0011, Variant::MegaChip), Instruction::MegaOn);
+        assert_eq!(decode(0x0123, Variant::MegaChip), Instruction::LoadLongI { nn: 0x23 });
+        assert_eq!(decode(0x00E0, Variant::MegaChip), Instruction::Cls);
+        assert!(decode(0x0010, Variant::MegaChip).needs_megachip_mode());
+        assert!(!decode(0x0011, Variant::MegaChip).needs_megachip_mode());
+    }
+}
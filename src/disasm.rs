@@ -0,0 +1,335 @@
+//! Disassembler. Turns ROMs, or any slice of memory, into listings of addresses, opcodes and mnemonics for the
+//! disassemble command and the debugger. Decoding is left to decode.rs, so listings always agree with execution.
+//!
+//! ```text
+//! L200:
+//! 200  6A02  LD VA, 02
+//! 202  2208  CALL 208
+//! ```
+//!
+//! Addresses jumped or called to get a label. With a symbol file, labelled addresses use its names instead, as do
+//! the operands of jumps, calls and LD I which point at them. By default every byte is decoded in turn, so data mixed
+//! in with code is disassembled like anything else. Following the program's flow from its first instruction lists
+//! what no jump, call, skip or fall through reaches as DB instead, though code only reached through JP V0 is missed.
+//! A coverage map from a run tells the two apart for certain, and bytes it saw read as data but never executed are
+//! listed as DB.
+
+// Self imports
+use crate::coverage::Coverage;
+use crate::decode::{decode, Instruction};
+use crate::symbols::Symbols;
+use crate::variant::Variant;
+
+// Std imports
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// One line of a listing.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Line {
+    Code {
+        addr: usize,
+        opcode: u16,
+        instruction: Instruction,
+    },
+
+    /// XO-CHIP's 4 byte LD I, long nnnn, with the address that follows the opcode.
+    Long { addr: usize, nnnn: u16 },
+
+    /// A byte of data, or a last byte too short to be an instruction.
+    Byte { addr: usize, byte: u8 },
+}
+
+impl Line {
+    pub fn addr(&self) -> usize {
+        match self {
+            Line::Code { addr, .. } | Line::Long { addr, .. } | Line::Byte { addr, .. } => *addr,
+        }
+    }
+
+    /// Where a jump or call goes.
+    pub fn target(&self) -> Option<usize> {
+        match self {
+            Line::Code { instruction, .. } => match instruction {
+                Instruction::Jump { nnn } | Instruction::Call { nnn } => Some(*nnn),
+                _ => None,
+            },
+            Line::Long { .. } | Line::Byte { .. } => None,
+        }
+    }
+
+    /// The address in the instruction, if it is one which can be given as a label.
+    pub fn operand(&self) -> Option<usize> {
+        match self {
+            Line::Code { instruction, .. } => match instruction {
+                Instruction::Jump { nnn } | Instruction::Call { nnn } => Some(*nnn),
+                Instruction::LoadI { nnn } | Instruction::JumpV0 { nnn } => Some(*nnn),
+                _ => None,
+            },
+            Line::Long { .. } | Line::Byte { .. } => None,
+        }
+    }
+
+    /// The line with its operand given as a label if there is one for it.
+    pub fn with_symbols(&self, symbols: &Symbols) -> String {
+        let text = self.to_string();
+        match self.operand().and_then(|addr| Some((addr, symbols.name(addr)?))) {
+            Some((addr, name)) => match text.strip_suffix(&format!("{:03X}", addr)) {
+                Some(start) => format!("{}{}", start, name),
+                None => text,
+            },
+            None => text,
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Code { addr, opcode, instruction } => write!(f, "{:03X}  {:04X}  {}", addr, opcode, instruction),
+            Line::Long { addr, nnnn } => write!(f, "{:03X}  F000  LD I, long {:04X}", addr, nnnn),
+            Line::Byte { addr, byte } => write!(f, "{:03X}  {:02X}    DB {:02X}", addr, byte, byte),
+        }
+    }
+}
+
+/// Disassemble bytes loaded at `start`, two at a time, or four for LD I, long nnnn.
+pub fn disassemble(bytes: &[u8], start: usize, variant: Variant) -> Vec<Line> {
+    disassemble_with(bytes, start, variant, |_| false)
+}
+
+/// The instruction at an address of bytes loaded at `start` and where execution can go after it: the next
+/// instruction, a jump or call's target or either side of a skip. Returns, exits and computed jumps go nowhere which
+/// can be known. None outside the bytes.
+pub fn successors(bytes: &[u8], start: usize, variant: Variant, addr: usize) -> Option<(Instruction, Vec<usize>)> {
+    let opcode_at = |addr: usize| {
+        let offset = addr.checked_sub(start).filter(|offset| offset + 1 < bytes.len())?;
+        Some((bytes[offset] as u16) << 8 | bytes[offset + 1] as u16)
+    };
+    let len = |opcode: Option<u16>| match opcode.map(|opcode| decode(opcode, variant)) {
+        Some(Instruction::LongI) => 4,
+        _ => 2,
+    };
+
+    let opcode = opcode_at(addr)?;
+    let instruction = decode(opcode, variant);
+    let next = addr + len(Some(opcode));
+    let successors = match instruction {
+        Instruction::Jump { nnn } => vec![nnn],
+        Instruction::Call { nnn } => vec![nnn, next],
+        Instruction::Ret | Instruction::Exit | Instruction::JumpV0 { .. } => vec![],
+        Instruction::SkipEqByte { .. }
+        | Instruction::SkipNeByte { .. }
+        | Instruction::SkipEqReg { .. }
+        | Instruction::SkipNeReg { .. }
+        | Instruction::SkipKey { .. }
+        | Instruction::SkipNotKey { .. }
+        | Instruction::SkipKey2 { .. }
+        | Instruction::SkipNotKey2 { .. } => vec![next, next + len(opcode_at(next))],
+        _ => vec![next],
+    };
+    Some((instruction, successors))
+}
+
+/// Where instructions start on every path through the program from its first instruction. Paths end at returns,
+/// exits, computed jumps and the end of the bytes.
+pub fn reachable(bytes: &[u8], start: usize, variant: Variant) -> BTreeSet<usize> {
+    let mut code = BTreeSet::new();
+    let mut todo = vec![start];
+    while let Some(addr) = todo.pop() {
+        if code.contains(&addr) {
+            continue;
+        }
+        if let Some((_, next)) = successors(bytes, start, variant, addr) {
+            code.insert(addr);
+            todo.extend(next);
+        }
+    }
+    code
+}
+
+// Disassemble, listing the bytes at addresses which are known to be data one at a time.
+fn disassemble_with(bytes: &[u8], start: usize, variant: Variant, data: impl Fn(usize) -> bool) -> Vec<Line> {
+    let word = |offset: usize| (bytes[offset] as u16) << 8 | bytes[offset + 1] as u16;
+    let mut lines = Vec::with_capacity(bytes.len() / 2 + 1);
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let addr = start + offset;
+        if offset + 1 == bytes.len() || data(addr) {
+            lines.push(Line::Byte { addr, byte: bytes[offset] });
+            offset += 1;
+            continue;
+        }
+
+        let opcode = word(offset);
+        let instruction = decode(opcode, variant);
+        if instruction == Instruction::LongI && offset + 3 < bytes.len() {
+            lines.push(Line::Long { addr, nnnn: word(offset + 2) });
+            offset += 4;
+        } else {
+            lines.push(Line::Code { addr, opcode, instruction });
+            offset += 2;
+        }
+    }
+
+    lines
+}
+
+/// A whole program's disassembly, with labels on the addresses it jumps or calls to.
+pub struct Listing {
+    pub lines: Vec<Line>,
+    pub labels: BTreeSet<usize>,
+    pub symbols: Symbols,
+}
+
+impl Listing {
+    pub fn new(bytes: &[u8], start: usize, variant: Variant) -> Self {
+        Self::from_lines(disassemble(bytes, start, variant))
+    }
+
+    /// A listing which shows what a run's coverage saw read as data, and never executed, as data.
+    pub fn with_coverage(bytes: &[u8], start: usize, variant: Variant, coverage: &Coverage) -> Self {
+        Self::from_lines(disassemble_with(bytes, start, variant, |addr| coverage.data_only(addr)))
+    }
+
+    /// A listing which follows the program's flow, listing bytes no path reaches as data.
+    pub fn following(bytes: &[u8], start: usize, variant: Variant) -> Self {
+        let code = reachable(bytes, start, variant);
+        Self::from_lines(disassemble_with(bytes, start, variant, |addr| !code.contains(&addr)))
+    }
+
+    /// Leave out the labels on addresses jumped or called to. Labels from a symbol file are still shown.
+    pub fn without_labels(mut self) -> Self {
+        self.labels.clear();
+        self
+    }
+
+    /// Name labels, and the operands pointing at them, from a symbol file.
+    pub fn with_symbols(mut self, symbols: Symbols) -> Self {
+        self.labels.extend(symbols.iter().map(|(addr, _)| addr));
+        self.symbols = symbols;
+        self
+    }
+
+    fn from_lines(lines: Vec<Line>) -> Self {
+        let labels = lines.iter().filter_map(Line::target).collect();
+        Self {
+            lines,
+            labels,
+            symbols: Symbols::default(),
+        }
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            let addr = line.addr();
+            match self.symbols.name(addr) {
+                Some(name) => writeln!(f, "{}:", name)?,
+                None if self.labels.contains(&addr) => writeln!(f, "L{:03X}:", addr)?,
+                None => {}
+            }
+            writeln!(f, "{}", line.with_symbols(&self.symbols))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    const ROM: [u8; 9] = [
+        0x6A, 0x02, // 0x200: LD VA, 02
+        0x22, 0x06, // 0x202: CALL 206
+        0x12, 0x04, // 0x204: JP 204
+        0x00, 0xEE, // 0x206: RET
+        0x7F,       // 0x208: A stray byte.
+    ];
+
+    #[test]
+    fn test_disassembling_lines() {
+        let lines = disassemble(&ROM, 0x200, Variant::Chip8);
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0].to_string(), "200  6A02  LD VA, 02");
+        assert_eq!(lines[1].target(), Some(0x206));
+        assert_eq!(lines[3].to_string(), "206  00EE  RET");
+        assert_eq!(lines[4], Line::Byte { addr: 0x208, byte: 0x7F });
+        assert_eq!(lines[4].to_string(), "208  7F    DB 7F");
+    }
+
+    #[test]
+    fn test_listing_labels_targets() {
+        let listing = Listing::new(&ROM, 0x200, Variant::Chip8);
+        assert_eq!(listing.labels.iter().copied().collect::<Vec<_>>(), vec![0x204, 0x206]);
+
+        let text = listing.to_string();
+        assert!(text.starts_with("200  6A02  LD VA, 02\n202  2206  CALL 206\nL204:\n204  1204  JP 204\nL206:\n"));
+    }
+
+    #[test]
+    fn test_symbols_name_labels_and_operands() {
+        let symbols = Symbols::parse("200 main\n206 draw").unwrap();
+        let text = Listing::new(&ROM, 0x200, Variant::Chip8).with_symbols(symbols).to_string();
+        assert!(text.starts_with("main:\n200  6A02  LD VA, 02\n202  2206  CALL draw\nL204:\n204  1204  JP 204\ndraw:\n"));
+    }
+
+    #[test]
+    fn test_long_loads_take_the_next_word() {
+        let lines = disassemble(&[0xF0, 0x00, 0x12, 0x34, 0x00, 0xE0, 0xF0, 0x00], 0x200, Variant::Chip8);
+        assert_eq!(lines[0], Line::Long { addr: 0x200, nnnn: 0x1234 });
+        assert_eq!(lines[0].to_string(), "200  F000  LD I, long 1234");
+        assert_eq!(lines[1].addr(), 0x204);
+
+        // Without an address after it.
+        assert_eq!(lines[2].to_string(), "206  F000  LD I, long");
+    }
+
+    #[test]
+    fn test_coverage_lists_data_as_bytes() {
+        let rom = [0xA2, 0x06, 0xD0, 0x02, 0x12, 0x04, 0xF0, 0x90];
+        let mut cpu = crate::cpu::CPU::default();
+        cpu.load(rom.to_vec()).unwrap();
+        cpu.track_coverage();
+        cpu.step_frame(10);
+
+        let listing = Listing::with_coverage(&rom, 0x200, Variant::Chip8, cpu.coverage().unwrap());
+        let lines: Vec<String> = listing.lines.iter().map(Line::to_string).collect();
+        assert_eq!(lines[2..], ["204  1204  JP 204", "206  F0    DB F0", "207  90    DB 90"]);
+    }
+
+    #[test]
+    fn test_following_the_flow_lists_unreached_bytes_as_data() {
+        #[rustfmt::skip]
+        let rom = [
+            0xA2, 0x0A, // 0x200: LD I, 20A
+            0x30, 0x00, // 0x202: SE V0, 00
+            0x12, 0x08, // 0x204: JP 208
+            0x00, 0xFD, // 0x206: EXIT
+            0x12, 0x06, // 0x208: JP 206
+            0x60, 0x90, // 0x20A: A sprite.
+        ];
+        let listing = Listing::following(&rom, 0x200, Variant::Chip8);
+        let lines: Vec<String> = listing.lines.iter().map(Line::to_string).collect();
+        assert_eq!(lines[3..], ["206  00FD  EXIT", "208  1206  JP 206", "20A  60    DB 60", "20B  90    DB 90"]);
+
+        // Without following, the sprite is an instruction.
+        let linear = Listing::new(&rom, 0x200, Variant::Chip8);
+        assert_eq!(linear.lines[5].to_string(), "20A  6090  LD V0, 90");
+    }
+
+    #[test]
+    fn test_labels_can_be_left_out() {
+        let text = Listing::new(&ROM, 0x200, Variant::Chip8).without_labels().to_string();
+        assert!(!text.contains(':'));
+    }
+
+    #[test]
+    fn test_disassembly_follows_variant() {
+        let lines = disassemble(&[0xB1, 0x23], 0x300, Variant::Chip8X);
+        assert_eq!(lines[0].to_string(), "300  B123  COL V1, V2, 3");
+    }
+}
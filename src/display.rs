@@ -0,0 +1,204 @@
+//! Where the VM shows the screen and reads keys from: SDL's window, the terminal, the GPU window, or a headless one
+//! for tests. The VM loop only talks to a `Display`, so a backend can be swapped in without touching it.
+//!
+//! A display is opened with `init` once the size of the screen is known. Frames which only need the palette are handed
+//! over as they are with `present`; frames the VM has coloured itself, with the CHIP-8X colour zones, the diff view,
+//! phosphor decay or MegaChip's screen, come through `draw_rgb`. Everything beyond that is optional, so a backend
+//! without a title bar, HUD or vsync doesn't have to do anything for them.
+
+// Self imports
+use crate::config::Hotkey;
+use crate::frame_buffer::{FrameBuffer, Palette};
+
+// Std imports
+#[cfg(test)]
+use std::cell::RefCell;
+#[cfg(test)]
+use std::rc::Rc;
+
+/// Everything read from the keyboard during one poll.
+#[derive(Debug, Default)]
+pub struct Input {
+    pub key: Option<u8>,        // Chip8 key currently held.
+    pub key2: Option<u8>,       // CHIP-8X second keypad key currently held.
+    pub hotkeys: Vec<Hotkey>,   // Hotkeys pressed since the last poll.
+    pub held: Vec<Hotkey>,      // Hotkeys currently held.
+    #[cfg(feature = "sdl")]
+    pub wheel: Vec<(u32, i32)>, // Mouse wheel notches since the last poll, by SDL window id. Up is positive.
+    #[cfg(feature = "sdl")]
+    pub closed: Vec<u32>, // Ids of SDL's other windows closed since the last poll, e.g. the memory window.
+}
+
+pub trait Display {
+    /// Get ready to show a screen of the given size in Chip8 pixels, e.g. by opening a window. Called once, before
+    /// anything else.
+    fn init(&mut self, columns: usize, rows: usize) -> Result<(), String>;
+
+    /// Read the keys. Fails once the display has been closed, which stops the VM.
+    fn poll(&mut self) -> Result<Input, ()>;
+
+    /// Show a frame in the palette.
+    fn present(&mut self, frame: &FrameBuffer) {
+        let colours = apply_palette(&frame.composite(), self.palette());
+        self.draw_rgb(&colours, frame.width());
+    }
+
+    fn palette(&self) -> &Palette;
+
+    /// Colour the frames presented from now on.
+    fn set_palette(&mut self, palette: Palette);
+
+    /// The screen changed size, e.g. when SCHIP's hires mode was switched on. The next frame is the new size.
+    fn resize(&mut self, columns: usize, rows: usize);
+
+    /// Draw a frame which has already been coloured, row by row. Frames may be any size (e.g. MegaChip's).
+    fn draw_rgb(&mut self, colours: &[[u8; 3]], width: usize);
+
+    /// Draw the last frame again, e.g. after the HUD changed.
+    fn redraw(&mut self);
+
+    /// Show the ROM's status after the window title, before any extra text.
+    fn set_title_status(&mut self, _status: &str) {}
+
+    /// Show extra text after the window title, or just the title if there is none.
+    fn set_title_info(&mut self, _info: Option<&str>) {}
+
+    /// Set the HUD's lines, or turn it off with None. Shown from the next frame drawn.
+    fn set_hud(&mut self, _lines: Option<Vec<String>>) {}
+
+    fn crt(&self) -> bool {
+        false
+    }
+
+    /// Turn the CRT look on or off.
+    fn set_crt(&mut self, _on: bool) {}
+
+    fn grid(&self) -> bool {
+        false
+    }
+
+    /// Show or hide lines between the screen's pixels.
+    fn set_grid(&mut self, _on: bool) {}
+
+    /// Whether the sound timer is running, for displays which make their own sound. SDL's is played separately.
+    fn set_sound(&mut self, _on: bool) {}
+
+    /// Whether presenting waits for the monitor's refresh, so the VM paces frames by it rather than sleeping.
+    fn vsync(&self) -> bool {
+        false
+    }
+
+    /// Show the last frame at the monitor's next refresh, waiting for it. Only called if vsync is on.
+    fn present_vsync(&mut self) {}
+}
+
+/// Map a frame of palette indices to colours.
+pub fn apply_palette(pixels: &[u8], palette: &Palette) -> Vec<[u8; 3]> {
+    pixels.iter().map(|index| palette[*index as usize]).collect()
+}
+
+/// A display which shows nothing, for tests. It closes after a number of polls, and clones share what was shown so a
+/// test can keep one while the VM runs another.
+#[cfg(test)]
+#[derive(Clone)]
+pub struct Headless {
+    polls: usize, // Polls left before it closes.
+    palette: Palette,
+    pub shown: Rc<RefCell<Shown>>,
+}
+
+/// What a headless display was asked to show.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct Shown {
+    pub screen: Option<(usize, usize)>,     // Size of the screen, from init and resize.
+    pub frames: Vec<(Vec<[u8; 3]>, usize)>, // Every frame drawn, with its width.
+    pub status: Option<String>,             // The ROM's status in the title.
+    pub title: Option<String>,              // The extra text after the status.
+}
+
+#[cfg(test)]
+impl Headless {
+    pub fn new(polls: usize) -> Self {
+        Self {
+            polls,
+            palette: crate::config::DEFAULT_PALETTE,
+            shown: Rc::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Display for Headless {
+    fn init(&mut self, columns: usize, rows: usize) -> Result<(), String> {
+        self.shown.borrow_mut().screen = Some((columns, rows));
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Result<Input, ()> {
+        self.polls = self.polls.checked_sub(1).ok_or(())?;
+        Ok(Input::default())
+    }
+
+    fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    fn resize(&mut self, columns: usize, rows: usize) {
+        self.shown.borrow_mut().screen = Some((columns, rows));
+    }
+
+    fn draw_rgb(&mut self, colours: &[[u8; 3]], width: usize) {
+        self.shown.borrow_mut().frames.push((colours.to_vec(), width));
+    }
+
+    fn redraw(&mut self) {
+        let mut shown = self.shown.borrow_mut();
+        if let Some(last) = shown.frames.last().cloned() {
+            shown.frames.push(last);
+        }
+    }
+
+    fn set_title_status(&mut self, status: &str) {
+        self.shown.borrow_mut().status = Some(status.to_string());
+    }
+
+    fn set_title_info(&mut self, info: Option<&str>) {
+        self.shown.borrow_mut().title = info.map(str::to_string);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_PALETTE;
+
+    #[test]
+    fn test_frames_are_presented_in_the_palette() {
+        let mut display = Headless::new(0);
+        let mut frame = FrameBuffer::new(true, true);
+        frame.draw_sprite(&[0x80], 0, 0);
+        display.present(&frame);
+
+        let palette = [[1; 3], [2; 3], [3; 3], [4; 3]];
+        display.set_palette(palette);
+        display.present(&frame);
+
+        let shown = display.shown.borrow();
+        let (first, second) = (&shown.frames[0], &shown.frames[1]);
+        assert_eq!((first.0[0], first.0[1], first.1), (DEFAULT_PALETTE[1], DEFAULT_PALETTE[0], 64));
+        assert_eq!((second.0[0], second.0[1]), ([2; 3], [1; 3]));
+    }
+
+    #[test]
+    fn test_headless_displays_close_after_their_polls() {
+        let mut display = Headless::new(2);
+        assert!(display.poll().is_ok());
+        assert!(display.poll().is_ok());
+        assert!(display.poll().is_err());
+    }
+}
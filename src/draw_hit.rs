@@ -0,0 +1,63 @@
+//! Breaking on draws. While the debugger has it on, the CPU stops a frame after each DXYN, like a watchpoint hit, and
+//! the sprite drawn is shown with where it came from and where it went:
+//!
+//! ```text
+//! draw: D015 at 20A drew 2F0 at (12, 8)
+//!   F0  ####....
+//!   90  #..#....
+//! ```
+//!
+//! SCHIP's 16x16 sprites show two bytes a row, and XO-CHIP draws on more than one plane show each plane's sprite in
+//! turn.
+
+// Std imports
+use std::fmt;
+
+/// A sprite drawn while breaking on draws.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DrawHit {
+    pub pc: usize,   // Address of the draw.
+    pub opcode: u16, // The draw itself.
+    pub addr: usize, // Where the sprite was read from, I at the time.
+    pub x: usize,
+    pub y: usize,
+    pub sprite: Vec<u8>, // For every plane drawn on.
+    pub bytes_per_row: usize,
+}
+
+impl fmt::Display for DrawHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "draw: {:04X} at {:03X} drew {:03X} at ({}, {})", self.opcode, self.pc, self.addr, self.x, self.y)?;
+        for row in self.sprite.chunks(self.bytes_per_row.max(1)) {
+            let bytes: Vec<String> = row.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let pixels: String = row
+                .iter()
+                .flat_map(|byte| (0..8).map(move |bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' }))
+                .collect();
+            write!(f, "\n  {}  {}", bytes.join(""), pixels)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_showing_sprites() {
+        let hit = DrawHit {
+            pc: 0x20A,
+            opcode: 0xD012,
+            addr: 0x2F0,
+            x: 12,
+            y: 8,
+            sprite: vec![0xF0, 0x90],
+            bytes_per_row: 1,
+        };
+        assert_eq!(hit.to_string(), "draw: D012 at 20A drew 2F0 at (12, 8)\n  F0  ####....\n  90  #..#....");
+
+        let wide = DrawHit { sprite: vec![0x80, 0x01], bytes_per_row: 2, ..hit };
+        assert_eq!(wide.to_string().lines().nth(1), Some("  8001  #..............#"));
+    }
+}
@@ -0,0 +1,211 @@
+//! Sound output. The machines which ran Chip8 beeped while the sound timer was non-zero. A `Beeper` generates the
+//! samples, both for the sound device and for `render`, which produces the same samples offline from a trace of events
+//! so sound can be tested without a device.
+
+// External imports
+use log::info;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+pub const SAMPLE_RATE: u32 = 44100;
+pub const DEFAULT_PITCH: u8 = 64;
+
+const AMPLITUDE: i16 = 3000;
+const BASE_FREQUENCY: f64 = 440.0; // Frequency at the default pitch.
+const FRAMES_PER_SECOND: u64 = 60;
+
+/// A change to the sound state, taking effect at the start of a frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AudioEvent {
+    pub frame: u64,
+    pub change: SoundChange,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SoundChange {
+    /// The sound timer was set. It beeps until the timer has counted down to 0, one step per frame.
+    SoundTimer(u8),
+
+    /// The pitch of the beep changed.
+    Pitch(u8),
+}
+
+/// Square wave generator.
+pub struct Beeper {
+    sample_rate: u32,
+    phase: u32, // Position in the wave. The first half of the range is high, the second low.
+    step: u32,  // Added to the phase every sample.
+    playing: bool,
+}
+
+impl Beeper {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut beeper = Self {
+            sample_rate,
+            phase: 0,
+            step: 0,
+            playing: false,
+        };
+        beeper.set_pitch(DEFAULT_PITCH);
+        beeper
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    /// Set the pitch as XO-CHIP numbers it: 64 is the default and every 48 steps is an octave.
+    pub fn set_pitch(&mut self, pitch: u8) {
+        let frequency = BASE_FREQUENCY * 2f64.powf((pitch as f64 - DEFAULT_PITCH as f64) / 48.0);
+        self.step = (frequency * (1u64 << 32) as f64 / self.sample_rate as f64).round() as u32;
+    }
+
+    /// Write the next samples. Silence while not playing. The wave pauses rather than resets, so there are no clicks
+    /// from restarting halfway through a cycle.
+    pub fn fill(&mut self, out: &mut [i16]) {
+        for sample in out {
+            *sample = match (self.playing, self.phase < 1 << 31) {
+                (false, _) => 0,
+                (true, true) => AMPLITUDE,
+                (true, false) => -AMPLITUDE,
+            };
+
+            if self.playing {
+                self.phase = self.phase.wrapping_add(self.step);
+            }
+        }
+    }
+}
+
+impl AudioCallback for Beeper {
+    type Channel = i16;
+
+    fn callback(&mut self, out: &mut [i16]) {
+        self.fill(out);
+    }
+}
+
+pub struct AudioDriver {
+    device: AudioDevice<Beeper>,
+}
+
+impl AudioDriver {
+    pub fn new(sdl_context: &sdl2::Sdl) -> Result<Self, String> {
+        let audio_subsystem = sdl_context.audio()?;
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio_subsystem.open_playback(None, &desired, |spec| Beeper::new(spec.freq as u32))?;
+        device.resume();
+        info!("opened sound at {} Hz", device.spec().freq);
+
+        Ok(Self { device })
+    }
+
+    /// Beep if the sound timer is running. Called once a frame.
+    pub fn update(&mut self, sound_timer: u8, pitch: u8) {
+        let mut beeper = self.device.lock();
+        beeper.set_playing(sound_timer > 0);
+        beeper.set_pitch(pitch);
+    }
+}
+
+/// Render the samples the AudioDriver would output over a number of frames, given every change to the sound state.
+/// Events must be in frame order.
+pub fn render(events: &[AudioEvent], frames: u64, sample_rate: u32) -> Vec<i16> {
+    let mut beeper = Beeper::new(sample_rate);
+    let mut events = events.iter().peekable();
+    let mut sound_timer = 0;
+    let mut samples = Vec::new();
+
+    for frame in 0..frames {
+        while let Some(event) = events.next_if(|event| event.frame <= frame) {
+            match event.change {
+                SoundChange::SoundTimer(value) => sound_timer = value,
+                SoundChange::Pitch(pitch) => beeper.set_pitch(pitch),
+            }
+        }
+
+        // Frames don't divide evenly into samples, so each frame ends on the last whole sample before its end.
+        let start = samples.len();
+        samples.resize(((frame + 1) * sample_rate as u64 / FRAMES_PER_SECOND) as usize, 0);
+
+        beeper.set_playing(sound_timer > 0);
+        beeper.fill(&mut samples[start..]);
+        sound_timer = sound_timer.saturating_sub(1);
+    }
+
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::Path;
+
+    const REFERENCE_DIR: &str = "./sounds/test";
+
+    fn event(frame: u64, change: SoundChange) -> AudioEvent {
+        AudioEvent { frame, change }
+    }
+
+    /// Compare a render against the stored reference, stored as 16-bit little endian PCM. Set UPDATE_REFERENCE_AUDIO
+    /// to regenerate references.
+    fn assert_matches_reference(name: &str, samples: &[i16]) {
+        let path = Path::new(REFERENCE_DIR).join(format!("{}.pcm", name));
+        let actual: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+
+        if std::env::var_os("UPDATE_REFERENCE_AUDIO").is_some() {
+            fs::create_dir_all(REFERENCE_DIR).unwrap();
+            fs::write(&path, &actual).unwrap();
+            return;
+        }
+
+        let expected = fs::read(&path).expect("missing reference audio");
+        assert!(actual == expected, "{}: samples differ from reference", name);
+    }
+
+    #[test]
+    fn test_beeps_while_sound_timer_runs() {
+        let samples = render(&[event(1, SoundChange::SoundTimer(2))], 4, 600);
+
+        // 10 samples per frame. Silent, two frames of beeping, then silent again.
+        assert_eq!(samples.len(), 40);
+        assert!(samples[..10].iter().all(|s| *s == 0));
+        assert!(samples[10..30].iter().all(|s| s.abs() == AMPLITUDE));
+        assert!(samples[30..].iter().all(|s| *s == 0));
+    }
+
+    #[test]
+    fn test_pitch_changes_frequency() {
+        let mut low = Beeper::new(SAMPLE_RATE);
+        let mut high = Beeper::new(SAMPLE_RATE);
+        high.set_pitch(DEFAULT_PITCH + 48);
+        assert!((high.step as i64 - low.step as i64 * 2).abs() <= 1); // An octave up doubles the frequency.
+
+        low.set_playing(true);
+        let mut samples = [0; 100];
+        low.fill(&mut samples);
+
+        // 440Hz at 44.1kHz is high for about 50 samples, then low.
+        assert!(samples[..50].iter().all(|s| *s == AMPLITUDE));
+        assert!(samples[51..].iter().all(|s| *s == -AMPLITUDE));
+    }
+
+    #[test]
+    fn test_render_matches_reference() {
+        let events = [
+            event(0, SoundChange::SoundTimer(3)),
+            event(5, SoundChange::Pitch(112)),
+            event(5, SoundChange::SoundTimer(2)),
+            event(6, SoundChange::SoundTimer(0)),
+            event(8, SoundChange::Pitch(40)),
+            event(8, SoundChange::SoundTimer(4)),
+        ];
+        assert_matches_reference("beep", &render(&events, 12, 8000));
+    }
+}
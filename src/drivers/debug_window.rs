@@ -0,0 +1,57 @@
+// Self imports
+use crate::cpu::CPU;
+use crate::debug_view::{self, COLUMNS, ROWS};
+use crate::hud;
+use crate::symbols::Symbols;
+
+// External imports
+use log::info;
+use sdl2::{self, pixels::Color, pixels::PixelFormatEnum, rect::Rect, render::Canvas, video::Window};
+
+const SCALE_FACTOR: u32 = 3;
+const TITLE: &str = "Chip8 debugger";
+
+/// A second window showing the debugger panels.
+pub struct DebugWindow {
+    canvas: Canvas<Window>,
+}
+
+impl DebugWindow {
+    pub fn new(sdl_context: &sdl2::Sdl) -> Result<Self, String> {
+        let (width, height) = hud::size(COLUMNS, ROWS);
+        let (width, height) = (width as u32, height as u32);
+
+        let window = sdl_context
+            .video()?
+            .window(TITLE, width * SCALE_FACTOR, height * SCALE_FACTOR)
+            .resizable()
+            .build()
+            .map_err(|e| e.to_string())?;
+        let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        let _ = canvas.set_logical_size(width, height);
+        info!("opened the debug window");
+
+        Ok(Self { canvas })
+    }
+
+    /// SDL's id for the window, which its events carry.
+    pub fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    pub fn draw(&mut self, cpu: &CPU, symbols: &Symbols) {
+        let (rgba, width, height) = hud::render(&debug_view::lines(cpu, symbols));
+        self.canvas.set_draw_color(Color::BLACK);
+        self.canvas.clear();
+
+        // Lines too long or too many for the window are cut off.
+        let (logical_width, logical_height) = self.canvas.logical_size();
+        let shown = Rect::new(0, 0, logical_width.min(width as u32), logical_height.min(height as u32));
+        let creator = self.canvas.texture_creator();
+        if let Ok(mut texture) = creator.create_texture_static(PixelFormatEnum::RGBA32, width as u32, height as u32) {
+            let _ = texture.update(None, &rgba, width * 4);
+            let _ = self.canvas.copy(&texture, shown, shown);
+        }
+        self.canvas.present();
+    }
+}
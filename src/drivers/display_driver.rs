@@ -1,52 +1,615 @@
-use sdl2::{self, pixels, rect::Rect, render::Canvas, video::Window};
+// Self imports
+use super::input_driver::InputDriver;
+use crate::config::Config;
+use crate::crt;
+use crate::display::{Display, Input};
+use crate::frame_buffer::{Palette, WIDTH};
+use crate::hud;
+
+// External imports
+use log::{error, info};
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext, WindowPos};
+use sdl2::{self, pixels, pixels::PixelFormatEnum, rect::Rect};
 
 const SCALE_FACTOR: u32 = 10;
-const SCREEN_WIDTH: u32 = 64 * SCALE_FACTOR;
-const SCREEN_HEIGHT: u32 = 32 * SCALE_FACTOR;
+const HUD_SCALE: u32 = 2; // Window pixels per HUD font pixel.
+const SCREEN_WIDTH: u32 = WIDTH as u32 * SCALE_FACTOR; // Screens wider than 64 pixels are scaled to about this width.
+const TITLE: &str = "Chip8 in Rust";
+const GRID_COLOUR: pixels::Color = pixels::Color::RGBA(128, 128, 128, 80); // Shows on lit and unlit pixels alike.
+const GRID_MIN_SCALE: u32 = 3;
 
 pub struct DisplayDriver {
     canvas: Canvas<Window>,
+    input: InputDriver, // SDL's events come to one place for every window, so the driver reads the keys too.
+    palette: Palette,
+    border: pixels::Color,
+    border_width: u32, // Least border around the screen, in window pixels.
+    last: (Vec<[u8; 3]>, usize), // The frame last drawn and its width, for redrawing with the HUD.
+    hud: Option<Vec<String>>,    // Lines drawn over the frame, if the HUD is on.
+
+    // Frames are uploaded to one streaming texture, made again when the frame size changes. Textures can't outlive
+    // their creator, so the creator lives as long as the program to let the driver keep one.
+    creator: &'static TextureCreator<WindowContext>,
+    texture: Option<(Texture<'static>, usize, usize)>, // With its width and height.
+    crt: bool,                                             // Whether frames are shown with the CRT look.
+    crt_texture: Option<(Texture<'static>, usize, usize)>, // The last frame with the CRT look, while it's on.
+    vsync: bool, // Whether presenting waits for the monitor's refresh, leaving it to present_vsync.
+    grid: bool,  // Whether lines are drawn between the screen's pixels.
+    title_status: String,
+    title_info: Option<String>,
 }
 
 impl DisplayDriver {
-    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
-        let video_subsystem = sdl_context.video().unwrap();
+    /// Make the window, hidden until init gives it the screen's size.
+    pub fn new(sdl_context: &sdl2::Sdl, config: &Config) -> Result<Self, String> {
+        let display = &config.display;
+        let video_subsystem = sdl_context.video()?;
         let window = video_subsystem
-            .window("Chip8 in Rust", SCREEN_WIDTH, SCREEN_HEIGHT)
-            .position_centered()
+            .window(TITLE, SCREEN_WIDTH, SCREEN_WIDTH / 2)
+            .hidden()
+            .resizable()
             .opengl()
             .build()
-            .unwrap();
+            .map_err(|e| format!("unable to open a window: {}", e))?;
 
-        let mut canvas = window.into_canvas().build().unwrap();
+        let canvas = match display.vsync {
+            true => window.into_canvas().present_vsync().build(),
+            false => window.into_canvas().build(),
+        };
+        let canvas = canvas.map_err(|e| format!("unable to draw in the window: {}", e))?;
+        let creator = Box::leak(Box::new(canvas.texture_creator()));
 
-        canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
-        canvas.clear();
-        canvas.present();
+        let [r, g, b] = display.border;
+        Ok(Self {
+            canvas,
+            input: InputDriver::new(sdl_context, config)?,
+            palette: display.palette,
+            border: pixels::Color::RGB(r, g, b),
+            border_width: display.border_width,
+            last: (Vec::new(), 1),
+            hud: None,
+            creator,
+            texture: None,
+            crt: display.crt,
+            crt_texture: None,
+            vsync: display.vsync,
+            grid: false,
+            title_status: String::new(),
+            title_info: None,
+        })
+    }
 
-        Self { canvas }
+    // A texture to upload frames of a size to, with the size. Without one the screen stays black, so say why.
+    fn streaming_texture(&self, width: usize, height: usize) -> Option<(Texture<'static>, usize, usize)> {
+        match self.creator.create_texture_streaming(PixelFormatEnum::RGBA32, width as u32, height as u32) {
+            Ok(texture) => Some((texture, width, height)),
+            Err(e) => {
+                error!("unable to create a {}x{} texture: {}", width, height, e);
+                None
+            }
+        }
     }
 
-    pub fn draw(&mut self, pixels: Vec<u64>) {
-        for (y, row) in pixels.iter().enumerate() {
-            for (x, col) in (0..64).rev().enumerate() {
-                let pixel = (row >> col) & 1;
+    // Give the last frame the CRT look. The whole frame is done every time, as the curvature bends rows into others.
+    fn render_crt(&mut self) {
+        let (rgba, width, height) = crt::render(&self.last.0, self.last.1);
+        if !matches!(self.crt_texture, Some((_, w, h)) if (w, h) == (width, height)) {
+            self.crt_texture = self.streaming_texture(width, height);
+        }
+        if let Some((texture, _, _)) = &mut self.crt_texture {
+            let _ = texture.update(None, &rgba, width * 4);
+        }
+    }
 
-                let rgb = if pixel == 0 {
-                    pixels::Color::RGB(0, 0, 0)
-                } else {
-                    pixels::Color::RGB(0, 250, 0)
-                };
+    fn update_title(&mut self) {
+        let mut parts = vec![TITLE];
+        if !self.title_status.is_empty() {
+            parts.push(&self.title_status);
+        }
+        parts.extend(self.title_info.as_deref());
+        let _ = self.canvas.window_mut().set_title(&parts.join(" | "));
+    }
 
-                let x = x as u32 * SCALE_FACTOR;
-                let y = y as u32 * SCALE_FACTOR;
+    fn present(&mut self) {
+        // Clearing fills the whole window, letterboxing included, so anything not covered by a pixel is border.
+        self.canvas.set_draw_color(self.border);
+        self.canvas.clear();
 
-                let rect = Rect::new(x as i32, y as i32, SCALE_FACTOR, SCALE_FACTOR);
+        let window = self.canvas.output_size().unwrap_or_default();
+        let frame = self.texture.as_ref().map_or((1, 1), |(_, width, height)| (*width as u32, *height as u32));
+        let screen = layout(window, frame, self.border_width);
 
-                self.canvas.set_draw_color(rgb);
-                let _ = self.canvas.fill_rect(rect);
+        let texture = if self.crt { &self.crt_texture } else { &self.texture };
+        if let Some((texture, _, _)) = texture {
+            let _ = self.canvas.copy(texture, None, screen);
+        }
+
+        if self.grid {
+            self.canvas.set_blend_mode(BlendMode::Blend);
+            self.canvas.set_draw_color(GRID_COLOUR);
+            let _ = self.canvas.fill_rects(&grid_lines(screen, frame));
+            self.canvas.set_blend_mode(BlendMode::None);
+        }
+
+        if let Some(lines) = &self.hud {
+            let (rgba, width, height) = hud::render(lines);
+            let format = PixelFormatEnum::RGBA32;
+            let texture = self.creator.create_texture_static(format, width as u32, height as u32);
+            if let Err(e) = &texture {
+                error!("unable to create the HUD's texture: {}", e);
+            }
+            if let Ok(mut texture) = texture {
+                texture.set_blend_mode(BlendMode::Blend);
+                let _ = texture.update(None, &rgba, width * 4);
+                let (width, height) = (width as u32 * HUD_SCALE, height as u32 * HUD_SCALE);
+                let _ = self.canvas.copy(&texture, None, Rect::new(screen.x(), screen.y(), width, height));
             }
         }
+
+        self.canvas.present();
+    }
+}
+
+impl Display for DisplayDriver {
+    /// Size the window to the screen, scaled to about 640 pixels wide, and show it.
+    fn init(&mut self, columns: usize, rows: usize) -> Result<(), String> {
+        let (columns, rows) = (columns as u32, rows as u32);
+        let scale = (SCREEN_WIDTH / columns).max(1);
+        let width = columns * scale + 2 * self.border_width;
+        let height = rows * scale + 2 * self.border_width;
+
+        let window = self.canvas.window_mut();
+        window.set_size(width, height).map_err(|e| format!("unable to size the window: {}", e))?;
+        window.set_position(WindowPos::Centered, WindowPos::Centered);
+        window.show();
+        info!("opened a {}x{} window, {} pixels to a Chip8 pixel", width, height, scale);
+
+        self.resize(columns as usize, rows as usize);
+        self.canvas.set_draw_color(self.border);
+        self.canvas.clear();
         self.canvas.present();
+        Ok(())
+    }
+
+    /// Closing the screen's window stops the VM even with the memory or debug window open, which SDL would keep going
+    /// for. Frames which don't change aren't presented, so the screen is drawn again when the window needs it.
+    fn poll(&mut self) -> Result<Input, ()> {
+        let (input, exposed) = self.input.poll()?;
+        let id = self.canvas.window().id();
+        if input.closed.contains(&id) {
+            return Err(());
+        }
+        if exposed.contains(&id) {
+            self.redraw();
+        }
+        Ok(input)
+    }
+
+    fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// The window can be made as small as one window pixel per Chip8 pixel. See layout for how the screen fits it.
+    fn resize(&mut self, columns: usize, rows: usize) {
+        let border = 2 * self.border_width;
+        let _ = self.canvas.window_mut().set_minimum_size(columns as u32 + border, rows as u32 + border);
+    }
+
+    fn set_title_status(&mut self, status: &str) {
+        self.title_status = status.to_string();
+        self.update_title();
+    }
+
+    fn set_title_info(&mut self, info: Option<&str>) {
+        self.title_info = info.map(str::to_string);
+        self.update_title();
+    }
+
+    /// Scale the frame to fill the screen. Only the rows which changed since the last frame are uploaded, and a frame
+    /// with none isn't presented at all unless the HUD is on.
+    fn draw_rgb(&mut self, colours: &[[u8; 3]], width: usize) {
+        let height = colours.len() / width;
+        let resized = !matches!(self.texture, Some((_, w, h)) if (w, h) == (width, height));
+        if resized {
+            self.texture = self.streaming_texture(width, height);
+        }
+
+        let rows = match resized {
+            true => Some((0, height.saturating_sub(1))),
+            false => dirty_rows(&self.last.0, colours, width),
+        };
+        if let (Some((first, last)), Some((texture, _, _))) = (rows, &mut self.texture) {
+            let area = Rect::new(0, first as i32, width as u32, (last + 1 - first) as u32);
+            let changed = &colours[first * width..(last + 1) * width];
+            let _ = texture.with_lock(area, |rgba, pitch| write_rgba(changed, width, rgba, pitch));
+        }
+
+        self.last = (colours.to_vec(), width);
+        if self.crt && rows.is_some() {
+            self.render_crt();
+        }
+        if rows.is_some() || self.hud.is_some() {
+            self.redraw();
+        }
+    }
+
+    fn crt(&self) -> bool {
+        self.crt
+    }
+
+    /// Turn the CRT look on or off, redrawing the last frame with or without it.
+    fn set_crt(&mut self, on: bool) {
+        self.crt = on;
+        match on {
+            true => self.render_crt(),
+            false => self.crt_texture = None,
+        }
+        self.redraw();
+    }
+
+    fn set_hud(&mut self, lines: Option<Vec<String>>) {
+        self.hud = lines;
+    }
+
+    fn grid(&self) -> bool {
+        self.grid
+    }
+
+    fn set_grid(&mut self, on: bool) {
+        self.grid = on;
+        self.redraw();
+    }
+
+    fn vsync(&self) -> bool {
+        self.vsync
+    }
+
+    /// With vsync, the window is drawn once a refresh by present_vsync instead, as each present waits for one.
+    fn redraw(&mut self) {
+        if !self.vsync {
+            self.present();
+        }
+    }
+
+    /// Does nothing without vsync, as frames are then presented when they're drawn.
+    fn present_vsync(&mut self) {
+        if self.vsync {
+            self.present();
+        }
+    }
+}
+
+/// Where the screen goes in a window, in window pixels. Frames are scaled by the largest whole number which leaves
+/// the border around them, so pixels stay square and all the same size, and centred with the rest letterboxed.
+fn layout((width, height): (u32, u32), (columns, rows): (u32, u32), border_width: u32) -> Rect {
+    let fit = |space: u32, pixels: u32| space.saturating_sub(2 * border_width) / pixels.max(1);
+    let scale = fit(width, columns).min(fit(height, rows)).max(1);
+    let (screen_width, screen_height) = (columns * scale, rows * scale);
+    let x = (width as i32 - screen_width as i32) / 2;
+    let y = (height as i32 - screen_height as i32) / 2;
+    Rect::new(x, y, screen_width, screen_height)
+}
+
+/// One window pixel wide lines along the left and top edge of every pixel but the first in each row and column, for
+/// a screen drawn at `screen` from a frame of the given size. Screens scaled less than 3 times would be mostly grid,
+/// so they get none.
+fn grid_lines(screen: Rect, (columns, rows): (u32, u32)) -> Vec<Rect> {
+    let scale = screen.width() / columns.max(1);
+    if scale < GRID_MIN_SCALE {
+        return Vec::new();
+    }
+
+    let (x, y) = (screen.x(), screen.y());
+    let vertical = (1..columns).map(|column| Rect::new(x + (column * scale) as i32, y, 1, screen.height()));
+    let horizontal = (1..rows).map(|row| Rect::new(x, y + (row * scale) as i32, screen.width(), 1));
+    vertical.chain(horizontal).collect()
+}
+
+/// The first and last rows which differ between two frames, or None if they are the same. Frames of different sizes
+/// differ everywhere.
+fn dirty_rows(before: &[[u8; 3]], after: &[[u8; 3]], width: usize) -> Option<(usize, usize)> {
+    if before.len() != after.len() {
+        return Some((0, (after.len() / width).saturating_sub(1)));
+    }
+    let differs = |row: &usize| before[row * width..(row + 1) * width] != after[row * width..(row + 1) * width];
+    let rows = after.len() / width;
+    let first = (0..rows).find(differs)?;
+    Some((first, (first..rows).rev().find(differs).unwrap_or(first)))
+}
+
+/// Write a coloured frame into a texture's RGBA pixels, whose rows start `pitch` bytes apart.
+fn write_rgba(colours: &[[u8; 3]], width: usize, rgba: &mut [u8], pitch: usize) {
+    for (y, row) in colours.chunks(width).enumerate() {
+        for (x, [r, g, b]) in row.iter().enumerate() {
+            rgba[y * pitch + x * 4..][..4].copy_from_slice(&[*r, *g, *b, 255]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8x::ColourZones;
+    use crate::config::DEFAULT_PALETTE;
+    use crate::display::apply_palette;
+    use crate::palettes;
+    use crate::phosphor::Phosphor;
+    use crate::frame_buffer::FrameBuffer;
+
+    use std::fs;
+    use std::path::Path;
+
+    const REFERENCE_DIR: &str = "./imgs/test";
+
+    // Maximum difference allowed in any one colour channel before a pixel is considered to differ.
+    const CHANNEL_TOLERANCE: u8 = 8;
+
+    // Font sprite for the digit 0.
+    const ZERO: [u8; 5] = [0xF0, 0x90, 0x90, 0x90, 0xF0];
+
+    /// A frame exercising drawing in the corner, the middle and wrapping around both edges.
+    fn test_frame() -> Vec<u8> {
+        let mut frame = FrameBuffer::new(true, true);
+        frame.draw_sprite(&ZERO, 0, 0);
+        frame.draw_sprite(&ZERO, 13, 30);
+        frame.draw_sprite(&ZERO, 29, 60);
+        frame.composite()
+    }
+
+    /// The same frame with a sprite on the second plane and one overlapping both.
+    fn test_plane_frame() -> Vec<u8> {
+        let mut frame = FrameBuffer::new(true, true);
+        frame.draw_sprite(&ZERO, 0, 0);
+        frame.select_planes(0b10);
+        frame.draw_sprite(&ZERO, 13, 30);
+        frame.select_planes(0b11);
+        frame.draw_sprite(&ZERO, 29, 60);
+        frame.composite()
+    }
+
+    /// How the driver is set up to show a frame.
+    struct Look {
+        window: (u32, u32),
+        border: [u8; 3],
+        border_width: u32,
+        crt: bool,
+        grid: bool,
+    }
+
+    impl Look {
+        fn window(width: u32, height: u32) -> Self {
+            Self { window: (width, height), border: [0; 3], border_width: 0, crt: false, grid: false }
+        }
+    }
+
+    /// Show a frame as draw_rgb and present do, returning the window's RGBA pixels. The frame is uploaded with
+    /// write_rgba, or crt::render with the CRT look, placed with layout and given grid_lines. Only SDL's canvas is
+    /// stood in for: the texture is scaled to the screen by nearest neighbour, and the grid is alpha blended over it.
+    fn show(colours: &[[u8; 3]], width: usize, look: &Look) -> Vec<u8> {
+        let height = colours.len() / width;
+        let (texture, texture_width, texture_height) = match look.crt {
+            true => crt::render(colours, width),
+            false => {
+                let mut rgba = vec![0; width * height * 4];
+                write_rgba(colours, width, &mut rgba, width * 4);
+                (rgba, width, height)
+            }
+        };
+
+        let (window_width, window_height) = (look.window.0 as usize, look.window.1 as usize);
+        let [r, g, b] = look.border;
+        let mut window = [r, g, b, 255].repeat(window_width * window_height);
+
+        let frame = (width as u32, height as u32);
+        let screen = layout(look.window, frame, look.border_width);
+        for y in 0..screen.height() as usize {
+            for x in 0..screen.width() as usize {
+                let (wx, wy) = (screen.x() as usize + x, screen.y() as usize + y);
+                let tx = x * texture_width / screen.width() as usize;
+                let ty = y * texture_height / screen.height() as usize;
+                let pixel = &texture[(ty * texture_width + tx) * 4..][..4];
+                window[(wy * window_width + wx) * 4..][..4].copy_from_slice(pixel);
+            }
+        }
+
+        let lines = match look.grid {
+            true => grid_lines(screen, frame),
+            false => Vec::new(),
+        };
+        let (colour, alpha) = ([GRID_COLOUR.r, GRID_COLOUR.g, GRID_COLOUR.b], GRID_COLOUR.a as u32);
+        for line in lines {
+            for y in line.y() as usize..line.bottom() as usize {
+                for x in line.x() as usize..line.right() as usize {
+                    let pixel = &mut window[(y * window_width + x) * 4..][..3];
+                    for (channel, grid) in pixel.iter_mut().zip(colour) {
+                        *channel = ((grid as u32 * alpha + *channel as u32 * (255 - alpha)) / 255) as u8;
+                    }
+                }
+            }
+        }
+        window
+    }
+
+    /// Encode an RGBA buffer as a binary PPM image. Alpha is dropped.
+    fn encode_ppm(rgba: &[u8], width: usize, height: usize) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+        for pixel in rgba.chunks(4) {
+            ppm.extend_from_slice(&pixel[..3]);
+        }
+        ppm
+    }
+
+    /// Compare a render against the stored reference image. Set UPDATE_REFERENCE_IMAGES to regenerate references.
+    fn assert_matches_reference(name: &str, rgba: &[u8], width: usize, height: usize) {
+        let path = Path::new(REFERENCE_DIR).join(format!("{}.ppm", name));
+        let actual = encode_ppm(rgba, width, height);
+
+        if std::env::var_os("UPDATE_REFERENCE_IMAGES").is_some() {
+            fs::create_dir_all(REFERENCE_DIR).unwrap();
+            fs::write(&path, &actual).unwrap();
+            return;
+        }
+
+        let expected = fs::read(&path).expect("missing reference image");
+        assert_eq!(actual.len(), expected.len(), "{}: image size differs", name);
+
+        let differing = actual
+            .iter()
+            .zip(expected.iter())
+            .filter(|(a, e)| (**a as i16 - **e as i16).abs() > CHANNEL_TOLERANCE as i16)
+            .count();
+        assert_eq!(differing, 0, "{}: {} channels differ from reference", name, differing);
+    }
+
+    #[test]
+    fn test_layout_keeps_whole_pixels() {
+        assert_eq!(layout((640, 320), (64, 32), 0), Rect::new(0, 0, 640, 320));
+        assert_eq!(layout((648, 328), (64, 32), 4), Rect::new(4, 4, 640, 320));
+
+        // Wider windows are letterboxed at the sides, and space for less than a whole pixel more is left over.
+        assert_eq!(layout((1000, 330), (64, 32), 0), Rect::new(180, 5, 640, 320));
+        assert_eq!(layout((700, 700), (128, 64), 4), Rect::new(30, 190, 640, 320));
+
+        // Never smaller than one window pixel per Chip8 pixel.
+        assert_eq!(layout((10, 10), (64, 32), 0), Rect::new(-27, -11, 64, 32));
+    }
+
+    #[test]
+    fn test_grid_lines() {
+        let lines = grid_lines(Rect::new(4, 4, 640, 320), (64, 32));
+        assert_eq!(lines.len(), 63 + 31);
+        assert_eq!(lines[0], Rect::new(14, 4, 1, 320));
+        assert_eq!(lines[63], Rect::new(4, 14, 640, 1));
+        assert!(grid_lines(Rect::new(0, 0, 128, 64), (64, 32)).is_empty());
+    }
+
+    #[test]
+    fn test_dirty_rows() {
+        let before = apply_palette(&test_frame(), &DEFAULT_PALETTE);
+        assert_eq!(dirty_rows(&before, &before, WIDTH), None);
+
+        let mut after = before.clone();
+        after[3 * WIDTH + 5] = [1, 2, 3];
+        after[9 * WIDTH] = [1, 2, 3];
+        assert_eq!(dirty_rows(&before, &after, WIDTH), Some((3, 9)));
+        assert_eq!(dirty_rows(&[], &after, WIDTH), Some((0, 31)));
+    }
+
+    #[test]
+    fn test_texture_rows_follow_the_pitch() {
+        let colours = apply_palette(&test_frame(), &DEFAULT_PALETTE);
+        let pitch = WIDTH * 4 + 16; // Rows padded as a texture's may be.
+        let mut rgba = vec![0; pitch * 32];
+        write_rgba(&colours, WIDTH, &mut rgba, pitch);
+
+        let rows: Vec<u8> = rgba.chunks(pitch).flat_map(|row| row[..WIDTH * 4].to_vec()).collect();
+        let expected: Vec<u8> = colours.iter().flat_map(|[r, g, b]| [*r, *g, *b, 255]).collect();
+        assert_eq!(rows, expected);
+    }
+
+    #[test]
+    fn test_windows_are_the_size_asked_for() {
+        let colours = [[0; 3]; WIDTH * 32];
+        assert_eq!(show(&colours, WIDTH, &Look::window(192, 96)).len(), 192 * 96 * 4);
+
+        let look = Look { border_width: 5, ..Look::window(202, 106) };
+        assert_eq!(show(&colours, WIDTH, &look).len(), 202 * 106 * 4);
+    }
+
+    #[test]
+    fn test_render_matches_reference_unscaled() {
+        let rgba = show(&apply_palette(&test_frame(), &DEFAULT_PALETTE), WIDTH, &Look::window(64, 32));
+        assert_matches_reference("frame_scale_1", &rgba, 64, 32);
+    }
+
+    #[test]
+    fn test_render_matches_reference_scaled() {
+        let rgba = show(&apply_palette(&test_frame(), &DEFAULT_PALETTE), WIDTH, &Look::window(256, 128));
+        assert_matches_reference("frame_scale_4", &rgba, 256, 128);
+    }
+
+    #[test]
+    fn test_render_matches_reference_with_border() {
+        let look = Look { border: [40, 40, 80], border_width: 3, ..Look::window(134, 70) };
+        let rgba = show(&apply_palette(&test_frame(), &DEFAULT_PALETTE), WIDTH, &look);
+        assert_matches_reference("frame_border", &rgba, 134, 70);
+    }
+
+    #[test]
+    fn test_render_matches_reference_letterboxed() {
+        // Too wide for the frame, and too short for more than 3 window pixels a Chip8 pixel.
+        let look = Look { border: [40, 40, 80], border_width: 2, ..Look::window(240, 110) };
+        let rgba = show(&apply_palette(&test_frame(), &DEFAULT_PALETTE), WIDTH, &look);
+        assert_matches_reference("frame_letterboxed", &rgba, 240, 110);
+    }
+
+    #[test]
+    fn test_render_matches_reference_with_planes() {
+        let rgba = show(&apply_palette(&test_plane_frame(), &DEFAULT_PALETTE), WIDTH, &Look::window(64, 32));
+        assert_matches_reference("frame_planes", &rgba, 64, 32);
+    }
+
+    #[test]
+    fn test_render_matches_reference_with_palette() {
+        let palette = palettes::load("amber").unwrap();
+        let rgba = show(&apply_palette(&test_plane_frame(), &palette), WIDTH, &Look::window(128, 64));
+        assert_matches_reference("frame_amber", &rgba, 128, 64);
+    }
+
+    #[test]
+    fn test_render_matches_reference_with_chip8x_zones() {
+        let mut zones = ColourZones::new(32);
+        zones.cycle_background();
+        zones.set_coarse(0x10, 0x00, 5);
+        zones.set_fine(0x03, 13, 3, 6);
+
+        let rgba = show(&zones.colourise(&test_frame()), WIDTH, &Look::window(64, 32));
+        assert_matches_reference("frame_chip8x", &rgba, 64, 32);
+    }
+
+    #[test]
+    fn test_render_matches_reference_hires() {
+        // SCHIP's 128x64 screen, scaled into a window made for the 64x32 one.
+        let mut frame = FrameBuffer::with_size(128, 64, true, true);
+        frame.draw_sprite(&ZERO, 0, 0);
+        frame.draw_sprite(&ZERO, 40, 100);
+        frame.draw_sprite(&ZERO, 62, 124);
+        let colours = apply_palette(&frame.composite(), &DEFAULT_PALETTE);
+
+        let look = Look { border_width: 4, ..Look::window(264, 136) };
+        assert_matches_reference("frame_hires", &show(&colours, frame.width(), &look), 264, 136);
+    }
+
+    #[test]
+    fn test_render_matches_reference_with_crt() {
+        let look = Look { crt: true, ..Look::window(256, 128) };
+        let rgba = show(&apply_palette(&test_frame(), &DEFAULT_PALETTE), WIDTH, &look);
+        assert_matches_reference("frame_crt", &rgba, 256, 128);
+    }
+
+    #[test]
+    fn test_render_matches_reference_with_grid() {
+        let look = Look { grid: true, ..Look::window(256, 128) };
+        let rgba = show(&apply_palette(&test_frame(), &DEFAULT_PALETTE), WIDTH, &look);
+        assert_matches_reference("frame_grid", &rgba, 256, 128);
+    }
+
+    #[test]
+    fn test_render_matches_reference_with_phosphor() {
+        // The frame, then the frame with its sprites erased two frames ago, fading out over four.
+        let mut phosphor = Phosphor::new(4, 1.0);
+        let lit = test_frame();
+        phosphor.render(&lit, &apply_palette(&lit, &DEFAULT_PALETTE));
+        let off = vec![0; lit.len()];
+        phosphor.render(&off, &apply_palette(&off, &DEFAULT_PALETTE));
+        let colours = phosphor.render(&off, &apply_palette(&off, &DEFAULT_PALETTE));
+
+        let rgba = show(&colours, WIDTH, &Look::window(128, 64));
+        assert_matches_reference("frame_phosphor", &rgba, 128, 64);
     }
 }
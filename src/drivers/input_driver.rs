@@ -1,20 +1,79 @@
-use sdl2::{self, event::Event, keyboard::Keycode};
+// Self imports
+use crate::config::{Config, Hotkey};
+use crate::display::Input;
+
+// External imports
+use log::debug;
+use sdl2::{
+    self,
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+};
 
 pub struct InputDriver {
     events: sdl2::EventPump,
+    keypad: Vec<(Keycode, u8)>,
+    keypad2: Vec<(Keycode, u8)>,
+    hotkeys: Vec<(Keycode, Hotkey)>,
 }
 
 impl InputDriver {
-    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
-        InputDriver {
-            events: sdl_context.event_pump().unwrap(),
+    pub fn new(sdl_context: &sdl2::Sdl, config: &Config) -> Result<Self, String> {
+        let keycode = |name: &str| Keycode::from_name(name).ok_or_else(|| format!("unknown key name: {}", name));
+
+        let mut keypad = Vec::new();
+        for (k, name) in config.keypad.iter().enumerate() {
+            keypad.push((keycode(name)?, k as u8));
+        }
+
+        let mut keypad2 = Vec::new();
+        for (k, name) in &config.keypad2 {
+            keypad2.push((keycode(name)?, *k));
+        }
+
+        let mut hotkeys = Vec::new();
+        for (hotkey, name) in &config.hotkeys {
+            hotkeys.push((keycode(name)?, *hotkey));
         }
+        let (keys, keys2) = (keypad.len(), keypad2.len());
+        debug!("bound {} keypad keys, {} second keypad keys and {} hotkeys", keys, keys2, hotkeys.len());
+
+        Ok(InputDriver {
+            events: sdl_context.event_pump().unwrap(),
+            keypad,
+            keypad2,
+            hotkeys,
+        })
     }
 
-    pub fn poll(&mut self) -> Result<Option<u8>, ()> {
+    /// Read the keyboard and window events since the last poll, with the ids of the windows uncovered or resized, which
+    /// need drawing again. Fails when the last window is closed.
+    pub fn poll(&mut self) -> Result<(Input, Vec<u32>), ()> {
+        let mut hotkeys = Vec::new();
+        let mut wheel = Vec::new();
+        let mut closed = Vec::new();
+        let mut exposed = Vec::new();
+
         for event in self.events.poll_iter() {
-            if let Event::Quit { .. } = event {
-                return Err(());
+            match event {
+                Event::Quit { .. } => return Err(()),
+                Event::KeyDown { keycode: Some(key), .. } => {
+                    if let Some((_, hotkey)) = self.hotkeys.iter().find(|(k, _)| *k == key) {
+                        hotkeys.push(*hotkey);
+                    }
+                }
+                Event::MouseWheel { window_id, y, .. } => wheel.push((window_id, y)),
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                } => closed.push(window_id),
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Exposed | WindowEvent::SizeChanged(..),
+                    ..
+                } => exposed.push(window_id),
+                _ => (),
             }
         }
 
@@ -26,33 +85,26 @@ impl InputDriver {
             .collect();
 
         // Map key from modern keyboard to hexadecimal Chip8 keypad.
-        for key in keys {
-            match key {
-                Keycode::Num1 => return Ok(Some(0x1)),
-                Keycode::Num2 => return Ok(Some(0x2)),
-                Keycode::Num3 => return Ok(Some(0x3)),
-                Keycode::Num4 => return Ok(Some(0xC)),
-
-                Keycode::Q => return Ok(Some(0x4)),
-                Keycode::W => return Ok(Some(0x5)),
-                Keycode::E => return Ok(Some(0x6)),
-                Keycode::R => return Ok(Some(0xD)),
-
-                Keycode::A => return Ok(Some(0x7)),
-                Keycode::S => return Ok(Some(0x8)),
-                Keycode::D => return Ok(Some(0x9)),
-                Keycode::F => return Ok(Some(0xE)),
-
-                Keycode::Z => return Ok(Some(0xA)),
-                Keycode::X => return Ok(Some(0x0)),
-                Keycode::C => return Ok(Some(0xB)),
-                Keycode::V => return Ok(Some(0xF)),
-
-                Keycode::Space => return Ok(Some(0xFF)),
+        let lookup = |keypad: &[(Keycode, u8)]| {
+            keys.iter()
+                .find_map(|key| keypad.iter().find(|(k, _)| k == key).map(|(_, value)| *value))
+        };
 
-                _ => (),
-            }
-        }
-        Ok(None)
+        let held = self
+            .hotkeys
+            .iter()
+            .filter(|(k, _)| keys.contains(k))
+            .map(|(_, hotkey)| *hotkey)
+            .collect();
+
+        let input = Input {
+            key: lookup(&self.keypad),
+            key2: lookup(&self.keypad2),
+            hotkeys,
+            held,
+            wheel,
+            closed,
+        };
+        Ok((input, exposed))
     }
 }
@@ -0,0 +1,60 @@
+// Self imports
+use crate::cpu::CPU;
+use crate::memory_view::{MemoryView, HEIGHT};
+
+// External imports
+use log::info;
+use sdl2::{self, pixels::PixelFormatEnum, render::Canvas, video::Window};
+
+const SCALE_FACTOR: u32 = 3;
+const TITLE: &str = "Chip8 memory";
+
+// Rows scrolled by each notch of the mouse wheel.
+const ROWS_PER_NOTCH: i32 = 3;
+
+/// A second window showing the memory view.
+pub struct MemoryWindow {
+    canvas: Canvas<Window>,
+    view: MemoryView,
+}
+
+impl MemoryWindow {
+    pub fn new(sdl_context: &sdl2::Sdl, cpu: &CPU) -> Result<Self, String> {
+        let view = MemoryView::new(cpu.memory_size());
+        let (width, height) = (view.width() as u32, HEIGHT as u32);
+
+        let window = sdl_context
+            .video()?
+            .window(TITLE, width * SCALE_FACTOR, height * SCALE_FACTOR)
+            .resizable()
+            .build()
+            .map_err(|e| e.to_string())?;
+        let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        let _ = canvas.set_logical_size(width, height);
+        info!("opened the memory window");
+
+        Ok(Self { canvas, view })
+    }
+
+    /// SDL's id for the window, which its events carry.
+    pub fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    /// Scroll by some notches of the mouse wheel, up for positive as SDL reports them.
+    pub fn scroll(&mut self, notches: i32, cpu: &CPU) {
+        self.view.scroll(-notches * ROWS_PER_NOTCH, cpu);
+    }
+
+    pub fn draw(&mut self, cpu: &CPU) {
+        let bytes: Vec<u8> = self.view.render(cpu).into_iter().flatten().collect();
+        let (width, height) = (self.view.width() as u32, HEIGHT as u32);
+
+        let creator = self.canvas.texture_creator();
+        if let Ok(mut texture) = creator.create_texture_static(PixelFormatEnum::RGB24, width, height) {
+            let _ = texture.update(None, &bytes, width as usize * 3);
+            let _ = self.canvas.copy(&texture, None, None);
+        }
+        self.canvas.present();
+    }
+}
@@ -1,5 +1,10 @@
+mod audio_driver;
+mod debug_window;
 mod display_driver;
 mod input_driver;
+mod memory_window;
 
+pub use audio_driver::{AudioDriver, DEFAULT_PITCH};
+pub use debug_window::DebugWindow;
 pub use display_driver::DisplayDriver;
-pub use input_driver::InputDriver;
+pub use memory_window::MemoryWindow;
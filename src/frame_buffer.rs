@@ -1,99 +1,351 @@
+// Self imports
+use crate::journal::Change;
+
+// External imports
+use serde::{Deserialize, Serialize};
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32; // Default height. Some variants have taller screens.
+
+// Number of bit-planes. XO-CHIP draws to two planes whose combination picks one of four colours.
+pub const PLANES: usize = 2;
+
+// Bitmask with a bit set for every plane.
+const ALL_PLANES: u8 = (1 << PLANES) - 1;
+
+/// Colours for each combination of lit planes, indexed by a bitmask of the planes lit at that pixel. Index 0 is the
+/// background, 1 is plane 0 alone, 2 is plane 1 alone and 3 is both.
+pub type Palette = [[u8; 3]; 1 << PLANES];
+
 /// Holds the pixel buffer and has methods for setting pixels, clearing the buffer and retrieving it.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct FrameBuffer {
-    buffer: [u64; 32], // 64x32 display represented using 32 64-bit integers.
-    prev_buffer: [u64; 32],
+    planes: [Vec<u64>; PLANES], // Each row is one 64-bit integer per 64 pixels, leftmost pixel in the top bit.
+    width: usize, // 64, or 128 in SCHIP's hires mode.
+    height: usize,
+    selected_planes: u8, // Bitmask of the planes which drawing, clearing and pixel access target.
+    visible_planes: u8,  // Bitmask of the planes included when compositing. Used to debug individual planes.
     wrap_x: bool,
     wrap_y: bool,
+
+    // Words overwritten since the owner last took them, while it is recording for the journal. Each is the plane,
+    // index in the plane and the word before and after.
+    written: Option<Vec<(u8, u16, u64, u64)>>,
 }
 
 impl FrameBuffer {
     pub fn new(wrap_x: bool, wrap_y: bool) -> Self {
+        Self::with_height(HEIGHT, wrap_x, wrap_y)
+    }
+
+    pub fn with_height(height: usize, wrap_x: bool, wrap_y: bool) -> Self {
+        Self::with_size(WIDTH, height, wrap_x, wrap_y)
+    }
+
+    /// A frame buffer of any size. The width must be 64 or 128.
+    pub fn with_size(width: usize, height: usize, wrap_x: bool, wrap_y: bool) -> Self {
+        let words = width / 64 * height;
         FrameBuffer {
-            buffer: [0; 32],
-            prev_buffer: [0; 32],
+            planes: std::array::from_fn(|_| vec![0; words]),
+            width,
+            height,
+            selected_planes: 1,
+            visible_planes: ALL_PLANES,
             wrap_x,
             wrap_y,
+            written: None,
+        }
+    }
+
+    /// Change the size of the screen, clearing every plane. Which planes are selected and visible is kept. The words
+    /// cleared aren't recorded as written, as the journal keeps both screens whole.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        *self = Self {
+            selected_planes: self.selected_planes,
+            visible_planes: self.visible_planes,
+            written: self.written.take(),
+            ..Self::with_size(width, height, self.wrap_x, self.wrap_y)
+        };
+    }
+
+    /// Composite the visible planes into one palette index per pixel, row by row. Pixels show as they are now; any
+    /// fading of pixels which turned off is left to the phosphor decay.
+    pub fn composite(&self) -> Vec<u8> {
+        let mut indices = Vec::with_capacity(self.width * self.height);
+        let words = self.words();
+
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let (word, bit) = (row * words + col / 64, 63 - col % 64);
+                let mut index = 0;
+                for plane in self.visible() {
+                    index |= (((self.planes[plane][word] >> bit) & 1) as u8) << plane;
+                }
+                indices.push(index);
+            }
         }
+
+        indices
+    }
+
+    /// Number of pixels in a row.
+    pub fn width(&self) -> usize {
+        self.width
     }
 
-    pub fn get_buffer(&mut self) -> Vec<u64> {
-        let mut buf = Vec::new();
+    /// Number of rows in the frame buffer.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Get the raw words of a single plane, row by row. Rows are one word wide, or two in hires mode.
+    pub fn plane(&self, plane: usize) -> &[u64] {
+        &self.planes[plane]
+    }
+
+    /// Copy the raw words of every plane.
+    pub fn planes(&self) -> [Vec<u64>; PLANES] {
+        self.planes.clone()
+    }
+
+    /// Overwrite a word of a single plane, indexed as in plane(). Used to undo and redo drawing, so isn't recorded as
+    /// written.
+    pub fn set_row(&mut self, plane: usize, row: usize, bits: u64) {
+        self.planes[plane][row] = bits;
+    }
+
+    /// Start or stop recording every word drawing, clearing and scrolling overwrite, as changes for the journal.
+    pub fn record_writes(&mut self, record: bool) {
+        self.written = record.then(Vec::new);
+    }
+
+    pub fn recording_writes(&self) -> bool {
+        self.written.is_some()
+    }
+
+    /// Words overwritten since the last call, oldest first. Empty when not recording.
+    pub fn take_writes(&mut self) -> impl Iterator<Item = Change> {
+        let written = self.written.as_mut().map(std::mem::take).unwrap_or_default();
+        written.into_iter().map(|(plane, row, before, after)| Change::Row { plane, row, before, after })
+    }
 
-        for i in 0..32 {
-            buf.push(self.prev_buffer[i] | self.buffer[i]);
+    // Every write to the planes, other than undoing and redoing, comes through here so it can be recorded.
+    fn set_word(&mut self, plane: usize, index: usize, bits: u64) {
+        let before = self.planes[plane][index];
+        if let Some(written) = self.written.as_mut().filter(|_| before != bits) {
+            written.push((plane as u8, index as u16, before, bits));
         }
+        self.planes[plane][index] = bits;
+    }
+
+    /// Select which planes drawing, clearing and pixel access target using a bitmask.
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask & ALL_PLANES;
+    }
+
+    /// Bitmask of the planes drawing, clearing and pixel access target.
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
 
-        self.prev_buffer = self.buffer.clone();
-        buf
+    /// Show or hide a plane when compositing.
+    pub fn set_plane_visible(&mut self, plane: usize, visible: bool) {
+        if visible {
+            self.visible_planes |= 1 << plane;
+        } else {
+            self.visible_planes &= !(1 << plane);
+        }
     }
 
-    /// Set every bit (pixel) in the buffer to be 0.
+    /// Hide every plane except the given one.
+    pub fn show_only_plane(&mut self, plane: usize) {
+        self.visible_planes = 1 << plane;
+    }
+
+    /// Show every plane.
+    pub fn show_all_planes(&mut self) {
+        self.visible_planes = ALL_PLANES;
+    }
+
+    /// Indices of the currently selected planes, lowest first.
+    pub fn selected(&self) -> impl Iterator<Item = usize> {
+        let mask = self.selected_planes;
+        (0..PLANES).filter(move |plane| mask >> plane & 1 == 1)
+    }
+
+    /// Indices of the currently visible planes.
+    fn visible(&self) -> impl Iterator<Item = usize> {
+        let mask = self.visible_planes;
+        (0..PLANES).filter(move |plane| mask >> plane & 1 == 1)
+    }
+
+    /// Words in each row.
+    fn words(&self) -> usize {
+        self.width / 64
+    }
+
+    /// Bits of the pixels in a row which are on the screen. Rows are handled as a u128 with the leftmost pixel in the
+    /// top bit, whatever the width.
+    fn row_mask(&self) -> u128 {
+        !0 << (128 - self.width)
+    }
+
+    fn row_bits(&self, plane: usize, row: usize) -> u128 {
+        let words = self.words();
+        let row = &self.planes[plane][row * words..(row + 1) * words];
+        row.iter().enumerate().fold(0, |bits, (word, value)| bits | (*value as u128) << (64 * (1 - word)))
+    }
+
+    fn set_row_bits(&mut self, plane: usize, row: usize, bits: u128) {
+        let words = self.words();
+        for word in 0..words {
+            self.set_word(plane, row * words + word, (bits >> (64 * (1 - word))) as u64);
+        }
+    }
+
+    /// Set every bit (pixel) in the selected planes to be 0.
     pub fn clear(&mut self) {
-        self.buffer = [0; 32];
+        for plane in self.selected() {
+            for index in 0..self.planes[plane].len() {
+                self.set_word(plane, index, 0);
+            }
+        }
     }
 
-    /// Draw sprite at given position
+    /// Move the selected planes down `rows` rows. Rows scrolled off the bottom are lost and blank rows appear at the top.
+    pub fn scroll_down(&mut self, rows: usize) {
+        let words = rows.min(self.height) * self.words();
+        for plane in self.selected() {
+            for index in (0..self.planes[plane].len()).rev() {
+                let bits = index.checked_sub(words).map_or(0, |from| self.planes[plane][from]);
+                self.set_word(plane, index, bits);
+            }
+        }
+    }
+
+    /// Move the selected planes up `rows` rows. Rows scrolled off the top are lost and blank rows appear at the bottom.
+    pub fn scroll_up(&mut self, rows: usize) {
+        let words = rows.min(self.height) * self.words();
+        for plane in self.selected() {
+            for index in 0..self.planes[plane].len() {
+                let bits = self.planes[plane].get(index + words).copied().unwrap_or(0);
+                self.set_word(plane, index, bits);
+            }
+        }
+    }
+
+    /// Move the selected planes right `cols` pixels. Pixels scrolled off the edge are lost.
+    pub fn scroll_right(&mut self, cols: usize) {
+        self.scroll_rows(|bits| bits.checked_shr(cols as u32).unwrap_or(0));
+    }
+
+    /// Move the selected planes left `cols` pixels. Pixels scrolled off the edge are lost.
+    pub fn scroll_left(&mut self, cols: usize) {
+        self.scroll_rows(|bits| bits.checked_shl(cols as u32).unwrap_or(0));
+    }
+
+    fn scroll_rows(&mut self, scroll: impl Fn(u128) -> u128) {
+        let mask = self.row_mask();
+        for plane in self.selected().collect::<Vec<_>>() {
+            for row in 0..self.height {
+                let bits = scroll(self.row_bits(plane, row)) & mask;
+                self.set_row_bits(plane, row, bits);
+            }
+        }
+    }
+
+    /// Draw sprite at given position on every selected plane. Returns true if any lit pixel was turned off.
     pub fn draw_sprite(&mut self, sprite: &[u8], row: usize, col: usize) -> bool {
         let mut change = false;
-        let shift_amount = 63i32 - col as i32 - 7i32;
+        for plane in self.selected() {
+            if self.draw_sprite_on(plane, sprite, row, col) {
+                change = true;
+            }
+        }
+        change
+    }
+
+    /// Draw sprite at given position on a single plane. Returns true if any lit pixel was turned off.
+    pub fn draw_sprite_on(&mut self, plane: usize, sprite: &[u8], row: usize, col: usize) -> bool {
+        let mut change = false;
         for (i, byte) in sprite.iter().enumerate() {
-            let byte = self.shift_byte(*byte, shift_amount as i32);
-            if self.draw_byte(row + i, byte) {
+            if self.draw_line(plane, row + i, col, (*byte as u128) << 120) {
                 change = true;
             }
         }
         change
     }
 
-    /// Cast a byte to a u64 and shift bits given amount. Wrap if flag is set.
-    fn shift_byte(&self, byte: u8, shift_amount: i32) -> u64 {
-        let byte = byte as u64;
+    /// Draw a SCHIP 16x16 sprite, two bytes per row, at given position on every selected plane. Returns true if any lit
+    /// pixel was turned off.
+    pub fn draw_wide_sprite(&mut self, sprite: &[u8], row: usize, col: usize) -> bool {
+        let mut change = false;
+        for plane in self.selected() {
+            if self.draw_wide_sprite_on(plane, sprite, row, col) {
+                change = true;
+            }
+        }
+        change
+    }
 
-        if shift_amount >= 0 {
-            byte << shift_amount
-        } else if self.wrap_x {
-            byte.rotate_right(shift_amount.abs() as u32) // Shifts right and wraps bits back to front of num.
-        } else {
-            byte.wrapping_shr(shift_amount.abs() as u32) // Shifts right. Ignores bits that overflow. Weird name tbh.
+    /// Draw a SCHIP 16x16 sprite at given position on a single plane. Returns true if any lit pixel was turned off.
+    pub fn draw_wide_sprite_on(&mut self, plane: usize, sprite: &[u8], row: usize, col: usize) -> bool {
+        let mut change = false;
+        for (i, pair) in sprite.chunks(2).enumerate() {
+            let bits = (pair[0] as u128) << 8 | pair.get(1).copied().unwrap_or(0) as u128;
+            if self.draw_line(plane, row + i, col, bits << 112) {
+                change = true;
+            }
         }
+        change
     }
 
-    /// Draw a byte (cast to a u64) to the pixel buffer and wrap vertically if flag is set.
-    fn draw_byte(&mut self, row: usize, byte: u64) -> bool {
-        if row < 32 {
-            self.buffer[row] ^= byte;
-            byte & self.buffer[row] != byte
+    /// XOR one line of a sprite, held in the top bits of `line`, onto a plane. Pixels off the right edge wrap if the
+    /// flag is set, as do rows off the bottom. Returns true if any lit pixel was turned off.
+    fn draw_line(&mut self, plane: usize, row: usize, col: usize, line: u128) -> bool {
+        let mask = self.row_mask();
+        let bits = if self.wrap_x {
+            let col = col % self.width;
+            let wrapped = line.checked_shl((self.width - col) as u32).unwrap_or(0);
+            (line >> col | wrapped) & mask
+        } else {
+            line.checked_shr(col as u32).unwrap_or(0) & mask
+        };
+
+        let row = if row < self.height {
+            row
         } else if self.wrap_y {
-            self.buffer[row % 32] ^= byte;
-            byte & self.buffer[row % 32] != byte
-        } else{
-            false
-        }
+            row % self.height
+        } else {
+            return false;
+        };
+
+        let before = self.row_bits(plane, row);
+        self.set_row_bits(plane, row, before ^ bits);
+        before & bits != 0
     }
 
-    /// Set the value of a pixel using a row and column.
+    /// Set the value of a pixel on the selected planes using a row and column.
     pub fn set_pixel(&mut self, row: usize, col: usize, status: bool) {
-        let col = 63 - col;
+        let (word, bit) = (row * self.words() + col / 64, 63 - col % 64);
 
-        if status {
-            self.buffer[row] |= 1 << col;
-        } else {
-            self.buffer[row] &= !(1 << col);
+        for plane in self.selected() {
+            let bits = self.planes[plane][word];
+            self.set_word(plane, word, if status { bits | 1 << bit } else { bits & !(1 << bit) });
         }
     }
 
-    // Get the status of a pixel using a row and column.
+    // Get the status of a pixel using a row and column. True if it is lit on any selected plane.
     pub fn get_pixel(&mut self, row: usize, col: usize) -> bool {
         self.check_bounds(row, col);
 
-        let col = 63 - col;
-        (self.buffer[row] >> col & 1) == 1
+        let (word, bit) = (row * self.words() + col / 64, 63 - col % 64);
+        self.selected().any(|plane| (self.planes[plane][word] >> bit & 1) == 1)
     }
 
     // Check if a given index is out of bounds.
     fn check_bounds(&self, row: usize, col: usize) {
-        if row >= 32 || col > 64 {
+        if row >= self.height || col >= self.width {
             panic!("out of bounds for pixel buffer: ({}, {})", col, row);
         }
     }
@@ -106,21 +358,21 @@ mod tests {
     #[test]
     fn test_creating_new_frame_buffer() {
         let frame_buffer = FrameBuffer::new(true, true);
-        assert_eq!(frame_buffer.buffer.len(), 32);
-        assert_eq!(frame_buffer.buffer[0], 0);
-        assert_eq!(frame_buffer.buffer[16], 0);
-        assert_eq!(frame_buffer.buffer[31], 0);
+        assert_eq!(frame_buffer.planes[0].len(), 32);
+        assert_eq!(frame_buffer.planes[0][0], 0);
+        assert_eq!(frame_buffer.planes[0][16], 0);
+        assert_eq!(frame_buffer.planes[0][31], 0);
     }
 
     #[test]
     fn test_clearing_screen() {
         let mut screen = FrameBuffer::new(true, true);
-        screen.buffer[0] = 1;
-        screen.buffer[16] = 1;
-        screen.buffer[31] = 1;
+        screen.planes[0][0] = 1;
+        screen.planes[0][16] = 1;
+        screen.planes[0][31] = 1;
 
         screen.clear();
-        assert_eq!(screen.buffer, [0; 32]);
+        assert_eq!(screen.planes[0], [0; 32]);
     }
 
     #[test]
@@ -128,16 +380,16 @@ mod tests {
         let mut screen = FrameBuffer::new(true, true);
 
         screen.set_pixel(0, 63, true);
-        assert_eq!(screen.buffer[0], 1);
+        assert_eq!(screen.planes[0][0], 1);
 
         screen.set_pixel(0, 63, false);
-        assert_eq!(screen.buffer[0], 0);
+        assert_eq!(screen.planes[0][0], 0);
 
         screen.set_pixel(31, 63, true);
-        assert_eq!(screen.buffer[31], 1);
+        assert_eq!(screen.planes[0][31], 1);
 
         screen.set_pixel(31, 63, false);
-        assert_eq!(screen.buffer[31], 0);
+        assert_eq!(screen.planes[0][31], 0);
     }
 
     #[test]
@@ -230,6 +482,83 @@ mod tests {
         assert_eq!(screen.get_pixel(17, 2), true);
     }
 
+    #[test]
+    fn test_drawing_wide_sprite() {
+        let mut screen = FrameBuffer::new(true, true);
+
+        let sprite = [0xFF; 32];
+        assert_eq!(screen.draw_wide_sprite(&sprite, 16, 56), false);
+
+        assert_eq!(screen.get_pixel(16, 56), true);
+        assert_eq!(screen.get_pixel(31, 63), true);
+        assert_eq!(screen.get_pixel(16, 7), true); // Wrapped horizontally.
+        assert_eq!(screen.get_pixel(16, 8), false);
+        assert_eq!(screen.get_pixel(0, 56), false);
+
+        // Drawing again erases every pixel, which is a collision.
+        assert_eq!(screen.draw_wide_sprite(&sprite, 16, 56), true);
+        assert_eq!(screen.planes[0], [0; 32]);
+    }
+
+    #[test]
+    fn test_scrolling() {
+        let mut screen = FrameBuffer::new(true, true);
+        screen.set_pixel(0, 10, true);
+        screen.set_pixel(31, 10, true);
+
+        screen.scroll_down(2);
+        assert_eq!(screen.get_pixel(2, 10), true);
+        assert_eq!(screen.get_pixel(0, 10), false);
+        assert_eq!(screen.get_pixel(1, 10), false); // The bottom row is lost rather than wrapping.
+
+        screen.scroll_right(4);
+        assert_eq!(screen.get_pixel(2, 14), true);
+
+        screen.scroll_left(8);
+        assert_eq!(screen.get_pixel(2, 6), true);
+        assert_eq!(screen.get_pixel(2, 14), false);
+
+        screen.scroll_up(2);
+        assert_eq!(screen.get_pixel(0, 6), true);
+        screen.scroll_up(1);
+        assert_eq!(screen.get_pixel(31, 6), false); // The top row is lost rather than wrapping.
+        assert_eq!(screen.get_pixel(0, 6), false);
+        screen.set_pixel(2, 6, true);
+
+        screen.scroll_down(40);
+        assert_eq!(screen.planes[0], [0; 32]);
+    }
+
+    #[test]
+    fn test_hires_frame_buffer() {
+        let mut screen = FrameBuffer::new(true, false);
+        screen.resize(128, 64);
+        assert_eq!(screen.planes[0].len(), 128);
+
+        // Crossing from the left half of the screen into the right.
+        let sprite = [0xFF; 32];
+        screen.draw_wide_sprite(&sprite, 0, 60);
+        assert_eq!(screen.get_pixel(0, 59), false);
+        assert_eq!(screen.get_pixel(0, 60), true);
+        assert_eq!(screen.get_pixel(15, 75), true);
+        assert_eq!(screen.get_pixel(0, 76), false);
+
+        // Wrapping off the right edge.
+        screen.draw_sprite(&[0xFF], 20, 124);
+        assert_eq!(screen.get_pixel(20, 127), true);
+        assert_eq!(screen.get_pixel(20, 3), true);
+        assert_eq!(screen.get_pixel(20, 4), false);
+
+        screen.scroll_right(4);
+        assert_eq!(screen.get_pixel(0, 64), true);
+        assert_eq!(screen.get_pixel(0, 79), true);
+        assert_eq!(screen.get_pixel(0, 63), false);
+
+        let indices = screen.composite();
+        assert_eq!(indices.len(), 128 * 64);
+        assert_eq!(indices[64], 1);
+    }
+
     #[test]
     fn test_no_wrapping_vertically() {
         let mut screen = FrameBuffer::new(true, false);
@@ -261,4 +590,87 @@ mod tests {
         assert_eq!(screen.get_pixel(0, 0), false);
         assert_eq!(screen.get_pixel(0, 2), false);
     }
+
+    #[test]
+    fn test_taller_frame_buffer() {
+        let mut screen = FrameBuffer::with_height(48, true, true);
+        assert_eq!(screen.height(), 48);
+        assert_eq!(screen.composite().len(), WIDTH * 48);
+
+        // Sprites drawn past the last row should wrap back to the top of the taller screen.
+        screen.draw_sprite(&[0x80, 0x80], 47, 0);
+        assert_eq!(screen.get_pixel(47, 0), true);
+        assert_eq!(screen.get_pixel(0, 0), true);
+        assert_eq!(screen.get_pixel(32, 0), false);
+    }
+
+    #[test]
+    fn test_drawing_targets_selected_planes() {
+        let mut screen = FrameBuffer::new(true, true);
+
+        screen.select_planes(0b10);
+        screen.draw_sprite(&[0x80], 0, 0);
+        assert_eq!(screen.plane(0)[0], 0);
+        assert_eq!(screen.plane(1)[0], 1 << 63);
+
+        screen.select_planes(0b11);
+        assert_eq!(screen.draw_sprite(&[0x80], 0, 0), true);
+        assert_eq!(screen.plane(0)[0], 1 << 63);
+        assert_eq!(screen.plane(1)[0], 0);
+    }
+
+    #[test]
+    fn test_clearing_only_selected_planes() {
+        let mut screen = FrameBuffer::new(true, true);
+
+        screen.select_planes(0b11);
+        screen.set_pixel(0, 0, true);
+
+        screen.select_planes(0b01);
+        screen.clear();
+        assert_eq!(screen.plane(0)[0], 0);
+        assert_eq!(screen.plane(1)[0], 1 << 63);
+    }
+
+    #[test]
+    fn test_compositing_planes_into_palette_indices() {
+        let mut screen = FrameBuffer::new(true, true);
+
+        screen.set_pixel(0, 0, true);
+        screen.select_planes(0b10);
+        screen.set_pixel(0, 1, true);
+        screen.select_planes(0b11);
+        screen.set_pixel(0, 2, true);
+
+        let indices = screen.composite();
+        assert_eq!(indices.len(), WIDTH * HEIGHT);
+        assert_eq!(&indices[..4], &[1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn test_hiding_planes_when_compositing() {
+        let mut screen = FrameBuffer::new(true, true);
+
+        screen.select_planes(0b11);
+        screen.set_pixel(0, 0, true);
+
+        screen.show_only_plane(1);
+        assert_eq!(screen.composite()[0], 2);
+
+        screen.set_plane_visible(1, false);
+        assert_eq!(screen.composite()[0], 0);
+
+        screen.show_all_planes();
+        assert_eq!(screen.composite()[0], 3);
+    }
+
+    #[test]
+    fn test_pixels_turned_off_are_off_in_the_next_frame() {
+        let mut screen = FrameBuffer::new(true, true);
+
+        screen.set_pixel(0, 0, true);
+        assert_eq!(screen.composite()[0], 1);
+        screen.set_pixel(0, 0, false);
+        assert_eq!(screen.composite()[0], 0);
+    }
 }
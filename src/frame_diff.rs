@@ -0,0 +1,156 @@
+//! "What changed" view, for tracking down draw order and flicker problems. Each frame is shown as the difference from
+//! the one before: pixels turned on are green, pixels turned off are red and pixels left alone are dimmed. Frames can
+//! also be written out as raw video.
+//!
+//! MegaChip mode draws its own screen and isn't diffed.
+
+// Std imports
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+pub const NEW: [u8; 3] = [0, 250, 0];
+pub const ERASED: [u8; 3] = [250, 0, 0];
+
+// Pixels left on are shown at a quarter of their brightness.
+const DIM_SHIFT: u8 = 2;
+
+#[derive(Default)]
+pub struct FrameDiff {
+    previous: Vec<u8>, // Plane bits of the last frame's pixels.
+}
+
+impl FrameDiff {
+    /// Colour a frame by how it differs from the last one. `pixels` are the frame's plane bits, 0 for off, and
+    /// `colours` the frame as it would normally be shown. The first frame, or one of a new size, shows nothing as new.
+    pub fn render(&mut self, pixels: &[u8], colours: &[[u8; 3]]) -> Vec<[u8; 3]> {
+        if self.previous.len() != pixels.len() {
+            self.previous = pixels.to_vec();
+        }
+
+        let diff = pixels
+            .iter()
+            .zip(&self.previous)
+            .zip(colours)
+            .map(|((now, before), colour)| match (*now != 0, now == before) {
+                (false, true) => *colour,
+                (true, true) => colour.map(|channel| channel >> DIM_SHIFT),
+                (true, false) => NEW,
+                (false, false) => ERASED,
+            })
+            .collect();
+
+        self.previous.copy_from_slice(pixels);
+        diff
+    }
+
+    /// Forget the last frame, so the next one shows nothing as changed.
+    pub fn reset(&mut self) {
+        self.previous.clear();
+    }
+}
+
+/// Frames written one after another as raw 24-bit RGB, at one pixel per CHIP-8 pixel. Every frame must be the same
+/// size. Play with e.g. `ffplay -f rawvideo -pixel_format rgb24 -video_size 64x32 -framerate 60 diff.rgb`.
+pub struct Video {
+    path: PathBuf,
+    out: BufWriter<File>,
+    size: Option<(usize, usize)>,
+    frames: usize,
+    skipped: usize, // Frames of a different size to the first.
+}
+
+impl Video {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("unable to create {}: {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            out: BufWriter::new(file),
+            size: None,
+            frames: 0,
+            skipped: 0,
+        })
+    }
+
+    pub fn write_frame(&mut self, colours: &[[u8; 3]], width: usize) -> Result<(), String> {
+        let size = (width, colours.len() / width);
+        if *self.size.get_or_insert(size) != size {
+            self.skipped += 1;
+            return Ok(());
+        }
+
+        let bytes: Vec<u8> = colours.iter().flatten().copied().collect();
+        self.out
+            .write_all(&bytes)
+            .map_err(|e| format!("unable to write to {}: {}", self.path.display(), e))?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Flush the video, describing what was written.
+    pub fn finish(mut self) -> Result<String, String> {
+        self.out.flush().map_err(|e| format!("unable to write to {}: {}", self.path.display(), e))?;
+
+        let (width, height) = self.size.unwrap_or_default();
+        let mut summary = format!("Wrote {} {}x{} frames to {}", self.frames, width, height, self.path.display());
+        if self.skipped > 0 {
+            summary += &format!(", skipping {} of a different size", self.skipped);
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 3] = [200, 200, 200];
+    const BLACK: [u8; 3] = [0, 0, 0];
+
+    fn colours(pixels: &[u8]) -> Vec<[u8; 3]> {
+        pixels.iter().map(|p| if *p == 0 { BLACK } else { WHITE }).collect()
+    }
+
+    #[test]
+    fn test_first_frame_has_no_changes() {
+        let mut diff = FrameDiff::default();
+        let pixels = [0, 1];
+        assert_eq!(diff.render(&pixels, &colours(&pixels)), vec![BLACK, [50, 50, 50]]);
+    }
+
+    #[test]
+    fn test_changes_are_coloured() {
+        let mut diff = FrameDiff::default();
+        let first = [0, 1, 0, 1];
+        diff.render(&first, &colours(&first));
+
+        let second = [1, 0, 0, 1];
+        assert_eq!(diff.render(&second, &colours(&second)), vec![NEW, ERASED, BLACK, [50, 50, 50]]);
+
+        // Unchanged since the last frame.
+        assert_eq!(diff.render(&second, &colours(&second)), vec![[50, 50, 50], BLACK, BLACK, [50, 50, 50]]);
+    }
+
+    #[test]
+    fn test_reset_forgets_the_last_frame() {
+        let mut diff = FrameDiff::default();
+        diff.render(&[0], &[BLACK]);
+        diff.reset();
+        assert_eq!(diff.render(&[1], &[WHITE]), vec![[50, 50, 50]]);
+    }
+
+    #[test]
+    fn test_writing_video() {
+        let path = std::env::temp_dir().join(format!("chip8-diff-{}.rgb", std::process::id()));
+        let mut video = Video::create(&path).unwrap();
+        video.write_frame(&[NEW, ERASED], 2).unwrap();
+        video.write_frame(&[BLACK; 4], 4).unwrap();
+        video.write_frame(&[WHITE, BLACK], 2).unwrap();
+
+        let summary = video.finish().unwrap();
+        assert!(summary.starts_with("Wrote 2 2x1 frames"));
+        assert!(summary.ends_with("skipping 1 of a different size"));
+        assert_eq!(std::fs::read(&path).unwrap(), vec![0, 250, 0, 250, 0, 0, 200, 200, 200, 0, 0, 0]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
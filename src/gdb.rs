@@ -0,0 +1,322 @@
+//! A stub speaking gdb's remote serial protocol, so ROMs can be debugged from gdb or a frontend which talks to a
+//! gdbserver. Run with --gdb <port> and connect with `target remote localhost:<port>`. The ROM waits, paused, until
+//! the debugger continues it.
+//!
+//! Supported: reading registers and memory, software and hardware breakpoints, stepping, continuing, interrupting a
+//! running ROM, detaching and killing. Registers and memory can't be written. The registers are sent in the order
+//! V0-VF, I, PC, SP, DT, ST, a byte each except I and PC, which are 16 bit little endian.
+
+// Self imports
+use crate::cpu::CPU;
+
+// Std imports
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+// Signals reported in stop replies: SIGTRAP for breakpoints and steps, SIGINT when the debugger interrupts the ROM.
+const SIGTRAP: u8 = 5;
+const SIGINT: u8 = 2;
+
+// Largest packet the debugger may send, and so the most memory read at once as each byte is two hex digits.
+const PACKET_SIZE: usize = 0x400;
+
+/// What the host should do after the stub has handled the debugger's packets.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Request {
+    Continue,
+    Stop,
+    Step, // The stub has executed an instruction, so the screen may need presenting.
+    Detach,
+    Kill,
+}
+
+// A packet from the debugger.
+#[derive(Clone, Debug, PartialEq)]
+enum Packet {
+    Command(String),
+    Corrupt,   // The checksum didn't match, so the debugger is asked to send it again.
+    Interrupt, // Ctrl-C, sent outside a packet.
+}
+
+// How to answer a command: with a reply, or by running the ROM.
+#[derive(Clone, Debug, PartialEq)]
+enum Response {
+    Reply(String),
+    Continue,
+    Step,
+    Detach,
+    Kill,
+}
+
+pub struct GdbStub {
+    stream: TcpStream,
+    received: Receiver<Vec<u8>>,
+    buffer: Vec<u8>, // Received bytes not yet making up a whole packet.
+    running: bool,   // Whether the debugger has continued the ROM and is waiting for it to stop.
+}
+
+impl GdbStub {
+    /// Wait for a debugger to connect on a local port. The connection is read on its own thread so the window keeps
+    /// responding while the debugger is quiet.
+    pub fn listen(port: u16) -> Result<Self, String> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .map_err(|e| format!("unable to listen for gdb on port {}: {}", port, e))?;
+        println!("Waiting for gdb to connect on port {}", port);
+        let (stream, peer) = listener.accept().map_err(|e| format!("unable to accept gdb connection: {}", e))?;
+        println!("gdb connected from {}", peer);
+
+        let _ = stream.set_nodelay(true);
+        let mut reader = stream.try_clone().map_err(|e| format!("unable to read gdb connection: {}", e))?;
+        let (sender, received) = mpsc::channel();
+        thread::spawn(move || {
+            let mut bytes = [0; PACKET_SIZE];
+            while let Ok(n @ 1..) = reader.read(&mut bytes) {
+                if sender.send(bytes[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            stream,
+            received,
+            buffer: Vec::new(),
+            running: false,
+        })
+    }
+
+    /// Answer everything the debugger has sent since the last call, and tell it when a running ROM has been paused,
+    /// e.g. at a breakpoint. Returns what the host should do next, if anything. Fails once the connection is lost.
+    pub fn update(&mut self, cpu: &mut CPU, paused: bool) -> Result<Option<Request>, String> {
+        let mut request = None;
+        if self.running && paused {
+            request = Some(self.stop(SIGTRAP)?);
+        }
+
+        loop {
+            match self.received.try_recv() {
+                Ok(bytes) => self.buffer.extend(bytes),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err("gdb disconnected".to_string()),
+            }
+        }
+
+        for packet in take_packets(&mut self.buffer) {
+            let command = match packet {
+                Packet::Command(command) => command,
+                Packet::Corrupt => {
+                    self.write(b"-")?;
+                    continue;
+                }
+                Packet::Interrupt if self.running => {
+                    request = Some(self.stop(SIGINT)?);
+                    continue;
+                }
+                Packet::Interrupt => continue,
+            };
+
+            self.write(b"+")?;
+            match respond(cpu, &command) {
+                Response::Reply(reply) => self.send(&reply)?,
+                Response::Continue => {
+                    self.running = true;
+                    request = Some(Request::Continue);
+                }
+                Response::Step => {
+                    cpu.cycle();
+                    self.send(&stop_reply(SIGTRAP))?;
+                    request = Some(Request::Step);
+                }
+                Response::Detach => {
+                    cpu.breakpoints_mut().clear();
+                    self.send("OK")?;
+                    return Ok(Some(Request::Detach));
+                }
+                Response::Kill => return Ok(Some(Request::Kill)),
+            }
+        }
+        Ok(request)
+    }
+
+    fn stop(&mut self, signal: u8) -> Result<Request, String> {
+        self.running = false;
+        self.send(&stop_reply(signal))?;
+        Ok(Request::Stop)
+    }
+
+    fn send(&mut self, data: &str) -> Result<(), String> {
+        self.write(frame(data).as_bytes())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.stream.write_all(bytes).map_err(|e| format!("gdb connection failed: {}", e))
+    }
+}
+
+// Take the whole packets out of the bytes received, leaving the start of any partial one. Acks are dropped as nothing
+// is ever resent.
+fn take_packets(buffer: &mut Vec<u8>) -> Vec<Packet> {
+    let mut packets = Vec::new();
+    loop {
+        match buffer.iter().position(|&b| b == b'$' || b == 0x03) {
+            Some(start) => drop(buffer.drain(..start)),
+            None => {
+                buffer.clear();
+                break;
+            }
+        }
+
+        if buffer[0] == 0x03 {
+            buffer.remove(0);
+            packets.push(Packet::Interrupt);
+            continue;
+        }
+
+        let end = match buffer.iter().position(|&b| b == b'#') {
+            Some(end) if buffer.len() >= end + 3 => end,
+            _ => break,
+        };
+        let data = &buffer[1..end];
+        let sent = std::str::from_utf8(&buffer[end + 1..end + 3]).ok().and_then(|s| u8::from_str_radix(s, 16).ok());
+        packets.push(match sent == Some(checksum(data)) {
+            true => Packet::Command(String::from_utf8_lossy(data).into_owned()),
+            false => Packet::Corrupt,
+        });
+        buffer.drain(..end + 3);
+    }
+    packets
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+// Wrap data in a packet.
+fn frame(data: &str) -> String {
+    format!("${}#{:02x}", data, checksum(data.as_bytes()))
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+// Answer a command. Commands which aren't supported get an empty reply, as the protocol asks.
+fn respond(cpu: &mut CPU, command: &str) -> Response {
+    let kind = command.get(..1).unwrap_or_default();
+    let args = &command[kind.len()..];
+    let reply = match kind {
+        "?" => stop_reply(SIGTRAP),
+        "g" => hex(&registers(cpu)),
+        "m" => match parse_pair(args, ',') {
+            Some((addr, len)) => {
+                let bytes: Vec<u8> = (addr..addr + len.min(PACKET_SIZE / 2)).map(|a| cpu.peek(a)).collect();
+                hex(&bytes)
+            }
+            None => "E01".to_string(),
+        },
+        "Z" | "z" => match args.split_once(',') {
+            Some(("0" | "1", rest)) => match parse_pair(rest, ',') {
+                Some((addr, _)) => {
+                    match kind {
+                        "Z" => cpu.breakpoints_mut().insert(addr),
+                        _ => cpu.breakpoints_mut().remove(&addr),
+                    };
+                    "OK".to_string()
+                }
+                None => "E01".to_string(),
+            },
+            _ => String::new(),
+        },
+        "c" => return Response::Continue,
+        "s" => return Response::Step,
+        "D" => return Response::Detach,
+        "k" => return Response::Kill,
+        "H" => "OK".to_string(),
+        "q" if args.starts_with("Supported") => format!("PacketSize={:x}", PACKET_SIZE),
+        "q" if args == "Attached" => "1".to_string(),
+        _ => String::new(),
+    };
+    Response::Reply(reply)
+}
+
+// The registers in the order they are sent.
+fn registers(cpu: &CPU) -> Vec<u8> {
+    let registers = cpu.registers();
+    let mut bytes = registers.v.to_vec();
+    bytes.extend((registers.i as u16).to_le_bytes());
+    bytes.extend((cpu.pc() as u16).to_le_bytes());
+    bytes.extend([registers.sp as u8, registers.delay_timer, registers.sound_timer]);
+    bytes
+}
+
+// Two hex numbers separated by a character, e.g. the address and length of "m200,10".
+fn parse_pair(s: &str, separator: char) -> Option<(usize, usize)> {
+    let (a, b) = s.split_once(separator)?;
+    Some((usize::from_str_radix(a, 16).ok()?, usize::from_str_radix(b, 16).ok()?))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framing_packets() {
+        assert_eq!(frame("OK"), "$OK#9a");
+        assert_eq!(frame(""), "$#00");
+    }
+
+    #[test]
+    fn test_taking_packets() {
+        let mut buffer = b"+$g#67+$m200,2#2f\x03$?#3f$s#".to_vec();
+        let packets = take_packets(&mut buffer);
+        assert_eq!(
+            packets,
+            vec![Packet::Command("g".to_string()), Packet::Corrupt, Packet::Interrupt, Packet::Command("?".to_string())]
+        );
+        assert_eq!(buffer, b"$s#");
+
+        buffer.extend(b"73");
+        assert_eq!(take_packets(&mut buffer), vec![Packet::Command("s".to_string())]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_reading_registers_and_memory() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x60, 0x2A, 0xA3, 0x45]).unwrap();
+        cpu.cycle();
+        cpu.cycle();
+
+        let expected = format!("2a{}45030402000000", "00".repeat(15));
+        assert_eq!(respond(&mut cpu, "g"), Response::Reply(expected));
+        assert_eq!(respond(&mut cpu, "m200,3"), Response::Reply("602aa3".to_string()));
+        assert_eq!(respond(&mut cpu, "m200"), Response::Reply("E01".to_string()));
+        assert_eq!(respond(&mut cpu, "vMustReplyEmpty"), Response::Reply(String::new()));
+    }
+
+    #[test]
+    fn test_breakpoints_stop_before_the_instruction() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x70, 0x01, 0x70, 0x01, 0x12, 0x00]).unwrap();
+
+        assert_eq!(respond(&mut cpu, "Z0,202,2"), Response::Reply("OK".to_string()));
+        cpu.step_frame(100);
+        assert_eq!(cpu.take_break_hit(), Some(0x202));
+        assert_eq!((cpu.pc(), cpu.register(0)), (0x202, 1));
+
+        // Continuing passes over the breakpoint stopped at, and stops when it comes round again.
+        cpu.step_frame(100);
+        assert_eq!(cpu.take_break_hit(), Some(0x202));
+        assert_eq!((cpu.pc(), cpu.register(0)), (0x202, 3));
+
+        assert_eq!(respond(&mut cpu, "z0,202,2"), Response::Reply("OK".to_string()));
+        cpu.step_frame(100);
+        assert_eq!(cpu.take_break_hit(), None);
+    }
+}
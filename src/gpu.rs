@@ -0,0 +1,278 @@
+//! GPU frontend, for platforms where SDL is awkward. Shows the VM's frames in a winit window whose frames the pixels
+//! crate uploads and scales on the GPU with wgpu, keeping whole pixels and letterboxing the rest. The VM drives it as
+//! it does the SDL window, so the config's palette, phosphor decay, CRT look and vsync apply. Built with the `gpu`
+//! feature.
+//!
+//! Keys come from the config's keypad bindings. Esc or closing the window quits. There is no sound, debugger or
+//! hotkeys; use run for those.
+
+// Self imports
+use crate::config::{Config, DEFAULT_PALETTE};
+use crate::crt;
+use crate::display::{Display, Input};
+use crate::frame_buffer::Palette;
+
+// External imports
+use log::error;
+use pixels::wgpu::PresentMode;
+use pixels::{Pixels, PixelsBuilder, SurfaceTexture};
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event_loop::EventLoop;
+use winit::platform::run_return::EventLoopExtRunReturn;
+use winit::window::{Window, WindowBuilder};
+
+const SCALE_FACTOR: u32 = 10;
+const TITLE: &str = "Chip8 in Rust";
+
+// The window and the pixels shown in it. The pixels come first, so they're dropped before the window.
+struct Surface {
+    pixels: Pixels,
+    window: Window,
+    buffer: (usize, usize), // Size of the frame pixels is holding.
+}
+
+impl Surface {
+    fn render(&self) {
+        if let Err(e) = self.pixels.render() {
+            error!("unable to draw: {}", e);
+        }
+    }
+}
+
+/// A winit window as the VM's display.
+pub struct Gpu {
+    event_loop: EventLoop<()>,
+    surface: Option<Surface>, // Opened by init.
+    config: Config,           // For the keypad's bindings.
+    palette: Palette,
+    held: Option<u8>,                // The key last pressed, until it's let go.
+    last: (Vec<[u8; 3]>, usize),     // The frame last drawn and its width.
+    crt: bool,                       // Whether frames are shown with the CRT look.
+    vsync: bool,                     // Whether rendering waits for the monitor's refresh, leaving it to present_vsync.
+    title: (String, Option<String>), // The status and any extra text after it.
+}
+
+impl Gpu {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            event_loop: EventLoop::new(),
+            surface: None,
+            config: config.clone(),
+            palette: DEFAULT_PALETTE,
+            held: None,
+            last: (Vec::new(), 1),
+            crt: config.display.crt,
+            vsync: config.display.vsync,
+            title: (String::new(), None),
+        }
+    }
+
+    // Hand the last frame to pixels, resizing its buffer if the frame's size changed.
+    fn upload(&mut self) {
+        let surface = match &mut self.surface {
+            Some(surface) => surface,
+            None => return,
+        };
+
+        let (rgba, size) = rgba(&self.last.0, self.last.1, self.crt);
+        if size != surface.buffer {
+            if let Err(e) = surface.pixels.resize_buffer(size.0 as u32, size.1 as u32) {
+                error!("unable to resize the screen: {}", e);
+                return;
+            }
+            surface.buffer = size;
+        }
+        surface.pixels.frame_mut().copy_from_slice(&rgba);
+    }
+
+    fn update_title(&self) {
+        let (status, info) = &self.title;
+        let mut parts = vec![TITLE];
+        if !status.is_empty() {
+            parts.push(status);
+        }
+        parts.extend(info.as_deref());
+        if let Some(surface) = &self.surface {
+            surface.window.set_title(&parts.join(" | "));
+        }
+    }
+}
+
+impl Display for Gpu {
+    fn init(&mut self, columns: usize, rows: usize) -> Result<(), String> {
+        let (width, height) = (columns as u32, rows as u32);
+        let window = WindowBuilder::new()
+            .with_title(TITLE)
+            .with_inner_size(LogicalSize::new(width * SCALE_FACTOR, height * SCALE_FACTOR))
+            .with_min_inner_size(LogicalSize::new(width, height))
+            .build(&self.event_loop)
+            .map_err(|e| format!("unable to open a window: {}", e))?;
+
+        let size = window.inner_size();
+        let texture = SurfaceTexture::new(size.width, size.height, &window);
+        let present_mode = if self.vsync { PresentMode::AutoVsync } else { PresentMode::AutoNoVsync };
+        let pixels = PixelsBuilder::new(width, height, texture)
+            .present_mode(present_mode)
+            .build()
+            .map_err(|e| format!("unable to start the GPU: {}", e))?;
+
+        let buffer = (columns, rows);
+        self.surface = Some(Surface { pixels, window, buffer });
+        Ok(())
+    }
+
+    /// Handle the window's events since the last poll. Esc or closing the window closes the display.
+    fn poll(&mut self) -> Result<Input, ()> {
+        let Self { event_loop, surface, config, held, .. } = self;
+        let mut open = true;
+
+        event_loop.run_return(|event, _, control_flow| match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => open = false,
+                WindowEvent::Resized(size) => {
+                    if let Some(surface) = surface {
+                        if let Err(e) = surface.pixels.resize_surface(size.width, size.height) {
+                            error!("unable to resize the window: {}", e);
+                        }
+                        surface.window.request_redraw();
+                    }
+                }
+                WindowEvent::KeyboardInput { input: KeyboardInput { virtual_keycode: Some(code), state, .. }, .. } => {
+                    if code == VirtualKeyCode::Escape {
+                        open = false;
+                        return;
+                    }
+                    let k = match config.keypad_key(&key_name(code)) {
+                        Some(k) => k,
+                        None => return,
+                    };
+                    match state {
+                        ElementState::Pressed => *held = Some(k),
+                        ElementState::Released if *held == Some(k) => *held = None,
+                        ElementState::Released => {}
+                    }
+                }
+                _ => {}
+            },
+
+            // Frames which don't change aren't uploaded again, so the window is drawn again when it needs it.
+            Event::RedrawRequested(_) => {
+                if let Some(surface) = surface {
+                    surface.render();
+                }
+            }
+
+            // Hand back to the VM once the events waiting have been handled.
+            Event::MainEventsCleared => control_flow.set_exit(),
+
+            _ => {}
+        });
+
+        match open {
+            true => Ok(Input {
+                key: *held,
+                ..Input::default()
+            }),
+            false => Err(()),
+        }
+    }
+
+    fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// The window can be made as small as one window pixel per Chip8 pixel.
+    fn resize(&mut self, columns: usize, rows: usize) {
+        if let Some(surface) = &self.surface {
+            surface.window.set_min_inner_size(Some(LogicalSize::new(columns as u32, rows as u32)));
+        }
+    }
+
+    fn draw_rgb(&mut self, colours: &[[u8; 3]], width: usize) {
+        self.last = (colours.to_vec(), width);
+        self.upload();
+        self.redraw();
+    }
+
+    /// With vsync, the window is drawn once a refresh by present_vsync instead, as each render waits for one.
+    fn redraw(&mut self) {
+        if let (false, Some(surface)) = (self.vsync, &self.surface) {
+            surface.render();
+        }
+    }
+
+    fn set_title_status(&mut self, status: &str) {
+        self.title.0 = status.to_string();
+        self.update_title();
+    }
+
+    fn set_title_info(&mut self, info: Option<&str>) {
+        self.title.1 = info.map(str::to_string);
+        self.update_title();
+    }
+
+    fn crt(&self) -> bool {
+        self.crt
+    }
+
+    fn set_crt(&mut self, on: bool) {
+        self.crt = on;
+        self.upload();
+        self.redraw();
+    }
+
+    fn vsync(&self) -> bool {
+        self.vsync
+    }
+
+    fn present_vsync(&mut self) {
+        if let (true, Some(surface)) = (self.vsync, &self.surface) {
+            surface.render();
+        }
+    }
+}
+
+// A frame of colours, row by row, as RGBA pixels with their width and height. With the CRT look, the frame is
+// scaled up to give room for its scanlines.
+fn rgba(colours: &[[u8; 3]], width: usize, crt: bool) -> (Vec<u8>, (usize, usize)) {
+    if crt {
+        let (rgba, width, height) = crt::render(colours, width);
+        return (rgba, (width, height));
+    }
+    let rgba = colours.iter().flat_map(|[r, g, b]| [*r, *g, *b, 255]).collect();
+    (rgba, (width, colours.len() / width))
+}
+
+// SDL's name for a key, which winit names the same way except for digits.
+fn key_name(code: VirtualKeyCode) -> String {
+    let name = format!("{:?}", code);
+    match name.strip_prefix("Key") {
+        Some(digit) if digit.len() == 1 => digit.to_string(),
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_names() {
+        assert_eq!(key_name(VirtualKeyCode::X), "X");
+        assert_eq!(key_name(VirtualKeyCode::Key4), "4");
+        assert_eq!(key_name(VirtualKeyCode::Space), "Space");
+    }
+
+    #[test]
+    fn test_frames_become_rgba() {
+        let (bytes, size) = rgba(&[[1, 2, 3], [4, 5, 6]], 1, false);
+        assert_eq!(bytes, [1, 2, 3, 255, 4, 5, 6, 255]);
+        assert_eq!(size, (1, 2));
+        assert_eq!(rgba(&[[0; 3]; 64 * 32], 64, true).1, (64 * crt::SCALE, 32 * crt::SCALE));
+    }
+}
@@ -0,0 +1,92 @@
+//! Detection of programs that have finished. Only SCHIP has an instruction to stop, 00FD, so most programs end by
+//! jumping to themselves forever, or by running off the end of their code into empty memory. None of these can ever do
+//! anything again, so the CPU stops executing and the host can say the program is finished.
+//!
+//! Halts are found from the decoded instruction, as the same opcode can mean different things. 00FD is SYS 0FD on the
+//! ETI-660, and 0000, which is what empty memory decodes to, is SYS 000, so the CPU doesn't count it as a halt while
+//! a SYS callback is there to handle it.
+
+// Self imports
+use crate::decode::Instruction;
+
+// Std imports
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Halt {
+    /// SCHIP's EXIT, asking for the interpreter to close.
+    Exit { addr: usize },
+
+    /// A jump to its own address, e.g. `JP 228` at 228.
+    JumpToSelf { addr: usize },
+
+    /// A 0000 opcode, SYS 000, which is what empty memory decodes to.
+    EmptyMemory { addr: usize },
+}
+
+impl Halt {
+    /// The halt an instruction at `pc` would cause, if any.
+    pub fn at(pc: usize, instruction: Instruction) -> Option<Halt> {
+        match instruction {
+            Instruction::Exit => Some(Halt::Exit { addr: pc }),
+            Instruction::Sys { nnn: 0 } => Some(Halt::EmptyMemory { addr: pc }),
+            Instruction::Jump { nnn } if nnn == pc => Some(Halt::JumpToSelf { addr: pc }),
+            _ => None,
+        }
+    }
+
+    pub fn addr(&self) -> usize {
+        match self {
+            Halt::Exit { addr } | Halt::JumpToSelf { addr } | Halt::EmptyMemory { addr } => *addr,
+        }
+    }
+}
+
+impl fmt::Display for Halt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Halt::Exit { addr } => write!(f, "Program exited at {:03X}", addr),
+            Halt::JumpToSelf { addr } => write!(f, "Program finished: jumping to itself at {:03X}", addr),
+            Halt::EmptyMemory { addr } => write!(f, "Program finished: ran into empty memory at {:03X}", addr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::decode::decode;
+    use crate::variant::Variant;
+
+    fn halt(pc: usize, opcode: u16, variant: Variant) -> Option<Halt> {
+        Halt::at(pc, decode(opcode, variant))
+    }
+
+    #[test]
+    fn test_exit_halts() {
+        assert_eq!(halt(0x2A0, 0x00FD, Variant::Chip8), Some(Halt::Exit { addr: 0x2A0 }));
+        assert_eq!(halt(0x6A0, 0x00FD, Variant::Eti660), None);
+        assert_eq!(halt(0x3A0, 0x00FD, Variant::Chip8X), None);
+    }
+
+    #[test]
+    fn test_jump_to_self_halts() {
+        assert_eq!(halt(0x228, 0x1228, Variant::Chip8), Some(Halt::JumpToSelf { addr: 0x228 }));
+        assert_eq!(halt(0x228, 0x1200, Variant::Chip8), None);
+    }
+
+    #[test]
+    fn test_empty_memory_halts() {
+        assert_eq!(halt(0x300, 0x0000, Variant::Chip8), Some(Halt::EmptyMemory { addr: 0x300 }));
+        assert_eq!(halt(0x300, 0x00E0, Variant::Chip8), None);
+    }
+
+    #[test]
+    fn test_halts_describe_themselves() {
+        assert_eq!(
+            Halt::JumpToSelf { addr: 0x228 }.to_string(),
+            "Program finished: jumping to itself at 228"
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Host callbacks run before and after every executed instruction, for tracers, cheats and coverage tools. Hooks are
+//! given the CPU, so a cheat can rewrite registers, and the instruction with the registers as they were at that point.
+
+// Self imports
+use crate::cpu::CPU;
+use crate::decode::Instruction;
+
+// Std imports
+use std::fmt;
+
+/// Called with the CPU and the instruction being executed.
+pub type Hook = Box<dyn FnMut(&mut CPU, &Step)>;
+
+/// An opcode split into the fields instructions use.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Opcode(pub u16);
+
+impl Opcode {
+    /// The first nibble, which picks the kind of instruction.
+    pub fn kind(&self) -> u8 {
+        (self.0 >> 12) as u8
+    }
+
+    pub fn x(&self) -> usize {
+        (self.0 as usize >> 8) & 0xF
+    }
+
+    pub fn y(&self) -> usize {
+        (self.0 as usize >> 4) & 0xF
+    }
+
+    pub fn n(&self) -> u8 {
+        (self.0 & 0xF) as u8
+    }
+
+    pub fn kk(&self) -> u8 {
+        (self.0 & 0xFF) as u8
+    }
+
+    pub fn nnn(&self) -> usize {
+        self.0 as usize & 0xFFF
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04X}", self.0)
+    }
+}
+
+/// Copy of the registers at a point in time.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Registers {
+    pub v: [u8; 16],
+    pub i: usize,
+    pub sp: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+/// The instruction a hook is called for. Before hooks see the registers before it executes, after hooks after.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Step {
+    pub pc: usize,
+    pub opcode: Opcode,
+    pub instruction: Instruction,
+    pub registers: Registers,
+}
+
+#[derive(Default)]
+pub struct Hooks {
+    before: Vec<Hook>,
+    after: Vec<Hook>,
+    executed: u64, // Instructions executed, hooked or not, for speed readouts.
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty()
+    }
+
+    /// Run a hook before every instruction. Changes it makes to registers are seen by the instruction, but the
+    /// instruction has already been fetched.
+    pub fn before(&mut self, hook: impl FnMut(&mut CPU, &Step) + 'static) {
+        self.before.push(Box::new(hook));
+    }
+
+    /// Run a hook after every instruction.
+    pub fn after(&mut self, hook: impl FnMut(&mut CPU, &Step) + 'static) {
+        self.after.push(Box::new(hook));
+    }
+
+    /// Instructions the CPU has executed since it was created. Restoring a saved session keeps the hooks, so doesn't
+    /// reset it.
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    pub(crate) fn count_instruction(&mut self) {
+        self.executed += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.before.clear();
+        self.after.clear();
+    }
+
+    pub fn run_before(&mut self, cpu: &mut CPU, step: &Step) {
+        for hook in &mut self.before {
+            hook(cpu, step);
+        }
+    }
+
+    pub fn run_after(&mut self, cpu: &mut CPU, step: &Step) {
+        for hook in &mut self.after {
+            hook(cpu, step);
+        }
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hooks({} before, {} after)", self.before.len(), self.after.len())
+    }
+}
+
+// Hooks can't be compared, so sets of hooks are equal if they have as many of each kind. The count of instructions
+// executed belongs to the host, not the machine, so isn't compared either.
+impl PartialEq for Hooks {
+    fn eq(&self, other: &Self) -> bool {
+        self.before.len() == other.before.len() && self.after.len() == other.after.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::collections::BTreeSet;
+    use std::rc::Rc;
+
+    // V0 += 1, then skip the next instruction if V0 is 3, looping back otherwise.
+    #[rustfmt::skip]
+    const ROM: [u8; 10] = [
+        0x70, 0x01, // 0x200: V0 += 1.
+        0x30, 0x03, // 0x202: Skip the jump if V0 is 3.
+        0x12, 0x00, // 0x204: Jump to 0x200.
+        0x61, 0xAA, // 0x206: V1 = 0xAA.
+        0x12, 0x08, // 0x208: Jump to itself, halting.
+    ];
+
+    fn cpu() -> CPU {
+        let mut cpu = CPU::default();
+        cpu.load(ROM.to_vec()).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn test_decoding_opcodes() {
+        let opcode = Opcode(0xD12F);
+        assert_eq!((opcode.kind(), opcode.x(), opcode.y(), opcode.n()), (0xD, 1, 2, 0xF));
+        assert_eq!((opcode.kk(), opcode.nnn()), (0x2F, 0x12F));
+        assert_eq!(opcode.to_string(), "D12F");
+    }
+
+    #[test]
+    fn test_counting_instructions() {
+        let loaded = cpu();
+        let mut cpu = cpu();
+        cpu.step_frame(5);
+        assert_eq!(cpu.hooks().executed(), 5);
+
+        // Swapping in another machine, e.g. from a save state, doesn't reset the count.
+        cpu.restore(loaded);
+        cpu.step_frame(5);
+        assert_eq!(cpu.hooks().executed(), 10);
+    }
+
+    #[test]
+    fn test_tracing_instructions() {
+        let trace = Rc::new(RefCell::new(Vec::new()));
+        let mut cpu = cpu();
+
+        let before = trace.clone();
+        cpu.hooks_mut().before(move |_, step| {
+            before.borrow_mut().push((step.pc, step.opcode, step.registers.v[0]));
+        });
+        for _ in 0..4 {
+            cpu.cycle();
+        }
+
+        let expected = vec![
+            (0x200, Opcode(0x7001), 0),
+            (0x202, Opcode(0x3003), 1),
+            (0x204, Opcode(0x1200), 1),
+            (0x200, Opcode(0x7001), 1),
+        ];
+        assert_eq!(*trace.borrow(), expected);
+    }
+
+    #[test]
+    fn test_after_hooks_see_results() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let mut cpu = cpu();
+
+        let after = seen.clone();
+        cpu.hooks_mut().after(move |_, step| after.borrow_mut().push(step.registers.v[0]));
+        cpu.step_frame(2);
+
+        assert_eq!(*seen.borrow(), vec![1, 1]);
+    }
+
+    #[test]
+    fn test_cheat_rewrites_registers() {
+        // Keep V0 at 2 before every add, so the loop exits straight away.
+        let mut cpu = cpu();
+        cpu.hooks_mut().before(|cpu, step| {
+            if let Instruction::AddByte { .. } = step.instruction {
+                cpu.set_register(0, 2);
+            }
+        });
+        cpu.step_frame(4);
+
+        assert_eq!(cpu.register(1), 0xAA);
+    }
+
+    #[test]
+    fn test_coverage() {
+        let covered = Rc::new(RefCell::new(BTreeSet::new()));
+        let mut cpu = cpu();
+
+        let after = covered.clone();
+        cpu.hooks_mut().after(move |_, step| {
+            after.borrow_mut().insert(step.pc);
+        });
+        cpu.step_frame(20);
+
+        // The jump to itself halts the program rather than executing.
+        assert_eq!(covered.borrow().iter().copied().collect::<Vec<_>>(), vec![0x200, 0x202, 0x204, 0x206]);
+    }
+}
@@ -0,0 +1,238 @@
+//! Per-address execution counts, so ROM authors can find the loops their programs spend their time in. Where
+//! opcode statistics say which kinds of instruction run most, this says which instructions. The report lists the
+//! hottest addresses, the hottest loops, found from the backward jumps taken, and a heatmap of the program:
+//!
+//! ```text
+//! 200  @@@@@@@%%%%#....
+//! ```
+//!
+//! with one character per two bytes, from `.` for rarely executed to `@` for the hottest. Addresses never executed
+//! are blank. Timing is optional, as for opcode statistics.
+
+// Self imports
+use crate::cpu::CPU;
+use crate::hooks::Step;
+
+// Std imports
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// Heatmap characters, from least to most executed.
+const SHADES: &[u8] = b" .:-=+*#%@";
+
+// Bytes on each line of the heatmap. Each character covers two.
+const BYTES_PER_LINE: usize = 64;
+
+/// Totals for one address.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Spot {
+    pub count: u64,
+    pub nanos: u64,
+}
+
+/// A backward jump taken, and how often.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Loop {
+    pub start: usize, // Where the jump goes.
+    pub end: usize,   // The jump.
+    pub iterations: u64,
+    pub instructions: u64, // Executed between start and end, including the jump.
+}
+
+pub struct Hotspots {
+    spots: HashMap<usize, Spot>,
+    jumps: HashMap<(usize, usize), u64>, // Backward jumps taken, by target and address.
+    timed: bool,
+    started: Option<Instant>, // When the current instruction started, when timing.
+}
+
+impl Hotspots {
+    pub fn new(timed: bool) -> Self {
+        Self {
+            spots: HashMap::new(),
+            jumps: HashMap::new(),
+            timed,
+            started: None,
+        }
+    }
+
+    /// Start counting every instruction the CPU executes.
+    pub fn attach(cpu: &mut CPU, timed: bool) -> Rc<RefCell<Self>> {
+        let hotspots = Rc::new(RefCell::new(Self::new(timed)));
+
+        if timed {
+            let before = hotspots.clone();
+            cpu.hooks_mut().before(move |_, _| before.borrow_mut().started = Some(Instant::now()));
+        }
+
+        let after = hotspots.clone();
+        cpu.hooks_mut().after(move |cpu, step| after.borrow_mut().record(step, cpu.pc()));
+
+        hotspots
+    }
+
+    fn record(&mut self, step: &Step, next: usize) {
+        let spot = self.spots.entry(step.pc).or_default();
+        spot.count += 1;
+        if let Some(started) = self.started.take() {
+            spot.nanos += started.elapsed().as_nanos() as u64;
+        }
+
+        // JP and JP V0 going backwards close a loop. Returns and skips don't.
+        if matches!(step.opcode.kind(), 0x1 | 0xB) && next <= step.pc {
+            *self.jumps.entry((next, step.pc)).or_default() += 1;
+        }
+    }
+
+    /// Times the instruction at an address has been executed.
+    pub fn count(&self, addr: usize) -> u64 {
+        self.spots.get(&addr).map_or(0, |spot| spot.count)
+    }
+
+    /// Instructions executed.
+    pub fn total(&self) -> u64 {
+        self.spots.values().map(|spot| spot.count).sum()
+    }
+
+    /// The most executed addresses, most executed first.
+    pub fn hottest(&self, n: usize) -> Vec<(usize, Spot)> {
+        let mut spots: Vec<(usize, Spot)> = self.spots.iter().map(|(&addr, &spot)| (addr, spot)).collect();
+        spots.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(&b.0)));
+        spots.truncate(n);
+        spots
+    }
+
+    /// The loops which executed the most instructions, most first.
+    pub fn loops(&self, n: usize) -> Vec<Loop> {
+        let mut loops: Vec<Loop> = self
+            .jumps
+            .iter()
+            .map(|(&(start, end), &iterations)| Loop {
+                start,
+                end,
+                iterations,
+                instructions: (start..=end).map(|addr| self.count(addr)).sum(),
+            })
+            .collect();
+        loops.sort_by(|a, b| b.instructions.cmp(&a.instructions).then(a.start.cmp(&b.start)));
+        loops.truncate(n);
+        loops
+    }
+
+    /// The heatmap, from the line holding the first address executed to the one holding the last.
+    pub fn heatmap(&self) -> String {
+        let (first, last) = match (self.spots.keys().min(), self.spots.keys().max()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return String::new(),
+        };
+        let max = self.spots.values().map(|spot| spot.count).max().unwrap_or(0);
+
+        let mut out = String::new();
+        for line in (first - first % BYTES_PER_LINE..=last).step_by(BYTES_PER_LINE) {
+            let shades: String = (line..line + BYTES_PER_LINE)
+                .step_by(2)
+                .map(|addr| shade(self.count(addr).max(self.count(addr + 1)), max))
+                .collect();
+            out += &format!("{:03X}  {}\n", line, shades.trim_end());
+        }
+        out
+    }
+
+    /// The report, with the n hottest addresses and loops disassembled from the CPU's memory.
+    pub fn report(&self, cpu: &CPU, n: usize) -> String {
+        let total = self.total();
+        let share = |count: u64| 100.0 * count as f64 / total.max(1) as f64;
+        let mut out = format!("{} instructions executed\n", total);
+
+        out += "Hottest addresses:\n";
+        for (addr, spot) in self.hottest(n) {
+            let line = cpu.disassemble(addr, 1).first().map(ToString::to_string).unwrap_or_default();
+            out += &format!("  {:<30}{:>12}{:>7.1}%", line, spot.count, share(spot.count));
+            match self.timed {
+                true => out += &format!("{:>12?}\n", Duration::from_nanos(spot.nanos)),
+                false => out += "\n",
+            }
+        }
+
+        out += "Hottest loops:\n";
+        for l in self.loops(n) {
+            out += &format!(
+                "  {:03X}-{:03X}  {:>12} instructions{:>7.1}%{:>10} iterations\n",
+                l.start,
+                l.end,
+                l.instructions,
+                share(l.instructions),
+                l.iterations
+            );
+        }
+
+        out + "Heatmap:\n" + &self.heatmap()
+    }
+}
+
+// A character for how often an address was executed compared with the hottest. Log scaled, as a few loops usually
+// take nearly all the time.
+fn shade(count: u64, max: u64) -> char {
+    let level = match (count, max) {
+        (0, _) => 0,
+        (_, 0 | 1) => SHADES.len() - 1,
+        _ => 1 + ((count as f64).ln() / (max as f64).ln() * (SHADES.len() - 2) as f64).round() as usize,
+    };
+    SHADES[level.min(SHADES.len() - 1)] as char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Adds to V0 in an inner loop run five times, then jumps back to the start.
+    const ROM: [u8; 12] = [0x61, 0x05, 0x70, 0x01, 0x71, 0xFF, 0x31, 0x00, 0x12, 0x02, 0x12, 0x00];
+
+    fn run(instructions: usize) -> (CPU, Rc<RefCell<Hotspots>>) {
+        let mut cpu = CPU::default();
+        cpu.load(ROM.to_vec()).unwrap();
+        let hotspots = Hotspots::attach(&mut cpu, false);
+        cpu.step_frame(instructions);
+        (cpu, hotspots)
+    }
+
+    #[test]
+    fn test_counting_addresses() {
+        let (_, hotspots) = run(21);
+        let hotspots = hotspots.borrow();
+
+        assert_eq!(hotspots.total(), 21);
+        assert_eq!((hotspots.count(0x200), hotspots.count(0x202), hotspots.count(0x208)), (1, 5, 4));
+        assert_eq!(hotspots.hottest(1), vec![(0x202, Spot { count: 5, nanos: 0 })]);
+    }
+
+    #[test]
+    fn test_finding_loops() {
+        let (_, hotspots) = run(42);
+        let loops = hotspots.borrow().loops(2);
+
+        assert_eq!(loops.len(), 2);
+        assert_eq!((loops[0].start, loops[0].end, loops[0].iterations), (0x200, 0x20A, 2));
+        assert_eq!((loops[1].start, loops[1].end, loops[1].iterations), (0x202, 0x208, 8));
+    }
+
+    #[test]
+    fn test_report() {
+        let (cpu, hotspots) = run(21);
+        let report = hotspots.borrow().report(&cpu, 1);
+
+        assert!(report.starts_with("21 instructions executed\nHottest addresses:\n  202  7001  ADD V0, 01"));
+        assert!(report.contains("Hottest loops:\n  200-20A            21 instructions  100.0%         1 iterations\n"));
+        assert!(report.ends_with("Heatmap:\n200  .@@@%.\n"));
+    }
+
+    #[test]
+    fn test_shades() {
+        assert_eq!(shade(0, 100), ' ');
+        assert_eq!(shade(1, 100), '.');
+        assert_eq!(shade(100, 100), '@');
+        assert_eq!(shade(1, 1), '@');
+    }
+}
@@ -0,0 +1,170 @@
+//! On-screen debug HUD, toggled with the hud hotkey. Shows the frame rate, the instructions executed per second, the
+//! PC, I, the timers and the next instruction in the corner of the screen:
+//!
+//! ```text
+//! FPS 60.0  IPS 660
+//! PC 20A  I 2F0
+//! DT 00  ST 00
+//! 20A  D015  DRW V0, V1, 5
+//! ```
+//!
+//! The display driver draws the text over each frame with a small built in font, as there is no text rendering.
+
+// Self imports
+use crate::cpu::CPU;
+use crate::debugger;
+
+// Std imports
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// How far back frame and instruction rates are averaged over.
+const WINDOW: Duration = Duration::from_secs(1);
+
+// Glyphs are 3x5 pixels. Each gets a column of space after it, and lines a row above and below.
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+const CELL_WIDTH: usize = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 2;
+
+const TEXT: [u8; 4] = [0xFA, 0xFA, 0xFA, 0xFF];
+const BACKGROUND: [u8; 4] = [0x00, 0x00, 0x00, 0xA0];
+
+// Rows of each glyph, top first, with the leftmost pixel in bit 2. Anything else is drawn as '?'.
+#[rustfmt::skip]
+const GLYPHS: &[(char, [u8; 5])] = &[
+    ('0', [7, 5, 5, 5, 7]), ('1', [2, 6, 2, 2, 7]), ('2', [7, 1, 7, 4, 7]), ('3', [7, 1, 7, 1, 7]),
+    ('4', [5, 5, 7, 1, 1]), ('5', [7, 4, 7, 1, 7]), ('6', [7, 4, 7, 5, 7]), ('7', [7, 1, 1, 1, 1]),
+    ('8', [7, 5, 7, 5, 7]), ('9', [7, 5, 7, 1, 7]), ('A', [2, 5, 7, 5, 5]), ('B', [6, 5, 6, 5, 6]),
+    ('C', [3, 4, 4, 4, 3]), ('D', [6, 5, 5, 5, 6]), ('E', [7, 4, 6, 4, 7]), ('F', [7, 4, 6, 4, 4]),
+    ('G', [3, 4, 5, 5, 3]), ('H', [5, 5, 7, 5, 5]), ('I', [7, 2, 2, 2, 7]), ('J', [1, 1, 1, 5, 2]),
+    ('K', [5, 5, 6, 5, 5]), ('L', [4, 4, 4, 4, 7]), ('M', [5, 7, 7, 5, 5]), ('N', [6, 5, 5, 5, 5]),
+    ('O', [2, 5, 5, 5, 2]), ('P', [6, 5, 6, 4, 4]), ('Q', [2, 5, 5, 6, 3]), ('R', [6, 5, 6, 5, 5]),
+    ('S', [3, 4, 2, 1, 6]), ('T', [7, 2, 2, 2, 2]), ('U', [5, 5, 5, 5, 7]), ('V', [5, 5, 5, 5, 2]),
+    ('W', [5, 5, 7, 7, 5]), ('X', [5, 5, 2, 5, 5]), ('Y', [5, 5, 2, 2, 2]), ('Z', [7, 1, 2, 4, 7]),
+    (' ', [0, 0, 0, 0, 0]), (':', [0, 2, 0, 2, 0]), (',', [0, 0, 0, 2, 4]), ('.', [0, 0, 0, 0, 2]),
+    ('-', [0, 0, 7, 0, 0]), ('+', [0, 2, 7, 2, 0]), ('=', [0, 7, 0, 7, 0]), ('/', [1, 1, 2, 4, 4]),
+    ('[', [6, 4, 4, 4, 6]), (']', [3, 1, 1, 1, 3]), ('>', [4, 2, 1, 2, 4]), ('_', [0, 0, 0, 0, 7]),
+    ('?', [7, 1, 2, 0, 2]),
+];
+
+/// Frame and instruction counts over the last second, for the rates shown.
+#[derive(Default)]
+pub struct Hud {
+    samples: VecDeque<(Instant, u64)>, // When each frame ended and how many instructions had been executed by then.
+}
+
+impl Hud {
+    /// Note the end of a frame.
+    pub fn sample(&mut self, now: Instant, executed: u64) {
+        self.samples.push_back((now, executed));
+        while let Some(&(oldest, _)) = self.samples.front() {
+            match now.duration_since(oldest) > WINDOW {
+                true => drop(self.samples.pop_front()),
+                false => break,
+            }
+        }
+    }
+
+    /// Frames and instructions per second.
+    pub fn rates(&self) -> (f64, f64) {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((start, first)), Some((end, last))) if end > start => {
+                let seconds = end.duration_since(*start).as_secs_f64();
+                ((self.samples.len() - 1) as f64 / seconds, (last - first) as f64 / seconds)
+            }
+            _ => (0.0, 0.0),
+        }
+    }
+
+    pub fn lines(&self, cpu: &CPU) -> Vec<String> {
+        let (fps, ips) = self.rates();
+        let registers = cpu.registers();
+        vec![
+            format!("FPS {:.1}  IPS {:.0}", fps, ips),
+            format!("PC {:03X}  I {:03X}", cpu.pc(), registers.i),
+            format!("DT {:02X}  ST {:02X}", registers.delay_timer, registers.sound_timer),
+            debugger::next_instruction(cpu).trim_end().to_string(),
+        ]
+    }
+}
+
+/// Width and height in pixels of text with some columns and lines.
+pub fn size(columns: usize, lines: usize) -> (usize, usize) {
+    (columns * CELL_WIDTH + 1, lines * LINE_HEIGHT)
+}
+
+/// Draw lines of text as RGBA pixels on a translucent background. Returns the pixels, width and height.
+pub fn render(lines: &[String]) -> (Vec<u8>, usize, usize) {
+    let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+    let (width, height) = size(columns, lines.len());
+    let mut rgba = BACKGROUND.repeat(width * height);
+
+    for (row, line) in lines.iter().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let glyph = glyph(c.to_ascii_uppercase());
+            for (y, bits) in glyph.iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if bits & (4 >> x) != 0 {
+                        let pixel = (row * LINE_HEIGHT + 1 + y) * width + 1 + column * CELL_WIDTH + x;
+                        rgba[pixel * 4..pixel * 4 + 4].copy_from_slice(&TEXT);
+                    }
+                }
+            }
+        }
+    }
+
+    (rgba, width, height)
+}
+
+fn glyph(c: char) -> [u8; 5] {
+    let find = |c| GLYPHS.iter().find(|(glyph, _)| *glyph == c).map(|(_, rows)| *rows);
+    find(c).or_else(|| find('?')).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates_over_the_last_second() {
+        let mut hud = Hud::default();
+        let start = Instant::now();
+        for frame in 0..=120 {
+            hud.sample(start + Duration::from_millis(frame * 50), frame * 100);
+        }
+
+        // Only the last second's 20 frames count.
+        let (fps, ips) = hud.rates();
+        assert!((fps - 20.0).abs() < 0.01);
+        assert!((ips - 2000.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0xA2, 0xF0, 0xD0, 0x15]).unwrap();
+        cpu.cycle();
+
+        let lines = Hud::default().lines(&cpu);
+        assert_eq!(lines[1..], ["PC 202  I 2F0", "DT 00  ST 00", "202  D015  DRW V0, V1, 5"]);
+    }
+
+    #[test]
+    fn test_rendering_text() {
+        let (rgba, width, height) = render(&["1".to_string(), "AB".to_string()]);
+        assert_eq!((width, height), (9, 14));
+        assert_eq!(rgba.len(), width * height * 4);
+
+        // The top row of the 1 has only its middle pixel lit.
+        let lit = |x: usize, y: usize| rgba[(y * width + x) * 4..][..4] == TEXT;
+        assert_eq!((lit(1, 1), lit(2, 1), lit(3, 1)), (false, true, false));
+        assert!(!lit(0, 0));
+    }
+
+    #[test]
+    fn test_unknown_characters_are_question_marks() {
+        assert_eq!(glyph('~'), glyph('?'));
+        assert_eq!(glyph('0'), [7, 5, 5, 5, 7]);
+    }
+}
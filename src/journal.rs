@@ -0,0 +1,517 @@
+//! A log of the changes a running CPU makes to its state, which can be undone and redone. Instructions, timer ticks
+//! and the host's key presses change the CPU through `Change`s, which it applies and, while recording, keeps. Each
+//! instruction's changes start with a mark, so they can be told apart.
+//!
+//! Everything which looks back over a run uses it. Rewinding undoes it a frame at a time, and the debugger's back and
+//! forward commands an instruction at a time, replaying exactly what was undone. Rolling back to apply input which
+//! arrived late is undoing frames then running forward again, which drops the old future. The crash trace shows what
+//! each instruction changed from it. What isn't built on it: `--trace` logs the registers before each instruction, in
+//! the format other emulators use, and suspend files and save states are whole snapshots as they must stand alone.
+//!
+//! Drawing, clearing and scrolling are noted a word at a time by the frame buffer as it writes them. The RNG, SCHIP's
+//! screen switches, the CHIP-8X colour zones and MegaChip's state are kept whole, copied either side of the change.
+//!
+//! Recording is off unless the host starts it. When off, a change is applied and dropped, and nothing is allocated.
+//! When on, changes which leave a value as it was aren't kept, so a frame's worth is usually a few hundred bytes. The
+//! journal drops its oldest frames to stay within a memory budget. A bench scenario's journal option measures the
+//! cost.
+
+// Self imports
+use crate::chip8x::ColourZones;
+use crate::cpu::{State, CPU};
+use crate::frame_buffer::FrameBuffer;
+#[cfg(feature = "megachip")]
+use crate::megachip::MegaChip;
+use crate::random::Random;
+
+// Std imports
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem;
+
+// External imports
+use serde::{Deserialize, Serialize};
+
+/// Memory the default journal may use. Enough for several minutes of a typical ROM.
+pub const BUDGET: usize = 16 << 20;
+
+/// One change to the CPU's state, holding the value before and after so it can be applied in either direction.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum Change {
+    /// Not a change itself, but marks where the changes made by the instruction at pc start. Lets the debugger undo
+    /// one instruction at a time.
+    Instruction { pc: u32 },
+
+    Register { x: u8, before: u8, after: u8 },
+    I { before: usize, after: usize },
+    Pc { before: usize, after: usize },
+    Sp { before: usize, after: usize },
+    Stack { index: u8, before: usize, after: usize },
+    DelayTimer { before: u8, after: u8 },
+    SoundTimer { before: u8, after: u8 },
+    State(Box<(State, State)>),
+    CycleDebt { before: u32, after: u32 },
+    PortOut { before: u8, after: u8 },
+
+    /// Keys held on a keypad. Pad 1 is the CHIP-8X second keypad.
+    Keys { pad: u8, before: u16, after: u16 },
+    Memory { addr: u32, before: u8, after: u8 },
+    Row { plane: u8, row: u16, before: u64, after: u64 },
+
+    /// XO-CHIP's PLANE n changed which planes are drawn to.
+    Planes { before: u8, after: u8 },
+
+    /// CXKK advanced the random number generator. Host-provided generators aren't recorded.
+    Rng(Box<(Random, Random)>),
+
+    /// SCHIP switched between lores and hires, resizing and clearing the screen. Both screens are kept whole.
+    Screen(Box<(FrameBuffer, FrameBuffer)>),
+
+    /// The CHIP-8X colour zones changed. Kept whole as they are small and rarely change.
+    Zones(Box<(ColourZones, ColourZones)>),
+
+    /// The MegaChip state changed. Kept whole, so MegaChip ROMs fill the journal much faster.
+    #[cfg(feature = "megachip")]
+    MegaChip(Box<(MegaChip, MegaChip)>),
+}
+
+impl Change {
+    /// True if applying the change would leave the state as it was, so it needn't be recorded.
+    pub fn unchanged(&self) -> bool {
+        match *self {
+            Change::Register { before, after, .. } => before == after,
+            Change::I { before, after } | Change::Pc { before, after } => before == after,
+            Change::Sp { before, after } => before == after,
+            Change::Stack { before, after, .. } => before == after,
+            Change::DelayTimer { before, after } | Change::SoundTimer { before, after } => before == after,
+            Change::State(ref states) => states.0 == states.1,
+            Change::CycleDebt { before, after } => before == after,
+            Change::PortOut { before, after } | Change::Planes { before, after } => before == after,
+            Change::Keys { before, after, .. } => before == after,
+            Change::Memory { before, after, .. } => before == after,
+            Change::Row { before, after, .. } => before == after,
+            _ => false,
+        }
+    }
+
+    /// Approximate memory used, for keeping the journal within its budget.
+    fn size(&self) -> usize {
+        let boxed = match self {
+            Change::State(_) => mem::size_of::<(State, State)>(),
+            Change::Rng(_) => mem::size_of::<(Random, Random)>(),
+            Change::Screen(screens) => 2 * bincode::serialized_size(&screens.0).unwrap_or(0) as usize,
+            Change::Zones(zones) => 2 * bincode::serialized_size(&zones.0).unwrap_or(0) as usize,
+            #[cfg(feature = "megachip")]
+            Change::MegaChip(mega) => 2 * bincode::serialized_size(&mega.0).unwrap_or(0) as usize,
+            _ => 0,
+        };
+        mem::size_of::<Change>() + boxed
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Instruction { pc } => write!(f, "EXEC {:03X}", pc),
+            Change::Register { x, before, after } => write!(f, "V{:X} {:02X} -> {:02X}", x, before, after),
+            Change::I { before, after } => write!(f, "I {:03X} -> {:03X}", before, after),
+            Change::Pc { before, after } => write!(f, "PC {:03X} -> {:03X}", before, after),
+            Change::Sp { before, after } => write!(f, "SP {} -> {}", before, after),
+            Change::Stack { index, before, after } => write!(f, "STACK[{}] {:03X} -> {:03X}", index, before, after),
+            Change::DelayTimer { before, after } => write!(f, "DT {} -> {}", before, after),
+            Change::SoundTimer { before, after } => write!(f, "ST {} -> {}", before, after),
+            Change::State(states) => write!(f, "STATE {:?} -> {:?}", states.0, states.1),
+            Change::CycleDebt { before, after } => write!(f, "DEBT {} -> {}", before, after),
+            Change::PortOut { before, after } => write!(f, "OUT {:02X} -> {:02X}", before, after),
+            Change::Keys { pad, before, after } => write!(f, "KEYS{} {:04X} -> {:04X}", pad, before, after),
+            Change::Memory { addr, before, after } => write!(f, "[{:03X}] {:02X} -> {:02X}", addr, before, after),
+            Change::Row { plane, row, before, after } => {
+                write!(f, "ROW {}.{} {:016X} -> {:016X}", plane, row, before, after)
+            }
+            Change::Planes { before, after } => write!(f, "PLANES {} -> {}", before, after),
+            Change::Rng(_) => write!(f, "RNG"),
+            Change::Screen(screens) => write!(
+                f,
+                "SCREEN {}x{} -> {}x{}",
+                screens.0.width(),
+                screens.0.height(),
+                screens.1.width(),
+                screens.1.height()
+            ),
+            Change::Zones(_) => write!(f, "ZONES"),
+            #[cfg(feature = "megachip")]
+            Change::MegaChip(_) => write!(f, "MEGACHIP"),
+        }
+    }
+}
+
+/// Held by a CPU while recording. Collects changes as they are made, and copies of anything kept whole taken before an
+/// instruction first changed it.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Recorder {
+    pub changes: Vec<Change>,
+    pub zones: Option<Box<ColourZones>>,
+    #[cfg(feature = "megachip")]
+    pub megachip: Option<Box<MegaChip>>,
+}
+
+impl Recorder {
+    /// Called before the colour zones are changed.
+    pub fn touch_zones(&mut self, zones: &ColourZones) {
+        if self.zones.is_none() {
+            self.zones = Some(Box::new(zones.clone()));
+        }
+    }
+
+    /// Called before the MegaChip state is changed.
+    #[cfg(feature = "megachip")]
+    pub fn touch_megachip(&mut self, megachip: &MegaChip) {
+        if self.megachip.is_none() {
+            self.megachip = Some(Box::new(megachip.clone()));
+        }
+    }
+}
+
+/// Recorded frames of changes, oldest first, and frames which have been undone and can be redone. Kept in suspend
+/// files so a resumed session can still be rewound.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Journal {
+    frames: VecDeque<Vec<Change>>,
+    undone: Vec<Vec<Change>>,
+    size: usize,
+    budget: usize,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self::new(BUDGET)
+    }
+}
+
+impl Journal {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            undone: Vec::new(),
+            size: 0,
+            budget,
+        }
+    }
+
+    /// Frames which can be undone.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Approximate memory used by the recorded frames.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Memory the recorded frames may use before the oldest are dropped.
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Recorded frames, oldest first.
+    pub fn frames(&self) -> impl Iterator<Item = &[Change]> {
+        self.frames.iter().map(|frame| frame.as_slice())
+    }
+
+    /// The changes made by the most recently recorded frame.
+    pub fn last(&self) -> Option<&[Change]> {
+        self.frames.back().map(|frame| frame.as_slice())
+    }
+
+    /// Called after every frame. Takes the CPU's changes as a new frame, dropping the oldest frames if over budget.
+    /// Anything undone can no longer be redone.
+    pub fn record(&mut self, cpu: &mut CPU) {
+        self.undone.clear();
+        self.push(cpu.take_changes());
+    }
+
+    /// Undo the most recent frame. Returns false if there is nothing left to undo.
+    pub fn undo(&mut self, cpu: &mut CPU) -> bool {
+        self.take_pending(cpu);
+        match self.pop() {
+            Some(frame) => {
+                cpu.undo(&frame);
+                self.undone.push(frame);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Undo the last instruction executed, whether it was stepped in the debugger or run as part of a frame. The rest
+    /// of its frame stays recorded, and frames which executed nothing, e.g. while waiting for a key, are passed over.
+    /// Returns false if there is nothing left to undo.
+    pub fn undo_instruction(&mut self, cpu: &mut CPU) -> bool {
+        self.take_pending(cpu);
+        while let Some(mut frame) = self.pop() {
+            let start = frame.iter().rposition(|change| matches!(change, Change::Instruction { .. }));
+            let changes = frame.split_off(start.unwrap_or(0));
+            cpu.undo(&changes);
+            self.undone.push(changes);
+
+            if !frame.is_empty() {
+                self.push(frame);
+            }
+            if start.is_some() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Redo the most recently undone frame. Returns false if there is nothing to redo.
+    pub fn redo(&mut self, cpu: &mut CPU) -> bool {
+        match self.undone.pop() {
+            Some(frame) => {
+                cpu.redo(&frame);
+                self.push(frame);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Redo the most recently undone instruction, or frame if it was undone a frame at a time, replaying exactly what
+    /// it did the first time. Returns false if there is no instruction to redo.
+    pub fn redo_instruction(&mut self, cpu: &mut CPU) -> bool {
+        while let Some(changes) = self.undone.pop() {
+            let instruction = changes.iter().any(|change| matches!(change, Change::Instruction { .. }));
+            cpu.redo(&changes);
+            self.push(changes);
+            if instruction {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Forget everything. Needed when the CPU is replaced, e.g. by loading a save state, as the changes no longer
+    /// lead to it.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.undone.clear();
+        self.size = 0;
+    }
+
+    // Changes made since the last frame was recorded are part of it, or there would be no way to undo them. Anything
+    // undone can no longer be redone, as they don't follow on from it.
+    fn take_pending(&mut self, cpu: &mut CPU) {
+        let pending = cpu.take_changes();
+        if !pending.is_empty() {
+            self.undone.clear();
+            match self.frames.back_mut() {
+                Some(frame) => {
+                    self.size += pending.iter().map(Change::size).sum::<usize>();
+                    frame.extend(pending);
+                }
+                None => self.push(pending),
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<Vec<Change>> {
+        let frame = self.frames.pop_back()?;
+        self.size -= frame.iter().map(Change::size).sum::<usize>();
+        Some(frame)
+    }
+
+    fn push(&mut self, frame: Vec<Change>) {
+        self.size += frame.iter().map(Change::size).sum::<usize>();
+        self.frames.push_back(frame);
+
+        while self.size > self.budget && self.frames.len() > 1 {
+            if let Some(oldest) = self.frames.pop_front() {
+                self.size -= oldest.iter().map(Change::size).sum::<usize>();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pattern;
+
+    fn recording_cpu() -> CPU {
+        let mut cpu = CPU::default();
+        cpu.seed_rng(7);
+        cpu.load(test_pattern::generate()).unwrap();
+        cpu.start_recording();
+        cpu
+    }
+
+    // Run frames, recording each, and return the state hash after each one.
+    fn run(cpu: &mut CPU, journal: &mut Journal, frames: usize) -> Vec<u64> {
+        (0..frames)
+            .map(|_| {
+                cpu.step_frame(9);
+                journal.record(cpu);
+                cpu.state_hash()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_changes_stay_small() {
+        // Every instruction pushes a handful of these, so they must stay cheap to copy.
+        assert!(mem::size_of::<Change>() <= 32, "Change is {} bytes", mem::size_of::<Change>());
+    }
+
+    #[test]
+    fn test_undo_and_redo_every_frame() {
+        let mut cpu = recording_cpu();
+        let mut journal = Journal::default();
+        let start = cpu.state_hash();
+        let hashes = run(&mut cpu, &mut journal, 200);
+
+        for frame in (0..199).rev() {
+            assert!(journal.undo(&mut cpu));
+            assert_eq!(cpu.state_hash(), hashes[frame], "frame {}", frame);
+        }
+        assert!(journal.undo(&mut cpu));
+        assert_eq!(cpu.state_hash(), start);
+        assert!(!journal.undo(&mut cpu));
+
+        while journal.redo(&mut cpu) {}
+        assert_eq!(cpu.state_hash(), hashes[199]);
+    }
+
+    #[test]
+    fn test_undoing_one_instruction_at_a_time() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x60, 0x01, 0x61, 0x02, 0xA3, 0x00]).unwrap();
+        cpu.start_recording();
+        let mut journal = Journal::default();
+
+        cpu.step_frame(3);
+        journal.record(&mut cpu);
+        let marks = journal.last().unwrap().iter().filter(|change| matches!(change, Change::Instruction { .. }));
+        assert_eq!(marks.count(), 3);
+
+        assert!(journal.undo_instruction(&mut cpu));
+        assert_eq!((cpu.pc(), cpu.registers().i), (0x204, 0));
+        assert_eq!(cpu.register(1), 2);
+        assert!(journal.undo_instruction(&mut cpu));
+        assert_eq!((cpu.pc(), cpu.register(1)), (0x202, 0));
+
+        assert!(journal.redo_instruction(&mut cpu));
+        assert_eq!((cpu.pc(), cpu.register(1)), (0x204, 2));
+
+        // Running on means what was undone can't be redone.
+        cpu.cycle();
+        journal.record(&mut cpu);
+        assert!(!journal.redo_instruction(&mut cpu));
+    }
+
+    #[test]
+    fn test_undoing_plane_selection() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0xF3, 0x01, 0xF2, 0x01]).unwrap();
+        cpu.start_recording();
+        let mut journal = Journal::default();
+
+        for _ in 0..2 {
+            cpu.step_frame(1);
+            journal.record(&mut cpu);
+        }
+        assert_eq!(cpu.frame().selected_planes(), 0b10);
+
+        assert!(journal.undo(&mut cpu));
+        assert_eq!(cpu.frame().selected_planes(), 0b11);
+        assert!(journal.undo(&mut cpu));
+        assert_eq!(cpu.frame().selected_planes(), 0b01);
+        assert!(journal.redo(&mut cpu));
+        assert_eq!(cpu.frame().selected_planes(), 0b11);
+    }
+
+    #[test]
+    fn test_undoing_screen_switches() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0xA0, 0x00, 0xD0, 0x05, 0x00, 0xFF, 0xD0, 0x05]).unwrap();
+        cpu.start_recording();
+        let mut journal = Journal::default();
+
+        let mut hashes = vec![cpu.state_hash()];
+        for _ in 0..4 {
+            cpu.step_frame(1);
+            journal.record(&mut cpu);
+            hashes.push(cpu.state_hash());
+        }
+        assert!(cpu.hires());
+
+        for frame in (0..4).rev() {
+            assert!(journal.undo(&mut cpu));
+            assert_eq!(cpu.state_hash(), hashes[frame], "frame {}", frame);
+        }
+        assert!(!cpu.hires());
+
+        while journal.redo(&mut cpu) {}
+        assert_eq!(cpu.state_hash(), hashes[4]);
+        assert!(cpu.hires());
+    }
+
+    #[test]
+    fn test_rollback_with_different_input() {
+        let mut cpu = recording_cpu();
+        let mut journal = Journal::default();
+        run(&mut cpu, &mut journal, 30);
+
+        // Something happens 5 frames late, e.g. a remote player's key. Roll back, apply it and run forward again.
+        let before_key = run(&mut cpu, &mut journal, 5)[0];
+        for _ in 0..5 {
+            journal.undo(&mut cpu);
+        }
+        cpu.set_key(1);
+        cpu.step_frame(9);
+        journal.record(&mut cpu);
+
+        assert_eq!(cpu.state_hash(), before_key); // The pattern ignores the keypad, so only the keys differ.
+        assert!(journal.last().unwrap().contains(&Change::Keys { pad: 0, before: 0, after: 2 }));
+        assert!(!journal.redo(&mut cpu)); // The old future is gone.
+    }
+
+    #[test]
+    fn test_oldest_frames_dropped_over_budget() {
+        let mut cpu = recording_cpu();
+        let mut journal = Journal::new(4096);
+        run(&mut cpu, &mut journal, 200);
+
+        assert!(journal.size() <= 4096);
+        assert!(journal.len() < 200);
+    }
+
+    #[test]
+    fn test_recording_does_not_change_execution() {
+        let mut plain = CPU::default();
+        plain.seed_rng(7);
+        plain.load(test_pattern::generate()).unwrap();
+        for _ in 0..200 {
+            plain.step_frame(9);
+        }
+
+        let mut cpu = recording_cpu();
+        let hashes = run(&mut cpu, &mut Journal::default(), 200);
+        assert_eq!(plain.state_hash(), hashes[199]);
+    }
+
+    #[test]
+    fn test_tracing_changes() {
+        let mut cpu = recording_cpu();
+        let mut journal = Journal::default();
+        run(&mut cpu, &mut journal, 1);
+
+        let trace: Vec<String> = journal.last().unwrap().iter().map(Change::to_string).collect();
+        assert_eq!(trace[0], "EXEC 200");
+        assert!(trace.contains(&"PC 200 -> 21A".to_string()), "{:?}", trace);
+        assert!(trace.iter().any(|line| line.starts_with("ROW 0.0")));
+    }
+}
@@ -33,7 +33,41 @@ originally keypad cannot truly be recreated.
 ------------------------------------------------------------------------------------------------------------------------
 */
 
-#[derive(Debug, PartialEq)]
+// Std imports
+use std::str::FromStr;
+
+// External imports
+use serde::{Deserialize, Serialize};
+
+/// When FX0A accepts a key. Interpreters disagree, most visibly over keys already held when FX0A starts, which decides
+/// whether a key held to pause a game also unpauses it.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum KeyWait {
+    /// COSMAC VIP. A key counts once it is released, including a key held before FX0A started.
+    #[default]
+    Release,
+
+    /// A key counts as soon as it is pressed. A key held before FX0A started counts straight away.
+    Press,
+
+    /// Keys held before FX0A started are ignored until they have been released. After that, as Release.
+    FreshPress,
+}
+
+impl FromStr for KeyWait {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "release" => Ok(KeyWait::Release),
+            "press" => Ok(KeyWait::Press),
+            "fresh-press" => Ok(KeyWait::FreshPress),
+            _ => Err(format!("unknown key wait: {} (expected release, press or fresh-press)", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Keypad {
     keys: u16,
 }
@@ -51,6 +85,16 @@ impl Keypad {
         self.keys = 1 << k;
     }
 
+    /// Bit k is set while key k is held.
+    pub fn pressed(&self) -> u16 {
+        self.keys
+    }
+
+    /// Hold exactly the keys whose bits are set.
+    pub fn set_keys(&mut self, keys: u16) {
+        self.keys = keys;
+    }
+
     pub fn is_pressed(&self, k: u8) -> bool {
         (self.keys >> k) & 1 == 1
     }
@@ -87,4 +131,12 @@ mod tests {
         keypad.set_pressed(0xF);
         assert_eq!(keypad.is_pressed(0xF), true);
     }
+
+    #[test]
+    fn test_parsing_key_wait() {
+        assert_eq!("release".parse(), Ok(KeyWait::Release));
+        assert_eq!("Press".parse(), Ok(KeyWait::Press));
+        assert_eq!("fresh-press".parse(), Ok(KeyWait::FreshPress));
+        assert!("hold".parse::<KeyWait>().is_err());
+    }
 }
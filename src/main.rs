@@ -1,20 +1,123 @@
 #![allow(dead_code)]
 
+mod analyze;
+mod asm;
+mod bench;
+mod chip8x;
+mod config;
+mod conformance;
+mod coverage;
 mod cpu;
+mod crt;
+mod crash_report;
+mod crash_trace;
+mod debug_view;
+mod debugger;
+mod decode;
+mod disasm;
+mod display;
+mod draw_hit;
+#[cfg(feature = "sdl")]
 mod drivers;
 mod frame_buffer;
+mod frame_diff;
+mod gdb;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod hotspots;
+mod hud;
+mod halt;
+mod hooks;
+mod journal;
 mod keypad;
+mod opcode_stats;
+mod palettes;
+mod phosphor;
+#[cfg(feature = "megachip")]
+mod megachip;
+mod memory_view;
+mod profile;
+mod protect;
+mod quirks;
+mod random;
+mod rpl;
+mod rewind;
+mod softlock;
+mod speed;
+mod state_diff;
+#[cfg(feature = "stream")]
+mod stream;
+mod suspend;
+mod symbols;
+mod sys;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "terminal")]
+mod terminal;
+mod test_pattern;
+mod title;
+mod trace;
+mod uninit;
+mod watch;
+mod variant;
+mod vip_timing;
+mod vsync;
+mod write_log;
 
 // Self imports
+use analyze::Analysis;
+use bench::Scenario;
+use config::{Config, Hotkey};
+use conformance::ReportFormat;
+use coverage::Coverage;
 use cpu::CPU;
-use drivers::{DisplayDriver, InputDriver};
+use crash_trace::CrashTrace;
+use debugger::{Command as DebugCommand, Debugger};
+use disasm::Listing;
+use display::{apply_palette, Display, Input};
+#[cfg(feature = "sdl")]
+use drivers::{AudioDriver, DebugWindow, DisplayDriver, MemoryWindow, DEFAULT_PITCH};
+use frame_diff::{FrameDiff, Video};
+use gdb::{GdbStub, Request as GdbRequest};
+use halt::Halt;
+use hotspots::Hotspots;
+use hud::Hud;
+use keypad::KeyWait;
+use opcode_stats::OpcodeStats;
+use phosphor::Phosphor;
+use profile::Profile;
+use protect::WriteProtect;
+use quirks::{Detection, Detector as QuirkDetector, Quirks};
+use random::Random;
+use rewind::Rewind;
+use rpl::RplFlags;
+use softlock::Detector;
+#[cfg(feature = "stream")]
+use stream::{Frame, FrameHook, SharedFrame};
+use suspend::{Snapshot, SLOTS};
+use symbols::Symbols;
+use sys::SysHandler;
+use title::Status;
+use trace::Trace;
+use uninit::UninitReads;
+#[cfg(feature = "tui")]
+use tui::{Action as TuiAction, Tui};
+use watch::Watchpoint;
+use variant::Variant;
+use vsync::Pacer;
 
 // Std imports
+use std::cell::RefCell;
 use std::fs::File;
 use std::io::Read;
-use std::time::Duration;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 // External imports
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
 
 // Constants
@@ -23,9 +126,674 @@ pub const WRAP_Y: bool = true; // Wrap vertically when drawing sprites?
 
 pub const OFFSET: usize = 0x200; // Beginning of memory reserved for program.
 
+const FRAMES_PER_SECOND: usize = 60; // Frames run (and timers tick) at 60Hz.
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / FRAMES_PER_SECOND as u64);
+const INSTRUCTIONS_PER_FRAME: usize = 9; // Number of instructions executed each frame.
+
+#[derive(StructOpt)]
+#[structopt(name = "chip8", about = "A Chip-8 interpreter.")]
+enum Command {
+    /// Run a ROM.
+    Run(RunOptions),
+
+    /// Run a ROM in the terminal, drawing the screen with half block characters. Needs the terminal feature.
+    Terminal(FrontendOptions),
+
+    /// Run a ROM in a window drawn on the GPU by the pixels crate rather than SDL. Needs the gpu feature.
+    Gpu(FrontendOptions),
+
+    /// Print which opcodes are implemented, the quirks affecting them and the tests covering them.
+    Conformance {
+        /// Either markdown or json.
+        #[structopt(long, default_value = "markdown")]
+        format: ReportFormat,
+    },
+
+    /// Assemble CHIP-8 mnemonics, as printed by disassemble, into a ROM.
+    Asm {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// ROM to write. Defaults to the input's path with a .ch8 extension.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Machine the program is for, which decides the address it is assembled to run from.
+        #[structopt(long, default_value = "chip8")]
+        variant: Variant,
+    },
+
+    /// Print a ROM as a listing of addresses, opcodes and mnemonics.
+    #[structopt(alias = "disasm")]
+    Disassemble {
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+
+        /// Machine the ROM was written for, which decides where it loads and what some opcodes mean.
+        #[structopt(long, default_value = "chip8")]
+        variant: Variant,
+
+        /// Coverage map saved by run --coverage. Bytes it saw read as data but never executed are listed as data.
+        #[structopt(long, parse(from_os_str))]
+        coverage: Option<PathBuf>,
+
+        /// Follow jumps, calls and skips from the first instruction, listing bytes they never reach as data, instead
+        /// of decoding every byte in turn.
+        #[structopt(long, conflicts_with = "coverage")]
+        follow: bool,
+
+        /// Leave out the labels generated for addresses jumped or called to.
+        #[structopt(long)]
+        no_labels: bool,
+
+        /// Octo style symbol file whose labels name addresses in the listing.
+        #[structopt(long, parse(from_os_str))]
+        symbols: Option<PathBuf>,
+    },
+
+    /// Report a ROM's opcodes, SCHIP and XO-CHIP instructions, data, stack depth and likely variant without running it.
+    Analyze {
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+
+        /// Machine to decode the ROM as, which decides where it loads.
+        #[structopt(long, default_value = "chip8")]
+        variant: Variant,
+    },
+
+    /// Report the registers, memory and screen pixels which differ between two save states or suspend files.
+    DiffState {
+        #[structopt(parse(from_os_str))]
+        a: PathBuf,
+
+        #[structopt(parse(from_os_str))]
+        b: PathBuf,
+    },
+
+    /// Write a generated test pattern ROM to the ROM path and run it. Checks displays and quirk settings.
+    GenTestPattern {
+        #[structopt(flatten)]
+        run: RunOptions,
+
+        /// Only write the ROM.
+        #[structopt(long)]
+        write_only: bool,
+    },
+
+    /// Time a scripted run of a ROM and check it ends in the expected state.
+    BenchScenario {
+        /// TOML file giving the ROM, seed, input script, cycle count and expected hash.
+        #[structopt(parse(from_os_str))]
+        scenario: PathBuf,
+
+        /// Number of times to run the scenario.
+        #[structopt(long, default_value = "5")]
+        runs: usize,
+
+        /// Write the state hash after every frame to the scenario's frame_hashes file instead of benchmarking. Later
+        /// runs check every frame against it and report the first which differs.
+        #[structopt(long)]
+        record_hashes: bool,
+    },
+}
+
+/// Options for the frontends other than run's SDL window, which only play the ROM.
+#[derive(StructOpt)]
+struct FrontendOptions {
+    #[structopt(parse(from_os_str))]
+    rom: PathBuf,
+
+    /// How fast to run, as for run. Defaults to the config's speed for the ROM, or vip.
+    #[structopt(long)]
+    speed: Option<Timing>,
+
+    /// Machine the ROM was written for, as for run.
+    #[structopt(long, default_value = "chip8")]
+    variant: Variant,
+
+    /// TOML file binding the keypad and setting up the display.
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Colours to draw the screen with, as for run.
+    #[structopt(long)]
+    palette: Option<String>,
+}
+
+#[derive(StructOpt)]
+struct RunOptions {
+    #[structopt(parse(from_os_str))]
+    rom: PathBuf,
+
+    /// Single step through the ROM using the space bar.
+    #[structopt(long)]
+    debug: bool,
+
+    /// How fast to run: vip (about 540 instructions a second), vip-cycles (charging each instruction its COSMAC VIP
+    /// cycle count), hp48 (30 a frame), octo (1000 a frame) or a number of instructions per frame. Overrides the
+    /// config's speed for the ROM. Defaults to vip.
+    #[structopt(long)]
+    speed: Option<Timing>,
+
+    /// Same as --speed vip-cycles.
+    #[structopt(long)]
+    vip_timing: bool,
+
+    /// Machine the ROM was written for: chip8, chip8x, chip8-hires, xochip (with 64K of memory), eti660 or
+    /// eti660-hires. Builds with the megachip feature also accept megachip.
+    #[structopt(long, default_value = "chip8")]
+    variant: Variant,
+
+    /// TOML file rebinding the keypad and hotkeys. Overrides the profile's config.
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Colours to draw the screen with: default, green, amber, gameboy, paper or the path of a palette file. Overrides
+    /// the config's palette.
+    #[structopt(long)]
+    palette: Option<String>,
+
+    /// File the suspend hotkey writes to. Defaults to the ROM's path with a .suspend extension, or the ROM's name in
+    /// the profile's saves.
+    #[structopt(long, parse(from_os_str))]
+    suspend_file: Option<PathBuf>,
+
+    /// Resume the session saved in the suspend file instead of starting the ROM afresh.
+    #[structopt(long)]
+    resume: bool,
+
+    /// What 0NNN (a call to a machine language routine) does: panic, ignore or log.
+    #[structopt(long, default_value = "panic")]
+    sys: SysHandler,
+
+    /// Pause when memory is accessed, e.g. x:000-1FF breaks on executing anything below 0x200 and rw:300-30F on
+    /// reading or writing 0x300 to 0x30F. May be repeated.
+    #[structopt(long, number_of_values = 1)]
+    watch: Vec<Watchpoint>,
+
+    /// Pause before executing the instruction at this hex address or label. May be repeated. Breakpoints can also be
+    /// toggled with the debugger's break command and the breakpoint hotkey.
+    #[structopt(long = "break", number_of_values = 1)]
+    breakpoints: Vec<String>,
+
+    /// Octo style symbol file of addresses and labels, for --break, the debugger and --trace.
+    #[structopt(long, parse(from_os_str))]
+    symbols: Option<PathBuf>,
+
+    /// When FX0A accepts a key: release (COSMAC VIP), press, or fresh-press to ignore keys held before it started.
+    #[structopt(long, default_value = "release")]
+    key_wait: KeyWait,
+
+    /// Quirks to switch on, comma separated: shift-vx for shifts which ignore Vy as on SCHIP, increment-i for loads and
+    /// stores which move I on as on the VIP. Defaults to none.
+    #[structopt(long, default_value = "none")]
+    quirks: Quirks,
+
+    /// Watch the ROM for signs of quirks it expects: off, suggest to log them, or apply to log them and switch them
+    /// on as they are found.
+    #[structopt(long, default_value = "off")]
+    detect_quirks: Detection,
+
+    /// What happens to writes between the fonts and the program, which usually mean a bug: off, log, or block to log
+    /// and drop them. Leave off for ROMs which use low memory on purpose.
+    #[structopt(long, default_value = "off")]
+    write_protect: WriteProtect,
+
+    /// What happens when the program reads memory it never wrote, which usually means a bug: off, log, or break to
+    /// pause in the debugger. Each byte is reported once.
+    #[structopt(long, default_value = "off")]
+    uninit_reads: UninitReads,
+
+    /// Publish every frame and the keys held to this file for streaming software, e.g. /dev/shm/chip8. Needs the
+    /// stream feature.
+    #[structopt(long, parse(from_os_str))]
+    stream: Option<PathBuf>,
+
+    /// Show a debugger view in the terminal alongside the window: disassembly, registers, the stack and memory. Needs
+    /// the tui feature.
+    #[structopt(long)]
+    tui: bool,
+
+    /// Wait for gdb, or another frontend speaking its remote protocol, to connect on this port and let it debug the
+    /// ROM, e.g. with target remote localhost:1234. The ROM starts paused.
+    #[structopt(long)]
+    gdb: Option<u16>,
+
+    /// Named profile with its own config, saves and play statistics.
+    #[structopt(long)]
+    profile: Option<String>,
+
+    /// Where CXKK's random bytes come from: pcg, pcg:<seed> to replay the same sequence every run, or vip for the
+    /// COSMAC VIP's generator. Resumed sessions carry on with the source they were suspended with.
+    #[structopt(long, default_value = "pcg")]
+    rng: Random,
+
+    /// Pause and save the state to a .softlock file if the ROM looks stuck for this many seconds: looping in a few
+    /// bytes with no screen changes or key checks. For unattended runs.
+    #[structopt(long)]
+    softlock: Option<u32>,
+
+    /// Start showing what changed each frame instead of the screen: new pixels green, erased red, the rest dimmed.
+    /// Toggled with the diff hotkey.
+    #[structopt(long)]
+    diff: bool,
+
+    /// Write what changed each frame to this file as raw RGB video, one pixel per CHIP-8 pixel at 60 frames a second.
+    #[structopt(long, parse(from_os_str))]
+    diff_video: Option<PathBuf>,
+
+    /// Count how often each opcode family is executed and print a report when the window closes.
+    #[structopt(long)]
+    opcode_stats: bool,
+
+    /// Also time each opcode family. Implies --opcode-stats. Slows execution down a lot.
+    #[structopt(long)]
+    time_opcodes: bool,
+
+    /// Count how often each address is executed and print the hottest addresses and loops, with a heatmap of the
+    /// program, when the window closes. The debugger's hot command shows the same report while paused. This is the
+    /// execution profiler; it isn't called --profile as that picks the user profile.
+    #[structopt(long)]
+    hotspots: bool,
+
+    /// Also time each address. Implies --hotspots. Slows execution down a lot.
+    #[structopt(long)]
+    time_hotspots: bool,
+
+    /// How many of the last instructions executed to print if the ROM stops the emulator, e.g. with an invalid
+    /// opcode. They go in the crash report too. 0 turns the trace off.
+    #[structopt(long, default_value = "32")]
+    crash_trace: usize,
+
+    /// Write a line for every instruction executed to this file: the PC, opcode and registers, in a fixed format for
+    /// diffing against other emulators' logs.
+    #[structopt(long, parse(from_os_str))]
+    trace: Option<PathBuf>,
+
+    /// Save a map of which addresses were executed and which were read as data to this file when the window closes.
+    /// The disassemble command can use it to tell code from data.
+    #[structopt(long, parse(from_os_str))]
+    coverage: Option<PathBuf>,
+}
+
 fn main() {
-    let mut vm = VM::new("./roms/tetris.ch8");
-    vm.run(Mode::Release);
+    // Warnings and errors are shown unless RUST_LOG asks for something else, e.g. RUST_LOG=chip8::cpu=debug.
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    match Command::from_args() {
+        Command::Run(options) => {
+            if let Err(e) = run(options) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Command::GenTestPattern { run: options, write_only } => {
+            if let Err(e) = gen_test_pattern(options, write_only) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Command::Terminal(options) => {
+            if let Err(e) = run_in_terminal(options) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Command::Gpu(options) => {
+            if let Err(e) = run_on_gpu(options) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Command::Conformance { format } => print!("{}", conformance::report(format)),
+
+        Command::Asm { input, output, variant } => {
+            if let Err(e) = assemble(&input, output, variant) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Command::Disassemble { rom, variant, coverage, follow, no_labels, symbols } => {
+            match disassemble(&rom, variant, coverage, follow, no_labels, symbols) {
+                Ok(listing) => print!("{}", listing),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Command::Analyze { rom, variant } => {
+            let bytes = rom_from_path(&rom);
+            print!("{}", Analysis::new(&bytes, variant.program_start(), variant));
+        }
+
+        Command::DiffState { a, b } => match (Snapshot::load(&a), Snapshot::load(&b)) {
+            (Ok(a), Ok(b)) => print!("{}", state_diff::report(&a.cpu, &b.cpu)),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+
+        Command::BenchScenario { scenario, runs, record_hashes } => {
+            if let Err(e) = bench_scenario(&scenario, runs, record_hashes) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+// Run a ROM in a window until it is closed or suspended.
+fn run(options: RunOptions) -> Result<(), String> {
+    let RunOptions {
+        rom,
+        debug,
+        speed,
+        vip_timing,
+        variant,
+        config,
+        palette,
+        suspend_file,
+        resume,
+        sys,
+        watch,
+        breakpoints,
+        symbols,
+        key_wait,
+        quirks,
+        detect_quirks,
+        write_protect,
+        uninit_reads,
+        stream,
+        tui,
+        gdb,
+        profile,
+        rng,
+        softlock,
+        diff,
+        diff_video,
+        opcode_stats,
+        time_opcodes,
+        hotspots,
+        time_hotspots,
+        crash_trace,
+        trace,
+        coverage,
+    } = options;
+
+    let mode = if debug || gdb.is_some() { Mode::Debug } else { Mode::Release };
+
+    let profile = profile.map(|name| Profile::open(&name)).transpose()?;
+    let mut config = match (config, &profile) {
+        (Some(path), _) => Config::load(&path)?,
+        (None, Some(profile)) => profile.config()?,
+        (None, None) => Config::default(),
+    };
+    if let Some(palette) = palette {
+        config.display.palette = palettes::load(&palette)?;
+    }
+
+    config.limits.check_rom(&rom)?;
+    let breaks = !watch.is_empty() || !breakpoints.is_empty() || uninit_reads == UninitReads::Break;
+    if debug || gdb.is_some() || breaks {
+        config.limits.check_debugger()?;
+    }
+
+    let timing = speed
+        .or_else(|| vip_timing.then_some(Timing::CosmacVip))
+        .or_else(|| config.speed.for_rom(&rom))
+        .unwrap_or(Timing::Fixed(INSTRUCTIONS_PER_FRAME));
+
+    // Saves go next to the ROM, or in the profile's saves.
+    let save_base = match &profile {
+        Some(profile) => profile.save_base(&rom),
+        None => rom.clone(),
+    };
+    let suspend_file = suspend_file.unwrap_or_else(|| save_base.with_extension("suspend"));
+
+    let mut snapshot = if resume {
+        Snapshot::load(&suspend_file)?
+    } else {
+        // Initialise CPU and load ROM.
+        let mut cpu = CPU::new(variant);
+        cpu.set_rng(rng);
+        cpu.load(rom_from_path(&rom))?;
+        Snapshot { cpu, timing, journal: None }
+    };
+
+    snapshot.cpu.set_rpl_flags(RplFlags::load(&save_base.with_extension("flags"))?);
+    snapshot.cpu.set_sys_handler(sys);
+    snapshot.cpu.set_key_wait(key_wait);
+    snapshot.cpu.set_quirks(quirks);
+    snapshot.cpu.set_write_protect(write_protect);
+    snapshot.cpu.set_uninit_reads(uninit_reads);
+    for watchpoint in watch {
+        snapshot.cpu.watchpoints_mut().add(watchpoint);
+    }
+    let symbols = symbols.map(|path| Symbols::load(&path)).transpose()?.unwrap_or_default();
+    for breakpoint in breakpoints {
+        snapshot.cpu.breakpoints_mut().insert(symbols.resolve(&breakpoint)?);
+    }
+
+    // Wait for the debugger before opening the window, which would stop responding in the meantime.
+    let gdb = gdb.map(GdbStub::listen).transpose()?;
+
+    let mut vm = VM::in_sdl_window(snapshot, config, save_base, suspend_file)?
+        .with_stream(stream)?
+        .with_tui(tui)?;
+    if let Some(seconds) = softlock {
+        vm.softlock = Some(Detector::attach(&mut vm.cpu, seconds as usize * FRAMES_PER_SECOND));
+    }
+    vm.gdb = gdb;
+    vm.show_diff = diff;
+    vm.diff_video = diff_video.map(|path| Video::create(&path)).transpose()?;
+    let stats = (opcode_stats || time_opcodes).then(|| OpcodeStats::attach(&mut vm.cpu, time_opcodes));
+    vm.hotspots = (hotspots || time_hotspots).then(|| Hotspots::attach(&mut vm.cpu, time_hotspots));
+
+    let trace = trace.map(|path| Trace::attach(&mut vm.cpu, &path, symbols.clone())).transpose()?;
+    vm.symbols = symbols;
+
+    vm.show_status(&rom);
+    if coverage.is_some() {
+        vm.cpu.track_coverage();
+    }
+    let crash_trace = (crash_trace > 0).then(|| CrashTrace::attach(&mut vm.cpu, crash_trace));
+    if detect_quirks != Detection::Off {
+        QuirkDetector::attach(&mut vm.cpu, detect_quirks);
+    }
+
+    // Errors in the ROM panic. Show how it got there and write a crash report before carrying on unwinding.
+    let start = Instant::now();
+    let exit = match panic::catch_unwind(AssertUnwindSafe(|| vm.run(mode))) {
+        Ok(exit) => exit,
+        Err(panic) => {
+            let crash_trace = crash_trace.as_ref().map(|trace| trace.borrow());
+            if let Some(crash_trace) = &crash_trace {
+                eprint!("{}", crash_trace);
+            }
+
+            let message = crash_report::message(panic.as_ref());
+            let report = crash_report::report(&vm.cpu, &vm.symbols, message, crash_trace.as_deref());
+            let path = vm.suspend_file.with_extension("crash");
+            match crash_report::write(&path, &report) {
+                Ok(()) => eprintln!("Crash report written to {}", path.display()),
+                Err(e) => error!("{}", e),
+            }
+            panic::resume_unwind(panic);
+        }
+    };
+    if let Some(halt) = exit {
+        println!("{}", halt);
+    }
+
+    if let Some(stats) = stats {
+        print!("{}", stats.borrow());
+    }
+    if let Some(hotspots) = &vm.hotspots {
+        print!("{}", hotspots.borrow().report(&vm.cpu, debugger::HOTTEST));
+    }
+    if let Some(video) = vm.diff_video.take() {
+        println!("{}", video.finish()?);
+    }
+    if let Some(trace) = trace {
+        println!("{}", trace.borrow_mut().finish()?);
+    }
+    if let (Some(path), Some(map)) = (coverage, vm.cpu.coverage()) {
+        map.save(&path, variant.program_start())?;
+    }
+
+    match profile {
+        Some(profile) => profile.record_play(&rom, start.elapsed()),
+        None => Ok(()),
+    }
+}
+
+// Assemble a source file into a ROM.
+fn assemble(input: &Path, output: Option<PathBuf>, variant: Variant) -> Result<(), String> {
+    let source = std::fs::read_to_string(input).map_err(|e| format!("unable to read {}: {}", input.display(), e))?;
+    let rom = asm::assemble(&source, variant.program_start()).map_err(|e| format!("{}: {}", input.display(), e))?;
+
+    let output = output.unwrap_or_else(|| input.with_extension("ch8"));
+    std::fs::write(&output, &rom).map_err(|e| format!("unable to write {}: {}", output.display(), e))?;
+    println!("Wrote {} bytes to {}", rom.len(), output.display());
+    Ok(())
+}
+
+// List a ROM, telling code from data with a coverage map and naming addresses from a symbol file if given.
+fn disassemble(
+    rom: &Path,
+    variant: Variant,
+    coverage: Option<PathBuf>,
+    follow: bool,
+    no_labels: bool,
+    symbols: Option<PathBuf>,
+) -> Result<Listing, String> {
+    let (bytes, start) = (rom_from_path(rom), variant.program_start());
+    let mut listing = match coverage {
+        Some(path) => Listing::with_coverage(&bytes, start, variant, &Coverage::load(&path)?),
+        None if follow => Listing::following(&bytes, start, variant),
+        None => Listing::new(&bytes, start, variant),
+    };
+    if no_labels {
+        listing = listing.without_labels();
+    }
+    match symbols {
+        Some(path) => Ok(listing.with_symbols(Symbols::load(&path)?)),
+        None => Ok(listing),
+    }
+}
+
+impl FrontendOptions {
+    /// Load the config and the ROM, working out how fast to run it, and play it on the display until that's closed or
+    /// the ROM exits.
+    #[cfg(any(feature = "terminal", feature = "gpu"))]
+    fn play(self, display: impl FnOnce(&Config) -> Box<dyn Display>) -> Result<(), String> {
+        let FrontendOptions { rom, speed, variant, config, palette } = self;
+
+        let mut config = config.map(|path| Config::load(&path)).transpose()?.unwrap_or_default();
+        if let Some(palette) = palette {
+            config.display.palette = palettes::load(&palette)?;
+        }
+        config.limits.check_rom(&rom)?;
+        let timing = speed
+            .or_else(|| config.speed.for_rom(&rom))
+            .unwrap_or(Timing::Fixed(INSTRUCTIONS_PER_FRAME));
+
+        let mut cpu = CPU::new(variant);
+        cpu.load(rom_from_path(&rom))?;
+
+        let display = display(&config);
+        let suspend_file = rom.with_extension("suspend");
+        let mut vm = VM::new(Snapshot { cpu, timing, journal: None }, config, rom.clone(), suspend_file, display)?;
+        vm.show_status(&rom);
+        let exit = vm.run(Mode::Release);
+
+        // Dropping the display hands the terminal back, so the way the ROM exited isn't lost with the screen.
+        drop(vm);
+        if let Some(halt) = exit {
+            println!("{}", halt);
+        }
+        Ok(())
+    }
+}
+
+// Run a ROM in the terminal until Esc is pressed or it exits.
+#[cfg(feature = "terminal")]
+fn run_in_terminal(options: FrontendOptions) -> Result<(), String> {
+    options.play(|config| Box::new(terminal::Terminal::new(config)))
+}
+
+#[cfg(not(feature = "terminal"))]
+fn run_in_terminal(_options: FrontendOptions) -> Result<(), String> {
+    Err("terminal needs chip8 to be built with the terminal feature".to_string())
+}
+
+// Run a ROM in a pixels window until it's closed or the ROM exits.
+#[cfg(feature = "gpu")]
+fn run_on_gpu(options: FrontendOptions) -> Result<(), String> {
+    options.play(|config| Box::new(gpu::Gpu::new(config)))
+}
+
+#[cfg(not(feature = "gpu"))]
+fn run_on_gpu(_options: FrontendOptions) -> Result<(), String> {
+    Err("gpu needs chip8 to be built with the gpu feature".to_string())
+}
+
+// Write the test pattern ROM and run it unless asked not to.
+fn gen_test_pattern(options: RunOptions, write_only: bool) -> Result<(), String> {
+    let rom = &options.rom;
+    std::fs::write(rom, test_pattern::generate()).map_err(|e| format!("unable to write {}: {}", rom.display(), e))?;
+
+    if write_only {
+        println!("Wrote the test pattern to {}", rom.display());
+        Ok(())
+    } else {
+        run(options)
+    }
+}
+
+// Run a benchmark scenario and print its timings. Fails if the final state doesn't match the scenario's expected hash.
+fn bench_scenario(path: &Path, runs: usize, record_hashes: bool) -> Result<(), String> {
+    let scenario = Scenario::load(path)?;
+    if let Some(hashes) = &scenario.frame_hashes {
+        let rom = &scenario.rom;
+        let rom = std::fs::read(rom).map_err(|e| format!("unable to read {}: {}", rom.display(), e))?;
+        if record_hashes {
+            let frames = scenario.hash_frames(&rom)?;
+            bench::save_hashes(hashes, &frames)?;
+            println!("Recorded {} frame hashes to {}", frames.len(), hashes.display());
+            return Ok(());
+        }
+
+        let expected = bench::load_hashes(hashes)?;
+        match scenario.verify(&rom, &expected)? {
+            Some(divergence) => return Err(divergence.to_string()),
+            None => println!("frames: all {} match {}", expected.len(), hashes.display()),
+        }
+    } else if record_hashes {
+        return Err(format!("{} has no frame_hashes file to record to", path.display()));
+    }
+
+    let report = scenario.bench(runs)?;
+    print!("{}", report);
+
+    match &scenario.expected_hash {
+        Some(expected) if *expected != report.hash() => {
+            Err(format!("hash mismatch: expected {}, got {}", expected, report.hash()))
+        }
+        Some(_) => Ok(()),
+        None => {
+            println!("No expected_hash in the scenario. Add expected_hash = \"{}\" to check it.", report.hash());
+            Ok(())
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -34,73 +802,809 @@ enum Mode {
     Release,
 }
 
+/// How many instructions are executed each frame.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Timing {
+    Fixed(usize),
+    CosmacVip,
+}
+
 struct VM {
     cpu: CPU,
-    display_driver: DisplayDriver,
-    input_driver: InputDriver,
+    timing: Timing,
+    config: Config,
+    save_base: PathBuf, // Save state slots are named after this path, e.g. pong.state3 for pong.ch8.
+    slot: u8,
+    rewind: Rewind,
+    softlock: Option<Detector>,
+    diff: FrameDiff,
+    show_diff: bool,
+    diff_video: Option<Video>,
+    phosphor: Option<Phosphor>, // Fades pixels out if the config has a decay.
+    finished: bool, // Whether the program has been reported as halted.
+    debugger: Option<Debugger>, // Started the first time the ROM is paused.
+    gdb: Option<GdbStub>, // Takes the place of typed debugger commands while connected.
+    hotspots: Option<Rc<RefCell<Hotspots>>>, // Started by --hotspots or the debugger's hot command.
+    symbols: Symbols,
+    suspend_file: PathBuf,
+    display: Box<dyn Display>,
+    screen: (usize, usize), // Size of the frames last shown, so the display hears when it changes.
+    hud: Option<Hud>,       // Toggled by the hud hotkey.
+    status: Option<Status>, // Shown in the window title.
+
+    // Sound and the windows beside the screen, which only come with SDL's display.
+    #[cfg(feature = "sdl")]
+    sdl_context: Option<sdl2::Sdl>,
+    #[cfg(feature = "sdl")]
+    audio_driver: Option<AudioDriver>, // None if there is no sound device.
+    #[cfg(feature = "sdl")]
+    memory_window: Option<MemoryWindow>, // Opened by the memory hotkey.
+    #[cfg(feature = "sdl")]
+    debug_window: Option<DebugWindow>, // Opened by the debug window hotkey.
+
+    // Called with every presented frame.
+    #[cfg(feature = "stream")]
+    frame_hook: Option<Box<dyn FrameHook>>,
+
+    // Debugger view in the terminal. Takes the place of typed debugger commands while it's open.
+    #[cfg(feature = "tui")]
+    tui: Option<Tui>,
 }
 
 impl VM {
-    pub fn new(path: &str) -> Self {
-        // Initialise CPU and load ROM.
-        let mut cpu = CPU::default();
-        cpu.load(rom_from_path(path));
-
+    pub fn new(
+        snapshot: Snapshot,
+        config: Config,
+        save_base: PathBuf,
+        suspend_file: PathBuf,
+        mut display: Box<dyn Display>,
+    ) -> Result<Self, String> {
+        let Snapshot { mut cpu, timing, journal } = snapshot;
+        let mut rewind = Rewind::default();
+        match journal {
+            Some(journal) if cpu.recording() => rewind.set_journal(journal), // Resuming, so rewinding carries on.
+            _ => cpu.start_recording(),
+        }
 
-        // Create SDL context and I/O drivers.
-        let sdl_context = sdl2::init().unwrap();
-        let mut display_driver = DisplayDriver::new(&sdl_context);
-        let mut input_driver = InputDriver::new(&sdl_context);
+        let screen = cpu.screen_size();
+        display.set_palette(config.display.palette);
+        display.init(screen.0, screen.1)?;
+        let (decay, rate) = (config.display.decay, config.display.decay_rate);
+        let phosphor = (decay > 0).then(|| Phosphor::new(decay, rate));
 
-        Self {
+        Ok(Self {
             cpu,
-            display_driver,
-            input_driver,
+            timing,
+            config,
+            save_base,
+            slot: 0,
+            rewind,
+            softlock: None,
+            diff: FrameDiff::default(),
+            show_diff: false,
+            diff_video: None,
+            phosphor,
+            finished: false,
+            debugger: None,
+            gdb: None,
+            hotspots: None,
+            symbols: Symbols::default(),
+            suspend_file,
+            display,
+            screen,
+            hud: None,
+            status: None,
+            #[cfg(feature = "sdl")]
+            sdl_context: None,
+            #[cfg(feature = "sdl")]
+            audio_driver: None,
+            #[cfg(feature = "sdl")]
+            memory_window: None,
+            #[cfg(feature = "sdl")]
+            debug_window: None,
+            #[cfg(feature = "stream")]
+            frame_hook: None,
+            #[cfg(feature = "tui")]
+            tui: None,
+        })
+    }
+
+    /// Show the ROM in SDL's window, with sound and the hotkeys' memory and debug windows.
+    #[cfg(feature = "sdl")]
+    fn in_sdl_window(
+        snapshot: Snapshot,
+        config: Config,
+        save_base: PathBuf,
+        suspend_file: PathBuf,
+    ) -> Result<Self, String> {
+        let sdl_context = sdl2::init()?;
+        let display = Box::new(DisplayDriver::new(&sdl_context, &config)?);
+        let mut vm = Self::new(snapshot, config, save_base, suspend_file, display)?;
+        vm.audio_driver = AudioDriver::new(&sdl_context)
+            .map_err(|e| warn!("sound disabled: {}", e))
+            .ok();
+        vm.sdl_context = Some(sdl_context);
+        Ok(vm)
+    }
+
+    #[cfg(not(feature = "sdl"))]
+    fn in_sdl_window(_: Snapshot, _: Config, _: PathBuf, _: PathBuf) -> Result<Self, String> {
+        Err("run needs chip8 to be built with the sdl feature".to_string())
+    }
+
+    /// Show the ROM, the variant it's run as and the one it looks written for, its speed and the frame rate in the
+    /// title.
+    fn show_status(&mut self, rom: &Path) {
+        let running = self.cpu.variant();
+        let likely = Analysis::new(self.cpu.rom(), running.program_start(), running).variant;
+        let status = Status::new(rom, running, likely, self.timing, Instant::now());
+        self.display.set_title_status(&status.to_string());
+        self.status = Some(status);
+    }
+
+    /// Publish frames to a shared file, if one was given.
+    #[cfg(feature = "stream")]
+    fn with_stream(mut self, path: Option<PathBuf>) -> Result<Self, String> {
+        if let Some(path) = path {
+            self.frame_hook = Some(Box::new(SharedFrame::create(&path)?));
+        }
+        Ok(self)
+    }
+
+    #[cfg(not(feature = "stream"))]
+    fn with_stream(self, path: Option<PathBuf>) -> Result<Self, String> {
+        match path {
+            Some(_) => Err("--stream needs chip8 to be built with the stream feature".to_string()),
+            None => Ok(self),
+        }
+    }
+
+    /// Show the debugger view in the terminal, if asked for.
+    #[cfg(feature = "tui")]
+    fn with_tui(mut self, enabled: bool) -> Result<Self, String> {
+        if enabled {
+            self.tui = Some(Tui::start()?);
         }
+        Ok(self)
     }
 
-    pub fn run(&mut self, mode: Mode) {
-        // Sleep duration. Ensure games run at reasonable speed.
-        let sleep_duration = Duration::from_micros(1800);
+    #[cfg(not(feature = "tui"))]
+    fn with_tui(self, enabled: bool) -> Result<Self, String> {
+        match enabled {
+            true => Err("--tui needs chip8 to be built with the tui feature".to_string()),
+            false => Ok(self),
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    fn tui_open(&self) -> bool {
+        self.tui.is_some()
+    }
+
+    #[cfg(not(feature = "tui"))]
+    fn tui_open(&self) -> bool {
+        false
+    }
 
-        // Render every 9th frame. Ensure games run at ~60FPS.
-        let mut cycle_counter = 0;
+    /// Redraw the terminal view and return what was asked for in it. The view is closed when q is pressed or it can't
+    /// be drawn.
+    #[cfg(feature = "tui")]
+    fn update_tui(&mut self, paused: bool) -> Option<TuiAction> {
+        let view = self.tui.as_mut()?;
+        let action = view.poll();
+        let drawn = view.draw(&self.cpu, paused);
 
-        while let Ok(keycode) = self.input_driver.poll() {
-            match keycode {
-                Some(255) => self.cpu.dbg(),
+        if let Err(e) = &drawn {
+            error!("{}", e);
+        }
+        if drawn.is_err() || action == Some(TuiAction::Quit) {
+            self.tui = None;
+            return None;
+        }
+        action
+    }
+
+    /// Answer the remote debugger, if one is connected, and return what it asked for. It is dropped when the
+    /// connection is lost.
+    fn update_gdb(&mut self, paused: bool) -> Option<GdbRequest> {
+        let stub = self.gdb.as_mut()?;
+        match stub.update(&mut self.cpu, paused) {
+            Ok(request) => request,
+            Err(e) => {
+                error!("{}", e);
+                self.gdb = None;
+                None
+            }
+        }
+    }
+
+    /// Run until the display is closed or the program exits. Returns how it exited, if it did.
+    pub fn run(&mut self, mut mode: Mode) -> Option<Halt> {
+        // The overlay starts on when debugging. It goes in the window title rather than the HUD because every display
+        // shows the title, the terminal in its status line, while only SDL's draws the HUD.
+        let mut overlay = matches!(mode, Mode::Debug);
+        // Paused without the debugger. The CPU and timers stop but input is still handled, so hotkeys work.
+        let mut paused = false;
+        // With vsync, each pass waits for the monitor's refresh and runs as many frames as the time since the last.
+        let mut pacer = self.display.vsync().then(|| Pacer::new(Instant::now()));
+
+        while let Ok(input) = self.display.poll() {
+            let frame_start = Instant::now();
+            let frames = pacer.as_mut().map_or(1, |pacer| pacer.frames(frame_start));
+
+            self.update_windows(&input);
+
+            match input.key {
                 Some(key) => self.cpu.set_key(key),
-                _ => self.cpu.clear_keys(),
+                None => self.cpu.clear_keys(),
+            }
+
+            match input.key2 {
+                Some(key) => self.cpu.set_key2(key),
+                None => self.cpu.clear_keys2(),
+            }
+
+            for hotkey in &input.hotkeys {
+                match hotkey {
+                    Hotkey::Step => self.cpu.dbg(),
+                    Hotkey::Help => print!("{}", self.config.help()),
+                    Hotkey::Suspend => match self.suspend() {
+                        Ok(()) => return None,
+                        Err(e) => error!("{}", e),
+                    },
+                    Hotkey::Reset => {
+                        self.cpu.reset();
+                        self.rewind.clear();
+                    }
+                    Hotkey::SaveState => {
+                        if let Err(e) = self.save_state() {
+                            error!("{}", e);
+                        }
+                    }
+                    Hotkey::LoadState => {
+                        if let Err(e) = self.load_state() {
+                            error!("{}", e);
+                        }
+                    }
+                    Hotkey::NextSlot => {
+                        self.slot = (self.slot + 1) % SLOTS;
+                        println!("Save state slot {}", self.slot);
+                    }
+                    Hotkey::Rewind => {} // Acts while held, below.
+                    Hotkey::FrameAdvance => {} // Depends on whether the ROM is paused, below.
+                    Hotkey::Pause => {
+                        paused = !paused;
+                        self.display.set_hud(None);
+                        self.display.redraw();
+                    }
+                    Hotkey::Breakpoint => {
+                        let command = DebugCommand::Break(Some(self.cpu.pc()));
+                        print!("{}", debugger::execute(&mut self.cpu, &command));
+                    }
+                    Hotkey::Overlay => {
+                        overlay = !overlay;
+                        self.display.set_title_info(None);
+                    }
+                    Hotkey::Memory => self.toggle_memory_window(),
+                    Hotkey::DebugWindow => self.toggle_debug_window(),
+                    Hotkey::Hud => {
+                        self.hud = match self.hud {
+                            Some(_) => None,
+                            None => Some(Hud::default()),
+                        };
+                        self.display.set_hud(None);
+                        self.display.redraw();
+                    }
+                    Hotkey::Crt => self.display.set_crt(!self.display.crt()),
+                    Hotkey::Grid => self.display.set_grid(!self.display.grid()),
+                    Hotkey::Diff => {
+                        self.show_diff = !self.show_diff;
+                        if self.diff_video.is_none() {
+                            self.diff.reset();
+                        }
+                    }
+                }
             }
 
             match mode {
+                // Rewinding stops at the oldest frame recorded until the hotkey is released.
+                Mode::Release if input.held.contains(&Hotkey::Rewind) => {
+                    if self.rewind.step_back(&mut self.cpu) {
+                        self.present();
+                    }
+                }
+
+                Mode::Release if paused => {
+                    if input.hotkeys.contains(&Hotkey::FrameAdvance) {
+                        self.advance_frame();
+                    }
+                }
+
+                Mode::Release if input.hotkeys.contains(&Hotkey::FrameAdvance) => paused = true,
+
                 Mode::Release => {
-                    self.cpu.cycle();
-                    cycle_counter += 1;
-                    std::thread::sleep(sleep_duration);
+                    // Frames after a hit would run past it before it's reported, below.
+                    for _ in 0..frames {
+                        if self.cpu.has_hit() {
+                            break;
+                        }
+                        self.advance_frame();
 
-                    if cycle_counter == 9 {
-                        self.display_driver.draw(self.cpu.get_framebuffer());
-                        cycle_counter = 0;
+                        let cpu = &self.cpu;
+                        if let Some(softlock) = self.softlock.as_mut().and_then(|detector| detector.end_frame(cpu)) {
+                            self.capture_softlock(&softlock.to_string());
+                            mode = Mode::Debug;
+                            break;
+                        }
                     }
                 }
 
                 Mode::Debug => {
-                    if let Some(255) = keycode {
+                    if input.hotkeys.contains(&Hotkey::Step) {
                         self.cpu.cycle();
-                        self.display_driver.draw(self.cpu.get_framebuffer());
+                        self.present();
+                    }
+                    if input.hotkeys.contains(&Hotkey::FrameAdvance) {
+                        self.advance_frame();
+                        print!("{}", debugger::next_instruction(&self.cpu));
+                    }
+
+                    if !self.tui_open() && self.gdb.is_none() {
+                        let symbols = &self.symbols;
+                        match self.debugger.get_or_insert_with(|| Debugger::spawn(symbols.clone())).poll(&self.cpu) {
+                            Some(DebugCommand::Continue) => mode = Mode::Release,
+                            Some(command @ DebugCommand::Goto(_)) => {
+                                print!("{}", debugger::execute(&mut self.cpu, &command));
+                                mode = Mode::Release;
+                            }
+                            Some(DebugCommand::Back(n)) => {
+                                self.step_back(n);
+                                print!("{}", debugger::next_instruction(&self.cpu));
+                            }
+                            Some(DebugCommand::Forward(n)) => {
+                                self.step_forward(n);
+                                print!("{}", debugger::next_instruction(&self.cpu));
+                            }
+                            Some(DebugCommand::Hot(n)) => match &self.hotspots {
+                                Some(hotspots) => print!("{}", hotspots.borrow().report(&self.cpu, n)),
+                                None => {
+                                    self.hotspots = Some(Hotspots::attach(&mut self.cpu, false));
+                                    println!("Counting executed addresses from here. Run hot again to see them");
+                                }
+                            },
+                            Some(command) => {
+                                print!("{}", debugger::execute(&mut self.cpu, &command));
+                                self.present();
+                            }
+                            None => {}
+                        }
                     }
                 }
             }
+
+            // Say when the program finishes, once. Rewinding or resetting can bring it back to life. Programs that exit
+            // close the window.
+            match self.cpu.halt() {
+                Some(halt @ Halt::Exit { .. }) => return Some(halt),
+                Some(halt) if !self.finished => {
+                    println!("{}", halt);
+                    self.display.set_title_info(Some("program finished"));
+                    self.finished = true;
+                }
+                Some(_) => {}
+                None if self.finished => {
+                    self.display.set_title_info(None);
+                    self.finished = false;
+                }
+                None => {}
+            }
+
+            let sound_timer = if paused { 0 } else { self.cpu.sound_timer() };
+            #[cfg(feature = "sdl")]
+            if let Some(audio_driver) = &mut self.audio_driver {
+                audio_driver.update(sound_timer, DEFAULT_PITCH);
+            }
+            self.display.set_sound(sound_timer > 0);
+
+            // Pause on watchpoint hits so they can be stepped through.
+            if let Some(hit) = self.cpu.take_watch_hit() {
+                println!("{}", hit);
+                self.cpu.dbg();
+                mode = Mode::Debug;
+            }
+
+            if let Some(read) = self.cpu.take_uninit_read() {
+                println!("{}", read);
+                mode = Mode::Debug;
+            }
+
+            // Pause after draws while breaking on them, showing what was drawn.
+            if let Some(hit) = self.cpu.take_draw_hit() {
+                println!("{}", hit);
+                mode = Mode::Debug;
+            }
+
+            // Pause at breakpoints, before the instruction there is executed.
+            if let Some(addr) = self.cpu.take_break_hit() {
+                match self.symbols.name(addr) {
+                    Some(name) => println!("Hit breakpoint at {:03X} ({})", addr, name),
+                    None => println!("Hit breakpoint at {:03X}", addr),
+                }
+                mode = Mode::Debug;
+            }
+
+            match self.update_gdb(matches!(mode, Mode::Debug)) {
+                Some(GdbRequest::Continue) => mode = Mode::Release,
+                Some(GdbRequest::Stop) => mode = Mode::Debug,
+                Some(GdbRequest::Step) => self.present(),
+                Some(GdbRequest::Detach) => {
+                    self.gdb = None;
+                    mode = Mode::Release;
+                }
+                Some(GdbRequest::Kill) => return None,
+                None => {}
+            }
+
+            #[cfg(feature = "tui")]
+            match self.update_tui(matches!(mode, Mode::Debug)) {
+                Some(TuiAction::Pause) => mode = Mode::Debug,
+                Some(TuiAction::Continue) => mode = Mode::Release,
+                Some(TuiAction::Step) if matches!(mode, Mode::Debug) => {
+                    self.cpu.cycle();
+                    self.present();
+                }
+                Some(TuiAction::Back) if matches!(mode, Mode::Debug) => self.step_back(1),
+                _ => {}
+            }
+
+            // A short hash of the machine state, so two sessions of the same ROM, seed and input can be seen to be in
+            // sync.
+            if overlay {
+                let info = format!("state {:08x}", self.cpu.short_hash());
+                self.display.set_title_info(Some(&info));
+            }
+
+            #[cfg(feature = "sdl")]
+            if let Some(window) = &mut self.memory_window {
+                window.draw(&self.cpu);
+            }
+            #[cfg(feature = "sdl")]
+            if let Some(window) = &mut self.debug_window {
+                window.draw(&self.cpu, &self.symbols);
+            }
+
+            if let Some(text) = self.status.as_mut().and_then(|status| status.frame(Instant::now())) {
+                self.display.set_title_status(&text);
+            }
+
+            // The HUD, and a note over it while paused.
+            let cpu = &self.cpu;
+            let mut text = self.hud.as_mut().map(|hud| {
+                hud.sample(Instant::now(), cpu.hooks().executed());
+                hud.lines(cpu)
+            });
+            if paused {
+                text.get_or_insert_with(Vec::new).insert(0, "PAUSED".to_string());
+            }
+            if text.is_some() {
+                self.display.set_hud(text);
+                self.display.redraw();
+            }
+
+            // Sleep for whatever is left of the frame. Ensures games run at ~60FPS. With vsync, presenting waits.
+            if self.display.vsync() {
+                self.display.present_vsync();
+            } else if let Some(remaining) = FRAME_DURATION.checked_sub(frame_start.elapsed()) {
+                std::thread::sleep(remaining);
+            }
+        }
+        None
+    }
+
+    /// Scroll the memory window with the mouse wheel, and forget the windows which have been closed.
+    #[cfg(feature = "sdl")]
+    fn update_windows(&mut self, input: &Input) {
+        if self.debug_window.as_ref().is_some_and(|window| input.closed.contains(&window.id())) {
+            self.debug_window = None;
+        }
+        let window = match &mut self.memory_window {
+            Some(window) => window,
+            None => return,
+        };
+
+        let id = window.id();
+        if input.closed.contains(&id) {
+            self.memory_window = None;
+            return;
+        }
+        for (_, notches) in input.wheel.iter().filter(|(window_id, _)| *window_id == id) {
+            window.scroll(*notches, &self.cpu);
+        }
+    }
+
+    #[cfg(not(feature = "sdl"))]
+    fn update_windows(&mut self, _input: &Input) {}
+
+    /// Open the memory window, or close it if it's open.
+    #[cfg(feature = "sdl")]
+    fn toggle_memory_window(&mut self) {
+        match (self.memory_window.take(), &self.sdl_context) {
+            (Some(_), _) => {} // Dropping the window closes it.
+            (None, Some(sdl_context)) => match MemoryWindow::new(sdl_context, &self.cpu) {
+                Ok(window) => self.memory_window = Some(window),
+                Err(e) => warn!("unable to open the memory window: {}", e),
+            },
+            (None, None) => warn!("the memory window needs SDL's display"),
+        }
+    }
+
+    #[cfg(not(feature = "sdl"))]
+    fn toggle_memory_window(&mut self) {
+        warn!("the memory window needs chip8 to be built with the sdl feature");
+    }
+
+    /// Open the debug window, or close it if it's open.
+    #[cfg(feature = "sdl")]
+    fn toggle_debug_window(&mut self) {
+        match (self.debug_window.take(), &self.sdl_context) {
+            (Some(_), _) => {}
+            (None, Some(sdl_context)) => match DebugWindow::new(sdl_context) {
+                Ok(window) => self.debug_window = Some(window),
+                Err(e) => warn!("unable to open the debug window: {}", e),
+            },
+            (None, None) => warn!("the debug window needs SDL's display"),
+        }
+    }
+
+    #[cfg(not(feature = "sdl"))]
+    fn toggle_debug_window(&mut self) {
+        warn!("the debug window needs chip8 to be built with the sdl feature");
+    }
+
+    /// Run a frame's worth of instructions and tick the timers once, then show the result.
+    fn advance_frame(&mut self) {
+        match self.timing {
+            Timing::Fixed(ipf) => self.cpu.step_frame(ipf),
+            Timing::CosmacVip => self.cpu.step_vip_frame(),
+        }
+        self.rewind.record(&mut self.cpu);
+        self.present();
+    }
+
+    /// Undo the last few instructions, as far back as has been recorded.
+    fn step_back(&mut self, instructions: usize) {
+        let undone = (0..instructions).take_while(|_| self.rewind.step_back_instruction(&mut self.cpu)).count();
+        if undone < instructions {
+            println!("Can't go back any further");
+        }
+        self.present();
+    }
+
+    /// Redo instructions undone by stepping back, as they first ran.
+    fn step_forward(&mut self, instructions: usize) {
+        let redone = (0..instructions).take_while(|_| self.rewind.step_forward_instruction(&mut self.cpu)).count();
+        if redone < instructions {
+            println!("Nothing more to go forward to");
+        }
+        self.present();
+    }
+
+    /// Report a softlock and save the state next to the suspend file so it can be resumed, and stepped back through, to
+    /// look into it.
+    fn capture_softlock(&mut self, report: &str) {
+        println!("{}. Paused", report);
+        self.cpu.dbg();
+
+        let path = self.suspend_file.with_extension("softlock");
+        match self.save_snapshot(&path, true) {
+            Ok(()) => println!("State saved to {}. Resume it with --resume --suspend-file", path.display()),
+            Err(e) => error!("{}", e),
+        }
+        self.display.set_title_info(Some("softlock - paused"));
+    }
+
+    /// Write the session to the suspend file, along with what has been recorded for rewinding.
+    fn suspend(&mut self) -> Result<(), String> {
+        self.save_snapshot(&self.suspend_file.clone(), true)?;
+        println!("Suspended to {}", self.suspend_file.display());
+        Ok(())
+    }
+
+    /// Save the session to the current slot.
+    fn save_state(&mut self) -> Result<(), String> {
+        let path = suspend::slot_path(&self.save_base, self.slot);
+        self.save_snapshot(&path, false)?;
+        println!("Saved slot {} to {}", self.slot, path.display());
+        Ok(())
+    }
+
+    // Write the session to a file, with the journal if asked. The CPU and journal are moved into the snapshot and
+    // back so they are never copied.
+    fn save_snapshot(&mut self, path: &Path, journal: bool) -> Result<(), String> {
+        let snapshot = Snapshot {
+            cpu: std::mem::take(&mut self.cpu),
+            timing: self.timing,
+            journal: journal.then(|| self.rewind.take_journal()),
+        };
+
+        let result = snapshot.save(path);
+        self.cpu = snapshot.cpu;
+        if let Some(journal) = snapshot.journal {
+            self.rewind.set_journal(journal);
+        }
+        result
+    }
+
+    /// Replace the session with the current slot, if it was saved from the same ROM.
+    fn load_state(&mut self) -> Result<(), String> {
+        let path = suspend::slot_path(&self.save_base, self.slot);
+        let Snapshot { cpu, timing, .. } = Snapshot::load_for(&path, &self.cpu)?;
+
+        self.cpu.restore(cpu);
+        self.rewind.clear();
+        self.timing = timing;
+        if let Some(status) = &mut self.status {
+            status.set_timing(timing);
+            self.display.set_title_status(&status.to_string());
+        }
+        self.present();
+
+        println!("Loaded slot {}", self.slot);
+        Ok(())
+    }
+
+    /// Draw the current frame, coloured by the CHIP-8X colour zones if there are any, or the MegaChip screen when in
+    /// MegaChip mode. The display hears first if the frame is a new size.
+    fn present(&mut self) {
+        let coloured = self.render();
+        let screen = match &coloured {
+            Some((colours, width)) => (*width, colours.len() / width),
+            None => (self.cpu.frame().width(), self.cpu.frame().height()),
+        };
+        if screen != self.screen {
+            self.screen = screen;
+            self.display.resize(screen.0, screen.1);
+        }
+        match &coloured {
+            Some((colours, width)) => self.display.draw_rgb(colours, *width),
+            None => self.display.present(self.cpu.frame()),
+        }
+
+        #[cfg(feature = "stream")]
+        if let Some(hook) = &mut self.frame_hook {
+            let (keys, keys2) = self.cpu.held_keys();
+            let colours = match coloured {
+                Some((colours, _)) => colours,
+                None => apply_palette(&self.cpu.get_framebuffer(), self.display.palette()),
+            };
+            let frame = Frame {
+                colours: &colours,
+                width: screen.0,
+                keys,
+                keys2,
+            };
+
+            // Give up on the hook rather than reporting the same failure every frame.
+            if let Err(e) = hook.frame(&frame) {
+                error!("{}", e);
+                self.frame_hook = None;
+            }
+        }
+    }
+
+    // Colour the screen as it should be shown. Returns the colours row by row and the width of a row, or None if the
+    // frame only needs the palette, which the display applies.
+    fn render(&mut self) -> Option<(Vec<[u8; 3]>, usize)> {
+        #[cfg(feature = "megachip")]
+        if let Some(mega) = self.cpu.megachip().filter(|mega| mega.enabled()) {
+            return Some((mega.render(), megachip::WIDTH));
+        }
+
+        // Colour zones only cover the lores screen.
+        let zones = self.cpu.colour_zones().filter(|_| !self.cpu.hires());
+        let diff = self.show_diff || self.diff_video.is_some();
+        if zones.is_none() && !diff && self.phosphor.is_none() {
+            return None;
+        }
+
+        let pixels = self.cpu.get_framebuffer();
+        let width = self.cpu.frame().width();
+        let colours = match zones {
+            Some(zones) => zones.colourise(&pixels),
+            None => apply_palette(&pixels, self.display.palette()),
+        };
+
+        if diff {
+            let diff = self.diff.render(&pixels, &colours);
+            if let Some(video) = &mut self.diff_video {
+                // Stop recording rather than reporting the same failure every frame.
+                if let Err(e) = video.write_frame(&diff, width) {
+                    error!("{}", e);
+                    self.diff_video = None;
+                }
+            }
+            if self.show_diff {
+                return Some((diff, width));
+            }
+        }
+
+        match &mut self.phosphor {
+            Some(phosphor) => Some((phosphor.render(&pixels, &colours), width)),
+            None => Some((colours, width)),
         }
     }
 }
 
 // Read ROM into &[u8] which can then be loaded into CPU memory.
-fn rom_from_path(path: &str) -> Vec<u8> {
+fn rom_from_path(path: &Path) -> Vec<u8> {
     let mut file = File::open(path).expect("unable to open file");
     let mut rom = Vec::new();
 
     file.read_to_end(&mut rom).expect("interrupted reading rom");
     rom
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_PALETTE;
+    use crate::display::Headless;
+
+    // Load a ROM into a VM with a headless display which closes after a number of polls.
+    fn vm(rom: &[u8], polls: usize) -> (VM, Headless) {
+        let mut cpu = CPU::new(Variant::Chip8);
+        cpu.load(rom.to_vec()).unwrap();
+        let snapshot = Snapshot { cpu, timing: Timing::Fixed(INSTRUCTIONS_PER_FRAME), journal: None };
+
+        let display = Headless::new(polls);
+        let (base, suspend_file) = (PathBuf::from("test.ch8"), PathBuf::from("test.suspend"));
+        let vm = VM::new(snapshot, Config::default(), base, suspend_file, Box::new(display.clone())).unwrap();
+        (vm, display)
+    }
+
+    // Run a ROM on a headless display which closes after a number of polls. Returns how the ROM exited, if it did.
+    fn play(rom: &[u8], polls: usize) -> (Option<Halt>, Headless) {
+        let (mut vm, display) = vm(rom, polls);
+        (vm.run(Mode::Release), display)
+    }
+
+    #[test]
+    fn test_vms_run_on_any_display() {
+        // LD V0, 0; LD F, V0; DRW V0, V0, 5; EXIT
+        let (exit, display) = play(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x00, 0xFD], 10);
+        assert_eq!(exit, Some(Halt::Exit { addr: 0x206 }));
+
+        let shown = display.shown.borrow();
+        assert_eq!(shown.screen, Some((64, 32)));
+        assert_eq!(shown.frames.len(), 1);
+        let (colours, width) = &shown.frames[0];
+        assert_eq!((colours[0], colours[4], *width), (DEFAULT_PALETTE[1], DEFAULT_PALETTE[0], 64));
+    }
+
+    #[test]
+    fn test_vms_stop_when_the_display_closes() {
+        // JP 200
+        let (exit, display) = play(&[0x12, 0x00], 3);
+        assert_eq!(exit, None);
+
+        let shown = display.shown.borrow();
+        assert_eq!(shown.frames.len(), 3);
+        assert_eq!(shown.title.as_deref(), Some("program finished"));
+    }
+
+    #[test]
+    fn test_displays_hear_when_the_screen_changes_size() {
+        // HIGH; EXIT
+        let (_, display) = play(&[0x00, 0xFF, 0x00, 0xFD], 10);
+        let shown = display.shown.borrow();
+        assert_eq!(shown.screen, Some((128, 64)));
+        assert_eq!(shown.frames[0].1, 128);
+    }
+    #[test]
+    fn test_status_comes_from_the_rom_loaded() {
+        // The file is never read, so it doesn't matter that there isn't one.
+        let (mut vm, display) = vm(&[0x12, 0x00], 0);
+        vm.show_status(Path::new("missing/loop.ch8"));
+        assert_eq!(display.shown.borrow().status.as_deref(), Some("loop.ch8 | chip8 | 1x"));
+    }
+}
@@ -0,0 +1,338 @@
+//! Experimental MegaChip support, enabled with the `megachip` feature. MegaChip ROMs start out as ordinary programs
+//! and switch into MegaChip mode with 0011. In that mode the screen is 256x192 with 255 colours from a palette the
+//! program loads, sprites are blocks of palette indices blended onto the screen, and digitised sounds can be played.
+//! Drawing goes to a back buffer which 00E0 presents.
+
+// External imports
+use serde::{Deserialize, Serialize};
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 192;
+
+// Sound data starts after a header holding the sample rate (2 bytes), the sample count (3 bytes) and a padding byte.
+const SOUND_HEADER: usize = 6;
+
+/// How a sprite's colours are combined with what is already on screen. Selected by 080N.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Blend {
+    Normal,
+    Alpha25,
+    Alpha50,
+    Add,
+    Multiply,
+}
+
+impl Blend {
+    pub fn from_nibble(n: usize) -> Option<Self> {
+        match n {
+            0 => Some(Blend::Normal),
+            1 => Some(Blend::Alpha25),
+            2 => Some(Blend::Alpha50),
+            3 => Some(Blend::Add),
+            4 => Some(Blend::Multiply),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, src: [u8; 3], dst: [u8; 3]) -> [u8; 3] {
+        let mut out = [0; 3];
+        for c in 0..3 {
+            let (s, d) = (src[c] as u16, dst[c] as u16);
+            out[c] = match self {
+                Blend::Normal => s,
+                Blend::Alpha25 => (s + 3 * d) / 4,
+                Blend::Alpha50 => (s + d) / 2,
+                Blend::Add => (s + d).min(255),
+                Blend::Multiply => s * d / 255,
+            } as u8;
+        }
+        out
+    }
+}
+
+/// A digitised sound started by 060N. Samples are unsigned 8-bit PCM.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sound {
+    pub rate: u16,
+    pub samples: Vec<u8>,
+    pub looping: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MegaChip {
+    enabled: bool,
+    back: Vec<[u8; 3]>,  // Screen being drawn to.
+    indices: Vec<u8>,    // Palette index last drawn at each pixel of the back buffer. Used for collisions.
+    front: Vec<[u8; 3]>, // Screen shown by the last 00E0.
+    palette: Vec<[u8; 3]>,
+    sprite_width: usize,
+    sprite_height: usize,
+    alpha: u8,
+    blend: Blend,
+    collision_colour: u8,
+    sound: Option<Sound>,
+}
+
+impl Default for MegaChip {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            back: vec![[0; 3]; WIDTH * HEIGHT],
+            indices: vec![0; WIDTH * HEIGHT],
+            front: vec![[0; 3]; WIDTH * HEIGHT],
+            palette: vec![[0; 3]; 256],
+            sprite_width: 1,
+            sprite_height: 1,
+            alpha: 255,
+            blend: Blend::Normal,
+            collision_colour: 0,
+            sound: None,
+        }
+    }
+}
+
+impl MegaChip {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Switch MegaChip mode on or off. Either way the MegaChip screen starts out blank.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        *self = Self {
+            enabled,
+            palette: std::mem::take(&mut self.palette),
+            sound: self.sound.take(),
+            ..Self::default()
+        };
+    }
+
+    /// Load colours into the palette from index 1 onwards. Each colour is 4 bytes, ARGB. The alpha byte is ignored.
+    pub fn load_palette(&mut self, argb: &[u8]) {
+        for (entry, colour) in self.palette.iter_mut().skip(1).zip(argb.chunks_exact(4)) {
+            *entry = [colour[1], colour[2], colour[3]];
+        }
+    }
+
+    /// Set the width of sprites drawn by DXYN. 0 means 256.
+    pub fn set_sprite_width(&mut self, width: u8) {
+        self.sprite_width = sprite_dimension(width);
+    }
+
+    /// Set the height of sprites drawn by DXYN. 0 means 256.
+    pub fn set_sprite_height(&mut self, height: u8) {
+        self.sprite_height = sprite_dimension(height);
+    }
+
+    /// Number of bytes in a sprite of the current size.
+    pub fn sprite_len(&self) -> usize {
+        self.sprite_width * self.sprite_height
+    }
+
+    pub fn set_alpha(&mut self, alpha: u8) {
+        self.alpha = alpha;
+    }
+
+    pub fn set_blend(&mut self, blend: Blend) {
+        self.blend = blend;
+    }
+
+    pub fn set_collision_colour(&mut self, index: u8) {
+        self.collision_colour = index;
+    }
+
+    /// Start a sound from its header and data. Sounds running off the end of the data are cut short.
+    pub fn play(&mut self, data: &[u8], looping: bool) {
+        if data.len() < SOUND_HEADER {
+            return;
+        }
+
+        let rate = u16::from_be_bytes([data[0], data[1]]);
+        let len = u32::from_be_bytes([0, data[2], data[3], data[4]]) as usize;
+        let end = (SOUND_HEADER + len).min(data.len());
+
+        self.sound = Some(Sound {
+            rate,
+            samples: data[SOUND_HEADER..end].to_vec(),
+            looping,
+        });
+    }
+
+    pub fn stop_sound(&mut self) {
+        self.sound = None;
+    }
+
+    pub fn sound(&self) -> Option<&Sound> {
+        self.sound.as_ref()
+    }
+
+    /// Blit a sprite of palette indices, sprite_width by sprite_height, with its top left at (x, y). Index 0 is
+    /// transparent and the sprite is clipped at the edges of the screen. Returns true if the sprite drew over a pixel
+    /// of the collision colour. Collision colour 0 turns collisions off, otherwise every sprite would hit the blank
+    /// screen.
+    pub fn draw_sprite(&mut self, sprite: &[u8], x: usize, y: usize) -> bool {
+        let mut collision = false;
+
+        for (row, line) in sprite.chunks(self.sprite_width).take(self.sprite_height).enumerate() {
+            for (col, index) in line.iter().enumerate() {
+                let (px, py) = (x + col, y + row);
+                if *index == 0 || px >= WIDTH || py >= HEIGHT {
+                    continue;
+                }
+
+                let p = py * WIDTH + px;
+                collision |= self.collision_colour != 0 && self.indices[p] == self.collision_colour;
+                self.indices[p] = *index;
+                self.back[p] = self.blend.apply(self.palette[*index as usize], self.back[p]);
+            }
+        }
+
+        collision
+    }
+
+    /// Scroll the back buffer up by n rows. Rows scrolled in are blank.
+    pub fn scroll_up(&mut self, n: usize) {
+        let n = n.min(HEIGHT) * WIDTH;
+        self.back.drain(..n);
+        self.back.resize(WIDTH * HEIGHT, [0; 3]);
+        self.indices.drain(..n);
+        self.indices.resize(WIDTH * HEIGHT, 0);
+    }
+
+    /// Show the back buffer and clear it for the next frame.
+    pub fn present(&mut self) {
+        self.front = std::mem::replace(&mut self.back, vec![[0; 3]; WIDTH * HEIGHT]);
+        self.indices = vec![0; WIDTH * HEIGHT];
+    }
+
+    /// Colours of the presented screen, row by row, faded by the screen alpha.
+    pub fn render(&self) -> Vec<[u8; 3]> {
+        let fade = |c: u8| (c as u16 * self.alpha as u16 / 255) as u8;
+        self.front.iter().map(|[r, g, b]| [fade(*r), fade(*g), fade(*b)]).collect()
+    }
+}
+
+fn sprite_dimension(n: u8) -> usize {
+    if n == 0 {
+        256
+    } else {
+        n as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_palette() -> MegaChip {
+        let mut mega = MegaChip::default();
+        mega.set_enabled(true);
+        mega.load_palette(&[0xFF, 200, 0, 0, 0xFF, 0, 100, 0]);
+        mega
+    }
+
+    #[test]
+    fn test_loading_palette_skips_index_0() {
+        let mega = with_palette();
+        assert_eq!(mega.palette[0], [0, 0, 0]);
+        assert_eq!(mega.palette[1], [200, 0, 0]);
+        assert_eq!(mega.palette[2], [0, 100, 0]);
+    }
+
+    #[test]
+    fn test_sprite_size_zero_means_256() {
+        let mut mega = MegaChip::default();
+        mega.set_sprite_width(0);
+        mega.set_sprite_height(3);
+        assert_eq!(mega.sprite_width, 256);
+        assert_eq!(mega.sprite_height, 3);
+        assert_eq!(mega.sprite_len(), 768);
+    }
+
+    #[test]
+    fn test_drawing_is_shown_after_present() {
+        let mut mega = with_palette();
+        mega.set_sprite_width(2);
+        mega.set_sprite_height(2);
+        mega.draw_sprite(&[1, 0, 2, 1], 10, 5);
+        assert_eq!(mega.render()[5 * WIDTH + 10], [0, 0, 0]);
+
+        mega.present();
+        let screen = mega.render();
+        assert_eq!(screen[5 * WIDTH + 10], [200, 0, 0]);
+        assert_eq!(screen[5 * WIDTH + 11], [0, 0, 0]); // Index 0 is transparent.
+        assert_eq!(screen[6 * WIDTH + 10], [0, 100, 0]);
+        assert_eq!(screen[6 * WIDTH + 11], [200, 0, 0]);
+
+        // Presenting clears the back buffer.
+        mega.present();
+        assert_eq!(mega.render()[5 * WIDTH + 10], [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_sprites_are_clipped() {
+        let mut mega = with_palette();
+        mega.set_sprite_width(4);
+        mega.draw_sprite(&[1, 1, 1, 1], WIDTH - 2, HEIGHT - 1);
+        mega.present();
+
+        let screen = mega.render();
+        assert_eq!(screen[WIDTH * HEIGHT - 1], [200, 0, 0]);
+        assert_eq!(screen[0], [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_collision_colour() {
+        let mut mega = with_palette();
+        mega.set_collision_colour(2);
+
+        assert!(!mega.draw_sprite(&[1], 0, 0));
+        assert!(!mega.draw_sprite(&[2], 0, 0));
+        assert!(mega.draw_sprite(&[1], 0, 0));
+    }
+
+    #[test]
+    fn test_blending() {
+        assert_eq!(Blend::Alpha50.apply([200, 0, 0], [0, 100, 0]), [100, 50, 0]);
+        assert_eq!(Blend::Alpha25.apply([200, 0, 0], [0, 100, 0]), [50, 75, 0]);
+        assert_eq!(Blend::Add.apply([200, 200, 0], [100, 0, 0]), [255, 200, 0]);
+        assert_eq!(Blend::Multiply.apply([255, 128, 0], [100, 100, 100]), [100, 50, 0]);
+        assert_eq!(Blend::from_nibble(5), None);
+    }
+
+    #[test]
+    fn test_screen_alpha_fades() {
+        let mut mega = with_palette();
+        mega.draw_sprite(&[1], 0, 0);
+        mega.present();
+        mega.set_alpha(128);
+        assert_eq!(mega.render()[0], [100, 0, 0]);
+    }
+
+    #[test]
+    fn test_scroll_up() {
+        let mut mega = with_palette();
+        mega.draw_sprite(&[1], 0, 3);
+        mega.scroll_up(2);
+        mega.present();
+        assert_eq!(mega.render()[WIDTH], [200, 0, 0]);
+        assert_eq!(mega.render()[3 * WIDTH], [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_playing_sound() {
+        let mut mega = MegaChip::default();
+        mega.play(&[0x1F, 0x40, 0x00, 0x00, 0x03, 0x00, 10, 20, 30, 40], false);
+
+        let sound = mega.sound().unwrap();
+        assert_eq!(sound.rate, 8000);
+        assert_eq!(sound.samples, vec![10, 20, 30]);
+        assert!(!sound.looping);
+
+        // Sounds longer than the data available are cut short.
+        mega.play(&[0x1F, 0x40, 0xFF, 0xFF, 0xFF, 0x00, 10], true);
+        assert_eq!(mega.sound().unwrap().samples, vec![10]);
+
+        mega.stop_sound();
+        assert_eq!(mega.sound(), None);
+    }
+}
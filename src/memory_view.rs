@@ -0,0 +1,172 @@
+//! Live hexdump of memory, shown in its own window by the memory hotkey. There is no text rendering, so it is drawn
+//! with the CHIP-8 font's hex digits, a row of 16 bytes at a time after the row's address. The bytes I and the PC
+//! point at are highlighted. The view follows the PC until it is scrolled with the mouse wheel.
+
+// Self imports
+use crate::cpu::{CPU, FONT};
+
+pub const BYTES_PER_ROW: usize = 16;
+pub const ROWS: usize = 32;
+
+// Font digits are 4x5 pixels. Each gets a column of space after it and two rows below.
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 5;
+const CELL_WIDTH: usize = GLYPH_WIDTH + 1;
+const CELL_HEIGHT: usize = GLYPH_HEIGHT + 2;
+
+/// Height of the view in pixels.
+pub const HEIGHT: usize = ROWS * CELL_HEIGHT;
+
+type Rgb = [u8; 3];
+
+const BACKGROUND: Rgb = [0x18, 0x18, 0x18];
+const ADDRESS: Rgb = [0x80, 0x80, 0x80];
+const BYTE: Rgb = [0xE0, 0xE0, 0xE0];
+const PC: Rgb = [0x00, 0x70, 0x00];
+const I: Rgb = [0x00, 0x30, 0x90];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryView {
+    top: usize,            // First row shown.
+    follow: bool,          // Whether to keep the PC in view.
+    address_digits: usize, // Enough for the last address, 3 for 4K.
+}
+
+impl MemoryView {
+    pub fn new(memory_size: usize) -> Self {
+        let last = format!("{:X}", memory_size.saturating_sub(1));
+        Self {
+            top: 0,
+            follow: true,
+            address_digits: last.len().max(3),
+        }
+    }
+
+    /// Width of the view in pixels. Each row has the address, two spaces, then the bytes separated by spaces.
+    pub fn width(&self) -> usize {
+        (self.address_digits + 2 + BYTES_PER_ROW * 3 - 1) * CELL_WIDTH
+    }
+
+    /// Move the view by some rows, up for negative. Stops following the PC.
+    pub fn scroll(&mut self, rows: i32, cpu: &CPU) {
+        let last = (cpu.memory_size() / BYTES_PER_ROW).saturating_sub(ROWS);
+        self.top = (self.top as i64 + rows as i64).clamp(0, last as i64) as usize;
+        self.follow = false;
+    }
+
+    /// Draw the rows in view as colours, row by row.
+    pub fn render(&mut self, cpu: &CPU) -> Vec<Rgb> {
+        let pc_row = cpu.pc() / BYTES_PER_ROW;
+        if self.follow && !(self.top..self.top + ROWS).contains(&pc_row) {
+            let last = (cpu.memory_size() / BYTES_PER_ROW).saturating_sub(ROWS);
+            self.top = pc_row.saturating_sub(ROWS / 4).min(last);
+        }
+
+        let width = self.width();
+        let mut pixels = vec![BACKGROUND; width * HEIGHT];
+        let i = cpu.registers().i;
+        for row in 0..ROWS {
+            let addr = (self.top + row) * BYTES_PER_ROW;
+            if addr >= cpu.memory_size() {
+                break;
+            }
+
+            let mut draw = |column: usize, text: &str, colour: Rgb, background: Rgb| {
+                for (n, digit) in text.chars().enumerate() {
+                    let value = digit.to_digit(16).unwrap_or(0) as usize;
+                    let corner = ((column + n) * CELL_WIDTH, row * CELL_HEIGHT);
+                    glyph(&mut pixels, width, corner, value, colour, background);
+                }
+            };
+
+            draw(0, &format!("{:0width$X}", addr, width = self.address_digits), ADDRESS, BACKGROUND);
+            for offset in 0..BYTES_PER_ROW {
+                let byte = addr + offset;
+                let background = match byte {
+                    _ if byte == cpu.pc() || byte == cpu.pc() + 1 => PC,
+                    _ if byte == i => I,
+                    _ => BACKGROUND,
+                };
+                draw(self.address_digits + 2 + offset * 3, &format!("{:02X}", cpu.peek(byte)), BYTE, background);
+            }
+        }
+        pixels
+    }
+}
+
+// Draw a hex digit in a cell with its top left corner at (x, y) in a view `width` pixels wide, filling the rest of
+// the cell with the background.
+fn glyph(pixels: &mut [Rgb], width: usize, (x, y): (usize, usize), digit: usize, colour: Rgb, background: Rgb) {
+    for row in 0..CELL_HEIGHT {
+        let bits = match row {
+            1..=GLYPH_HEIGHT => FONT[digit * GLYPH_HEIGHT + row - 1],
+            _ => 0,
+        };
+        for column in 0..CELL_WIDTH {
+            let lit = column < GLYPH_WIDTH && bits & (0x80 >> column) != 0;
+            pixels[(y + row) * width + x + column] = if lit { colour } else { background };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 52 * CELL_WIDTH; // With 3 address digits.
+
+    // Whether any pixel in the cell at a column and row has a colour.
+    fn cell_has(pixels: &[Rgb], column: usize, row: usize, colour: Rgb) -> bool {
+        (0..CELL_HEIGHT).any(|y| {
+            (0..CELL_WIDTH).any(|x| pixels[(row * CELL_HEIGHT + y) * WIDTH + column * CELL_WIDTH + x] == colour)
+        })
+    }
+
+    #[test]
+    fn test_view_follows_the_pc() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x12, 0x00]).unwrap();
+        let mut view = MemoryView::new(4096);
+
+        let pixels = view.render(&cpu);
+        assert_eq!(pixels.len(), WIDTH * HEIGHT);
+        assert_eq!(view.top, 0x200 / BYTES_PER_ROW - ROWS / 4);
+
+        // The PC's two bytes are highlighted, and nothing else is.
+        let row = ROWS / 4;
+        assert!(cell_has(&pixels, 5, row, PC));
+        assert!(cell_has(&pixels, 8, row, PC));
+        assert!(!cell_has(&pixels, 11, row, PC));
+        assert!(cell_has(&pixels, 5, row, BYTE));
+    }
+
+    #[test]
+    fn test_scrolling_stops_following() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x12, 0x00]).unwrap();
+        let mut view = MemoryView::new(4096);
+
+        view.scroll(-1000, &cpu);
+        view.render(&cpu);
+        assert_eq!(view.top, 0);
+
+        view.scroll(1000, &cpu);
+        assert_eq!(view.top, 4096 / BYTES_PER_ROW - ROWS);
+    }
+
+    #[test]
+    fn test_addresses_fit_the_memory() {
+        assert_eq!(MemoryView::new(4096).width(), WIDTH);
+        assert_eq!(MemoryView::new(0x10000).width(), WIDTH + CELL_WIDTH);
+    }
+
+    #[test]
+    fn test_digits_are_drawn_with_the_font() {
+        let mut pixels = vec![BACKGROUND; WIDTH * HEIGHT];
+        glyph(&mut pixels, WIDTH, (0, 0), 0xF, BYTE, I);
+
+        let lit: Vec<bool> = (0..CELL_WIDTH).map(|x| pixels[WIDTH + x] == BYTE).collect();
+        assert_eq!(lit, vec![true, true, true, true, false]);
+        assert_eq!(pixels[0], I);
+    }
+}
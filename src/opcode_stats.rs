@@ -0,0 +1,167 @@
+//! Per-opcode execution statistics, so ROM authors can see where their programs spend cycles. Counts are kept for
+//! every opcode and grouped into the families of the conformance table when reported. Timing is optional as reading
+//! the clock twice per instruction costs far more than the instructions themselves.
+
+// Self imports
+use crate::conformance;
+use crate::cpu::CPU;
+
+// Std imports
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Totals for one opcode family.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Family {
+    pub pattern: &'static str,
+    pub mnemonic: &'static str,
+    pub count: u64,
+    pub time: Option<Duration>,
+}
+
+pub struct OpcodeStats {
+    counts: Vec<u64>,         // Indexed by opcode.
+    nanos: Option<Vec<u64>>,  // Indexed by opcode, when timing.
+    started: Option<Instant>, // When the current instruction started, when timing.
+}
+
+impl OpcodeStats {
+    pub fn new(timed: bool) -> Self {
+        Self {
+            counts: vec![0; 1 << 16],
+            nanos: if timed { Some(vec![0; 1 << 16]) } else { None },
+            started: None,
+        }
+    }
+
+    /// Start collecting statistics for every instruction the CPU executes.
+    pub fn attach(cpu: &mut CPU, timed: bool) -> Rc<RefCell<Self>> {
+        let stats = Rc::new(RefCell::new(Self::new(timed)));
+
+        if timed {
+            let before = stats.clone();
+            cpu.hooks_mut().before(move |_, _| before.borrow_mut().started = Some(Instant::now()));
+        }
+
+        let after = stats.clone();
+        cpu.hooks_mut().after(move |_, step| after.borrow_mut().record(step.opcode.0));
+
+        stats
+    }
+
+    fn record(&mut self, opcode: u16) {
+        self.counts[opcode as usize] += 1;
+
+        if let (Some(nanos), Some(started)) = (&mut self.nanos, self.started.take()) {
+            nanos[opcode as usize] += started.elapsed().as_nanos() as u64;
+        }
+    }
+
+    /// Times an opcode has been executed.
+    pub fn count(&self, opcode: u16) -> u64 {
+        self.counts[opcode as usize]
+    }
+
+    /// Instructions executed.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Totals for every family executed, most executed first. Opcodes outside the table are grouped under ????.
+    pub fn families(&self) -> Vec<Family> {
+        let mut families: HashMap<&'static str, Family> = HashMap::new();
+
+        for (opcode, count) in self.counts.iter().enumerate().filter(|(_, count)| **count > 0) {
+            let info = conformance::family(opcode as u16);
+            let pattern = info.map_or("????", |info| info.pattern);
+
+            let family = families.entry(pattern).or_insert_with(|| Family {
+                pattern,
+                mnemonic: info.map_or("", |info| info.mnemonic),
+                count: 0,
+                time: self.nanos.as_ref().map(|_| Duration::default()),
+            });
+            family.count += count;
+            if let (Some(time), Some(nanos)) = (&mut family.time, &self.nanos) {
+                *time += Duration::from_nanos(nanos[opcode]);
+            }
+        }
+
+        let mut families: Vec<Family> = families.into_values().collect();
+        families.sort_by(|a, b| b.count.cmp(&a.count).then(a.pattern.cmp(b.pattern)));
+        families
+    }
+}
+
+/// The end of run report. One line per family, most executed first.
+impl fmt::Display for OpcodeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total();
+        writeln!(f, "{} instructions executed", total)?;
+
+        for family in self.families() {
+            let share = 100.0 * family.count as f64 / total as f64;
+            write!(f, "{:<6}{:<18}{:>12}{:>7.1}%", family.pattern, family.mnemonic, family.count, share)?;
+            match family.time {
+                Some(time) => writeln!(f, "{:>12?}", time)?,
+                None => writeln!(f)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Adds to V0 three times then loops back.
+    const ROM: [u8; 8] = [0x70, 0x01, 0x70, 0x02, 0x70, 0x03, 0x12, 0x00];
+
+    fn run(timed: bool) -> Rc<RefCell<OpcodeStats>> {
+        let mut cpu = CPU::default();
+        cpu.load(ROM.to_vec()).unwrap();
+        let stats = OpcodeStats::attach(&mut cpu, timed);
+        cpu.step_frame(40);
+        stats
+    }
+
+    #[test]
+    fn test_counting_opcodes() {
+        let stats = run(false);
+        let stats = stats.borrow();
+
+        assert_eq!(stats.total(), 40);
+        assert_eq!(stats.count(0x7001), 10);
+        assert_eq!(stats.count(0x1200), 10);
+    }
+
+    #[test]
+    fn test_grouping_families() {
+        let stats = run(false);
+        let families = stats.borrow().families();
+
+        assert_eq!(families.len(), 2);
+        assert_eq!((families[0].pattern, families[0].mnemonic, families[0].count), ("7XKK", "ADD Vx, kk", 30));
+        assert_eq!((families[1].pattern, families[1].count), ("1NNN", 10));
+        assert!(families[0].time.is_none());
+    }
+
+    #[test]
+    fn test_timing() {
+        let stats = run(true);
+        let families = stats.borrow().families();
+        assert!(families.iter().all(|family| family.time.is_some()));
+    }
+
+    #[test]
+    fn test_report() {
+        let report = run(false).borrow().to_string();
+        assert!(report.starts_with("40 instructions executed\n7XKK  ADD Vx, kk"));
+        assert!(report.contains("75.0%"));
+    }
+}
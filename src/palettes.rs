@@ -0,0 +1,96 @@
+//! Named palettes, and palette files for any others. Anywhere a palette name is accepted (--palette, or a name in
+//! place of the colours in the config's display section), the path of a palette file is too.
+//!
+//! A palette file lists colours as the config writes them, separated by spaces or new lines: four for off, plane 0,
+//! plane 1 and both planes, or just two for off and on, e.g. a Game Boy screen
+//!
+//! ```text
+//! #9BBC0F
+//! #0F380F
+//! ```
+
+// Self imports
+use crate::config::{parse_colour, DEFAULT_PALETTE};
+use crate::frame_buffer::Palette;
+
+// Std imports
+use std::fs;
+
+pub struct Preset {
+    pub name: &'static str,
+    pub palette: Palette,
+    pub description: &'static str,
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "default",
+        palette: DEFAULT_PALETTE,
+        description: "Green, orange and white on black. The default",
+    },
+    Preset {
+        name: "green",
+        palette: [[0, 20, 0], [51, 255, 51], [26, 158, 26], [179, 255, 179]],
+        description: "Classic green phosphor monitor",
+    },
+    Preset {
+        name: "amber",
+        palette: [[26, 15, 0], [255, 176, 0], [179, 107, 0], [255, 223, 153]],
+        description: "Amber phosphor monitor",
+    },
+    Preset {
+        name: "gameboy",
+        palette: [[155, 188, 15], [15, 56, 15], [139, 172, 15], [48, 98, 48]],
+        description: "The original Game Boy's four greens",
+    },
+    Preset {
+        name: "paper",
+        palette: [[245, 241, 230], [30, 30, 30], [140, 140, 140], [80, 80, 80]],
+        description: "Dark ink on paper white",
+    },
+];
+
+/// A preset's palette, or else the palette in the file at the given path.
+pub fn load(name: &str) -> Result<Palette, String> {
+    if let Some(preset) = PRESETS.iter().find(|preset| preset.name.eq_ignore_ascii_case(name)) {
+        return Ok(preset.palette);
+    }
+
+    match fs::read_to_string(name) {
+        Ok(text) => parse(&text).map_err(|e| format!("{}: {}", name, e)),
+        Err(_) => {
+            let names: Vec<&str> = PRESETS.iter().map(|preset| preset.name).collect();
+            Err(format!("unknown palette: {} (expected {} or a palette file)", name, names.join(", ")))
+        }
+    }
+}
+
+/// Read a palette file's colours.
+pub fn parse(text: &str) -> Result<Palette, String> {
+    let colours = text.split_whitespace().map(parse_colour).collect::<Result<Vec<_>, _>>()?;
+    match colours[..] {
+        [off, on] => Ok([off, on, on, on]),
+        [off, plane0, plane1, both] => Ok([off, plane0, plane1, both]),
+        _ => Err(format!("palette needs 2 or 4 colours, not {}", colours.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loading_presets() {
+        assert_eq!(load("default"), Ok(DEFAULT_PALETTE));
+        assert_eq!(load("GameBoy"), Ok(PRESETS[3].palette));
+        assert!(load("no-such-palette").unwrap_err().starts_with("unknown palette: no-such-palette"));
+    }
+
+    #[test]
+    fn test_parsing_palette_files() {
+        assert_eq!(parse("#000000\n#FFFFFF\n"), Ok([[0; 3], [255; 3], [255; 3], [255; 3]]));
+        assert_eq!(parse("000000 111111  222222\n333333"), Ok([[0; 3], [0x11; 3], [0x22; 3], [0x33; 3]]));
+        assert_eq!(parse("#000000 #111111 #222222"), Err("palette needs 2 or 4 colours, not 3".to_string()));
+        assert_eq!(parse("#00000G #111111"), Err("invalid colour: #00000G".to_string()));
+    }
+}
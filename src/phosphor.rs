@@ -0,0 +1,105 @@
+//! Phosphor decay, emulating a CRT's persistence. Pixels which turn off fade into the background over a number of
+//! frames instead of vanishing at once, which hides the flicker of games erasing and redrawing sprites every frame.
+//! Set with `decay` in the display section of the config, and how the fade goes with `decay_rate`. At 1 a pixel
+//! dims by the same amount each frame. Higher rates dim it quickly at first and then linger, like real phosphor,
+//! and lower ones keep it bright for longer before it drops away.
+//!
+//! This is the only persistence there is. Without decay, a pixel turned off is off in the very next frame.
+//!
+//! MegaChip mode draws its own screen and doesn't fade.
+
+pub struct Phosphor {
+    frames: u32,                // Frames a pixel takes to fade out.
+    rate: f32,                  // Power the share of the fade left is raised to. 1 fades evenly.
+    glow: Vec<([u8; 3], u32)>, // Colour each pixel was last lit with and the frames since.
+}
+
+impl Phosphor {
+    pub fn new(frames: u32, rate: f32) -> Self {
+        Self { frames, rate, glow: Vec::new() }
+    }
+
+    /// Fade the pixels which have turned off. `pixels` are the frame's plane bits, 0 for off, and `colours` the frame
+    /// as it would normally be shown. Nothing fades into the first frame, or one of a new size.
+    pub fn render(&mut self, pixels: &[u8], colours: &[[u8; 3]]) -> Vec<[u8; 3]> {
+        if self.glow.len() != pixels.len() {
+            self.glow = vec![([0; 3], self.frames); pixels.len()];
+        }
+
+        let (frames, rate) = (self.frames, self.rate);
+        pixels
+            .iter()
+            .zip(colours)
+            .zip(&mut self.glow)
+            .map(|((pixel, colour), (lit, age))| {
+                if *pixel != 0 {
+                    *lit = *colour;
+                    *age = 0;
+                    return *colour;
+                }
+
+                *age = age.saturating_add(1);
+                match *age < frames {
+                    true => fade(*lit, *colour, *age, frames, rate),
+                    false => *colour,
+                }
+            })
+            .collect()
+    }
+}
+
+// The colour `age` frames of `frames` along the way from a lit colour to the background.
+fn fade(lit: [u8; 3], background: [u8; 3], age: u32, frames: u32, rate: f32) -> [u8; 3] {
+    let glow = (1.0 - age as f32 / frames as f32).powf(rate);
+    let mut out = [0; 3];
+    for (channel, out) in out.iter_mut().enumerate() {
+        let (from, to) = (lit[channel] as f32, background[channel] as f32);
+        *out = (to + (from - to) * glow).round() as u8;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFF: [u8; 3] = [0, 0, 0];
+    const ON: [u8; 3] = [0, 240, 120];
+
+    #[test]
+    fn test_pixels_fade_out() {
+        let mut phosphor = Phosphor::new(4, 1.0);
+        assert_eq!(phosphor.render(&[1, 0], &[ON, OFF]), [ON, OFF]);
+
+        let fading: Vec<[u8; 3]> = (0..4).map(|_| phosphor.render(&[0, 0], &[OFF, OFF])[0]).collect();
+        assert_eq!(fading, [[0, 180, 90], [0, 120, 60], [0, 60, 30], OFF]);
+    }
+
+    #[test]
+    fn test_fade_rates() {
+        let fading = |rate| {
+            let mut phosphor = Phosphor::new(4, rate);
+            phosphor.render(&[1], &[ON]);
+            (0..4).map(|_| phosphor.render(&[0], &[OFF])[0][1]).collect::<Vec<u8>>()
+        };
+        assert_eq!(fading(2.0), [135, 60, 15, 0]);
+        assert_eq!(fading(0.5), [208, 170, 120, 0]);
+    }
+
+    #[test]
+    fn test_relit_pixels_show_at_once() {
+        let mut phosphor = Phosphor::new(4, 1.0);
+        phosphor.render(&[1], &[ON]);
+        phosphor.render(&[0], &[OFF]);
+        assert_eq!(phosphor.render(&[1], &[ON]), [ON]);
+    }
+
+    #[test]
+    fn test_one_frame_is_no_decay() {
+        for frames in [0, 1] {
+            let mut phosphor = Phosphor::new(frames, 1.0);
+            phosphor.render(&[1], &[ON]);
+            assert_eq!(phosphor.render(&[0], &[OFF]), [OFF]);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Named user profiles. Each profile keeps its own config, saves and play statistics in its own directory:
+//!
+//! ```text
+//! <chip8 dir>/profiles/<name>/config.toml   Same format as --config. Use [limits] for e.g. a kid mode.
+//! <chip8 dir>/profiles/<name>/saves/        Suspend files and save state slots.
+//! <chip8 dir>/profiles/<name>/stats.toml    How often and how long each ROM has been played.
+//! ```
+//!
+//! The chip8 directory is $CHIP8_HOME if set, otherwise chip8 in $XDG_CONFIG_HOME or ~/.config.
+
+// Self imports
+use crate::config::Config;
+
+// Std imports
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// External imports
+use serde::{Deserialize, Serialize};
+
+/// Play statistics for one ROM.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RomStats {
+    pub plays: u32,
+    pub seconds: u64,
+}
+
+/// Play statistics for every ROM a profile has run, keyed by the ROM's file name.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    #[serde(default)]
+    pub roms: BTreeMap<String, RomStats>,
+}
+
+pub struct Profile {
+    name: String,
+    dir: PathBuf,
+}
+
+impl Profile {
+    /// Open a profile in the chip8 directory, creating it if it doesn't exist yet.
+    pub fn open(name: &str) -> Result<Self, String> {
+        Self::open_in(&chip8_dir()?, name)
+    }
+
+    fn open_in(chip8_dir: &Path, name: &str) -> Result<Self, String> {
+        let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(format!("invalid profile name: {} (use letters, digits, - and _)", name));
+        }
+
+        let dir = chip8_dir.join("profiles").join(name);
+        fs::create_dir_all(dir.join("saves")).map_err(|e| format!("unable to create {}: {}", dir.display(), e))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            dir,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The profile's config, or the default config if it doesn't have one.
+    pub fn config(&self) -> Result<Config, String> {
+        let path = self.dir.join("config.toml");
+        if path.exists() {
+            Config::load(&path)
+        } else {
+            Ok(Config::default())
+        }
+    }
+
+    /// Path save files for a ROM are named after, by replacing its extension. e.g. pong.suspend for pong.ch8.
+    pub fn save_base(&self, rom: &Path) -> PathBuf {
+        self.dir.join("saves").join(rom.file_name().unwrap_or_default())
+    }
+
+    pub fn stats(&self) -> Result<Stats, String> {
+        let path = self.dir.join("stats.toml");
+        if !path.exists() {
+            return Ok(Stats::default());
+        }
+
+        let text = fs::read_to_string(&path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Add a play of a ROM to the profile's statistics.
+    pub fn record_play(&self, rom: &Path, played: Duration) -> Result<(), String> {
+        let mut stats = self.stats()?;
+        let name = rom.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+        let entry = stats.roms.entry(name).or_default();
+        entry.plays += 1;
+        entry.seconds += played.as_secs();
+
+        let path = self.dir.join("stats.toml");
+        let text = toml::to_string(&stats).map_err(|e| e.to_string())?;
+        fs::write(&path, text).map_err(|e| format!("unable to write {}: {}", path.display(), e))
+    }
+}
+
+fn chip8_dir() -> Result<PathBuf, String> {
+    if let Some(dir) = env::var_os("CHIP8_HOME") {
+        return Ok(PathBuf::from(dir));
+    }
+
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    config_dir
+        .map(|dir| dir.join("chip8"))
+        .ok_or_else(|| "unable to find a directory for profiles. Set CHIP8_HOME".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("chip8-profile-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_profiles_are_separate() {
+        let dir = temp_dir("separate");
+        let kid = Profile::open_in(&dir, "kid").unwrap();
+        let dev = Profile::open_in(&dir, "dev").unwrap();
+
+        fs::write(kid.dir.join("config.toml"), "[limits]\ndebugger = false\n").unwrap();
+        assert!(!kid.config().unwrap().limits.debugger);
+        assert!(dev.config().unwrap().limits.debugger);
+
+        let rom = Path::new("roms/pong.ch8");
+        assert_ne!(kid.save_base(rom), dev.save_base(rom));
+        assert!(kid.save_base(rom).ends_with("profiles/kid/saves/pong.ch8"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recording_plays() {
+        let dir = temp_dir("stats");
+        let profile = Profile::open_in(&dir, "dev").unwrap();
+        let rom = Path::new("roms/pong.ch8");
+
+        profile.record_play(rom, Duration::from_secs(90)).unwrap();
+        profile.record_play(rom, Duration::from_secs(30)).unwrap();
+
+        let stats = profile.stats().unwrap();
+        assert_eq!(stats.roms["pong.ch8"], RomStats { plays: 2, seconds: 120 });
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        let dir = temp_dir("names");
+        assert!(Profile::open_in(&dir, "").is_err());
+        assert!(Profile::open_in(&dir, "../escape").is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
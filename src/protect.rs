@@ -0,0 +1,72 @@
+//! Write protection of the interpreter area. On the original machines the memory below the program held the
+//! interpreter itself, so a ROM writing there was almost always a bug: a stray I or a runaway FX55. Writing over the
+//! fonts is left alone as some ROMs replace them with their own. ROMs which use low memory on purpose run as usual with
+//! protection off, which is the default.
+
+// Std imports
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum WriteProtect {
+    /// Allow every write.
+    #[default]
+    Off,
+
+    /// Print writes to the protected area to stderr and carry on.
+    Log,
+
+    /// Print writes to the protected area to stderr and drop them, leaving memory as it was.
+    Block,
+}
+
+impl FromStr for WriteProtect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(WriteProtect::Off),
+            "log" => Ok(WriteProtect::Log),
+            "block" => Ok(WriteProtect::Block),
+            _ => Err(format!("unknown write protection: {} (expected off, log or block)", s)),
+        }
+    }
+}
+
+/// A write to the protected area.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProtectedWrite {
+    pub addr: usize,
+    pub byte: u8,
+    pub pc: usize, // Address of the instruction which made the write.
+    pub blocked: bool,
+}
+
+impl fmt::Display for ProtectedWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = if self.blocked { "blocked" } else { "allowed" };
+        write!(
+            f,
+            "write protection: {} write of {:02X} to {:03X} by instruction at {:03X}",
+            action, self.byte, self.addr, self.pc
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_modes() {
+        assert_eq!("off".parse(), Ok(WriteProtect::Off));
+        assert_eq!("Block".parse(), Ok(WriteProtect::Block));
+        assert!("warn".parse::<WriteProtect>().is_err());
+    }
+
+    #[test]
+    fn test_describing_writes() {
+        let write = ProtectedWrite { addr: 0x1F0, byte: 0xAB, pc: 0x24C, blocked: true };
+        assert_eq!(write.to_string(), "write protection: blocked write of AB to 1F0 by instruction at 24C");
+    }
+}
@@ -0,0 +1,310 @@
+//! Quirks: behaviours interpreters disagree on, which ROMs come to depend on. By default shifts take Vy like the COSMAC
+//! VIP, and FX55 and FX65 leave I where it was like SCHIP. Either can be switched with --quirks.
+//!
+//! ROMs rarely say which they expect, so --detect-quirks watches for signs of a quirk which is off while one runs:
+//!
+//! - a shift of Vy into another register when the program has never set Vy, as SCHIP programs often leave Y as 0,
+//! - SCHIP instructions outside XO-CHIP, as programs using them were written for SCHIP's shifts,
+//! - two loads or two stores through I in a row without setting it, which only make sense if I moved on.
+//!
+//! Each quirk is reported once, as a warning in the log. With suggest the report says how to switch it on, with apply
+//! it is switched on straight away.
+
+// Self imports
+use crate::conformance::{self, Platform};
+use crate::cpu::CPU;
+use crate::decode::Instruction;
+use crate::hooks::Step;
+use crate::variant::Variant;
+
+// Std imports
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+
+// External imports
+use log::warn;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Quirk {
+    /// 8XY6 and 8XYE shift Vx in place and ignore Vy, as on SCHIP.
+    ShiftVx,
+
+    /// FX55 and FX65 leave I just after the last register, as on the VIP.
+    IncrementI,
+}
+
+impl Quirk {
+    pub const ALL: [Quirk; 2] = [Quirk::ShiftVx, Quirk::IncrementI];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Quirk::ShiftVx => "shift-vx",
+            Quirk::IncrementI => "increment-i",
+        }
+    }
+}
+
+/// The quirks switched on.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Quirks {
+    pub shift_vx: bool,
+    pub increment_i: bool,
+}
+
+impl Quirks {
+    pub fn is_on(&self, quirk: Quirk) -> bool {
+        match quirk {
+            Quirk::ShiftVx => self.shift_vx,
+            Quirk::IncrementI => self.increment_i,
+        }
+    }
+
+    pub fn switch_on(&mut self, quirk: Quirk) {
+        match quirk {
+            Quirk::ShiftVx => self.shift_vx = true,
+            Quirk::IncrementI => self.increment_i = true,
+        }
+    }
+}
+
+/// A comma separated list of quirks to switch on, e.g. shift-vx,increment-i, or none.
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut quirks = Quirks::default();
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty() && *name != "none") {
+            match Quirk::ALL.iter().find(|quirk| quirk.name() == name.to_lowercase()) {
+                Some(quirk) => quirks.switch_on(*quirk),
+                None => return Err(format!("unknown quirk: {} (expected shift-vx, increment-i or none)", name)),
+            }
+        }
+        Ok(quirks)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Detection {
+    /// Don't look for quirks.
+    #[default]
+    Off,
+
+    /// Print the quirks a ROM seems to expect, with how to switch them on.
+    Suggest,
+
+    /// Print the quirks a ROM seems to expect and switch them on.
+    Apply,
+}
+
+impl FromStr for Detection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(Detection::Off),
+            "suggest" => Ok(Detection::Suggest),
+            "apply" => Ok(Detection::Apply),
+            _ => Err(format!("unknown quirk detection: {} (expected off, suggest or apply)", s)),
+        }
+    }
+}
+
+/// A sign that a ROM expects a quirk which is off.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub quirk: Quirk,
+    pub pc: usize,
+    pub opcode: u16,
+    pub reason: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "quirks: {:04X} at {:03X} {}, so the ROM likely expects {}",
+            self.opcode,
+            self.pc,
+            self.reason,
+            self.quirk.name()
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct Detector {
+    written: u16,                    // Registers the program has set, a bit each.
+    load_store: Option<Instruction>, // The last FX55 or FX65, if I hasn't been set since.
+    findings: Vec<Finding>,
+}
+
+impl Detector {
+    /// Watch the instructions a CPU executes for signs of quirks, reporting each and switching it on if applying.
+    pub fn attach(cpu: &mut CPU, detection: Detection) -> Rc<RefCell<Self>> {
+        let detector = Rc::new(RefCell::new(Self::default()));
+
+        let seen = detector.clone();
+        cpu.hooks_mut().before(move |cpu, step| {
+            let finding = seen.borrow_mut().observe(cpu.quirks(), cpu.variant(), step);
+            if let Some(finding) = finding {
+                match detection {
+                    Detection::Apply => {
+                        let mut quirks = cpu.quirks();
+                        quirks.switch_on(finding.quirk);
+                        cpu.set_quirks(quirks);
+                        warn!("{}, switched it on", finding);
+                    }
+                    _ => warn!("{}, try --quirks {}", finding, finding.quirk.name()),
+                }
+            }
+        });
+
+        detector
+    }
+
+    /// Signs found so far.
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
+    /// Look at an instruction about to be executed. Returns the first sign of a quirk which is off and hasn't been
+    /// reported yet.
+    pub fn observe(&mut self, quirks: Quirks, variant: Variant, step: &Step) -> Option<Finding> {
+        use Instruction::*;
+
+        let platform = conformance::family(step.opcode.0).map(|info| info.platform);
+        let again = matches!(
+            (self.load_store, step.instruction),
+            (Some(StoreRegisters { .. }), StoreRegisters { .. }) | (Some(LoadRegisters { .. }), LoadRegisters { .. })
+        );
+        let sign = match step.instruction {
+            ShiftRight { x, y } | ShiftLeft { x, y } if x != y && self.written & 1 << y == 0 => {
+                Some((Quirk::ShiftVx, format!("shifts V{:X} into V{:X} but V{:X} was never set", y, x, y)))
+            }
+            _ if platform == Some(Platform::SuperChip) && variant != Variant::XoChip => {
+                Some((Quirk::ShiftVx, "is a SCHIP instruction".to_string()))
+            }
+            _ if again => Some((Quirk::IncrementI, "goes through I again without setting it".to_string())),
+            _ => None,
+        };
+
+        self.written |= writes(step.instruction);
+        match step.instruction {
+            StoreRegisters { .. } | LoadRegisters { .. } => self.load_store = Some(step.instruction),
+            LoadI { .. } | LongI | AddI { .. } | LoadFont { .. } | LoadBigFont { .. } => self.load_store = None,
+            _ => {}
+        }
+
+        let (quirk, reason) = sign?;
+        let reported = self.findings.iter().any(|finding| finding.quirk == quirk);
+        if quirks.is_on(quirk) || reported {
+            return None;
+        }
+
+        let finding = Finding { quirk, pc: step.pc, opcode: step.opcode.0, reason };
+        self.findings.push(finding.clone());
+        Some(finding)
+    }
+}
+
+// Registers an instruction sets, a bit each.
+fn writes(instruction: Instruction) -> u16 {
+    use Instruction::*;
+
+    let up_to = |x: usize| u16::MAX >> (15 - x);
+    match instruction {
+        LoadByte { x, .. } | AddByte { x, .. } | Random { x, .. } => 1 << x,
+        LoadReg { x, .. } | Or { x, .. } | And { x, .. } | Xor { x, .. } => 1 << x,
+        LoadDelay { x } | WaitKey { x } => 1 << x,
+        AddReg { x, .. } | Sub { x, .. } | SubN { x, .. } => 1 << x | 1 << 0xF,
+        ShiftRight { x, .. } | ShiftLeft { x, .. } => 1 << x | 1 << 0xF,
+        Draw { .. } => 1 << 0xF,
+        LoadRegisters { x } | LoadFlags { x } => up_to(x),
+        LoadRange { x, y } => up_to(x.max(y)) & !(up_to(x.min(y)) >> 1),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::decode;
+    use crate::hooks::{Opcode, Registers};
+
+    fn step(pc: usize, opcode: u16) -> Step {
+        let registers = Registers { v: [0; 16], i: 0, sp: 0, delay_timer: 0, sound_timer: 0 };
+        Step { pc, opcode: Opcode(opcode), instruction: decode(opcode, Variant::Chip8), registers }
+    }
+
+    // Run opcodes through a detector, returning the quirks found.
+    fn detect(opcodes: &[u16], variant: Variant) -> Vec<Quirk> {
+        let mut detector = Detector::default();
+        for (n, opcode) in opcodes.iter().enumerate() {
+            detector.observe(Quirks::default(), variant, &step(0x200 + n * 2, *opcode));
+        }
+        detector.findings().iter().map(|finding| finding.quirk).collect()
+    }
+
+    #[test]
+    fn test_parsing_quirks() {
+        assert_eq!("none".parse(), Ok(Quirks::default()));
+        assert_eq!("shift-vx, Increment-I".parse(), Ok(Quirks { shift_vx: true, increment_i: true }));
+        assert!("vf-reset".parse::<Quirks>().is_err());
+        assert_eq!("apply".parse(), Ok(Detection::Apply));
+    }
+
+    #[test]
+    fn test_shifting_an_unset_register() {
+        // V0 was never set, so 8106 must mean shift V1.
+        assert_eq!(detect(&[0x6104, 0x8106], Variant::Chip8), [Quirk::ShiftVx]);
+        assert_eq!(detect(&[0x6004, 0x8106, 0x8116], Variant::Chip8), []);
+    }
+
+    #[test]
+    fn test_schip_instructions() {
+        assert_eq!(detect(&[0x00FF], Variant::Chip8), [Quirk::ShiftVx]);
+        assert_eq!(detect(&[0x00FF], Variant::XoChip), []);
+    }
+
+    #[test]
+    fn test_loading_through_i_twice() {
+        assert_eq!(detect(&[0xA300, 0xF165, 0xF165], Variant::Chip8), [Quirk::IncrementI]);
+        assert_eq!(detect(&[0xA300, 0xF165, 0xA302, 0xF165], Variant::Chip8), []);
+        assert_eq!(detect(&[0xA300, 0xF165, 0xF155], Variant::Chip8), []);
+        assert_eq!(detect(&[0xA300, 0xF155, 0xF355], Variant::Chip8), [Quirk::IncrementI]);
+    }
+
+    #[test]
+    fn test_quirks_are_reported_once() {
+        let mut detector = Detector::default();
+        let found = detector.observe(Quirks::default(), Variant::Chip8, &step(0x202, 0x00FE)).unwrap();
+        assert_eq!(found.to_string(), "quirks: 00FE at 202 is a SCHIP instruction, so the ROM likely expects shift-vx");
+
+        assert_eq!(detector.observe(Quirks::default(), Variant::Chip8, &step(0x204, 0x00FF)), None);
+        let on = Quirks { increment_i: true, ..Quirks::default() };
+        detector.observe(on, Variant::Chip8, &step(0x206, 0xF065));
+        assert_eq!(detector.observe(on, Variant::Chip8, &step(0x208, 0xF065)), None);
+    }
+
+    #[test]
+    fn test_registers_written() {
+        assert_eq!(writes(decode(0x8124, Variant::Chip8)), 0x8002);
+        assert_eq!(writes(decode(0xF265, Variant::Chip8)), 0x0007);
+        assert_eq!(writes(decode(0xFF65, Variant::Chip8)), 0xFFFF);
+        assert_eq!(writes(decode(0x5473, Variant::Chip8)), 0x00F0);
+    }
+
+    #[test]
+    fn test_applying_quirks() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x61, 0x04, 0x81, 0x06]).unwrap();
+        let detector = Detector::attach(&mut cpu, Detection::Apply);
+
+        cpu.step_frame(2);
+        assert!(cpu.quirks().shift_vx);
+        assert_eq!(cpu.registers().v[1], 2);
+        assert_eq!(detector.borrow().findings().len(), 1);
+    }
+}
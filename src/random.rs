@@ -0,0 +1,169 @@
+//! Where CXKK's random bytes come from. By default a PCG generator, seeded from the OS unless a seed is given so a
+//! session can be replayed exactly. The COSMAC VIP's generator can be used for hardware-like randomness, or the host
+//! can provide its own source.
+
+// Std imports
+use std::fmt;
+use std::str::FromStr;
+
+// External imports
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+
+/// A source of random bytes. `program` is memory from where programs load, for sources which draw on it like the
+/// VIP's did.
+pub trait RngSource {
+    fn next_byte(&mut self, program: &[u8]) -> u8;
+}
+
+impl RngSource for Pcg32 {
+    fn next_byte(&mut self, _program: &[u8]) -> u8 {
+        self.gen()
+    }
+}
+
+/// The VIP interpreter's generator. It had no entropy to draw on: it walked a pointer through a page of memory and
+/// added the byte found to its last result. The VIP walked its own interpreter code, which isn't here, so this walks
+/// the first page of the program instead. Every run of a ROM sees the same sequence.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct VipRandom {
+    pointer: u8,
+    value: u8,
+}
+
+impl RngSource for VipRandom {
+    fn next_byte(&mut self, program: &[u8]) -> u8 {
+        self.pointer = self.pointer.wrapping_add(1);
+        let byte = program.get(self.pointer as usize).copied().unwrap_or(0);
+        self.value = self.value.wrapping_add(byte);
+        self.value
+    }
+}
+
+/// The CPU's source of random bytes. Only the built in sources can be suspended; a host-provided source is kept by
+/// the host, and saving a CPU using one fails.
+#[derive(Serialize, Deserialize)]
+pub enum Random {
+    Pcg(Pcg32),
+    Vip(VipRandom),
+    #[serde(skip)]
+    Custom(Box<dyn RngSource>),
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Random::Pcg(Pcg32::from_entropy())
+    }
+}
+
+impl Random {
+    pub fn seeded(seed: u64) -> Self {
+        Random::Pcg(Pcg32::seed_from_u64(seed))
+    }
+
+    /// A copy of a built in source. Host-provided sources can't be copied.
+    pub fn try_clone(&self) -> Option<Self> {
+        match self {
+            Random::Pcg(rng) => Some(Random::Pcg(rng.clone())),
+            Random::Vip(rng) => Some(Random::Vip(*rng)),
+            Random::Custom(_) => None,
+        }
+    }
+}
+
+impl RngSource for Random {
+    fn next_byte(&mut self, program: &[u8]) -> u8 {
+        match self {
+            Random::Pcg(rng) => rng.next_byte(program),
+            Random::Vip(rng) => rng.next_byte(program),
+            Random::Custom(rng) => rng.next_byte(program),
+        }
+    }
+}
+
+impl fmt::Debug for Random {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Random::Pcg(_) => write!(f, "Pcg"),
+            Random::Vip(rng) => write!(f, "{:?}", rng),
+            Random::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+// Host-provided sources can't be compared, so they are equal if they are both host-provided.
+impl PartialEq for Random {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Random::Pcg(a), Random::Pcg(b)) => a == b,
+            (Random::Vip(a), Random::Vip(b)) => a == b,
+            (Random::Custom(_), Random::Custom(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Parses pcg, pcg:<seed> or vip.
+impl FromStr for Random {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        match s.split_once(':') {
+            None if s == "pcg" => Ok(Random::default()),
+            None if s == "vip" => Ok(Random::Vip(VipRandom::default())),
+            Some(("pcg", seed)) => seed
+                .parse()
+                .map(Random::seeded)
+                .map_err(|_| format!("invalid seed: {}", seed)),
+            _ => Err(format!("unknown random source: {} (expected pcg, pcg:<seed> or vip)", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(rng: &mut Random, program: &[u8], n: usize) -> Vec<u8> {
+        (0..n).map(|_| rng.next_byte(program)).collect()
+    }
+
+    #[test]
+    fn test_seeded_sources_repeat() {
+        let mut a: Random = "pcg:42".parse().unwrap();
+        let mut b = Random::seeded(42);
+        assert_eq!(bytes(&mut a, &[], 16), bytes(&mut b, &[], 16));
+        assert_ne!(bytes(&mut a, &[], 16), bytes(&mut Random::seeded(43), &[], 16));
+    }
+
+    #[test]
+    fn test_vip_walks_program() {
+        let mut rng: Random = "vip".parse().unwrap();
+        assert_eq!(bytes(&mut rng, &[0, 1, 2, 3], 4), vec![1, 3, 6, 6]);
+    }
+
+    #[test]
+    fn test_custom_source() {
+        struct Counter(u8);
+        impl RngSource for Counter {
+            fn next_byte(&mut self, _program: &[u8]) -> u8 {
+                self.0 += 1;
+                self.0
+            }
+        }
+
+        let mut rng = Random::Custom(Box::new(Counter(0)));
+        assert_eq!(bytes(&mut rng, &[], 3), vec![1, 2, 3]);
+        assert!(bincode::serialize(&rng).is_err());
+    }
+
+    #[test]
+    fn test_parsing_sources() {
+        assert!(matches!("PCG".parse(), Ok(Random::Pcg(_))));
+        assert!("pcg:x".parse::<Random>().is_err());
+        assert!("vip:1".parse::<Random>().is_err());
+        assert!("thread".parse::<Random>().is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Rewinding. While a ROM runs every frame's changes are recorded in a journal, and holding the rewind hotkey undoes
+//! them newest first. Each frame of rewinding undoes several frames, so time runs backwards faster than it ran
+//! forwards.
+//!
+//! The debugger steps backwards one instruction at a time with the same journal, using the marks where each
+//! instruction's changes start, and forwards again by replaying what was undone.
+
+// Self imports
+use crate::cpu::CPU;
+use crate::journal::{Journal, BUDGET};
+
+/// Frames undone per frame of rewinding.
+pub const SPEED: usize = 4;
+
+pub struct Rewind {
+    journal: Journal,
+    speed: usize,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(BUDGET, SPEED)
+    }
+}
+
+impl Rewind {
+    pub fn new(budget: usize, speed: usize) -> Self {
+        Self {
+            journal: Journal::new(budget),
+            speed: speed.max(1),
+        }
+    }
+
+    /// Frames which can be rewound.
+    pub fn len(&self) -> usize {
+        self.journal.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.journal.is_empty()
+    }
+
+    /// Called after every frame. The CPU must be recording.
+    pub fn record(&mut self, cpu: &mut CPU) {
+        self.journal.record(cpu);
+    }
+
+    /// Undo the next few frames. Returns false if there is nothing left to rewind to.
+    pub fn step_back(&mut self, cpu: &mut CPU) -> bool {
+        let mut undone = false;
+        for _ in 0..self.speed {
+            undone |= self.journal.undo(cpu);
+        }
+        undone
+    }
+
+    /// Undo the last instruction executed, whether it was stepped in the debugger or run as part of a frame. Returns
+    /// false if there is nothing left to go back to.
+    pub fn step_back_instruction(&mut self, cpu: &mut CPU) -> bool {
+        self.journal.undo_instruction(cpu)
+    }
+
+    /// Redo the last instruction stepped back over, exactly as it first ran, keys held and all. Returns false if
+    /// nothing has been stepped back over since the program last ran.
+    pub fn step_forward_instruction(&mut self, cpu: &mut CPU) -> bool {
+        self.journal.redo_instruction(cpu)
+    }
+
+    /// Take what has been recorded, e.g. to suspend it, leaving nothing to rewind.
+    pub fn take_journal(&mut self) -> Journal {
+        let empty = Journal::new(self.journal.budget());
+        std::mem::replace(&mut self.journal, empty)
+    }
+
+    /// Carry on from what was recorded before, e.g. by a suspended session.
+    pub fn set_journal(&mut self, journal: Journal) {
+        self.journal = journal;
+    }
+
+    /// Forget everything recorded. Called when the CPU is replaced or reset, as it can't be rewound past that.
+    pub fn clear(&mut self) {
+        self.journal.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts up in V0 forever.
+    const ROM: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+    fn running_cpu() -> CPU {
+        let mut cpu = CPU::default();
+        cpu.load(ROM.to_vec()).unwrap();
+        cpu.start_recording();
+        cpu
+    }
+
+    #[test]
+    fn test_rewinding_restores_earlier_states() {
+        let mut cpu = running_cpu();
+        let mut rewind = Rewind::new(BUDGET, 2);
+        let mut hashes = vec![cpu.state_hash()];
+
+        for _ in 0..6 {
+            cpu.step_frame(3);
+            rewind.record(&mut cpu);
+            hashes.push(cpu.state_hash());
+        }
+        assert_eq!(rewind.len(), 6);
+
+        // Two frames back at a time.
+        for frame in [4, 2, 0].iter() {
+            assert!(rewind.step_back(&mut cpu));
+            assert_eq!(cpu.state_hash(), hashes[*frame]);
+        }
+        assert!(!rewind.step_back(&mut cpu));
+    }
+
+    #[test]
+    fn test_oldest_frames_are_dropped() {
+        let mut cpu = running_cpu();
+        let mut rewind = Rewind::new(1024, 1);
+
+        for _ in 0..100 {
+            cpu.step_frame(3);
+            rewind.record(&mut cpu);
+        }
+        assert!(rewind.len() < 100);
+
+        while rewind.step_back(&mut cpu) {}
+        assert!(rewind.is_empty());
+        assert!(cpu.state_hash() != CPU::default().state_hash());
+    }
+
+    #[test]
+    fn test_stepping_back_instructions() {
+        let mut cpu = running_cpu();
+        let mut rewind = Rewind::default();
+        let mut hashes = vec![cpu.state_hash()];
+
+        // Three instructions in a frame, then two stepped one at a time.
+        cpu.step_frame(3);
+        rewind.record(&mut cpu);
+        let frame_end = cpu.state_hash();
+        for _ in 0..2 {
+            cpu.cycle();
+            hashes.push(cpu.state_hash());
+        }
+
+        assert!(rewind.step_back_instruction(&mut cpu));
+        assert_eq!(cpu.state_hash(), hashes[1]);
+        assert!(rewind.step_back_instruction(&mut cpu));
+        assert_eq!(cpu.state_hash(), frame_end);
+
+        // Back through the frame, whose timer tick is undone with its last instruction.
+        for v0 in [1, 1, 0].iter() {
+            assert!(rewind.step_back_instruction(&mut cpu));
+            assert_eq!(cpu.register(0), *v0);
+        }
+        assert_eq!(cpu.state_hash(), hashes[0]);
+        assert!(!rewind.step_back_instruction(&mut cpu));
+
+        // Forward again to where stepping back started.
+        while rewind.step_forward_instruction(&mut cpu) {}
+        assert_eq!(cpu.state_hash(), hashes[2]);
+    }
+
+    #[test]
+    fn test_rewinding_across_a_reset_is_not_possible() {
+        let mut cpu = running_cpu();
+        let mut rewind = Rewind::default();
+        cpu.step_frame(3);
+        rewind.record(&mut cpu);
+
+        cpu.reset();
+        rewind.clear();
+        assert!(cpu.recording());
+        assert!(!rewind.step_back(&mut cpu));
+    }
+}
@@ -0,0 +1,89 @@
+//! SCHIP's RPL user flags. On the HP48 FX75 saved registers into eight flags belonging to the calculator rather than
+//! the program, where they outlived the game, so games used them for high scores. Here they are kept in a file per ROM
+//! and written as soon as they change.
+//!
+//! Flags belong to the host like battery backed memory: they aren't part of save states and rewinding doesn't undo
+//! them.
+
+// Std imports
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Number of flags.
+pub const FLAGS: usize = 8;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RplFlags {
+    flags: [u8; FLAGS],
+    path: Option<PathBuf>, // Where the flags are kept, or None to keep them in memory only.
+}
+
+impl RplFlags {
+    /// Flags kept in the given file. A missing file means every flag is 0.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut flags = [0; FLAGS];
+        match fs::read(path) {
+            Ok(bytes) => {
+                let len = bytes.len().min(FLAGS);
+                flags[..len].copy_from_slice(&bytes[..len]);
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("unable to read {}: {}", path.display(), e)),
+        }
+
+        Ok(Self {
+            flags,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    pub fn flags(&self) -> &[u8; FLAGS] {
+        &self.flags
+    }
+
+    /// Set the first flags to the given values, saving them if they are kept in a file. Values beyond the last flag
+    /// are ignored.
+    pub fn save(&mut self, values: &[u8]) -> Result<(), String> {
+        let len = values.len().min(FLAGS);
+        self.flags[..len].copy_from_slice(&values[..len]);
+
+        match &self.path {
+            Some(path) => fs::write(path, self.flags).map_err(|e| format!("unable to write {}: {}", path.display(), e)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("chip8-rpl-{}-{}.flags", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_missing_file_is_all_zeros() {
+        let flags = RplFlags::load(&temp_path("missing")).unwrap();
+        assert_eq!(flags.flags(), &[0; FLAGS]);
+    }
+
+    #[test]
+    fn test_flags_persist() {
+        let path = temp_path("persist");
+        let mut flags = RplFlags::load(&path).unwrap();
+        flags.save(&[1, 2, 3]).unwrap();
+
+        let reloaded = RplFlags::load(&path).unwrap();
+        assert_eq!(reloaded.flags(), &[1, 2, 3, 0, 0, 0, 0, 0]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_extra_values_are_ignored() {
+        let mut flags = RplFlags::default();
+        flags.save(&[9; 16]).unwrap();
+        assert_eq!(flags.flags(), &[9; FLAGS]);
+    }
+}
@@ -0,0 +1,174 @@
+//! Softlock detection for unattended runs. A ROM is taken to be stuck once, for long enough, the screen hasn't changed,
+//! the keypad hasn't been checked and every instruction executed has been within a few bytes of the others. This
+//! catches programs spinning in a short loop waiting on something that will never happen, without tripping on title
+//! screens waiting for a key. A jump to itself isn't a softlock: it is how programs finish, and halts the CPU.
+
+// Self imports
+use crate::cpu::CPU;
+use crate::decode::Instruction;
+use crate::frame_buffer::PLANES;
+
+// Std imports
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// Widest a loop can be, in bytes, and still count as a tight loop.
+pub const LOOP_SPAN: usize = 16;
+
+/// What the detector's hook has seen since the last frame.
+#[derive(Default)]
+struct Activity {
+    pcs: Option<(usize, usize)>, // Lowest and highest addresses executed.
+    polled: bool,                // Whether a key instruction ran.
+}
+
+/// Where a ROM got stuck.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Softlock {
+    pub low: usize,
+    pub high: usize,
+    pub frames: usize,
+}
+
+impl fmt::Display for Softlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Possible softlock: looping in {:03X}-{:03X} for {} frames with no screen changes or key checks",
+            self.low, self.high, self.frames
+        )
+    }
+}
+
+pub struct Detector {
+    threshold: usize, // Frames a ROM must look stuck for.
+    frames: usize,    // Frames it has looked stuck for so far.
+    span: Option<(usize, usize)>,
+    screen: Vec<u64>,
+    activity: Rc<RefCell<Activity>>,
+}
+
+impl Detector {
+    /// Watch a CPU, reporting a softlock after it has looked stuck for `threshold` frames.
+    pub fn attach(cpu: &mut CPU, threshold: usize) -> Self {
+        let activity = Rc::new(RefCell::new(Activity::default()));
+
+        let seen = activity.clone();
+        cpu.hooks_mut().after(move |_, step| {
+            let mut seen = seen.borrow_mut();
+            let pc = step.pc;
+            seen.pcs = Some(seen.pcs.map_or((pc, pc), |(low, high)| (low.min(pc), high.max(pc))));
+
+            seen.polled |= matches!(
+                step.instruction,
+                Instruction::SkipKey { .. }
+                    | Instruction::SkipNotKey { .. }
+                    | Instruction::SkipKey2 { .. }
+                    | Instruction::SkipNotKey2 { .. }
+                    | Instruction::WaitKey { .. }
+            );
+        });
+
+        Self {
+            threshold: threshold.max(1),
+            frames: 0,
+            span: None,
+            screen: screen(cpu),
+            activity,
+        }
+    }
+
+    /// Called after every frame. Returns the softlock once the ROM has looked stuck for long enough, then starts
+    /// watching afresh.
+    pub fn end_frame(&mut self, cpu: &CPU) -> Option<Softlock> {
+        let activity = self.activity.replace(Activity::default());
+        let current = screen(cpu);
+
+        // Waiting on FX0A polls the keypad without executing anything.
+        let polled = activity.polled || cpu.waiting_for_key();
+        let span = match (self.span, activity.pcs) {
+            (Some((low, high)), Some((l, h))) => Some((low.min(l), high.max(h))),
+            (span, pcs) => span.or(pcs),
+        };
+        let tight = span.is_some_and(|(low, high)| high - low < LOOP_SPAN);
+
+        // A halted program has finished rather than got stuck.
+        if polled || !tight || current != self.screen || cpu.halt().is_some() {
+            self.frames = 0;
+            self.span = activity.pcs;
+            self.screen = current;
+            return None;
+        }
+
+        self.frames += 1;
+        self.span = span;
+        if self.frames < self.threshold {
+            return None;
+        }
+
+        self.frames = 0;
+        span.map(|(low, high)| Softlock { low, high, frames: self.threshold })
+    }
+}
+
+fn screen(cpu: &CPU) -> Vec<u64> {
+    (0..PLANES).flat_map(|plane| cpu.frame().plane(plane).to_vec()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(rom: &[u8], frames: usize) -> Option<Softlock> {
+        let mut cpu = CPU::default();
+        cpu.load(rom.to_vec()).unwrap();
+        let mut detector = Detector::attach(&mut cpu, 30);
+
+        (0..frames).find_map(|_| {
+            cpu.step_frame(9);
+            detector.end_frame(&cpu)
+        })
+    }
+
+    #[test]
+    fn test_tight_loop() {
+        let softlock = run(&[0x00, 0xE0, 0x12, 0x00], 40).unwrap();
+        assert_eq!((softlock.low, softlock.high), (0x200, 0x202));
+    }
+
+    #[test]
+    fn test_takes_threshold_frames() {
+        assert!(run(&[0x00, 0xE0, 0x12, 0x00], 29).is_none());
+        assert!(run(&[0x00, 0xE0, 0x12, 0x00], 30).is_some());
+    }
+
+    #[test]
+    fn test_finished_program_is_not_stuck() {
+        assert!(run(&[0x00, 0xE0, 0x12, 0x02], 100).is_none());
+    }
+
+    #[test]
+    fn test_polling_keys_is_not_stuck() {
+        // Skip the jump back once key 0 is pressed.
+        assert!(run(&[0x60, 0x00, 0xE0, 0x9E, 0x12, 0x02], 100).is_none());
+
+        // Waiting on FX0A.
+        assert!(run(&[0xF0, 0x0A, 0x12, 0x00], 100).is_none());
+    }
+
+    #[test]
+    fn test_drawing_is_not_stuck() {
+        // Draws the font digit 0 over and over, toggling it on and off.
+        assert!(run(&[0xA0, 0x00, 0xD0, 0x05, 0x12, 0x02], 100).is_none());
+    }
+
+    #[test]
+    fn test_wide_loops_are_not_stuck() {
+        // Jumps between 0x200 and 0x220.
+        let mut rom = vec![0x00; 0x20];
+        rom[..2].copy_from_slice(&[0x12, 0x20]);
+        rom.extend_from_slice(&[0x12, 0x00]);
+        assert!(run(&rom, 100).is_none());
+    }
+}
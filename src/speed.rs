@@ -0,0 +1,77 @@
+//! Named speed presets, so a ROM can be run like the interpreter it was written for without knowing how many
+//! instructions that interpreter managed each frame. Anywhere a preset is accepted, a number of instructions per frame
+//! is too.
+
+// Self imports
+use crate::Timing;
+
+// Std imports
+use std::str::FromStr;
+
+pub struct Preset {
+    pub name: &'static str,
+    pub timing: Timing,
+    pub description: &'static str,
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "vip",
+        timing: Timing::Fixed(9),
+        description: "COSMAC VIP on average, about 540 instructions a second. The default",
+    },
+    Preset {
+        name: "vip-cycles",
+        timing: Timing::CosmacVip,
+        description: "COSMAC VIP, charging each instruction its historical cycle count",
+    },
+    Preset {
+        name: "hp48",
+        timing: Timing::Fixed(30),
+        description: "HP48 SCHIP interpreters, about 30 instructions a frame",
+    },
+    Preset {
+        name: "octo",
+        timing: Timing::Fixed(1000),
+        description: "Octo's fastest usual setting, 1000 instructions a frame",
+    },
+];
+
+/// Parses a preset name or a number of instructions per frame.
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(preset) = PRESETS.iter().find(|preset| preset.name.eq_ignore_ascii_case(s)) {
+            return Ok(preset.timing);
+        }
+
+        match s.parse() {
+            Ok(0) | Err(_) => {
+                let names: Vec<&str> = PRESETS.iter().map(|preset| preset.name).collect();
+                Err(format!("unknown speed: {} (expected {} or instructions per frame)", s, names.join(", ")))
+            }
+            Ok(ipf) => Ok(Timing::Fixed(ipf)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_speeds() {
+        assert_eq!("vip".parse(), Ok(Timing::Fixed(9)));
+        assert_eq!("VIP-Cycles".parse(), Ok(Timing::CosmacVip));
+        assert_eq!("octo".parse(), Ok(Timing::Fixed(1000)));
+        assert_eq!("15".parse(), Ok(Timing::Fixed(15)));
+        assert!("0".parse::<Timing>().is_err());
+        assert!("turbo".parse::<Timing>().is_err());
+    }
+
+    #[test]
+    fn test_default_preset_matches_default_speed() {
+        assert_eq!(PRESETS[0].timing, Timing::Fixed(crate::INSTRUCTIONS_PER_FRAME));
+    }
+}
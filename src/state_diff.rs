@@ -0,0 +1,182 @@
+//! Differences between two save states, for understanding what a sequence of instructions changed. Save a state,
+//! run a little, save another, then compare them with `diff-state`:
+//!
+//! ```text
+//! PC    20A -> 212
+//! V1    00 -> 05
+//! I     000 -> 2F0
+//!
+//! Memory, 3 bytes differ:
+//!   300-302  00 00 00 -> 01 02 03
+//!
+//! Screen plane 0: 20 pixels turned on, 0 turned off, within (12, 8)-(19, 12)
+//! ```
+
+// Self imports
+use crate::cpu::CPU;
+use crate::frame_buffer::PLANES;
+
+// Most bytes of a differing range shown before the rest is left out.
+const SHOWN_BYTES: usize = 8;
+
+/// Everything that differs between two machines, or a note that nothing does.
+pub fn report(a: &CPU, b: &CPU) -> String {
+    let mut sections = vec![];
+    if a.rom_hash() != b.rom_hash() {
+        sections.push("The states were saved from different ROMs\n".to_string());
+    }
+    let differences = vec![registers(a, b), memory(a, b), screen(a, b)];
+    sections.extend(differences.into_iter().filter(|section| !section.is_empty()));
+
+    match sections.is_empty() {
+        true => "No differences\n".to_string(),
+        false => sections.join("\n"),
+    }
+}
+
+fn registers(a: &CPU, b: &CPU) -> String {
+    let (ra, rb) = (a.registers(), b.registers());
+    let mut changes = vec![];
+    let mut compare = |name: String, before: String, after: String| {
+        if before != after {
+            changes.push(format!("{:<5} {} -> {}\n", name, before, after));
+        }
+    };
+
+    compare("Variant".to_string(), a.variant().to_string(), b.variant().to_string());
+    compare("PC".to_string(), format!("{:03X}", a.pc()), format!("{:03X}", b.pc()));
+    for x in 0..16 {
+        compare(format!("V{:X}", x), format!("{:02X}", ra.v[x]), format!("{:02X}", rb.v[x]));
+    }
+    compare("I".to_string(), format!("{:03X}", ra.i), format!("{:03X}", rb.i));
+    compare("Stack".to_string(), stack(a), stack(b));
+    compare("DT".to_string(), format!("{:02X}", ra.delay_timer), format!("{:02X}", rb.delay_timer));
+    compare("ST".to_string(), format!("{:02X}", ra.sound_timer), format!("{:02X}", rb.sound_timer));
+    changes.concat()
+}
+
+fn stack(cpu: &CPU) -> String {
+    let addrs: Vec<String> = cpu.stack().iter().map(|addr| format!("{:03X}", addr)).collect();
+    format!("[{}]", addrs.join(", "))
+}
+
+// Runs of differing bytes, with the bytes on either side.
+fn memory(a: &CPU, b: &CPU) -> String {
+    let size = a.memory_size().min(b.memory_size());
+    let mut runs: Vec<(usize, usize)> = vec![];
+    for addr in (0..size).filter(|addr| a.peek(*addr) != b.peek(*addr)) {
+        match runs.last_mut() {
+            Some((_, last)) if *last + 1 == addr => *last = addr,
+            _ => runs.push((addr, addr)),
+        }
+    }
+    if runs.is_empty() {
+        return String::new();
+    }
+
+    let bytes = |cpu: &CPU, first: usize, last: usize| {
+        let shown: Vec<String> =
+            (first..=last).take(SHOWN_BYTES).map(|addr| format!("{:02X}", cpu.peek(addr))).collect();
+        let more = if last + 1 - first > SHOWN_BYTES { " ..." } else { "" };
+        shown.join(" ") + more
+    };
+
+    let differing: usize = runs.iter().map(|(first, last)| last + 1 - first).sum();
+    let mut out = format!("Memory, {} bytes differ:\n", differing);
+    for (first, last) in runs {
+        let range = if first == last { format!("{:03X}", first) } else { format!("{:03X}-{:03X}", first, last) };
+        out += &format!("  {:<8} {} -> {}\n", range, bytes(a, first, last), bytes(b, first, last));
+    }
+    out
+}
+
+// Pixels turned on and off on each plane, with the box they fall within.
+fn screen(a: &CPU, b: &CPU) -> String {
+    let (fa, fb) = (a.frame(), b.frame());
+    if (fa.width(), fa.height()) != (fb.width(), fb.height()) {
+        return format!("Screen {}x{} -> {}x{}\n", fa.width(), fa.height(), fb.width(), fb.height());
+    }
+
+    let words = fa.width() / 64;
+    let mut out = String::new();
+    for plane in 0..PLANES {
+        let (mut on, mut off, mut bounds) = (0, 0, None);
+        for (word, (before, after)) in fa.plane(plane).iter().zip(fb.plane(plane)).enumerate() {
+            let changed = before ^ after;
+            if changed == 0 {
+                continue;
+            }
+            on += (after & !before).count_ones();
+            off += (before & !after).count_ones();
+
+            let (y, x) = (word / words, word % words * 64);
+            let (left, right) = (x + changed.leading_zeros() as usize, x + 63 - changed.trailing_zeros() as usize);
+            let (x0, y0, x1, y1) = bounds.unwrap_or((left, y, right, y));
+            bounds = Some((x0.min(left), y0.min(y), x1.max(right), y1.max(y)));
+        }
+
+        if let Some((x0, y0, x1, y1)) = bounds {
+            out += &format!(
+                "Screen plane {}: {} pixels turned on, {} turned off, within ({}, {})-({}, {})\n",
+                plane, on, off, x0, y0, x1, y1
+            );
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Stores three registers at 300 and draws them as a sprite at (12, 8).
+    #[rustfmt::skip]
+    const ROM: [u8; 16] = [
+        0x60, 0x01, // 0x200: V0 = 1.
+        0x61, 0x02, // 0x202: V1 = 2.
+        0x62, 0x03, // 0x204: V2 = 3.
+        0xA3, 0x00, // 0x206: I = 0x300.
+        0xF2, 0x55, // 0x208: Store V0 to V2 at I.
+        0x63, 0x0C, // 0x20A: V3 = 12.
+        0x64, 0x08, // 0x20C: V4 = 8.
+        0xD3, 0x43, // 0x20E: Draw 3 rows from I at (V3, V4).
+    ];
+
+    fn after(instructions: usize) -> CPU {
+        let mut cpu = CPU::default();
+        cpu.load(ROM.to_vec()).unwrap();
+        for _ in 0..instructions {
+            cpu.cycle();
+        }
+        cpu
+    }
+
+    #[test]
+    fn test_identical_states() {
+        assert_eq!(report(&after(3), &after(3)), "No differences\n");
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let report = report(&after(3), &after(5));
+        let memory = "Memory, 3 bytes differ:\n  300-302  00 00 00 -> 01 02 03\n";
+        assert_eq!(report, format!("PC    206 -> 20A\nI     000 -> 300\n\n{}", memory));
+    }
+
+    #[test]
+    fn test_screen() {
+        let report = report(&after(7), &after(8));
+        let screen = "Screen plane 0: 4 pixels turned on, 0 turned off, within (18, 8)-(19, 10)\n";
+        assert_eq!(report, format!("PC    20E -> 210\n\n{}", screen));
+    }
+
+    #[test]
+    fn test_long_runs_are_shortened() {
+        let (a, mut b) = (CPU::default(), CPU::default());
+        for addr in 0x400..0x410 {
+            b.poke(addr, 0xFF);
+        }
+        let line = "  400-40F  00 00 00 00 00 00 00 00 ... -> FF FF FF FF FF FF FF FF ...\n";
+        assert!(report(&a, &b).contains(line));
+    }
+}
@@ -0,0 +1,128 @@
+//! Frame post-hooks, enabled with the `stream` feature. After every presented frame the VM hands its hook the screen as
+//! shown along with the keys held, so streaming software can capture the emulator and draw an input display without
+//! capturing the window.
+
+// Std imports
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"C8FR";
+const HEADER_LEN: usize = 20;
+
+/// A presented frame.
+pub struct Frame<'a> {
+    pub colours: &'a [[u8; 3]], // Row by row.
+    pub width: usize,
+    pub keys: u16,  // Bit k is set while key k is held.
+    pub keys2: u16, // CHIP-8X second keypad.
+}
+
+pub trait FrameHook {
+    fn frame(&mut self, frame: &Frame) -> Result<(), String>;
+}
+
+/// Publishes frames through a file other processes can map, e.g. one in /dev/shm. Layout, all little endian:
+///
+/// | Offset | Size | Field                                    |
+/// |--------|------|------------------------------------------|
+/// | 0      | 4    | "C8FR"                                   |
+/// | 4      | 4    | Sequence number                          |
+/// | 8      | 4    | Width                                    |
+/// | 12     | 4    | Height                                   |
+/// | 16     | 2    | Keys held                                |
+/// | 18     | 2    | Keys held on the second keypad           |
+/// | 20     | w*h*3| Pixels, RGB, row by row                  |
+///
+/// The sequence number is odd while a frame is being written and even once it is complete. A reader which sees the
+/// same even number before and after copying a frame has a whole one.
+pub struct SharedFrame {
+    file: File,
+    sequence: u32,
+}
+
+impl SharedFrame {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(|e| format!("unable to create {}: {}", path.display(), e))?;
+
+        Ok(Self { file, sequence: 0 })
+    }
+
+    fn write_sequence(&mut self) -> std::io::Result<()> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&self.sequence.to_le_bytes())
+    }
+
+    fn publish(&mut self, frame: &Frame) -> std::io::Result<()> {
+        self.sequence = self.sequence.wrapping_add(1);
+        let bytes = encode(frame, self.sequence);
+
+        // Mark the frame as incomplete before anything else changes, then write it and mark it complete.
+        self.file.set_len(bytes.len() as u64)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&bytes[..HEADER_LEN])?;
+        self.file.write_all(&bytes[HEADER_LEN..])?;
+
+        self.sequence = self.sequence.wrapping_add(1);
+        self.write_sequence()?;
+        self.file.flush()
+    }
+}
+
+impl FrameHook for SharedFrame {
+    fn frame(&mut self, frame: &Frame) -> Result<(), String> {
+        self.publish(frame).map_err(|e| format!("unable to publish frame: {}", e))
+    }
+}
+
+fn encode(frame: &Frame, sequence: u32) -> Vec<u8> {
+    let height = frame.colours.len() / frame.width;
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + frame.colours.len() * 3);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&sequence.to_le_bytes());
+    bytes.extend_from_slice(&(frame.width as u32).to_le_bytes());
+    bytes.extend_from_slice(&(height as u32).to_le_bytes());
+    bytes.extend_from_slice(&frame.keys.to_le_bytes());
+    bytes.extend_from_slice(&frame.keys2.to_le_bytes());
+    bytes.extend(frame.colours.iter().flatten());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_publishing_frames() {
+        let path = std::env::temp_dir().join(format!("chip8-stream-test-{}", std::process::id()));
+        let mut shared = SharedFrame::create(&path).unwrap();
+
+        let colours = [[1, 2, 3], [4, 5, 6], [7, 8, 9], [10, 11, 12]];
+        let frame = Frame {
+            colours: &colours,
+            width: 2,
+            keys: 1 << 0xA,
+            keys2: 0,
+        };
+        shared.frame(&frame).unwrap();
+        shared.frame(&frame).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(&bytes[..4], MAGIC);
+        assert_eq!(bytes[4..8], 4u32.to_le_bytes()); // Two complete frames.
+        assert_eq!(bytes[8..12], 2u32.to_le_bytes());
+        assert_eq!(bytes[12..16], 2u32.to_le_bytes());
+        assert_eq!(bytes[16..18], 0x400u16.to_le_bytes());
+        assert_eq!(bytes[HEADER_LEN..], [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+    }
+}
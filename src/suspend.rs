@@ -0,0 +1,208 @@
+//! Suspend-to-disk. Writes the session so it can be resumed where it left off: the CPU with its memory, timers, frame
+//! buffer, keypads, CHIP-8X peripherals and RNG, how frames are scheduled, and what is being recorded for rewinding,
+//! i.e. whether the CPU is recording, the changes it hasn't handed over and the journal's frames. Save state slots are
+//! snapshots too, without the journal, as loading one starts a new history.
+//!
+//! Left out is what belongs to the host, which sets it up again on resume: the SYS handler, key wait, quirks, write
+//! protection, watchpoints, breakpoints, write logs, coverage and hooks, and a host-provided RNG, which can't be saved.
+//! RPL flags have their own file.
+
+// Self imports
+use crate::cpu::CPU;
+use crate::journal::Journal;
+use crate::Timing;
+
+// Std imports
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// External imports
+use serde::{Deserialize, Serialize};
+
+// Start of every suspend file. The version is bumped whenever the layout of anything suspended changes.
+const MAGIC: &[u8; 4] = b"C8SS";
+const VERSION: u32 = 9;
+
+/// Number of save state slots for each ROM.
+pub const SLOTS: u8 = 10;
+
+/// File holding a save state slot, kept next to the ROM. e.g. slot 3 of roms/pong.ch8 is roms/pong.state3.
+pub fn slot_path(rom: &Path, slot: u8) -> PathBuf {
+    rom.with_extension(format!("state{}", slot))
+}
+
+/// A whole suspended session.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub cpu: CPU,
+    pub timing: Timing,
+    pub journal: Option<Journal>, // What has been recorded for rewinding. Only suspend files keep it.
+}
+
+impl Snapshot {
+    /// Write the snapshot to a file, replacing anything already there.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.encode()?).map_err(|e| format!("unable to write {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        Self::decode(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Load a save state, checking it was saved while running the same ROM as `cpu`.
+    pub fn load_for(path: &Path, cpu: &CPU) -> Result<Self, String> {
+        let snapshot = Self::load(path)?;
+        snapshot.check_rom(cpu).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(snapshot)
+    }
+
+    fn check_rom(&self, cpu: &CPU) -> Result<(), String> {
+        if self.cpu.rom_hash() != cpu.rom_hash() {
+            return Err("saved from a different ROM".to_string());
+        }
+        Ok(())
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, self).map_err(|e| e.to_string())?;
+        Ok(bytes)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 8 || &bytes[..4] != MAGIC {
+            return Err("not a suspend file".to_string());
+        }
+
+        let version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        if version != VERSION {
+            return Err(format!("suspend file version {} is not supported (expected {})", version, VERSION));
+        }
+
+        bincode::deserialize(&bytes[8..]).map_err(|e| format!("corrupt suspend file: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::Variant;
+
+    // A CHIP-8X program which draws, sets colours and then loops drawing random sprites, so every part of the CPU is
+    // in use when it is suspended.
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 18] = [
+        0x02, 0xA0, // 0x300: Cycle background colour.
+        0x61, 0x25, // 0x302: V1 = 0x25.
+        0xB1, 0x33, // 0x304: Set colour zones.
+        0xC0, 0x0F, // 0x306: V0 = random & 0x0F.
+        0xC2, 0x3F, // 0x308: V2 = random & 0x3F.
+        0xF0, 0x29, // 0x30A: I = font sprite of V0.
+        0xD2, 0x05, // 0x30C: Draw at (V2, V0).
+        0xF0, 0x15, // 0x30E: Delay timer = V0.
+        0x13, 0x06, // 0x310: Jump to 0x306.
+    ];
+
+    // Recording for rewinding, with one instruction stepped since the last frame was recorded.
+    fn running_snapshot() -> Snapshot {
+        let mut cpu = CPU::new(Variant::Chip8X);
+        cpu.load(PROGRAM.to_vec()).unwrap();
+        cpu.start_recording();
+        cpu.set_key2(0x7);
+
+        let mut journal = Journal::default();
+        for _ in 0..20 {
+            cpu.step_vip_frame();
+            journal.record(&mut cpu);
+        }
+        cpu.cycle();
+
+        Snapshot {
+            cpu,
+            timing: Timing::CosmacVip,
+            journal: Some(journal),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let snapshot = running_snapshot();
+        let bytes = snapshot.encode().unwrap();
+        assert_eq!(Snapshot::decode(&bytes).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn test_resumed_session_matches_uninterrupted_session() {
+        let mut uninterrupted = running_snapshot();
+        let mut resumed = Snapshot::decode(&uninterrupted.encode().unwrap()).unwrap();
+
+        for _ in 0..20 {
+            uninterrupted.cpu.step_vip_frame();
+            resumed.cpu.step_vip_frame();
+        }
+
+        assert_eq!(resumed, uninterrupted);
+        assert_eq!(resumed.cpu.get_framebuffer(), uninterrupted.cpu.get_framebuffer());
+    }
+
+    #[test]
+    fn test_resumed_session_can_be_rewound() {
+        let mut uninterrupted = running_snapshot();
+        let mut resumed = Snapshot::decode(&uninterrupted.encode().unwrap()).unwrap();
+        assert!(resumed.cpu.recording());
+
+        for snapshot in [&mut uninterrupted, &mut resumed] {
+            let journal = snapshot.journal.as_mut().unwrap();
+            assert!(journal.undo_instruction(&mut snapshot.cpu));
+            while journal.undo(&mut snapshot.cpu) {}
+        }
+
+        assert_eq!(resumed, uninterrupted);
+
+        let mut start = CPU::new(Variant::Chip8X);
+        start.load(PROGRAM.to_vec()).unwrap();
+        assert_eq!(resumed.cpu.state_hash(), start.state_hash());
+    }
+
+    #[test]
+    fn test_host_settings_are_left_out() {
+        let mut snapshot = running_snapshot();
+        snapshot.cpu.breakpoints_mut().insert(0x306);
+
+        let mut resumed = Snapshot::decode(&snapshot.encode().unwrap()).unwrap();
+        assert!(resumed.cpu.breakpoints_mut().is_empty());
+    }
+
+    #[test]
+    fn test_save_states_need_the_same_rom() {
+        let snapshot = running_snapshot();
+
+        let mut same = CPU::new(Variant::Chip8X);
+        same.load(PROGRAM.to_vec()).unwrap();
+        assert_eq!(snapshot.check_rom(&same), Ok(()));
+
+        let mut other = CPU::new(Variant::Chip8X);
+        other.load(PROGRAM[..16].to_vec()).unwrap();
+        assert_eq!(snapshot.check_rom(&other).unwrap_err(), "saved from a different ROM");
+    }
+
+    #[test]
+    fn test_slot_paths() {
+        assert_eq!(slot_path(Path::new("roms/pong.ch8"), 3), PathBuf::from("roms/pong.state3"));
+    }
+
+    #[test]
+    fn test_rejecting_other_files() {
+        assert_eq!(Snapshot::decode(b"\x00\xE0").unwrap_err(), "not a suspend file");
+
+        let mut bytes = running_snapshot().encode().unwrap();
+        bytes[4] = 0xFF;
+        assert!(Snapshot::decode(&bytes).unwrap_err().contains("version 255 is not supported"));
+
+        bytes[4] = VERSION as u8;
+        bytes.truncate(100);
+        assert!(Snapshot::decode(&bytes).unwrap_err().starts_with("corrupt suspend file"));
+    }
+}
@@ -0,0 +1,133 @@
+//! Symbol files, Octo style: one label per line, the address in hex followed by its name.
+//!
+//! ```text
+//! ; pong.sym
+//! 0x200 main
+//! 0x20C main_loop
+//! 0x2F0 paddle_sprite
+//! ```
+//!
+//! Comments start with `;` or `#`. Names are case insensitive when looked up, like the assembler's labels, but are
+//! shown as written. The disassembler uses them for labels and operands, the debugger and --break accept them in
+//! place of addresses, and the trace marks the lines at labelled addresses.
+
+// Self imports
+use crate::debugger;
+
+// Std imports
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Symbols {
+    names: BTreeMap<usize, String>,
+    addrs: HashMap<String, usize>, // By lower case name.
+}
+
+impl Symbols {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut symbols = Self::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split([';', '#']).next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let at_line = |e: String| format!("line {}: {}", n + 1, e);
+            let (addr, name) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [addr, name] => (debugger::parse_addr(addr).map_err(at_line)?, name),
+                _ => return Err(at_line(format!("expected an address and a name: {}", line))),
+            };
+            symbols.insert(addr, name).map_err(at_line)?;
+        }
+        Ok(symbols)
+    }
+
+    fn insert(&mut self, addr: usize, name: &str) -> Result<(), String> {
+        if self.addrs.insert(name.to_lowercase(), addr).is_some() {
+            return Err(format!("{} is defined more than once", name));
+        }
+        self.names.insert(addr, name.to_string());
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The label at an address.
+    pub fn name(&self, addr: usize) -> Option<&str> {
+        self.names.get(&addr).map(String::as_str)
+    }
+
+    /// The address of a label.
+    pub fn addr(&self, name: &str) -> Option<usize> {
+        self.addrs.get(&name.to_lowercase()).copied()
+    }
+
+    /// An address given as a label or in hex. Labels win, so a label like `add` can still be used.
+    pub fn resolve(&self, s: &str) -> Result<usize, String> {
+        match self.addr(s) {
+            Some(addr) => Ok(addr),
+            None if self.is_empty() => debugger::parse_addr(s),
+            None => debugger::parse_addr(s).map_err(|_| format!("unknown label or invalid address: {}", s)),
+        }
+    }
+
+    /// Where an address is relative to the closest label at or before it, e.g. `main_loop+6`.
+    pub fn locate(&self, addr: usize) -> Option<String> {
+        match self.names.range(..=addr).next_back()? {
+            (&label, name) if label == addr => Some(name.clone()),
+            (&label, name) => Some(format!("{}+{:X}", name, addr - label)),
+        }
+    }
+
+    /// Every label, in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.names.iter().map(|(&addr, name)| (addr, name.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_symbols() {
+        let symbols = Symbols::parse("; pong\n0x200 main\n20C  Main_Loop  # The frame loop.\n\n").unwrap();
+        assert_eq!(symbols.name(0x20C), Some("Main_Loop"));
+        assert_eq!(symbols.addr("main_loop"), Some(0x20C));
+        assert_eq!(symbols.iter().collect::<Vec<_>>(), vec![(0x200, "main"), (0x20C, "Main_Loop")]);
+    }
+
+    #[test]
+    fn test_resolving_addresses() {
+        let symbols = Symbols::parse("300 add").unwrap();
+        assert_eq!(symbols.resolve("ADD"), Ok(0x300));
+        assert_eq!(symbols.resolve("0x2A0"), Ok(0x2A0));
+        assert!(symbols.resolve("main").unwrap_err().contains("unknown label"));
+        assert_eq!(Symbols::default().resolve("main"), Err("invalid address: main".to_string()));
+    }
+
+    #[test]
+    fn test_locating_addresses() {
+        let symbols = Symbols::parse("200 main
+20C main_loop").unwrap();
+        assert_eq!(symbols.locate(0x20C), Some("main_loop".to_string()));
+        assert_eq!(symbols.locate(0x21A), Some("main_loop+E".to_string()));
+        assert_eq!(symbols.locate(0x1FE), None);
+    }
+
+    #[test]
+    fn test_invalid_symbols() {
+        assert!(Symbols::parse("200").unwrap_err().starts_with("line 1:"));
+        assert!(Symbols::parse("zz main").is_err());
+        assert!(Symbols::parse("200 main\n202 MAIN").unwrap_err().contains("more than once"));
+    }
+}
@@ -0,0 +1,72 @@
+//! Handling of 0NNN, which on the original machines called a machine language routine at NNN. There is no machine
+//! language here, so what happens is up to whoever is running the CPU.
+
+// Self imports
+use crate::cpu::CPU;
+
+// Std imports
+use std::fmt;
+use std::str::FromStr;
+
+/// Called with the CPU and NNN. The program counter has not yet moved past the instruction.
+pub type SysCallback = Box<dyn FnMut(&mut CPU, usize)>;
+
+#[derive(Default)]
+pub enum SysHandler {
+    /// Stop the program. Nothing can be done for a ROM that relies on machine language routines.
+    #[default]
+    Panic,
+
+    /// Carry on as if the instruction wasn't there.
+    Ignore,
+
+    /// Print the call to stderr and carry on.
+    Log,
+
+    /// Run a host-provided trap.
+    Callback(SysCallback),
+}
+
+impl fmt::Debug for SysHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SysHandler::Panic => write!(f, "Panic"),
+            SysHandler::Ignore => write!(f, "Ignore"),
+            SysHandler::Log => write!(f, "Log"),
+            SysHandler::Callback(_) => write!(f, "Callback"),
+        }
+    }
+}
+
+// Callbacks can't be compared, so handlers are equal if they are the same kind.
+impl PartialEq for SysHandler {
+    fn eq(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl FromStr for SysHandler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "panic" => Ok(SysHandler::Panic),
+            "ignore" => Ok(SysHandler::Ignore),
+            "log" => Ok(SysHandler::Log),
+            _ => Err(format!("unknown SYS handler: {} (expected panic, ignore or log)", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_handlers() {
+        assert_eq!("ignore".parse(), Ok(SysHandler::Ignore));
+        assert_eq!("LOG".parse(), Ok(SysHandler::Log));
+        assert_eq!("panic".parse(), Ok(SysHandler::Panic));
+        assert!("trap".parse::<SysHandler>().is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//! Terminal frontend, for running ROMs over SSH or anywhere without a display. Each character cell shows two pixels
+//! with the upper half block, its foreground the top pixel and its background the bottom one, so a 64x32 screen takes
+//! 64 columns and 16 rows. The terminal needs true colour. Built with the `terminal` feature.
+//!
+//! The VM drives it as it does the SDL window, so the config's palette and phosphor decay apply. Keys come from the
+//! config's keypad bindings. Terminals only say when a key is pressed, not when it's let go, so a key counts as held
+//! for a few frames after each press and the terminal's key repeat keeps it held. Esc quits.
+
+// Self imports
+use crate::config::{Config, DEFAULT_PALETTE};
+use crate::display::{Display, Input};
+use crate::frame_buffer::Palette;
+
+// Std imports
+use std::io::{self, Stdout, Write};
+use std::time::Duration;
+
+// External imports
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use log::{error, warn};
+
+// Frames a key stays held after it's pressed. Long enough to bridge the gap before a terminal's key repeat starts.
+const HOLD_FRAMES: u32 = 30;
+
+// Upper half block: the top pixel is drawn in the foreground colour and the bottom one shows the background.
+const HALF_BLOCK: char = '\u{2580}';
+
+/// Holds the terminal in raw mode on the alternate screen, handing it back when dropped.
+struct Screen {
+    out: Stdout,
+}
+
+impl Screen {
+    fn start() -> Result<Self, String> {
+        terminal::enable_raw_mode().map_err(|e| format!("unable to take over the terminal: {}", e))?;
+        let mut out = io::stdout();
+        let _ = execute!(out, EnterAlternateScreen, Hide);
+        Ok(Self { out })
+    }
+
+    // Draw a frame of colours, row by row, two rows to a line.
+    fn draw(&mut self, colours: &[[u8; 3]], width: usize) -> io::Result<()> {
+        for (line, cells) in cells(colours, width).chunks(width).enumerate() {
+            queue!(self.out, MoveTo(0, line as u16))?;
+            for (top, bottom) in cells {
+                queue!(self.out, SetForegroundColor(rgb(*top)), SetBackgroundColor(rgb(*bottom)), Print(HALF_BLOCK))?;
+            }
+        }
+        queue!(self.out, ResetColor)?;
+        self.out.flush()
+    }
+
+    // Write the status on the given line, in place of whatever was there.
+    fn status(&mut self, line: usize, status: &str) -> io::Result<()> {
+        queue!(self.out, MoveTo(0, line as u16), Clear(ClearType::CurrentLine), Print(status))?;
+        self.out.flush()
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(self.out, ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The terminal as the VM's display. The ROM's status goes on the line below the screen.
+pub struct Terminal {
+    screen: Option<Screen>, // Taken over by init.
+    config: Config,         // For the keypad's bindings.
+    palette: Palette,
+    held: Option<(u8, u32)>,         // The key held and the frames left before it's let go.
+    last: (Vec<[u8; 3]>, usize),     // The frame last drawn and its width.
+    lines: usize,                    // Lines the screen takes, which the status goes below.
+    title: (String, Option<String>), // The status and any extra text after it.
+    sounding: bool,                  // Whether the sound timer was running at the last frame.
+}
+
+impl Terminal {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            screen: None,
+            config: config.clone(),
+            palette: DEFAULT_PALETTE,
+            held: None,
+            last: (Vec::new(), 1),
+            lines: 0,
+            title: (String::new(), None),
+            sounding: false,
+        }
+    }
+
+    // Check the terminal has room for a screen and its status, and note where the status goes.
+    fn fit(&mut self, columns: usize, rows: usize) -> Result<(), String> {
+        let (width, height) = terminal::size().map_err(|e| format!("unable to read the terminal's size: {}", e))?;
+        self.lines = rows.div_ceil(2);
+        match (width as usize) < columns || (height as usize) <= self.lines {
+            true => Err(format!("the terminal needs to be at least {}x{}", columns, self.lines + 1)),
+            false => Ok(()),
+        }
+    }
+
+    fn draw_status(&mut self) {
+        let (status, info) = &self.title;
+        let text = match info {
+            Some(info) => format!("{} | {}", status, info),
+            None => status.clone(),
+        };
+        if let Some(screen) = &mut self.screen {
+            if let Err(e) = screen.status(self.lines, &text) {
+                error!("unable to draw: {}", e);
+            }
+        }
+    }
+}
+
+impl Display for Terminal {
+    fn init(&mut self, columns: usize, rows: usize) -> Result<(), String> {
+        self.fit(columns, rows)?;
+        self.screen = Some(Screen::start()?);
+        Ok(())
+    }
+
+    /// Esc closes the display.
+    fn poll(&mut self) -> Result<Input, ()> {
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            let key = match event::read() {
+                Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => key,
+                _ => continue,
+            };
+            if key.code == KeyCode::Esc {
+                return Err(());
+            }
+            if let Some(k) = key_name(&key.code).and_then(|name| self.config.keypad_key(&name)) {
+                self.held = Some((k, HOLD_FRAMES));
+            }
+        }
+
+        self.held = self.held.filter(|(_, frames)| *frames > 0).map(|(k, frames)| (k, frames - 1));
+        Ok(Input {
+            key: self.held.map(|(k, _)| k),
+            ..Input::default()
+        })
+    }
+
+    fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// Clear what the old screen left behind and move the status below the new one.
+    fn resize(&mut self, columns: usize, rows: usize) {
+        if let Err(e) = self.fit(columns, rows) {
+            warn!("{}", e);
+        }
+        if let Some(screen) = &mut self.screen {
+            let _ = execute!(screen.out, Clear(ClearType::All));
+        }
+        self.draw_status();
+    }
+
+    /// Only frames which changed are drawn, as terminals are slow.
+    fn draw_rgb(&mut self, colours: &[[u8; 3]], width: usize) {
+        if (colours, width) != (&self.last.0[..], self.last.1) {
+            self.last = (colours.to_vec(), width);
+            self.redraw();
+        }
+    }
+
+    fn redraw(&mut self) {
+        let (colours, width) = (&self.last.0, self.last.1);
+        if let Some(screen) = &mut self.screen {
+            if let Err(e) = screen.draw(colours, width) {
+                error!("unable to draw: {}", e);
+            }
+        }
+    }
+
+    fn set_title_status(&mut self, status: &str) {
+        self.title.0 = status.to_string();
+        self.draw_status();
+    }
+
+    fn set_title_info(&mut self, info: Option<&str>) {
+        self.title.1 = info.map(str::to_string);
+        self.draw_status();
+    }
+
+    /// Ring the terminal's bell as each sound starts, as there is no audio.
+    fn set_sound(&mut self, on: bool) {
+        if let (true, false, Some(screen)) = (on, self.sounding, &mut self.screen) {
+            let _ = screen.out.write_all(b"\x07");
+        }
+        self.sounding = on;
+    }
+}
+
+// SDL's name for a key pressed in the terminal, which is the upper case character for letters and digits.
+fn key_name(code: &KeyCode) -> Option<String> {
+    match code {
+        KeyCode::Char(' ') => Some("Space".to_string()),
+        KeyCode::Char(c) => Some(c.to_ascii_uppercase().to_string()),
+        _ => None,
+    }
+}
+
+// Pair each pixel with the one below it, a line of cells at a time. An odd last row is paired with black.
+fn cells(colours: &[[u8; 3]], width: usize) -> Vec<([u8; 3], [u8; 3])> {
+    let rows: Vec<&[[u8; 3]]> = colours.chunks(width).collect();
+    rows.chunks(2)
+        .flat_map(|pair| (0..width).map(move |x| (pair[0][x], pair.get(1).map_or([0; 3], |row| row[x]))))
+        .collect()
+}
+
+fn rgb([r, g, b]: [u8; 3]) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cells_pair_rows() {
+        let (a, b, c) = ([1; 3], [2; 3], [3; 3]);
+        assert_eq!(cells(&[a, b, c, a, b, c], 2), [(a, c), (b, a), (b, [0; 3]), (c, [0; 3])]);
+    }
+
+    #[test]
+    fn test_key_names() {
+        assert_eq!(key_name(&KeyCode::Char('x')).as_deref(), Some("X"));
+        assert_eq!(key_name(&KeyCode::Char(' ')).as_deref(), Some("Space"));
+        assert_eq!(key_name(&KeyCode::Enter), None);
+    }
+}
@@ -0,0 +1,207 @@
+//! A generated ROM for checking displays, filters and quirk settings without hunting for ROM files. It loops through
+//! three screens, each shown for a second or so:
+//!
+//! 1. A checkerboard covering the whole screen, one pixel per square.
+//! 2. Boxes straddling the bottom right corner, the bottom edge and the right edge, which should wrap onto the
+//!    opposite edges.
+//! 3. A ball moving diagonally, wrapping around the edges.
+
+// Std imports
+use std::collections::HashMap;
+
+// Sprites. Every sprite is 8 rows.
+const CHECKER: [u8; 8] = [0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55];
+const BOX: [u8; 8] = [0xFF, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0xFF];
+const BALL: [u8; 8] = [0x3C, 0x7E, 0xFF, 0xFF, 0xFF, 0xFF, 0x7E, 0x3C];
+
+const PAUSE: u8 = 60; // Frames each still screen is shown for.
+const BALL_STEPS: u8 = 96;
+
+/// Just enough of an assembler to write the pattern with named addresses.
+struct Asm {
+    rom: Vec<u8>,
+    labels: HashMap<&'static str, usize>,
+    fixups: Vec<(usize, &'static str)>, // Opcodes whose NNN is the address of a label.
+}
+
+impl Asm {
+    fn new() -> Self {
+        Self {
+            rom: Vec::new(),
+            labels: HashMap::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    fn label(&mut self, name: &'static str) {
+        self.labels.insert(name, 0x200 + self.rom.len());
+    }
+
+    fn op(&mut self, opcode: u16) {
+        self.rom.extend_from_slice(&opcode.to_be_bytes());
+    }
+
+    /// An opcode of the form XNNN where NNN is the address of a label.
+    fn op_to(&mut self, opcode: u16, label: &'static str) {
+        self.fixups.push((self.rom.len(), label));
+        self.op(opcode);
+    }
+
+    fn data(&mut self, label: &'static str, bytes: &[u8]) {
+        self.label(label);
+        self.rom.extend_from_slice(bytes);
+    }
+
+    fn finish(mut self) -> (Vec<u8>, HashMap<&'static str, usize>) {
+        for (offset, label) in &self.fixups {
+            let addr = self.labels[label] as u16;
+            let opcode = u16::from_be_bytes([self.rom[*offset], self.rom[offset + 1]]) | addr;
+            self.rom[*offset..offset + 2].copy_from_slice(&opcode.to_be_bytes());
+        }
+        (self.rom, self.labels)
+    }
+}
+
+/// The test pattern ROM. Loads at 0x200.
+pub fn generate() -> Vec<u8> {
+    assemble().0
+}
+
+fn assemble() -> (Vec<u8>, HashMap<&'static str, usize>) {
+    let mut asm = Asm::new();
+
+    asm.op_to(0x1000, "main");
+    asm.data("checker", &CHECKER);
+    asm.data("box", &BOX);
+    asm.data("ball", &BALL);
+
+    asm.label("main");
+    asm.op(0x00E0);
+
+    // Checkerboard. V0 and V1 step through the screen 8 pixels at a time.
+    asm.op_to(0xA000, "checker");
+    asm.op(0x6100);
+    asm.label("checker_row");
+    asm.op(0x6000);
+    asm.label("checker_col");
+    asm.op(0xD018);
+    asm.op(0x7008);
+    asm.op(0x3040); // Skip the jump once V0 reaches 64.
+    asm.op_to(0x1000, "checker_col");
+    asm.op(0x7108);
+    asm.op(0x3120); // Skip the jump once V1 reaches 32.
+    asm.op_to(0x1000, "checker_row");
+    asm.op(0x6200 | PAUSE as u16);
+    asm.op_to(0x2000, "wait");
+    asm.op(0x00E0);
+
+    // Boxes straddling the edges.
+    asm.op_to(0xA000, "box");
+    asm.op(0x603C);
+    asm.op(0x611C);
+    asm.op(0xD018); // (60, 28) wraps into all four corners.
+    asm.op(0x601C);
+    asm.op(0xD018); // (28, 28) wraps onto the top edge.
+    asm.op(0x603C);
+    asm.op(0x610C);
+    asm.op(0xD018); // (60, 12) wraps onto the left edge.
+    asm.op(0x6200 | PAUSE as u16);
+    asm.op_to(0x2000, "wait");
+    asm.op(0x00E0);
+
+    // Moving ball. Drawn, shown for 2 frames then drawn again to erase it. V3 counts the steps left.
+    asm.op_to(0xA000, "ball");
+    asm.op(0x6000);
+    asm.op(0x6100);
+    asm.op(0x6300 | BALL_STEPS as u16);
+    asm.op(0x643F); // Masks keeping the ball's position on screen.
+    asm.op(0x651F);
+    asm.label("ball_step");
+    asm.op(0xD018);
+    asm.op(0x6202);
+    asm.op_to(0x2000, "wait");
+    asm.op(0xD018);
+    asm.op(0x7001);
+    asm.op(0x7101);
+    asm.op(0x8042);
+    asm.op(0x8152);
+    asm.op(0x73FF);
+    asm.op(0x3300); // Skip the jump once V3 reaches 0.
+    asm.op_to(0x1000, "ball_step");
+    asm.op_to(0x1000, "main");
+
+    // Wait V2 frames.
+    asm.label("wait");
+    asm.op(0xF215);
+    asm.label("wait_loop");
+    asm.op(0xF207);
+    asm.op(0x3200);
+    asm.op_to(0x1000, "wait_loop");
+    asm.op(0x00EE);
+
+    asm.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::watch::{Access, Watchpoint};
+
+    // Run until the pattern starts waiting for the nth time, showing the nth screen.
+    fn run_to_wait(n: usize) -> CPU {
+        let (rom, labels) = assemble();
+        let mut cpu = CPU::default();
+        cpu.load(rom).unwrap();
+
+        let wait = labels["wait"];
+        cpu.watchpoints_mut().add(Watchpoint {
+            start: wait,
+            end: wait,
+            access: Access::EXECUTE,
+        });
+
+        // Frames end early on a watchpoint hit, so the screen is as the pattern drew it.
+        for _ in 0..n {
+            cpu.step_frame(9);
+            while cpu.take_watch_hit().is_none() {
+                cpu.step_frame(9);
+            }
+        }
+        cpu
+    }
+
+    fn lit(cpu: &mut CPU, x: usize, y: usize) -> bool {
+        cpu.get_framebuffer()[y * 64 + x] != 0
+    }
+
+    #[test]
+    fn test_checkerboard_covers_screen() {
+        let mut cpu = run_to_wait(1);
+        for y in 0..32 {
+            for x in 0..64 {
+                assert_eq!(lit(&mut cpu, x, y), (x + y) % 2 == 0, "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_boxes_wrap_around_edges() {
+        let mut cpu = run_to_wait(2);
+
+        // The corner box's top left is at (60, 28) and its bottom right wraps to (3, 3).
+        assert!(lit(&mut cpu, 60, 28));
+        assert!(lit(&mut cpu, 3, 3));
+        assert!(lit(&mut cpu, 28, 3));
+        assert!(lit(&mut cpu, 3, 19));
+        assert!(!lit(&mut cpu, 32, 16));
+    }
+
+    #[test]
+    fn test_pattern_loops() {
+        // Two still screens, then a wait for every step of the ball, then back to the checkerboard.
+        let mut cpu = run_to_wait(2 + BALL_STEPS as usize + 1);
+        assert!(lit(&mut cpu, 0, 0));
+        assert!(lit(&mut cpu, 63, 31));
+    }
+}
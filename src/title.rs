@@ -0,0 +1,125 @@
+//! Status shown in the window title: the ROM's name, the variant it's run as, its speed and the frame rate, e.g.
+//!
+//! ```text
+//! Chip8 in Rust | pong.ch8 | chip8 | 1x | 60 FPS
+//! ```
+//!
+//! The speed is a multiple of the COSMAC VIP's average of 9 instructions a frame. If the ROM looks like it was
+//! written for another variant, that one is shown too. The VM notes each frame shown and the frame rate is updated
+//! once a second.
+
+// Self imports
+use crate::variant::Variant;
+use crate::{Timing, INSTRUCTIONS_PER_FRAME};
+
+// Std imports
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// How often the frame rate is worked out, and so how often the title changes.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct Status {
+    rom: String,
+    variant: String,
+    speed: String,
+    since: Instant,   // When the frames being counted started.
+    frames: u32,      // Frames shown since then.
+    fps: Option<f64>, // None until the first second has passed.
+}
+
+impl Status {
+    /// `likely` is the variant the ROM looks written for, as analyze reports it.
+    pub fn new(rom: &Path, variant: Variant, likely: Variant, timing: Timing, now: Instant) -> Self {
+        let variant = match likely == variant {
+            true => variant.to_string(),
+            false => format!("{} (looks like {})", variant, likely),
+        };
+        Self {
+            rom: rom.file_name().unwrap_or(rom.as_os_str()).to_string_lossy().into_owned(),
+            variant,
+            speed: speed(timing),
+            since: now,
+            frames: 0,
+            fps: None,
+        }
+    }
+
+    /// Show a new speed, e.g. after loading a state saved at another.
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.speed = speed(timing);
+    }
+
+    /// Note a frame shown. Returns the status once a second, when the frame rate changes.
+    pub fn frame(&mut self, now: Instant) -> Option<String> {
+        self.frames += 1;
+        let elapsed = now.saturating_duration_since(self.since);
+        if elapsed < INTERVAL {
+            return None;
+        }
+
+        self.fps = Some(self.frames as f64 / elapsed.as_secs_f64());
+        self.since = now;
+        self.frames = 0;
+        Some(self.to_string())
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} | {} | {}", self.rom, self.variant, self.speed)?;
+        match self.fps {
+            Some(fps) => write!(f, " | {:.0} FPS", fps),
+            None => Ok(()),
+        }
+    }
+}
+
+// Instructions a frame as a multiple of the VIP's, e.g. 3.33x for the hp48 preset. Counting cycles runs at the VIP's
+// own speed, which varies from frame to frame.
+fn speed(timing: Timing) -> String {
+    match timing {
+        Timing::Fixed(ipf) => {
+            let multiplier = format!("{:.2}", ipf as f64 / INSTRUCTIONS_PER_FRAME as f64);
+            format!("{}x", multiplier.trim_end_matches('0').trim_end_matches('.'))
+        }
+        Timing::CosmacVip => "vip-cycles".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_speeds() {
+        assert_eq!(speed(Timing::Fixed(9)), "1x");
+        assert_eq!(speed(Timing::Fixed(30)), "3.33x");
+        assert_eq!(speed(Timing::Fixed(18)), "2x");
+        assert_eq!(speed(Timing::CosmacVip), "vip-cycles");
+    }
+
+    #[test]
+    fn test_status_updates_once_a_second() {
+        let start = Instant::now();
+        let rom = Path::new("roms/pong.ch8");
+        let mut status = Status::new(rom, Variant::Chip8, Variant::Chip8, Timing::Fixed(9), start);
+        assert_eq!(status.to_string(), "pong.ch8 | chip8 | 1x");
+
+        let frame = |n: u64| start + Duration::from_nanos(n * 1_000_000_000 / 60);
+        assert!((1..60).all(|n| status.frame(frame(n)).is_none()));
+        assert_eq!(status.frame(frame(60)).as_deref(), Some("pong.ch8 | chip8 | 1x | 60 FPS"));
+        assert_eq!(status.frame(frame(61)), None);
+
+        status.set_timing(Timing::Fixed(30));
+        assert_eq!(status.to_string(), "pong.ch8 | chip8 | 3.33x | 60 FPS");
+    }
+
+    #[test]
+    fn test_other_likely_variants_are_shown() {
+        let (rom, now) = (Path::new("a.ch8"), Instant::now());
+        let status = Status::new(rom, Variant::Chip8, Variant::XoChip, Timing::CosmacVip, now);
+        assert_eq!(status.to_string(), "a.ch8 | chip8 (looks like xochip) | vip-cycles");
+    }
+}
@@ -0,0 +1,122 @@
+//! Execution trace with one line per instruction, for diffing a run against another emulator's log to find where the
+//! two diverge. Each line has the address and opcode followed by the registers as they were before the instruction
+//! executed:
+//!
+//! ```text
+//! PC:0200 OP:6001 V0:00 V1:00 V2:00 ... VE:00 VF:00 I:0000 SP:0
+//! ```
+//!
+//! with all sixteen registers. Every field is fixed width upper case hex, so lines from the same state compare equal.
+//! With a symbol file, lines at labelled addresses end with the label, which other emulators' logs won't have.
+
+// Self imports
+use crate::cpu::CPU;
+use crate::hooks::Step;
+use crate::symbols::Symbols;
+
+// Std imports
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+pub struct Trace {
+    out: Box<dyn Write>,
+    symbols: Symbols,
+    lines: u64,
+    error: Option<io::Error>, // The first write to fail. Nothing more is written after it.
+}
+
+impl Trace {
+    pub fn new(out: Box<dyn Write>, symbols: Symbols) -> Self {
+        Self {
+            out,
+            symbols,
+            lines: 0,
+            error: None,
+        }
+    }
+
+    /// Start writing a line to a file for every instruction the CPU executes.
+    pub fn attach(cpu: &mut CPU, path: &Path, symbols: Symbols) -> Result<Rc<RefCell<Self>>, String> {
+        let file = File::create(path).map_err(|e| format!("unable to create {}: {}", path.display(), e))?;
+        let trace = Rc::new(RefCell::new(Self::new(Box::new(BufWriter::new(file)), symbols)));
+
+        let writer = trace.clone();
+        cpu.hooks_mut().before(move |_, step| writer.borrow_mut().write(step));
+        Ok(trace)
+    }
+
+    fn write(&mut self, step: &Step) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let line = match self.symbols.name(step.pc) {
+            Some(name) => format!("{} {}", line(step), name),
+            None => line(step),
+        };
+        match writeln!(self.out, "{}", line) {
+            Ok(()) => self.lines += 1,
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Flush the trace and say how much was written, or why writing stopped.
+    pub fn finish(&mut self) -> Result<String, String> {
+        if let Some(e) = self.error.take().or_else(|| self.out.flush().err()) {
+            return Err(format!("trace stopped after {} instructions: {}", self.lines, e));
+        }
+        Ok(format!("Traced {} instructions", self.lines))
+    }
+}
+
+fn line(step: &Step) -> String {
+    let registers = &step.registers;
+    let v: Vec<String> = registers.v.iter().enumerate().map(|(x, v)| format!("V{:X}:{:02X}", x, v)).collect();
+    format!("PC:{:04X} OP:{} {} I:{:04X} SP:{:X}", step.pc, step.opcode, v.join(" "), registers.i, registers.sp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Collects what is written, so tests can read it back.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_lines_show_registers_before_each_instruction() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x6F, 0x2A, 0xA3, 0x00, 0x22, 0x08, 0x00, 0x00, 0x00, 0xEE]).unwrap();
+        let out = Shared::default();
+        let symbols = Symbols::parse("208 done").unwrap();
+        let trace = Rc::new(RefCell::new(Trace::new(Box::new(out.clone()), symbols)));
+        let writer = trace.clone();
+        cpu.hooks_mut().before(move |_, step| writer.borrow_mut().write(step));
+
+        for _ in 0..4 {
+            cpu.cycle();
+        }
+        assert_eq!(trace.borrow_mut().finish(), Ok("Traced 4 instructions".to_string()));
+
+        let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("PC:0200 OP:6F2A V0:00 V1:00"));
+        assert!(lines[0].ends_with("VF:00 I:0000 SP:0"));
+        assert!(lines[3].starts_with("PC:0208 OP:00EE V0:00"));
+        assert!(lines[3].ends_with("VF:2A I:0300 SP:1 done"));
+    }
+}
@@ -0,0 +1,157 @@
+//! Debugger view in the terminal: disassembly around the program counter, the registers, the stack and memory from I,
+//! redrawn every frame as the CPU runs or steps. It runs alongside the window, which still shows the screen and takes
+//! keypad input. Built with the `tui` feature.
+//!
+//! Keys pressed in the terminal: p pauses, s steps one instruction while paused, b steps back one, c continues and q
+//! closes the view, handing the terminal back.
+
+// Self imports
+use crate::cpu::CPU;
+use crate::debugger;
+
+// Std imports
+use std::time::Duration;
+
+// External imports
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::DefaultTerminal;
+
+// Instructions shown before the program counter, and in total.
+const DISASSEMBLY_BEFORE: usize = 6;
+const DISASSEMBLY_LINES: usize = 24;
+
+// Bytes of memory shown from I.
+const MEMORY_BYTES: usize = 128;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Action {
+    Pause,
+    Step,
+    Back,
+    Continue,
+    Quit,
+}
+
+pub struct Tui {
+    terminal: DefaultTerminal,
+}
+
+impl Tui {
+    /// Take over the terminal. It is handed back when the view is dropped.
+    pub fn start() -> Result<Self, String> {
+        let terminal = ratatui::try_init().map_err(|e| format!("unable to start the terminal view: {}", e))?;
+        Ok(Self { terminal })
+    }
+
+    /// The first key pressed in the terminal since the last call, if it means anything.
+    pub fn poll(&mut self) -> Option<Action> {
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            let key = match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+
+            let action = match key.code {
+                KeyCode::Char('p') => Action::Pause,
+                KeyCode::Char('s') => Action::Step,
+                KeyCode::Char('b') => Action::Back,
+                KeyCode::Char('c') => Action::Continue,
+                KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+                _ => continue,
+            };
+            return Some(action);
+        }
+        None
+    }
+
+    pub fn draw(&mut self, cpu: &CPU, paused: bool) -> Result<(), String> {
+        self.terminal
+            .draw(|frame| {
+                let [main, status] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+                let [code, state] = Layout::horizontal([Constraint::Length(36), Constraint::Min(0)]).areas(main);
+                let [regs, stack, memory] =
+                    Layout::vertical([Constraint::Length(5), Constraint::Length(10), Constraint::Min(0)]).areas(state);
+
+                let disassembly: Vec<Line> = disassembly(cpu)
+                    .into_iter()
+                    .map(|(line, current)| match current {
+                        true => Line::styled(format!("> {}", line), Style::new().add_modifier(Modifier::REVERSED)),
+                        false => Line::raw(format!("  {}", line)),
+                    })
+                    .collect();
+                frame.render_widget(Paragraph::new(disassembly).block(pane("Code")), code);
+                frame.render_widget(Paragraph::new(debugger::regs(cpu)).block(pane("Registers")), regs);
+                frame.render_widget(Paragraph::new(stack_lines(cpu)).block(pane("Stack")), stack);
+
+                let title = format!("Memory at I ({:03X})", cpu.registers().i);
+                let dump = debugger::mem(cpu, cpu.registers().i, MEMORY_BYTES);
+                frame.render_widget(Paragraph::new(dump).block(pane(&title)), memory);
+
+                let state = if paused { "PAUSED" } else { "RUNNING" };
+                let help = format!(" {}   p pause   s step   b back   c continue   q close", state);
+                frame.render_widget(Line::styled(help, Style::new().add_modifier(Modifier::BOLD)), status);
+            })
+            .map(|_| ())
+            .map_err(|e| format!("unable to draw the terminal view: {}", e))
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+fn pane(title: &str) -> Block<'_> {
+    Block::default().borders(Borders::ALL).title(title)
+}
+
+// Disassembly from a few instructions before the program counter, each marked with whether it's the next to execute.
+fn disassembly(cpu: &CPU) -> Vec<(String, bool)> {
+    let start = cpu.pc().saturating_sub(DISASSEMBLY_BEFORE * 2);
+    cpu.disassemble(start, DISASSEMBLY_LINES)
+        .iter()
+        .map(|line| (line.to_string(), line.addr() == cpu.pc()))
+        .collect()
+}
+
+// Return addresses on the stack, innermost first.
+fn stack_lines(cpu: &CPU) -> String {
+    let stack = cpu.stack();
+    if stack.is_empty() {
+        return "empty".to_string();
+    }
+    stack.iter().enumerate().rev().map(|(level, addr)| format!("{:>2}  {:03X}\n", level, addr)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassembly_marks_the_program_counter() {
+        let mut cpu = CPU::default();
+        cpu.load(vec![0x60, 0x01, 0x60, 0x02, 0x12, 0x02]).unwrap();
+        cpu.cycle();
+
+        let lines = disassembly(&cpu);
+        assert_eq!(lines.len(), DISASSEMBLY_LINES);
+        let current: Vec<&String> = lines.iter().filter(|(_, current)| *current).map(|(line, _)| line).collect();
+        assert_eq!(current, vec!["202  6002  LD V0, 02"]);
+    }
+
+    #[test]
+    fn test_stack_shows_innermost_call_first() {
+        let mut cpu = CPU::default();
+        assert_eq!(stack_lines(&cpu), "empty");
+
+        cpu.load(vec![0x22, 0x02, 0x22, 0x04]).unwrap();
+        cpu.cycle();
+        cpu.cycle();
+        assert_eq!(stack_lines(&cpu), " 1  204\n 0  202\n");
+    }
+}
@@ -0,0 +1,123 @@
+//! Detection of reads from memory the program never gave a value. Memory past the ROM starts as zeroes here, but
+//! on other interpreters it may hold anything, so a ROM reading it before writing it is usually a bug: a sprite drawn
+//! from the wrong I, or FX65 loading a table which was never stored. Everything below the program counts as
+//! initialized, as do the ROM's bytes and anything written since.
+
+// Std imports
+use std::cell::Cell;
+use std::fmt;
+use std::str::FromStr;
+
+const INITIALIZED: u8 = 1;
+const REPORTED: u8 = 2;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum UninitReads {
+    /// Don't track initialization.
+    #[default]
+    Off,
+
+    /// Print the first read of each uninitialized byte to stderr and carry on.
+    Log,
+
+    /// Pause in the debugger at the first read of each uninitialized byte.
+    Break,
+}
+
+impl FromStr for UninitReads {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(UninitReads::Off),
+            "log" => Ok(UninitReads::Log),
+            "break" => Ok(UninitReads::Break),
+            _ => Err(format!("unknown uninitialized read mode: {} (expected off, log or break)", s)),
+        }
+    }
+}
+
+/// A read of a byte which was never initialized.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UninitRead {
+    pub addr: usize,
+    pub pc: usize,   // Address of the instruction which made the read.
+    pub opcode: u16, // The instruction itself.
+}
+
+impl fmt::Display for UninitRead {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "uninitialized read: {:03X} by instruction {:04X} at {:03X}",
+            self.addr, self.opcode, self.pc
+        )
+    }
+}
+
+/// Which bytes of memory have a value. The marks are Cells so reads can record that they have been reported without
+/// needing mutable access, like coverage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Initialized {
+    marks: Vec<Cell<u8>>,
+}
+
+impl Initialized {
+    /// Marks for memory holding a program loaded at `start`. Bytes which aren't zero count as initialized too, as
+    /// something must have written them, e.g. before a saved session was resumed.
+    pub fn new(memory: &[u8], start: usize, rom_len: usize) -> Self {
+        let initialized = |addr: usize| addr < start + rom_len || memory[addr] != 0;
+        Self {
+            marks: (0..memory.len()).map(|addr| Cell::new(initialized(addr) as u8 * INITIALIZED)).collect(),
+        }
+    }
+
+    pub fn write(&self, addr: usize) {
+        if let Some(mark) = self.marks.get(addr) {
+            mark.set(mark.get() | INITIALIZED);
+        }
+    }
+
+    /// Whether a read should be reported: the byte has never been initialized and hasn't been reported before.
+    pub fn read(&self, addr: usize) -> bool {
+        match self.marks.get(addr) {
+            Some(mark) if mark.get() == 0 => {
+                mark.set(REPORTED);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_modes() {
+        assert_eq!("log".parse(), Ok(UninitReads::Log));
+        assert_eq!("Break".parse(), Ok(UninitReads::Break));
+        assert!("warn".parse::<UninitReads>().is_err());
+    }
+
+    #[test]
+    fn test_reads_are_reported_once() {
+        let mut memory = vec![0; 0x400];
+        memory[0x300] = 0xFF;
+        let initialized = Initialized::new(&memory, 0x200, 4);
+
+        assert!(!initialized.read(0x100) && !initialized.read(0x203) && !initialized.read(0x300));
+        assert!(initialized.read(0x204));
+        assert!(!initialized.read(0x204));
+
+        initialized.write(0x206);
+        assert!(!initialized.read(0x206));
+    }
+
+    #[test]
+    fn test_describing_reads() {
+        let read = UninitRead { addr: 0x300, pc: 0x204, opcode: 0xF065 };
+        assert_eq!(read.to_string(), "uninitialized read: 300 by instruction F065 at 204");
+    }
+}
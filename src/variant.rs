@@ -0,0 +1,168 @@
+//! The different machines and interpreters a ROM can be written for. Each variant decides where programs are loaded
+//! and the geometry of the screen, and how much memory there is.
+
+// Self imports
+use crate::OFFSET;
+
+// Std imports
+use std::fmt;
+use std::str::FromStr;
+
+// External imports
+use serde::{Deserialize, Serialize};
+
+// Size of addressable memory. Addresses outside of this range wrap back around to the start.
+const MEMORY_SIZE: usize = 4096;
+
+// XO-CHIP addresses 64K through its 16-bit I and F000 NNNN.
+const XO_CHIP_MEMORY_SIZE: usize = 0x1_0000;
+
+// MegaChip's I register is 24 bits wide.
+#[cfg(feature = "megachip")]
+const MEGACHIP_MEMORY_SIZE: usize = 0x100_0000;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Variant {
+    /// The original COSMAC VIP interpreter. Programs load at 0x200 and the screen is 64x32.
+    #[default]
+    Chip8,
+
+    /// CHIP-8X for the VIP with the VP-590 colour board and a second keypad. Programs load at 0x300.
+    Chip8X,
+
+    /// The ETI-660 interpreter. Programs load at 0x600 and the screen is 64x48.
+    Eti660,
+
+    /// The ETI-660 with its 64x64 high resolution display.
+    Eti660Hires,
+
+    /// Two-page hi-res CHIP-8 for the VIP. Programs load at 0x200 like CHIP-8 but the screen is 64x64.
+    Chip8Hires,
+
+    /// XO-CHIP. Like CHIP-8 with 64K of memory, so I and the program counter can go beyond 4K.
+    XoChip,
+
+    /// Experimental MegaChip. Starts out like CHIP-8 until 0011 switches to the 256x192 MegaChip screen.
+    #[cfg(feature = "megachip")]
+    MegaChip,
+}
+
+impl Variant {
+    /// Address programs are loaded to and execution starts from.
+    pub fn program_start(&self) -> usize {
+        match self {
+            Variant::Chip8 | Variant::Chip8Hires | Variant::XoChip => OFFSET,
+            #[cfg(feature = "megachip")]
+            Variant::MegaChip => OFFSET,
+            Variant::Chip8X => 0x300,
+            Variant::Eti660 | Variant::Eti660Hires => 0x600,
+        }
+    }
+
+    /// Number of rows on the screen. Every variant is 64 pixels wide.
+    pub fn screen_height(&self) -> usize {
+        match self {
+            Variant::Chip8 | Variant::Chip8X | Variant::XoChip => 32,
+            #[cfg(feature = "megachip")]
+            Variant::MegaChip => 32,
+            Variant::Eti660 => 48,
+            Variant::Eti660Hires | Variant::Chip8Hires => 64,
+        }
+    }
+
+    /// Bytes of addressable memory.
+    pub fn memory_size(&self) -> usize {
+        #[cfg(feature = "megachip")]
+        if *self == Variant::MegaChip {
+            return MEGACHIP_MEMORY_SIZE;
+        }
+
+        match self {
+            Variant::XoChip => XO_CHIP_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Variant::Chip8),
+            "chip8x" | "chip-8x" => Ok(Variant::Chip8X),
+            "chip8-hires" | "chip-8-hires" => Ok(Variant::Chip8Hires),
+            "xochip" | "xo-chip" => Ok(Variant::XoChip),
+            #[cfg(feature = "megachip")]
+            "megachip" | "mega-chip" => Ok(Variant::MegaChip),
+            "eti660" | "eti-660" => Ok(Variant::Eti660),
+            "eti660-hires" | "eti-660-hires" => Ok(Variant::Eti660Hires),
+            _ => Err(format!("unknown variant: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Variant::Chip8 => write!(f, "chip8"),
+            Variant::Chip8X => write!(f, "chip8x"),
+            Variant::Chip8Hires => write!(f, "chip8-hires"),
+            Variant::XoChip => write!(f, "xochip"),
+            #[cfg(feature = "megachip")]
+            Variant::MegaChip => write!(f, "megachip"),
+            Variant::Eti660 => write!(f, "eti660"),
+            Variant::Eti660Hires => write!(f, "eti660-hires"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_variants() {
+        assert_eq!("chip8".parse(), Ok(Variant::Chip8));
+        assert_eq!("ETI-660".parse(), Ok(Variant::Eti660));
+        assert_eq!("eti660-hires".parse(), Ok(Variant::Eti660Hires));
+        assert!("schip".parse::<Variant>().is_err());
+    }
+
+    #[test]
+    fn test_variant_names_round_trip() {
+        let variants = [
+            Variant::Chip8,
+            Variant::Chip8X,
+            Variant::Chip8Hires,
+            Variant::XoChip,
+            Variant::Eti660,
+            Variant::Eti660Hires,
+        ];
+
+        for variant in &variants {
+            assert_eq!(variant.to_string().parse(), Ok(*variant));
+        }
+    }
+
+    #[test]
+    fn test_chip8_hires_geometry() {
+        assert_eq!("chip8-hires".parse(), Ok(Variant::Chip8Hires));
+        assert_eq!(Variant::Chip8Hires.program_start(), 0x200);
+        assert_eq!(Variant::Chip8Hires.screen_height(), 64);
+    }
+
+    #[test]
+    fn test_memory_sizes() {
+        assert_eq!(Variant::Chip8.memory_size(), 0x1000);
+        assert_eq!(Variant::XoChip.memory_size(), 0x1_0000);
+        assert_eq!("xo-chip".parse(), Ok(Variant::XoChip));
+    }
+
+    #[test]
+    fn test_eti660_geometry() {
+        assert_eq!(Variant::Eti660.program_start(), 0x600);
+        assert_eq!(Variant::Eti660.screen_height(), 48);
+        assert_eq!(Variant::Eti660Hires.screen_height(), 64);
+    }
+}
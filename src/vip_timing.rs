@@ -0,0 +1,205 @@
+//! COSMAC VIP instruction timings, counted from the original CHIP-8 interpreter's 1802 code as annotated in Laurence
+//! Scotford's "Chip-8 on the COSMAC VIP" articles. Addresses in the comments below are the interpreter's.
+//!
+//! The VIP's 1802 runs at 1.7609MHz with 8 clock cycles per machine cycle, giving ~3668 machine cycles per 60Hz
+//! frame. Roughly 1024 of those are stolen by the display DMA, leaving the rest for the interpreter. Every 1802
+//! instruction the interpreter uses takes 2 machine cycles, so each cost is the number of instructions run on the way
+//! through: the fetch and decode loop, then the instruction's own handler along the path its operands send it down.
+
+// Self imports
+use crate::decode::Instruction;
+
+/// Machine cycles available to the interpreter each frame.
+pub const CYCLES_PER_FRAME: u32 = 3668 - 1024;
+
+// Machine cycles per 1802 instruction.
+const OP: u32 = 2;
+
+// The loop at 01B fetches an instruction, looks its handler up by the first nibble and jumps to it, then branches back
+// once the handler returns. 0NNN leaves the loop early at 026 to run the machine code at NNN instead.
+const FETCH: u32 = 34 * OP;
+const FETCH_SYS: u32 = 20 * OP;
+
+// The screen DXYN draws to, in bytes of 8 pixels.
+const COLUMNS: usize = 8;
+const ROWS: usize = 32;
+
+/// The parts of the machine an instruction's path through the interpreter depends on, as they are before it runs.
+pub struct Machine<'a> {
+    pub v: &'a [u8; 16],
+    pub i: usize,
+    pub memory: &'a [u8],
+    pub screen: &'a [u64], // One word per row, leftmost pixel in the top bit. Empty if the screen isn't 64 wide.
+}
+
+/// Machine cycles an instruction takes on the VIP.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cost {
+    pub cycles: u32,                  // Until the instruction finishes or waits for the display interrupt.
+    pub skip: u32,                    // Extra cycles if it skips the next instruction.
+    pub after_interrupt: Option<u32>, // Cycles after the display interrupt, for instructions which wait for it.
+}
+
+impl Cost {
+    fn new(cycles: u32) -> Self {
+        Self { cycles, skip: 0, after_interrupt: None }
+    }
+
+    // A skip which takes `ops` instructions when it doesn't skip. Skipping runs INC R5 twice more, at 188.
+    fn skip(ops: u32) -> Self {
+        Self { cycles: FETCH + ops * OP, skip: 2 * OP, after_interrupt: None }
+    }
+}
+
+/// Machine cycles the given instruction takes on the VIP. Machine code called by 0NNN, and the monitor's keypad
+/// routine FX0A calls, aren't counted as they aren't emulated, apart from 00E0 and 00EE which are the interpreter's
+/// own routines. Instructions the VIP doesn't have are charged as the machine code calls or unknown handlers they
+/// would be there.
+pub fn cost(instruction: &Instruction, machine: &Machine) -> Cost {
+    use Instruction::*;
+
+    let v = machine.v;
+    // Adding to the low byte of an address at 1A9 and 120 carries into its high byte with a few more instructions.
+    let carries = |low: usize, add: u8| (low & 0xFF) + add as usize > 0xFF;
+
+    match *instruction {
+        Cls => Cost::new(FETCH_SYS + 1539 * OP), // 0E0: clears 256 bytes at 6 instructions each.
+        Ret => Cost::new(FETCH_SYS + 5 * OP),    // 0EE
+        Sys { .. } | Exit | ScrollDown { .. } | ScrollUp { .. } | ScrollRight | ScrollLeft | Lores | Hires => {
+            Cost::new(FETCH_SYS)
+        }
+        Jump { .. } => Cost::new(FETCH + 6 * OP),  // 17C
+        Call { .. } => Cost::new(FETCH + 13 * OP), // 175: pushes the return address then jumps.
+        SkipEqByte { .. } | SkipNeByte { .. } => Cost::skip(5), // 183, 18B
+        SkipEqReg { .. } | SkipNeReg { .. } => Cost::skip(7),   // 195, 191: load Vy then compare as above.
+        SaveRange { .. } | LoadRange { .. } => Cost::skip(7),   // Any 5XYN is 5XY0 on the VIP.
+        LoadByte { .. } => Cost::new(FETCH + 3 * OP),           // 1B4
+        AddByte { .. } => Cost::new(FETCH + 5 * OP),            // 1B7
+        LoadReg { .. } => Cost::new(FETCH + 6 * OP),            // 1BC: a copy, without the ALU path.
+        // 1C4: writes the 1802 ALU instruction F0-FE selected by N to the stack, runs it there, then sets VF from DF.
+        Or { .. } | And { .. } | Xor { .. } | AddReg { .. } | Sub { .. } | ShiftRight { .. } | SubN { .. }
+        | ShiftLeft { .. } => Cost::new(FETCH + 22 * OP),
+        LoadI { .. } => Cost::new(FETCH + 6 * OP), // 1EB
+        JumpV0 { nnn } => Cost::new(FETCH + (11 + carries(nnn, v[0]) as u32) * OP), // 1A4
+        Random { .. } => Cost::new(FETCH + 18 * OP), // 1D9
+        Draw { x, y, n } => draw(v[x] as usize, v[y] as usize, n, machine),
+        // 199 latches Vx on the keypad then jumps to 19E or 1A1 by the opcode's low byte.
+        SkipKey { .. } | SkipNotKey { .. } => Cost::skip(7),
+        // 105 jumps to 1KK for FXKK, adding 2 instructions to each.
+        LoadDelay { .. } => Cost::new(FETCH + (2 + 3) * OP), // 107
+        WaitKey { .. } => Cost::new(FETCH + (2 + 9) * OP),   // 10A, calling the monitor at 8195.
+        SetDelay { .. } => Cost::new(FETCH + (2 + 3) * OP),  // 115
+        SetSound { .. } => Cost::new(FETCH + (2 + 3) * OP),  // 118
+        AddI { x } => Cost::new(FETCH + (2 + 6 + 3 * carries(machine.i, v[x]) as u32) * OP), // 11E
+        LoadFont { .. } => Cost::new(FETCH + (2 + 8) * OP), // 129
+        // 133 subtracts 100, 10 and 1 from Vx until it would go negative, counting each digit up one at a time.
+        Bcd { x } => {
+            let value = v[x] as u32;
+            let digits = value / 100 + value / 10 % 10 + value % 10;
+            Cost::new(FETCH + (2 + 40 + 8 * digits) * OP)
+        }
+        // 155 and 165 copy a register at a time, up to and including Vx.
+        StoreRegisters { x } | LoadRegisters { x } => Cost::new(FETCH + (2 + 7 + 7 * (x as u32 + 1)) * OP),
+        _ => Cost::new(FETCH),
+    }
+}
+
+// DXYN at 070. Before waiting for the display interrupt at 0AC, it shifts each row of the sprite into a pair of bytes,
+// one instruction loop per pixel x is past a byte boundary, and winds I back. After, so the screen isn't drawn to while
+// it is being shown, it XORs the rows onto the screen. That stops at the bottom edge, skips the right hand byte at the
+// right edge, and takes 2 more instructions for each byte which collides.
+fn draw(x: usize, y: usize, n: usize, machine: &Machine) -> Cost {
+    let (shift, column, top) = (x % 8, x / 8 % COLUMNS, y % ROWS);
+    let before = 34 + n as u32 * (23 + 10 * shift as u32);
+
+    let rows = n.min(ROWS - top);
+    let mut after = 5 + 6 + if top + n < ROWS { 2 } else { 0 };
+    for row in 0..rows {
+        let sprite = machine.memory[(machine.i + row) % machine.memory.len()] as u16;
+        let bits = sprite << (8 - shift);
+        let screen = |column: usize| machine.screen.get(top + row).map_or(0, |word| word >> (56 - 8 * column)) as u8;
+
+        after += 17 + 2 * ((bits >> 8) as u8 & screen(column) != 0) as u32;
+        if column < COLUMNS - 1 {
+            after += 8 + 2 * (bits as u8 & screen(column + 1) != 0) as u32;
+        }
+    }
+
+    Cost { cycles: FETCH + before * OP, skip: 0, after_interrupt: Some(after * OP) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::decode;
+    use crate::variant::Variant;
+
+    const MEMORY: [u8; 0x1000] = [0xFF; 0x1000];
+
+    fn machine<'a>(v: &'a [u8; 16], screen: &'a [u64]) -> Machine<'a> {
+        Machine { v, i: 0x300, memory: &MEMORY, screen }
+    }
+
+    fn cycles(opcode: u16, v: &[u8; 16]) -> u32 {
+        cost(&decode(opcode, Variant::Chip8), &machine(v, &[])).cycles
+    }
+
+    #[test]
+    fn test_costs_follow_the_interpreter() {
+        let v = [0; 16];
+        assert_eq!(cycles(0x6012, &v), 74);
+        assert_eq!(cycles(0x1200, &v), 80);
+        assert_eq!(cycles(0x00EE, &v), 50);
+        assert_eq!(cycles(0x00E0, &v), 3118);
+        assert_eq!(cycles(0x0123, &v), 40);
+    }
+
+    #[test]
+    fn test_skips_cost_more_when_taken() {
+        let v = [0; 16];
+        for opcode in [0x3012, 0x4012, 0x5010, 0x9010, 0xE09E, 0xE0A1] {
+            let cost = cost(&decode(opcode, Variant::Chip8), &machine(&v, &[]));
+            assert_eq!(cost.skip, 4, "{:04X}", opcode);
+        }
+        assert!(cycles(0x3012, &v) < cycles(0x5010, &v));
+    }
+
+    #[test]
+    fn test_only_alu_ops_take_the_alu_path() {
+        let v = [0; 16];
+        assert_eq!(cycles(0x8010, &v), 80);
+        assert!((1..=7).chain([0xE]).all(|n| cycles(0x8010 | n, &v) == 112));
+    }
+
+    #[test]
+    fn test_carries_and_digits_cost_more() {
+        let mut v = [0; 16];
+        v[0] = 16;
+        v[1] = 15;
+        assert_eq!(cycles(0xB2E0, &v) + 2, cycles(0xB2F8, &v));
+        assert_eq!(cycles(0xF033, &v), cycles(0xF133, &v) + 16);
+        assert_eq!(cycles(0xF055, &v) + 14, cycles(0xF155, &v));
+        assert_eq!(cycles(0xF365, &v), cycles(0xF355, &v));
+    }
+
+    #[test]
+    fn test_draws_are_split_by_the_display_interrupt() {
+        let mut v = [0; 16];
+        let draw = |v: &[u8; 16], screen: &[u64]| cost(&decode(0xD015, Variant::Chip8), &machine(v, screen));
+
+        let aligned = draw(&v, &[]);
+        assert_eq!(aligned, Cost { cycles: 68 + 2 * (34 + 5 * 23), skip: 0, after_interrupt: Some(2 * (13 + 5 * 25)) });
+
+        v[0] = 3;
+        assert_eq!(draw(&v, &[]).cycles, aligned.cycles + 5 * 3 * 10 * 2);
+
+        // Collisions on the left hand byte of every row.
+        let screen = [0xFF << 56; 32];
+        assert_eq!(draw(&v, &screen).after_interrupt, Some(2 * (13 + 5 * 27)));
+
+        // The right edge has no right hand byte, and the bottom edge cuts the sprite off after two rows.
+        v[0] = 60;
+        v[1] = 30;
+        assert_eq!(draw(&v, &[]).after_interrupt, Some(2 * (11 + 2 * 17)));
+    }
+}
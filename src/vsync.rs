@@ -0,0 +1,70 @@
+//! Frame pacing for the vsync display option. Presenting waits for the monitor's refresh, which is rarely exactly
+//! 60Hz, so instead of sleeping out each frame the time between refreshes is added up and a frame is run (instructions
+//! and a timer tick) for every 60th of a second that has passed. A 60Hz monitor runs one frame a refresh, a 120Hz one
+//! every other refresh and a 50Hz one sometimes two.
+
+// Self imports
+use crate::FRAME_DURATION;
+
+// Std imports
+use std::time::{Duration, Instant};
+
+// Frames caught up on at most in one refresh. Time owed past this, e.g. after the window was dragged, is dropped
+// rather than run all at once.
+const MAX_FRAMES: u32 = 4;
+
+pub struct Pacer {
+    last: Instant,  // When frames were last worked out.
+    owed: Duration, // Time passed which hasn't been run as frames yet.
+}
+
+impl Pacer {
+    pub fn new(now: Instant) -> Self {
+        Self { last: now, owed: Duration::default() }
+    }
+
+    /// Frames to run for the time since the last call.
+    pub fn frames(&mut self, now: Instant) -> u32 {
+        self.owed += now.saturating_duration_since(self.last);
+        self.last = now;
+
+        let frames = (self.owed.as_nanos() / FRAME_DURATION.as_nanos()) as u32;
+        match frames > MAX_FRAMES {
+            true => {
+                self.owed = Duration::default();
+                MAX_FRAMES
+            }
+            false => {
+                self.owed -= FRAME_DURATION * frames;
+                frames
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refreshes(hz: u64, count: u64) -> Vec<u32> {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(start);
+        (1..=count).map(|n| pacer.frames(start + Duration::from_nanos(n * 1_000_000_000 / hz))).collect()
+    }
+
+    #[test]
+    fn test_frames_follow_the_refresh_rate() {
+        assert_eq!(refreshes(60, 6), [1; 6]);
+        assert_eq!(refreshes(120, 6), [0, 1, 0, 1, 0, 1]);
+        assert_eq!(refreshes(50, 200).iter().sum::<u32>(), 240);
+        assert_eq!(refreshes(144, 144).iter().sum::<u32>(), 60);
+    }
+
+    #[test]
+    fn test_stalls_are_not_caught_up() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(start);
+        assert_eq!(pacer.frames(start + Duration::from_secs(2)), MAX_FRAMES);
+        assert_eq!(pacer.frames(start + Duration::from_secs(2) + FRAME_DURATION), 1);
+    }
+}
@@ -0,0 +1,234 @@
+//! Watchpoints. Each watches a range of addresses for some combination of reads, writes and instruction fetches. The
+//! ranges are flattened into an interval map so checking an access is a single lookup however many watchpoints there
+//! are, and the CPU skips the lookup entirely while there are none.
+
+// Std imports
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::BitOr;
+use std::str::FromStr;
+
+/// Set of memory access types.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Access(u8);
+
+impl Access {
+    pub const NONE: Access = Access(0);
+    pub const READ: Access = Access(1);
+    pub const WRITE: Access = Access(2);
+    pub const EXECUTE: Access = Access(4);
+
+    pub fn contains(&self, other: Access) -> bool {
+        self.0 & other.0 == other.0 && other.0 != 0
+    }
+}
+
+impl BitOr for Access {
+    type Output = Access;
+
+    fn bitor(self, rhs: Access) -> Access {
+        Access(self.0 | rhs.0)
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = [(Access::READ, "read"), (Access::WRITE, "write"), (Access::EXECUTE, "execute")];
+        let names: Vec<&str> = names.iter().filter(|(a, _)| self.contains(*a)).map(|(_, n)| *n).collect();
+        write!(f, "{}", names.join("/"))
+    }
+}
+
+/// Watch an inclusive range of addresses for the given kinds of access.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub start: usize,
+    pub end: usize,
+    pub access: Access,
+}
+
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    /// Parse `<access>:<start>[-<end>]`, where access is any of r, w and x and addresses are hex. e.g. `x:000-1FF`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid watchpoint: {} (expected e.g. rw:300-30F)", s);
+        let (flags, range) = s.split_once(':').ok_or_else(invalid)?;
+
+        let mut access = Access::NONE;
+        for flag in flags.chars() {
+            access = access
+                | match flag.to_ascii_lowercase() {
+                    'r' => Access::READ,
+                    'w' => Access::WRITE,
+                    'x' => Access::EXECUTE,
+                    _ => return Err(invalid()),
+                };
+        }
+
+        let parse = |addr: &str| usize::from_str_radix(addr.trim_start_matches("0x"), 16).map_err(|_| invalid());
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => (parse(range)?, parse(range)?),
+        };
+
+        if access == Access::NONE || end < start {
+            return Err(invalid());
+        }
+
+        Ok(Watchpoint { start, end, access })
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    /// In the form it's parsed from.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = [(Access::READ, 'r'), (Access::WRITE, 'w'), (Access::EXECUTE, 'x')];
+        let flags: String = flags.iter().filter(|(a, _)| self.access.contains(*a)).map(|(_, c)| *c).collect();
+        match self.start == self.end {
+            true => write!(f, "{}:{:03X}", flags, self.start),
+            false => write!(f, "{}:{:03X}-{:03X}", flags, self.start, self.end),
+        }
+    }
+}
+
+/// An access which matched a watchpoint.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WatchHit {
+    pub addr: usize,
+    pub access: Access,
+    pub pc: usize,   // Address of the instruction which made the access.
+    pub opcode: u16, // The instruction itself.
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "watchpoint: {} of {:03X} by instruction {:04X} at {:03X}",
+            self.access, self.addr, self.opcode, self.pc
+        )
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Watchpoints {
+    list: Vec<Watchpoint>,
+
+    // Start of each segment of the address space mapped to the accesses watched from there until the next segment.
+    segments: BTreeMap<usize, Access>,
+}
+
+impl Watchpoints {
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn list(&self) -> &[Watchpoint] {
+        &self.list
+    }
+
+    pub fn add(&mut self, watchpoint: Watchpoint) {
+        self.list.push(watchpoint);
+        self.rebuild();
+    }
+
+    /// Remove the watchpoint at the given position in the list.
+    pub fn remove(&mut self, index: usize) -> Option<Watchpoint> {
+        if index >= self.list.len() {
+            return None;
+        }
+
+        let watchpoint = self.list.remove(index);
+        self.rebuild();
+        Some(watchpoint)
+    }
+
+    /// Accesses watched at an address.
+    pub fn watched(&self, addr: usize) -> Access {
+        self.segments.range(..=addr).next_back().map_or(Access::NONE, |(_, access)| *access)
+    }
+
+    // Split the address space at the start and just past the end of every watchpoint, then give each segment the
+    // union of the accesses watched by every watchpoint covering it.
+    fn rebuild(&mut self) {
+        self.segments.clear();
+
+        for watchpoint in &self.list {
+            for boundary in &[watchpoint.start, watchpoint.end + 1] {
+                let access = self.watched(*boundary);
+                self.segments.insert(*boundary, access);
+            }
+
+            for (_, access) in self.segments.range_mut(watchpoint.start..=watchpoint.end) {
+                *access = *access | watchpoint.access;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_watchpoints() {
+        let watchpoint: Watchpoint = "x:000-1FF".parse().unwrap();
+        assert_eq!(watchpoint, Watchpoint { start: 0, end: 0x1FF, access: Access::EXECUTE });
+
+        let watchpoint: Watchpoint = "RW:0x300".parse().unwrap();
+        assert_eq!(watchpoint.start, 0x300);
+        assert_eq!(watchpoint.end, 0x300);
+        assert_eq!(watchpoint.access, Access::READ | Access::WRITE);
+
+        assert!("300-30F".parse::<Watchpoint>().is_err());
+        assert!("q:300".parse::<Watchpoint>().is_err());
+        assert!(":300".parse::<Watchpoint>().is_err());
+        assert!("r:30F-300".parse::<Watchpoint>().is_err());
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut watchpoints = Watchpoints::default();
+        watchpoints.add("r:300-30F".parse().unwrap());
+
+        assert_eq!(watchpoints.watched(0x2FF), Access::NONE);
+        assert_eq!(watchpoints.watched(0x300), Access::READ);
+        assert_eq!(watchpoints.watched(0x30F), Access::READ);
+        assert_eq!(watchpoints.watched(0x310), Access::NONE);
+    }
+
+    #[test]
+    fn test_overlapping_ranges() {
+        let mut watchpoints = Watchpoints::default();
+        watchpoints.add("r:300-30F".parse().unwrap());
+        watchpoints.add("w:308-317".parse().unwrap());
+        watchpoints.add("x:000-FFF".parse().unwrap());
+
+        assert_eq!(watchpoints.watched(0x200), Access::EXECUTE);
+        assert_eq!(watchpoints.watched(0x304), Access::READ | Access::EXECUTE);
+        assert_eq!(watchpoints.watched(0x30A), Access::READ | Access::WRITE | Access::EXECUTE);
+        assert_eq!(watchpoints.watched(0x312), Access::WRITE | Access::EXECUTE);
+        assert_eq!(watchpoints.watched(0x1000), Access::NONE);
+
+        watchpoints.remove(2);
+        assert_eq!(watchpoints.watched(0x200), Access::NONE);
+        assert_eq!(watchpoints.watched(0x30A), Access::READ | Access::WRITE);
+        assert_eq!(watchpoints.remove(2), None);
+    }
+
+    #[test]
+    fn test_access_display() {
+        assert_eq!((Access::READ | Access::EXECUTE).to_string(), "read/execute");
+        let hit = WatchHit { addr: 0x300, access: Access::WRITE, pc: 0x20A, opcode: 0xF355 };
+        assert_eq!(hit.to_string(), "watchpoint: write of 300 by instruction F355 at 20A");
+    }
+
+    #[test]
+    fn test_watchpoint_display_parses_back() {
+        for s in &["x:000-1FF", "rw:300"] {
+            let watchpoint: Watchpoint = s.parse().unwrap();
+            assert_eq!(watchpoint.to_string(), *s);
+        }
+    }
+}
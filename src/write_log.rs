@@ -0,0 +1,150 @@
+//! Write history for ranges of memory, e.g. a game's variables. Every write into a logged range is kept with the
+//! instruction which made it and the byte before and after, and the debugger's log command shows them oldest first:
+//!
+//! ```text
+//!  0  300-30F
+//!       2A4  F255  300: 00 -> 03
+//!       2A4  F255  301: 00 -> 10
+//! ```
+//!
+//! Only the most recent writes are kept, so a ROM writing in a loop can't use up memory.
+
+// Std imports
+use std::collections::VecDeque;
+use std::fmt;
+
+// Writes kept before the oldest are dropped.
+const LIMIT: usize = 1000;
+
+/// One write into a logged range.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LoggedWrite {
+    pub addr: usize,
+    pub pc: usize,   // Address of the instruction which made the write.
+    pub opcode: u16, // The instruction itself.
+    pub before: u8,
+    pub after: u8,
+}
+
+impl fmt::Display for LoggedWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:03X}  {:04X}  {:03X}: {:02X} -> {:02X}", self.pc, self.opcode, self.addr, self.before, self.after)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct WriteLog {
+    ranges: Vec<(usize, usize)>, // Inclusive.
+    writes: VecDeque<LoggedWrite>,
+}
+
+impl WriteLog {
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn ranges(&self) -> &[(usize, usize)] {
+        &self.ranges
+    }
+
+    pub fn add(&mut self, start: usize, end: usize) {
+        self.ranges.push((start, end));
+    }
+
+    /// Stop logging the range at the given position in the list, forgetting the writes into it.
+    pub fn remove(&mut self, index: usize) -> Option<(usize, usize)> {
+        if index >= self.ranges.len() {
+            return None;
+        }
+        let range = self.ranges.remove(index);
+        let ranges = &self.ranges;
+        self.writes.retain(|write| ranges.iter().any(|(start, end)| (*start..=*end).contains(&write.addr)));
+        Some(range)
+    }
+
+    /// Note a write if it is into a logged range.
+    pub fn record(&mut self, write: LoggedWrite) {
+        if !self.ranges.iter().any(|(start, end)| (*start..=*end).contains(&write.addr)) {
+            return;
+        }
+        if self.writes.len() == LIMIT {
+            self.writes.pop_front();
+        }
+        self.writes.push_back(write);
+    }
+
+    /// Writes into a range, oldest first.
+    pub fn writes(&self, start: usize, end: usize) -> impl Iterator<Item = &LoggedWrite> {
+        self.writes.iter().filter(move |write| (start..=end).contains(&write.addr))
+    }
+}
+
+/// Each range, numbered for removing, followed by the writes into it.
+impl fmt::Display for WriteLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (n, (start, end)) in self.ranges.iter().enumerate() {
+            match start == end {
+                true => writeln!(f, "{:>2}  {:03X}", n, start)?,
+                false => writeln!(f, "{:>2}  {:03X}-{:03X}", n, start, end)?,
+            }
+            for write in self.writes(*start, *end) {
+                writeln!(f, "      {}", write)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(addr: usize, after: u8) -> LoggedWrite {
+        LoggedWrite { addr, pc: 0x2A4, opcode: 0xF255, before: 0, after }
+    }
+
+    #[test]
+    fn test_only_logged_ranges_are_recorded() {
+        let mut log = WriteLog::default();
+        log.add(0x300, 0x30F);
+        log.add(0x400, 0x400);
+        for addr in [0x2FF, 0x300, 0x30F, 0x310, 0x400] {
+            log.record(write(addr, 1));
+        }
+
+        let addrs: Vec<usize> = log.writes(0x300, 0x30F).map(|write| write.addr).collect();
+        assert_eq!(addrs, [0x300, 0x30F]);
+        let expected = concat!(
+            " 0  300-30F\n",
+            "      2A4  F255  300: 00 -> 01\n",
+            "      2A4  F255  30F: 00 -> 01\n",
+            " 1  400\n",
+            "      2A4  F255  400: 00 -> 01\n",
+        );
+        assert_eq!(log.to_string(), expected);
+    }
+
+    #[test]
+    fn test_removing_ranges() {
+        let mut log = WriteLog::default();
+        log.add(0x300, 0x30F);
+        log.record(write(0x300, 1));
+
+        assert_eq!(log.remove(1), None);
+        assert_eq!(log.remove(0), Some((0x300, 0x30F)));
+        assert!(log.is_empty());
+        assert_eq!(log.writes(0, 0xFFF).count(), 0);
+    }
+
+    #[test]
+    fn test_only_recent_writes_are_kept() {
+        let mut log = WriteLog::default();
+        log.add(0x300, 0x300);
+        for n in 0..LIMIT + 5 {
+            log.record(write(0x300, n as u8));
+        }
+
+        assert_eq!(log.writes(0x300, 0x300).count(), LIMIT);
+        assert_eq!(log.writes(0x300, 0x300).next().unwrap().after, 5);
+    }
+}